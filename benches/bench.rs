@@ -10,6 +10,40 @@ fn add_char(c: char, n: i32) -> char {
     std::char::from_u32(c as u32 + n as u32).unwrap_or(c)
 }
 
+/// ひらがなかカタカナで3〜7文字のランダムな名前を作ります。
+fn random_name(rng: &mut impl Rng, first: char) -> String {
+    let len = rng.gen_range(0, 5) + 3;
+    let mut name = String::new();
+    name.reserve(len);
+    for _ in 0..len {
+        name.push(add_char(first, rng.gen_range(0, 82)));
+    }
+    name
+}
+
+/// shohinテーブルのカラムごとの値を作ります。
+fn generate_shohin() -> (Vec<i32>, Vec<String>, Vec<i32>, Vec<i32>) {
+    let mut rng = rand::thread_rng();
+    let n = 100000;
+    let shohin_ids = (0..n).map(|i| i + 1).collect();
+    let shohin_names = (0..n).map(|_| random_name(&mut rng, 'あ')).collect();
+    let kubun_ids = (0..n).map(|_| rng.gen_range(0, 10000) + 1).collect();
+    let prices = (0..n).map(|_| rng.gen_range(0, 990) * 10).collect();
+    (shohin_ids, shohin_names, kubun_ids, prices)
+}
+
+fn create_shohin() -> Table {
+    Table::create(
+        "shohin",
+        attributes![
+            ("shohin_id", TypeKind::Integer),
+            ("shohin_name", TypeKind::Varchar),
+            ("kubun_id", TypeKind::Integer),
+            ("price", TypeKind::Integer)
+        ],
+    )
+}
+
 /// テスト用テーブルを作成します。
 /// shohin: 100000
 ///   shohin_id: seq
@@ -21,29 +55,16 @@ fn add_char(c: char, n: i32) -> char {
 ///   kubun_name: 8*82?
 fn setup_tables() -> (Table, Table) {
     let mut rng = rand::thread_rng();
-    let mut shohin = Table::create(
-        "shohin",
-        attributes![
-            ("shohin_id", TypeKind::Integer),
-            ("shohin_name", TypeKind::Varchar),
-            ("kubun_id", TypeKind::Integer),
-            ("price", TypeKind::Integer)
-        ],
-    );
-    for i in 0..100000 {
-        let len = rng.gen_range(0, 5) + 3;
-        let mut name = String::new();
-        name.reserve(len);
-        for _ in 0..len {
-            name.push(add_char('あ', rng.gen_range(0, 82)));
-        }
-        shohin.insert(values![
-            i + 1,
-            &name,
-            rng.gen_range(0, 10000) + 1,
-            rng.gen_range(0, 990) * 10
-        ]);
-    }
+    let (shohin_ids, shohin_names, kubun_ids, prices) = generate_shohin();
+    let mut shohin = create_shohin();
+    shohin
+        .append_columns(vec![
+            shohin_ids.into(),
+            shohin_names.into(),
+            kubun_ids.into(),
+            prices.into(),
+        ])
+        .unwrap();
     let mut kubun = Table::create(
         "kubun",
         attributes![
@@ -51,18 +72,50 @@ fn setup_tables() -> (Table, Table) {
             ("kubun_name", TypeKind::Varchar)
         ],
     );
-    for i in 0..10000 {
-        let len = rng.gen_range(0, 5) + 3;
-        let mut name = String::new();
-        name.reserve(len);
-        for _ in 0..len {
-            name.push(add_char('ア', rng.gen_range(0, 82)));
-        }
-        kubun.insert(values![i + 1, &name]);
-    }
+    let kubun_ids: Vec<i32> = (0..10000).map(|i| i + 1).collect();
+    let kubun_names: Vec<String> = (0..10000).map(|_| random_name(&mut rng, 'ア')).collect();
+    kubun
+        .append_columns(vec![kubun_ids.into(), kubun_names.into()])
+        .unwrap();
     (shohin, kubun)
 }
 
+/// 1行ずつinsertするベンチマークテストです。
+#[bench]
+fn bench_insert(b: &mut test::Bencher) {
+    let (shohin_ids, shohin_names, kubun_ids, prices) = generate_shohin();
+    b.iter(|| {
+        let mut shohin = create_shohin();
+        for i in 0..shohin_ids.len() {
+            shohin.insert(values![
+                shohin_ids[i],
+                &shohin_names[i],
+                kubun_ids[i],
+                prices[i]
+            ]);
+        }
+        shohin
+    })
+}
+
+/// カラムごとにまとめて追記するベンチマークテストです。
+#[bench]
+fn bench_append_columns(b: &mut test::Bencher) {
+    let (shohin_ids, shohin_names, kubun_ids, prices) = generate_shohin();
+    b.iter(|| {
+        let mut shohin = create_shohin();
+        shohin
+            .append_columns(vec![
+                shohin_ids.clone().into(),
+                shohin_names.clone().into(),
+                kubun_ids.clone().into(),
+                prices.clone().into(),
+            ])
+            .unwrap();
+        shohin
+    })
+}
+
 /// LessThanのベンチマークテストです。
 #[bench]
 fn bench_less_than(b: &mut test::Bencher) {
@@ -73,6 +126,50 @@ fn bench_less_than(b: &mut test::Bencher) {
     })
 }
 
+/// 辞書を並べ直したテーブルでのLessThanのベンチマークテストです。
+#[bench]
+fn bench_less_than_sorted(b: &mut test::Bencher) {
+    let (mut shohin, _) = setup_tables();
+    shohin.sort_keys();
+    b.iter(|| {
+        let mut rng = rand::thread_rng();
+        shohin.less_than("price", rng.gen_range(0, 10000))
+    })
+}
+
+/// EqualToのベンチマークテストです。
+#[bench]
+fn bench_equal_to(b: &mut test::Bencher) {
+    let (shohin, _) = setup_tables();
+    b.iter(|| {
+        let mut rng = rand::thread_rng();
+        shohin.equal_to("kubun_id", rng.gen_range(0, 10000) + 1)
+    })
+}
+
+/// ビットマップインデックスを作成したテーブルでのEqualToのベンチマークテストです。
+#[bench]
+fn bench_equal_to_indexed(b: &mut test::Bencher) {
+    let (mut shohin, _) = setup_tables();
+    shohin.create_index("kubun_id");
+    b.iter(|| {
+        let mut rng = rand::thread_rng();
+        shohin.equal_to("kubun_id", rng.gen_range(0, 10000) + 1)
+    })
+}
+
+/// ビットマップインデックスを作成したテーブルでのINのベンチマークテストです。
+#[bench]
+fn bench_in_indexed(b: &mut test::Bencher) {
+    let (mut shohin, _) = setup_tables();
+    shohin.create_index("price");
+    b.iter(|| {
+        let mut rng = rand::thread_rng();
+        let prices: Vec<i32> = (0..5).map(|_| rng.gen_range(0, 990) * 10).collect();
+        shohin.filter(&Predicate::is_in("price", &prices))
+    })
+}
+
 /// GroupByのベンチマークテストです。
 #[bench]
 fn bench_group_by(b: &mut test::Bencher) {
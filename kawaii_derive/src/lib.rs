@@ -0,0 +1,110 @@
+//!
+//! かわいいデータベースの`#[derive(Row)]`を実装する手続きマクロです。
+//!
+//! 使い方は`kawaii::Row`を参照してください。
+//!
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, LitStr};
+
+///
+/// 名前付きフィールドの構造体に`kawaii::Row`を実装します。
+///
+/// フィールドに対応するカラムの定義だけを作ります。値の変換はserdeの導出に任せます。
+/// フィールド名がカラム名になります。`#[serde(rename = "名前")]`でカラム名を変えられます。
+///
+#[proc_macro_derive(Row)]
+pub fn derive_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(not_named_struct(input)),
+        },
+        _ => return Err(not_named_struct(input)),
+    };
+    // カラム名がserdeのフィールド名と食い違う指定は受け付けない
+    for attr in serde_attrs(&input.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") || meta.path.is_ident("transparent") {
+                Err(unsupported(&meta))
+            } else {
+                skip_meta(&meta)
+            }
+        })?;
+    }
+    let mut types = Vec::new();
+    let mut names = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("名前付きフィールド");
+        let mut name = ident.to_string().trim_start_matches("r#").to_string();
+        for attr in serde_attrs(&field.attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") && meta.input.peek(syn::Token![=]) {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    name = lit.value();
+                    Ok(())
+                } else if [
+                    "rename",
+                    "flatten",
+                    "skip",
+                    "skip_serializing",
+                    "skip_deserializing",
+                    "skip_serializing_if",
+                ]
+                .iter()
+                .any(|ident| meta.path.is_ident(ident))
+                {
+                    Err(unsupported(&meta))
+                } else {
+                    skip_meta(&meta)
+                }
+            })?;
+        }
+        types.push(&field.ty);
+        names.push(name);
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::kawaii::Row for #ident #ty_generics #where_clause {
+            fn attributes() -> ::std::vec::Vec<::kawaii::Attribute> {
+                ::std::vec![
+                    #( <#types as ::kawaii::RowField>::attribute(#names) ),*
+                ]
+            }
+        }
+    })
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("serde"))
+}
+
+/// カラムの定義に関係しない`#[serde(...)]`の項目を読み飛ばします。
+fn skip_meta(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|meta| skip_meta(&meta))?;
+    }
+    Ok(())
+}
+
+fn unsupported(meta: &ParseNestedMeta) -> syn::Error {
+    meta.error("Rowを導出する構造体には使えないserdeの指定です")
+}
+
+fn not_named_struct(input: &DeriveInput) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        "Rowを導出できるのは名前付きフィールドの構造体だけです",
+    )
+}
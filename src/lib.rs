@@ -7,7 +7,27 @@
 //!
 //! * 値を表すValue構造体
 //! * リレーションTraitとそれを実装するテーブルとかいろいろ
-//! * Select, LessThan, EqualsTo, GroupBy
+//! * Select, LessThan, EqualsTo, IsNull, GroupBy, OrderBy
+//! * 三値論理(NULL)に対応した条件式によるFilter
+//! * ビットマップインデックスによる等値・IN・範囲条件の抽出
+//! * 墓標による行の削除とcompactによる領域の回収
+//! * 条件式による行の更新(Update)
+//! * 主キー・UNIQUE制約とUpsert(INSERT ... ON CONFLICT)
+//! * NOT NULL・DEFAULT・CHECK制約と、違反理由を返すtry_insert
+//! * テーブルを管理するDatabaseと、外部キー制約(RESTRICT・CASCADE・SET NULL)
+//! * カラム名と値の組による挿入(省略したカラムはデフォルト値かNULL)
+//! * カラムごとの値の配列による一括追記
+//! * カラムの追加・削除・名前変更・型変更(ALTER TABLE)
+//! * バージョン付きのバイナリ形式によるテーブルの保存と読み込み
+//! * 先行書き込みログ(WAL)による更新の永続化とクラッシュからの回復
+//! * 保存したテーブルをmmapして読み込まずにそのまま検索する読み取り専用テーブル
+//! * ブロックごとのチェックサムによる保存データの破損検出と検査(verify)
+//! * CSVの読み込み(スキーマの指定か型の推測)
+//! * 任意のリレーションのCSV・TSVへの書き出し
+//! * JSON Linesの読み込みと書き出し
+//! * Apache Arrowのレコードバッチとの相互変換とIPCファイルの読み書き
+//! * 辞書エンコーディングと行グループごとの統計情報を使ったParquetファイルの読み書き
+//! * `#[derive(Row)]`による構造体と行の対応付け
 //!
 //! ## できてないもの
 //!
@@ -43,19 +63,60 @@
 //!     * スレッドローカルとMVCCを駆使するしかないか...
 //! * Shared-Nothing, Read-Only なシステムなら使えるかな
 //!
+extern crate self as kawaii;
+
+pub use arrow_array;
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int32Type;
+use arrow_array::{
+    Array, ArrayRef, DictionaryArray, Int32Array, RecordBatch, RecordBatchOptions, StringArray,
+};
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+pub use arrow_schema;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
 use bitvec::prelude::*;
+use bytes::Bytes;
+pub use kawaii_derive::Row;
+use memmap2::Mmap;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{
+    Compression, Encoding as ParquetEncoding, LogicalType, Repetition, Type as PhysicalType,
+};
+use parquet::column::page::{CompressedPage, Page, PageWriter};
+use parquet::column::writer::ColumnCloseResult;
+use parquet::data_type::ByteArray;
+use parquet::errors::ParquetError;
+use parquet::file::metadata::ColumnChunkMetaData;
+use parquet::file::properties::WriterProperties;
+use parquet::file::statistics::Statistics as ParquetStatistics;
+use parquet::file::writer::{SerializedFileWriter, SerializedPageWriter, TrackedWrite};
+use parquet::schema::types::{ColumnDescPtr, Type as ParquetType};
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::Serialize;
 use std::borrow::Borrow;
 use std::cmp::{self, Ordering};
-use std::collections::{btree_map, BTreeMap, HashMap};
-use std::convert::From;
+use std::collections::{btree_map, BTreeMap, BTreeSet, HashMap};
+use std::convert::{From, TryFrom, TryInto};
 use std::fmt;
+use std::fs;
+use std::io::Write;
 use std::iter::Map;
-use std::ops::{Deref, DerefMut, Index, IndexMut, Range, RangeBounds};
+use std::ops::{Bound, Deref, DerefMut, Index, IndexMut, Range, RangeBounds};
+use std::path::Path;
+use std::sync::Arc;
 
 /// BTreeの範囲検索結果。
 type BTreeRange<'a, K, V> = btree_map::Range<'a, K, V>;
 /// ビットマップ。
 type BitMap = BitVec;
+/// Dictionaryの範囲検索結果。
+type KeyRange<'a, Key> = Map<
+    BTreeRange<'a, ValuePtr<Key>, KeyId>,
+    for<'b> fn((&'b ValuePtr<Key>, &'b KeyId)) -> (&'b Key, &'b KeyId),
+>;
 
 /// NULLを表す構造体。
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
@@ -80,6 +141,15 @@ pub enum TypeKind {
     Integer,
 }
 
+impl fmt::Display for TypeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeKind::Varchar => write!(f, "VARCHAR"),
+            TypeKind::Integer => write!(f, "INTEGER"),
+        }
+    }
+}
+
 ///
 /// 文字列, 整数, NULLのいずれかを持つEnumです。
 ///
@@ -149,29 +219,29 @@ impl<'a> fmt::Display for Value<'a> {
 /// ```
 ///
 pub trait AsValue {
-    fn as_value_ref(&self) -> Value;
+    fn as_value_ref(&self) -> Value<'_>;
 }
 
-impl<'a> AsValue for &'a str {
-    fn as_value_ref(&self) -> Value {
+impl AsValue for &str {
+    fn as_value_ref(&self) -> Value<'_> {
         (*self).into()
     }
 }
 
 impl AsValue for i32 {
-    fn as_value_ref(&self) -> Value {
+    fn as_value_ref(&self) -> Value<'_> {
         (*self).into()
     }
 }
 
 impl AsValue for Null {
-    fn as_value_ref(&self) -> Value {
+    fn as_value_ref(&self) -> Value<'_> {
         (*self).into()
     }
 }
 
 impl AsValue for Value<'_> {
-    fn as_value_ref(&self) -> Value {
+    fn as_value_ref(&self) -> Value<'_> {
         match self {
             Value::Varchar(val) => Value::Varchar(val),
             Value::Integer(val) => Value::Integer(*val),
@@ -180,15 +250,315 @@ impl AsValue for Value<'_> {
     }
 }
 
+impl<'a> Value<'a> {
+    /// 値の型を返します。NULLの場合は`None`を返します。
+    pub fn kind(&self) -> Option<TypeKind> {
+        match self {
+            Value::Varchar(_) => Some(TypeKind::Varchar),
+            Value::Integer(_) => Some(TypeKind::Integer),
+            Value::Null(_) => None,
+        }
+    }
+    /// NULLかどうかを返します。
+    pub fn is_null(&self) -> bool {
+        self.kind().is_none()
+    }
+    ///
+    /// 型を変換した値を返します。NULLはNULLのままです。
+    /// 整数として読めない文字列を整数に変換しようとした場合は`None`を返します。
+    ///
+    pub fn cast(&self, kind: TypeKind) -> Option<OwnedValue> {
+        match (self, kind) {
+            (Value::Varchar(value), TypeKind::Integer) => {
+                value.trim().parse::<i32>().ok().map(Into::into)
+            }
+            (Value::Integer(value), TypeKind::Varchar) => Some(value.to_string().into()),
+            (value, _) => Some(value.clone().into()),
+        }
+    }
+}
+
+///
+/// 所有権を持つ値です。述語の定数など、借用元のない値を保持するのに使います。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::{AsValue, OwnedValue, Value, NULL};
+/// let s: OwnedValue = "str".into();
+/// assert_eq!(s.as_value_ref(), Value::Varchar("str"));
+/// let n: OwnedValue = NULL.into();
+/// assert_eq!(n.as_value_ref(), Value::Null(NULL));
+/// ```
+///
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub enum OwnedValue {
+    /// 文字列
+    Varchar(String),
+    /// 整数
+    Integer(i32),
+    /// NULL
+    Null(Null),
+}
+
+impl From<&str> for OwnedValue {
+    fn from(item: &str) -> Self {
+        OwnedValue::Varchar(item.to_string())
+    }
+}
+
+impl From<String> for OwnedValue {
+    fn from(item: String) -> Self {
+        OwnedValue::Varchar(item)
+    }
+}
+
+impl From<i32> for OwnedValue {
+    fn from(item: i32) -> Self {
+        OwnedValue::Integer(item)
+    }
+}
+
+impl From<Null> for OwnedValue {
+    fn from(item: Null) -> Self {
+        OwnedValue::Null(item)
+    }
+}
+
+impl From<Value<'_>> for OwnedValue {
+    fn from(item: Value<'_>) -> Self {
+        match item {
+            Value::Varchar(val) => OwnedValue::Varchar(val.to_string()),
+            Value::Integer(val) => OwnedValue::Integer(val),
+            Value::Null(val) => OwnedValue::Null(val),
+        }
+    }
+}
+
+impl AsValue for OwnedValue {
+    fn as_value_ref(&self) -> Value<'_> {
+        match self {
+            OwnedValue::Varchar(val) => Value::Varchar(val),
+            OwnedValue::Integer(val) => Value::Integer(*val),
+            OwnedValue::Null(val) => Value::Null(*val),
+        }
+    }
+}
+
+impl fmt::Display for OwnedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_value_ref().fmt(f)
+    }
+}
+
+impl OwnedValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, OwnedValue::Null(_))
+    }
+    /// SQLのリテラルとして書き出します(文字列は'で囲む)。
+    fn fmt_literal(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OwnedValue::Varchar(value) => write!(f, "'{}'", value.replace('\'', "''")),
+            OwnedValue::Integer(value) => write!(f, "{}", value),
+            OwnedValue::Null(_) => write!(f, "NULL"),
+        }
+    }
+}
+
+///
+/// 制約違反など、操作が失敗した理由を表します。
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Error {
+    /// カラム数が定義と合わない
+    ColumnCount {
+        expected: ColumnId,
+        actual: ColumnId,
+    },
+    /// カラムごとの行数が揃っていない
+    RowCount {
+        column: String,
+        expected: RowId,
+        actual: RowId,
+    },
+    /// 存在しないカラム名
+    UnknownColumn(String),
+    /// 同じ名前のカラムが既にある
+    ColumnExists(String),
+    /// 制約で使われているカラムを削除・変更しようとした
+    ColumnInUse(String),
+    /// 値の型がカラムの型と合わない
+    TypeMismatch {
+        column: String,
+        expected: TypeKind,
+        value: OwnedValue,
+    },
+    /// NOT NULL制約(主キーを含む)に違反した
+    NotNull(String),
+    /// CHECK制約に違反した
+    Check {
+        column: String,
+        check: Predicate,
+        value: OwnedValue,
+    },
+    /// 主キーまたはUNIQUE制約に違反した
+    Unique {
+        columns: Vec<String>,
+        values: Vec<OwnedValue>,
+    },
+    /// 存在しないテーブル名
+    UnknownTable(String),
+    /// 同じ名前のテーブルが既にある
+    TableExists(String),
+    /// 外部キーの参照先が主キーでもUNIQUE制約でもない
+    NotUniqueKey { table: String, columns: Vec<String> },
+    /// 外部キーの値が参照先のテーブルに存在しない
+    ForeignKey {
+        columns: Vec<String>,
+        table: String,
+        values: Vec<OwnedValue>,
+    },
+    /// 削除・更新しようとした値が他の行の外部キーから参照されている(RESTRICT)
+    Referenced {
+        table: String,
+        columns: Vec<String>,
+        values: Vec<OwnedValue>,
+    },
+    /// ファイルの読み書きに失敗した
+    Io(String),
+    /// ファイルの形式が正しくない
+    InvalidFormat(String),
+    /// ファイルのブロックのチェックサムが一致しない(壊れているか書きかけ)
+    Corrupted(Block),
+    /// CSVの読み込みに失敗した(行と列の番号は1から数える)
+    Csv {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// JSON Linesの読み込みに失敗した(行番号は1から数える)
+    JsonLines { line: usize, message: String },
+    /// serdeでの構造体と行の変換に失敗した
+    Serde(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err.to_string())
+    }
+}
+
+/// 値の組を`(1, りんご)`の形式で書くための文字列にします。
+fn join_values(values: &[OwnedValue]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(", ")
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ColumnCount { expected, actual } => write!(
+                f,
+                "カラム数が合いません(定義は{}個、値は{}個)",
+                expected, actual
+            ),
+            Error::RowCount {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "カラム{}の行数が合いません(他のカラムは{}行、このカラムは{}行)",
+                column, expected, actual
+            ),
+            Error::UnknownColumn(column) => write!(f, "カラム{}は存在しません", column),
+            Error::ColumnExists(column) => write!(f, "カラム{}は既に存在します", column),
+            Error::ColumnInUse(column) => {
+                write!(f, "カラム{}は制約で使われているので変更できません", column)
+            }
+            Error::TypeMismatch {
+                column,
+                expected,
+                value,
+            } => write!(
+                f,
+                "カラム{}は{}型ですが、値{}が指定されました",
+                column, expected, value
+            ),
+            Error::NotNull(column) => write!(f, "カラム{}にNULLは格納できません", column),
+            Error::Check {
+                column,
+                check,
+                value,
+            } => write!(
+                f,
+                "カラム{}の値{}はCHECK制約({})を満たしません",
+                column, value, check
+            ),
+            Error::Unique { columns, values } => write!(
+                f,
+                "一意性制約({})に違反しています: ({})",
+                columns.join(", "),
+                join_values(values)
+            ),
+            Error::UnknownTable(table) => write!(f, "テーブル{}は存在しません", table),
+            Error::TableExists(table) => write!(f, "テーブル{}は既に存在します", table),
+            Error::NotUniqueKey { table, columns } => write!(
+                f,
+                "テーブル{}の({})は主キーでもUNIQUE制約でもないので参照できません",
+                table,
+                columns.join(", ")
+            ),
+            Error::ForeignKey {
+                columns,
+                table,
+                values,
+            } => write!(
+                f,
+                "外部キー({})の値({})はテーブル{}に存在しません",
+                columns.join(", "),
+                join_values(values),
+                table
+            ),
+            Error::Referenced {
+                table,
+                columns,
+                values,
+            } => write!(
+                f,
+                "値({})はテーブル{}の外部キー({})から参照されています",
+                join_values(values),
+                table,
+                columns.join(", ")
+            ),
+            Error::Io(message) => write!(f, "ファイルの読み書きに失敗しました: {}", message),
+            Error::InvalidFormat(message) => {
+                write!(f, "ファイルの形式が正しくありません: {}", message)
+            }
+            Error::Corrupted(block) => {
+                write!(f, "{}が壊れています(チェックサムが一致しません)", block)
+            }
+            Error::Csv {
+                line,
+                column,
+                message,
+            } => write!(f, "CSVの{}行{}列目: {}", line, column, message),
+            Error::JsonLines { line, message } => {
+                write!(f, "JSON Linesの{}行目: {}", line, message)
+            }
+            Error::Serde(message) => write!(f, "構造体と行の変換に失敗しました: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 /// 値に割り振られるID
 type KeyId = usize;
 /// 行番号
 type RowId = usize;
 /// 列番号
 type ColumnId = usize;
-/// NULLを表すKeyId
-/// FIXME: KeyIdをEnumにしてif文を無くしたい
-const NULL_KEY_ID: KeyId = KeyId::max_value();
 
 #[derive(Debug, Eq, Clone, Copy)]
 pub struct ValuePtr<Key: Ord>(*const Key);
@@ -234,9 +604,7 @@ impl<Key: Ord> PartialEq for ValuePtr<Key> {
 // make partial_cmp() just return result from cmp()
 impl<Key: Ord> PartialOrd for ValuePtr<Key> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let lhs = self.as_ref();
-        let rhs = other.as_ref();
-        lhs.partial_cmp(rhs)
+        Some(self.cmp(other))
     }
 }
 
@@ -260,6 +628,12 @@ pub struct PagedArray<Key: Ord + Clone + Default> {
     pages: Vec<Vec<Key>>,
 }
 
+impl<Key: Ord + Clone + Default> Default for PagedArray<Key> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<Key: Ord + Clone + Default> PagedArray<Key> {
     const ARRAY_SIZE: usize = 16;
     fn to_addresses(idx: usize) -> (usize, usize) {
@@ -286,1346 +660,11893 @@ impl<Key: Ord + Clone + Default> PagedArray<Key> {
 }
 
 ///
-/// 値とそのIDを管理します。
+/// 整数を必要最小限のビット幅に詰めて保持する配列です。
+///
+/// 最大値が現在のビット幅に収まらない値が追加されると、全体を詰め直します。
 ///
 /// # Examples
 ///
 /// ```
-/// // ちなみにドキュメンテーションコメント内のテストは cargo test で実行されます。
-/// let mut dictionary = kawaii::Dictionary::new();
-/// assert_eq!(dictionary.insert("Alice"), 0);
-/// assert_eq!(dictionary.insert("Bob"), 1);
-/// assert_eq!(dictionary.insert("Alice"), 0);
-/// assert_eq!(dictionary.num_keys(), 2);
-/// assert_eq!(dictionary.id_of("Alice"), Some(0));
-/// assert_eq!(dictionary.id_of("Bob"), Some(1));
-/// assert_eq!(dictionary.id_of("Chris"), None);
-/// let mut iter = dictionary.range("Alice".."Chris");
-/// assert_eq!(iter.next(), Some((&"Alice", &0)));
-/// assert_eq!(iter.next(), Some((&"Bob", &1)));
-/// assert_eq!(iter.next(), None);
-/// assert_eq!(dictionary.key_of(0), &"Alice");
-/// assert_eq!(dictionary.key_of(1), &"Bob");
+/// use kawaii::PackedArray;
+/// let mut array = PackedArray::new();
+/// array.push(0);
+/// array.push(3);
+/// assert_eq!(array.bit_width(), 2);
+/// array.push(4);
+/// assert_eq!(array.bit_width(), 3);
+/// assert_eq!(array.get(1), 3);
+/// assert_eq!(array.get(2), 4);
+/// assert_eq!(array.pop(), Some(4));
+/// assert_eq!(array.len(), 2);
 /// ```
 ///
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Dictionary<Key>
-where
-    Key: Ord + Clone + Default,
-{
-    key_to_id: BTreeMap<ValuePtr<Key>, KeyId>,
-    keys: PagedArray<Key>,
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct PackedArray {
+    words: Vec<u64>,
+    bit_width: u32,
+    len: usize,
 }
 
-impl<Key> Dictionary<Key>
-where
-    Key: Ord + Clone + Default,
-{
-    ///
-    /// Dictionaryインスタンスを新規に作成します。
-    ///
+impl PackedArray {
+    const WORD_BITS: usize = 64;
     pub fn new() -> Self {
         Self {
-            key_to_id: BTreeMap::new(),
-            keys: PagedArray::new(),
+            words: Vec::new(),
+            bit_width: 0,
+            len: 0,
         }
     }
-    ///
-    /// 値の数を返します。
-    ///
-    pub fn num_keys(&self) -> KeyId {
-        self.key_to_id.len()
+    /// 値を表すのに必要なビット幅を返します。
+    fn bit_width_of(value: usize) -> u32 {
+        usize::BITS - value.leading_zeros()
     }
-    ///
-    /// 値を登録します。すでに存在していればそのIDを、新規なら新しいIDを返します。
-    ///
-    pub fn insert(&mut self, key: Key) -> KeyId {
-        let new_key_id = self.num_keys();
-        let key = ValuePtr(self.keys.set(new_key_id, key));
-        let key_id = self.key_to_id.entry(key).or_insert(new_key_id);
-        *key_id
+    fn num_words(len: usize, bit_width: u32) -> usize {
+        (len * bit_width as usize).div_ceil(Self::WORD_BITS)
     }
-    ///
-    /// 値に対応するIDを返します。存在しなければ`None`が返ります。
-    ///
-    pub fn id_of<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
-    where
-        ValuePtr<Key>: Borrow<Q>,
-    {
-        self.key_to_id.get(key).copied()
+    fn mask(&self) -> u64 {
+        Self::mask_of(self.bit_width)
     }
-    ///
-    /// 値の範囲を指定して、対応する範囲のイテレーターを返します。
-    ///
-    pub fn range<T, R>(
-        &self,
-        range: R,
-    ) -> Map<
-        BTreeRange<ValuePtr<Key>, KeyId>,
-        for<'a> fn((&'a ValuePtr<Key>, &'a KeyId)) -> (&'a Key, &'a KeyId),
-    >
-    where
-        ValuePtr<Key>: Borrow<T>,
-        R: RangeBounds<T>,
-        T: Ord + ?Sized,
-    {
-        self.key_to_id.range(range).map(|(k, v)| (k.as_ref(), v))
+    fn mask_of(bit_width: u32) -> u64 {
+        if bit_width as usize == Self::WORD_BITS {
+            u64::MAX
+        } else {
+            (1 << bit_width) - 1
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// 1要素あたりのビット幅を返します。
+    pub fn bit_width(&self) -> u32 {
+        self.bit_width
+    }
+    /// 値の格納に使っているバイト数を返します。
+    pub fn num_bytes(&self) -> usize {
+        self.words.len() * std::mem::size_of::<u64>()
+    }
+    /// 容量を確保します。
+    pub fn reserve(&mut self, additional: usize) {
+        let n_words = Self::num_words(self.len + additional, self.bit_width);
+        self.words.reserve(n_words.saturating_sub(self.words.len()));
     }
     ///
-    /// 値の範囲を指定して、対応するIDがtrueになったビットマップを返します。
+    /// `idx`番目の値を返します。
     ///
-    /// # Examples
+    /// # Panics
     ///
-    /// ```
-    /// let mut dictionary = kawaii::Dictionary::new();
-    /// dictionary.insert("Alice");
-    /// dictionary.insert("Bob");
-    /// dictionary.insert("Alice");
-    /// let result = dictionary.range_into_bits("Alice".."Bob");
-    /// assert_eq!(result[0], true);
-    /// assert_eq!(result[1], false);
-    /// ```
+    /// `idx >= self.len()` の場合
     ///
-    pub fn range_into_bits<T, R>(&self, range: R) -> BitMap
-    where
-        ValuePtr<Key>: Borrow<T>,
-        R: RangeBounds<T>,
-        T: Ord + ?Sized,
-    {
-        let mut bitmap = bitvec![0; self.num_keys()];
-        for (&_, &key_id) in self.key_to_id.range(range) {
-            bitmap.set(key_id, true);
+    pub fn get(&self, idx: usize) -> usize {
+        assert!(idx < self.len, "index out of range");
+        Self::get_in(&self.words, self.bit_width, idx)
+    }
+    /// ビット幅`bit_width`で詰めた`words`から`idx`番目の値を読みます。
+    fn get_in(words: &[u64], bit_width: u32, idx: usize) -> usize {
+        if bit_width == 0 {
+            return 0;
         }
-        bitmap
+        let bit = idx * bit_width as usize;
+        let (word, offset) = (bit / Self::WORD_BITS, bit % Self::WORD_BITS);
+        let mut value = words[word] >> offset;
+        if offset + bit_width as usize > Self::WORD_BITS {
+            value |= words[word + 1] << (Self::WORD_BITS - offset);
+        }
+        (value & Self::mask_of(bit_width)) as usize
     }
     ///
-    /// IDに対応する値の参照を返します。
+    /// `idx`番目に値を書き込みます。必要ならビット幅を広げます。
     ///
     /// # Panics
     ///
-    /// ``key_id >= self.num_keys()` の場合
+    /// `idx >= self.len()` の場合
     ///
-    pub fn key_of(&self, key_id: KeyId) -> &Key {
-        self.keys.get(key_id).expect("index out of range")
+    pub fn set(&mut self, idx: usize, value: usize) {
+        assert!(idx < self.len, "index out of range");
+        let bit_width = Self::bit_width_of(value);
+        if bit_width > self.bit_width {
+            self.repack(bit_width);
+        }
+        if self.bit_width == 0 {
+            return;
+        }
+        let mask = self.mask();
+        let value = value as u64;
+        let bit = idx * self.bit_width as usize;
+        let (word, offset) = (bit / Self::WORD_BITS, bit % Self::WORD_BITS);
+        self.words[word] &= !(mask << offset);
+        self.words[word] |= value << offset;
+        if offset + self.bit_width as usize > Self::WORD_BITS {
+            let shift = Self::WORD_BITS - offset;
+            self.words[word + 1] &= !(mask >> shift);
+            self.words[word + 1] |= value >> shift;
+        }
+    }
+    /// 末尾に値を追加します。
+    pub fn push(&mut self, value: usize) {
+        self.len += 1;
+        self.words
+            .resize(Self::num_words(self.len, self.bit_width), 0);
+        self.set(self.len - 1, value);
+    }
+    /// 末尾の値を削除してそれを返します。空の場合 `None`を返します。
+    pub fn pop(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.get(self.len - 1);
+        // 比較のために使わなくなったビットは0にしておく
+        self.set(self.len - 1, 0);
+        self.len -= 1;
+        self.words
+            .truncate(Self::num_words(self.len, self.bit_width));
+        Some(value)
+    }
+    /// ビット幅を変えて詰め直します。
+    fn repack(&mut self, bit_width: u32) {
+        let mut packed = PackedArray {
+            words: vec![0; Self::num_words(self.len, bit_width)],
+            bit_width,
+            len: self.len,
+        };
+        for idx in 0..self.len {
+            packed.set(idx, self.get(idx));
+        }
+        *self = packed;
+    }
+    /// 値のイテレーターを返します。
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).map(move |idx| self.get(idx))
+    }
+}
+
+impl Extend<usize> for PackedArray {
+    /// 最大値に合わせて一度だけ詰め直してから追加します。
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        let values: Vec<usize> = iter.into_iter().collect();
+        let max = values.iter().copied().max().unwrap_or(0);
+        let bit_width = Self::bit_width_of(max);
+        if bit_width > self.bit_width {
+            self.repack(bit_width);
+        }
+        self.reserve(values.len());
+        for value in values {
+            self.push(value);
+        }
     }
 }
 
 ///
-/// カラムを表します。
+/// 同じ値が続く区間(ラン)ごとに値を保持する配列です。
 ///
 /// # Examples
 ///
 /// ```
-/// use kawaii::Column;
-/// let mut column = Column::new();
-/// assert_eq!(column.append("Alice"), 0);
-/// assert_eq!(column.append("Bob"), 1);
-/// assert_eq!(column.append("Alice"), 0);
-/// assert_eq!(column.num_keys(), 2);
-/// assert_eq!(column.num_rows(), 3);
-/// assert_eq!(column.id_of("Alice"), Some(0));
-/// assert_eq!(column.id_of("Bob"), Some(1));
-/// assert_eq!(column.id_of("Chris"), None);
-/// let mut iter = column.range("Alice".."Chris");
-/// assert_eq!(iter.next(), Some((&"Alice", &0)));
-/// assert_eq!(iter.next(), Some((&"Bob", &1)));
-/// assert_eq!(iter.next(), None);
-/// assert_eq!(column.key_of(0), &"Alice");
-/// assert_eq!(column.key_of(1), &"Bob");
-/// assert_eq!(column.key_at(0), Some(&"Alice"));
-/// assert_eq!(column.key_at(1), Some(&"Bob"));
-/// assert_eq!(column.key_at(2), Some(&"Alice"));
+/// use kawaii::RunLengthArray;
+/// let mut array = RunLengthArray::new();
+/// for value in &[1, 1, 1, 2, 2, 1] {
+///     array.push(*value);
+/// }
+/// assert_eq!(array.len(), 6);
+/// assert_eq!(array.num_runs(), 3);
+/// assert_eq!(array.get(4), 2);
+/// assert_eq!(array.run_end(4), 5);
+/// assert_eq!(array.runs().collect::<Vec<_>>(), vec![(0..3, 1), (3..5, 2), (5..6, 1)]);
 /// ```
 ///
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Column<Key>
-where
-    Key: Ord + Clone + Default,
-{
-    dictionary: Dictionary<Key>,
-    key_ids: Vec<KeyId>,
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct RunLengthArray<T: Copy + Eq> {
+    /// 各ランの終端(排他的)
+    ends: Vec<usize>,
+    /// 各ランの値
+    values: Vec<T>,
 }
 
-impl<Key> Column<Key>
-where
-    Key: Ord + Clone + Default,
-{
-    /// 新しい`Column`インスタンスを作成します。
-    pub fn new() -> Column<Key> {
-        Column {
-            dictionary: Dictionary::new(),
-            key_ids: Vec::new(),
+impl<T: Copy + Eq> RunLengthArray<T> {
+    pub fn new() -> Self {
+        Self {
+            ends: Vec::new(),
+            values: Vec::new(),
         }
     }
-    /// 登録されている値の数を返します。
-    pub fn num_keys(&self) -> KeyId {
-        self.dictionary.num_keys()
-    }
-    /// 行数を返します。
-    pub fn num_rows(&self) -> RowId {
-        self.key_ids.len()
+    pub fn len(&self) -> usize {
+        self.ends.last().copied().unwrap_or(0)
     }
-    /// 値を追記し、その値に割り振られたIDを返します。
-    pub fn append(&mut self, key: Key) -> KeyId {
-        let key_id = self.dictionary.insert(key);
-        self.key_ids.push(key_id);
-        key_id
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
     }
-    /// NULLを追記し、NULL_KEY_IDを返します。
-    /// FIXME: IDでの判定をやめたい
-    pub fn append_null(&mut self) -> KeyId {
-        self.key_ids.push(NULL_KEY_ID);
-        NULL_KEY_ID
+    /// ランの数を返します。
+    pub fn num_runs(&self) -> usize {
+        self.ends.len()
     }
-    /// 末尾のIDを削除してそれを返します。num_rows() == 0 の場合 `None`を返します。
-    pub fn pop(&mut self) -> Option<KeyId> {
-        self.key_ids.pop()
+    /// `idx`を含むランの番号を返します。
+    fn run_index(&self, idx: usize) -> usize {
+        assert!(idx < self.len(), "index out of range");
+        self.ends.partition_point(|&end| end <= idx)
     }
-    pub fn id_of<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
-    where
-        ValuePtr<Key>: Borrow<Q>,
-    {
-        self.dictionary.id_of(key)
-    }
-    pub fn range<T, R>(
-        &self,
-        range: R,
-    ) -> Map<
-        BTreeRange<ValuePtr<Key>, KeyId>,
-        for<'a> fn((&'a ValuePtr<Key>, &'a KeyId)) -> (&'a Key, &'a KeyId),
-    >
-    where
-        ValuePtr<Key>: Borrow<T>,
-        R: RangeBounds<T>,
-        T: Ord + ?Sized,
-    {
-        self.dictionary.range(range)
-    }
-    pub fn range_into_bits<T, R>(&self, range: R) -> BitMap
-    where
-        ValuePtr<Key>: Borrow<T>,
-        R: RangeBounds<T>,
-        T: Ord + ?Sized,
-    {
-        self.dictionary.range_into_bits(range)
-    }
-    pub fn key_of(&self, key_id: KeyId) -> &Key {
-        self.dictionary.key_of(key_id)
+    ///
+    /// `idx`番目の値を返します。
+    ///
+    /// # Panics
+    ///
+    /// `idx >= self.len()` の場合
+    ///
+    pub fn get(&self, idx: usize) -> T {
+        self.values[self.run_index(idx)]
     }
     ///
-    /// 行番号を指定してキーIDを取得します。
+    /// `idx`を含むランの終端(排他的)を返します。
     ///
     /// # Panics
     ///
-    /// `row_id >= self.num_rows()` の場合
+    /// `idx >= self.len()` の場合
     ///
-    pub fn id_at(&self, row_id: RowId) -> KeyId {
-        assert!(row_id < self.num_rows());
-        self.key_ids[row_id]
+    pub fn run_end(&self, idx: usize) -> usize {
+        self.ends[self.run_index(idx)]
+    }
+    /// 末尾に値を追加します。末尾のランと同じ値ならランを伸ばします。
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        match (self.values.last(), self.ends.last_mut()) {
+            (Some(last), Some(end)) if *last == value => *end += 1,
+            _ => {
+                self.ends.push(len + 1);
+                self.values.push(value);
+            }
+        }
+    }
+    /// 末尾の値を削除してそれを返します。空の場合 `None`を返します。
+    pub fn pop(&mut self) -> Option<T> {
+        let value = *self.values.last()?;
+        let start = if self.ends.len() >= 2 {
+            self.ends[self.ends.len() - 2]
+        } else {
+            0
+        };
+        let end = self.ends.last_mut()?;
+        *end -= 1;
+        if *end == start {
+            self.ends.pop();
+            self.values.pop();
+        }
+        Some(value)
     }
     ///
-    /// 行番号を指定してキーを取得します。
+    /// `idx`番目の値を置き換えます。ランは必要に応じて分割・結合されます。
     ///
     /// # Panics
     ///
-    /// `row_id >= self.num_rows()` の場合
+    /// `idx >= self.len()` の場合
     ///
-    pub fn key_at(&self, row_id: RowId) -> Option<&Key> {
-        let key_id = self.id_at(row_id);
-        // TODO: enumつかう
-        if key_id == NULL_KEY_ID {
-            return None;
+    pub fn set(&mut self, idx: usize, value: T) {
+        let run = self.run_index(idx);
+        let old = self.values[run];
+        if old == value {
+            return;
+        }
+        let start = if run == 0 { 0 } else { self.ends[run - 1] };
+        let end = self.ends[run];
+        // [start, idx), [idx, idx + 1), [idx + 1, end) に分割する
+        let mut ends = Vec::new();
+        let mut values = Vec::new();
+        if start < idx {
+            ends.push(idx);
+            values.push(old);
+        }
+        let mid = run + ends.len();
+        ends.push(idx + 1);
+        values.push(value);
+        if idx + 1 < end {
+            ends.push(end);
+            values.push(old);
+        }
+        self.ends.splice(run..=run, ends);
+        self.values.splice(run..=run, values);
+        // 前後のランと値が同じなら結合する
+        if mid + 1 < self.values.len() && self.values[mid + 1] == value {
+            self.ends.remove(mid);
+            self.values.remove(mid);
+        }
+        if mid > 0 && self.values[mid - 1] == value {
+            self.ends.remove(mid - 1);
+            self.values.remove(mid - 1);
         }
-        Some(self.key_of(key_id))
+    }
+    /// ランの範囲と値のイテレーターを返します。
+    pub fn runs(&self) -> impl Iterator<Item = (Range<usize>, T)> + '_ {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(self.ends.iter().copied())
+            .zip(self.values.iter().copied())
+            .map(|((start, end), value)| (start..end, value))
+    }
+    /// 各ランの値を置き換えます。
+    pub fn map_values<F: FnMut(T) -> T>(&mut self, f: F) {
+        let values = self.values.drain(..).map(f).collect();
+        self.values = values;
     }
 }
 
-///
-/// カラムとしてのふるまいを規定するTraitです。
-///
-pub trait AsColumn: fmt::Debug {
-    fn num_keys(&self) -> KeyId;
-    fn num_rows(&self) -> RowId;
-    fn append(&mut self, key: &dyn AsValue) -> Option<KeyId>;
-    fn pop(&mut self) -> Option<KeyId>;
-    fn id_of(&self, key: &dyn AsValue) -> Option<KeyId>;
-    fn range(&self, range: Range<&dyn AsValue>) -> Option<BitMap>;
-    fn range_from(&self, key: &dyn AsValue) -> Option<BitMap>;
-    fn range_to(&self, key: &dyn AsValue) -> Option<BitMap>;
-    fn key_of(&self, key_id: KeyId) -> Value;
-    fn id_at(&self, row_id: RowId) -> KeyId;
-    fn key_at(&self, row_id: RowId) -> Value;
+/// カラムのキーIDの格納方式を表します。
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Encoding {
+    /// 行ごとにビット詰めして保持する
+    Packed,
+    /// 同じ値が続く区間ごとに保持する(ソート済みやクラスタ化されたデータ向け)
+    RunLength,
 }
 
-/// Table用カラム
+/// カラムのキーIDの格納領域です。
 #[derive(PartialEq, Eq, Debug, Clone)]
-pub enum TableColumn {
-    Varchar(Column<String>),
-    Integer(Column<i32>),
+enum KeyIds {
+    /// NULLの行には0を詰めておく
+    Packed(PackedArray),
+    RunLength(RunLengthArray<Option<KeyId>>),
 }
 
-impl TableColumn {
-    pub fn new(kind: TypeKind) -> TableColumn {
-        match kind {
-            TypeKind::Varchar => TableColumn::Varchar(Column::new()),
-            TypeKind::Integer => TableColumn::Integer(Column::new()),
-        }
+impl Default for KeyIds {
+    fn default() -> Self {
+        KeyIds::Packed(PackedArray::new())
     }
 }
 
-impl AsColumn for TableColumn {
-    fn num_keys(&self) -> KeyId {
+impl KeyIds {
+    fn len(&self) -> RowId {
         match self {
-            TableColumn::Varchar(column) => column.num_keys(),
-            TableColumn::Integer(column) => column.num_keys(),
+            KeyIds::Packed(array) => array.len(),
+            KeyIds::RunLength(array) => array.len(),
         }
     }
-    fn num_rows(&self) -> RowId {
+    fn push(&mut self, key_id: Option<KeyId>) {
         match self {
-            TableColumn::Varchar(column) => column.num_rows(),
-            TableColumn::Integer(column) => column.num_rows(),
+            KeyIds::Packed(array) => array.push(key_id.unwrap_or(0)),
+            KeyIds::RunLength(array) => array.push(key_id),
         }
     }
-    fn append(&mut self, key: &dyn AsValue) -> Option<KeyId> {
+    fn pop(&mut self) -> Option<()> {
         match self {
-            TableColumn::Varchar(column) => match key.as_value_ref() {
-                Value::Varchar(key) => Some(column.append(key.to_string())),
-                Value::Null(_) => Some(column.append_null()),
-                _ => None,
-            },
-            TableColumn::Integer(column) => match key.as_value_ref() {
-                Value::Integer(key) => Some(column.append(key)),
-                Value::Null(_) => Some(column.append_null()),
-                _ => None,
-            },
+            KeyIds::Packed(array) => array.pop().map(|_| ()),
+            KeyIds::RunLength(array) => array.pop().map(|_| ()),
         }
     }
-    fn pop(&mut self) -> Option<KeyId> {
+    fn set(&mut self, row_id: RowId, key_id: Option<KeyId>) {
         match self {
-            TableColumn::Varchar(column) => column.pop(),
-            TableColumn::Integer(column) => column.pop(),
+            KeyIds::Packed(array) => array.set(row_id, key_id.unwrap_or(0)),
+            KeyIds::RunLength(array) => array.set(row_id, key_id),
         }
     }
-    fn id_of(&self, key: &dyn AsValue) -> Option<KeyId> {
-        match (self, key.as_value_ref()) {
-            (TableColumn::Varchar(column), Value::Varchar(key)) => column.id_of(key),
-            (TableColumn::Integer(column), Value::Integer(key)) => column.id_of(&key),
-            _ => None,
+    fn extend(&mut self, key_ids: &[Option<KeyId>]) {
+        match self {
+            KeyIds::Packed(array) => array.extend(key_ids.iter().map(|key_id| key_id.unwrap_or(0))),
+            KeyIds::RunLength(array) => {
+                for &key_id in key_ids {
+                    array.push(key_id);
+                }
+            }
         }
     }
-    fn range(&self, range: Range<&dyn AsValue>) -> Option<BitMap> {
-        match (self, range.start.as_value_ref(), range.end.as_value_ref()) {
-            (TableColumn::Varchar(column), Value::Varchar(start), Value::Varchar(end)) => {
-                Some(column.range_into_bits(start.to_string()..end.to_string()))
+}
+
+///
+/// キーIDごとに、その値を持つ行番号の集合を圧縮して持つ転置索引です。
+/// 等値条件やIN、値の種類が少ない範囲条件を行番号の集合の和集合で求められます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::BitmapIndex;
+/// let mut index = BitmapIndex::new();
+/// index.insert(0, 0);
+/// index.insert(1, 1);
+/// index.insert(0, 2);
+/// assert_eq!(index.rows_of(0).iter().collect::<Vec<_>>(), vec![0, 2]);
+/// assert_eq!(index.union(0..2).len(), 3);
+/// index.remove(0, 2);
+/// assert_eq!(index.rows_of(0).len(), 1);
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct BitmapIndex {
+    /// キーIDごとの行番号の集合
+    row_sets: Vec<RowSet>,
+}
+
+/// 空の行番号の集合
+static EMPTY_ROW_SET: RowSet = RowSet::new();
+
+impl BitmapIndex {
+    pub fn new() -> Self {
+        BitmapIndex {
+            row_sets: Vec::new(),
+        }
+    }
+    /// `row_id`の行の値が`key_id`であることを登録します。
+    pub fn insert(&mut self, key_id: KeyId, row_id: RowId) {
+        if self.row_sets.len() <= key_id {
+            self.row_sets.resize_with(key_id + 1, RowSet::new);
+        }
+        self.row_sets[key_id].insert(row_id);
+    }
+    /// `insert`した登録を取り消します。
+    pub fn remove(&mut self, key_id: KeyId, row_id: RowId) {
+        if let Some(row_set) = self.row_sets.get_mut(key_id) {
+            row_set.remove(row_id);
+        }
+    }
+    /// 値が`key_id`である行の集合を返します。
+    pub fn rows_of(&self, key_id: KeyId) -> &RowSet {
+        self.row_sets.get(key_id).unwrap_or(&EMPTY_ROW_SET)
+    }
+    /// 値がいずれかの`key_ids`である行の集合を返します。
+    pub fn union<I: IntoIterator<Item = KeyId>>(&self, key_ids: I) -> RowSet {
+        let mut rows = RowSet::new();
+        for key_id in key_ids {
+            let row_set = self.rows_of(key_id);
+            if !row_set.is_empty() {
+                rows = rows.union(row_set);
             }
-            (TableColumn::Integer(column), Value::Integer(start), Value::Integer(end)) => {
-                Some(column.range_into_bits(start..end))
+        }
+        rows
+    }
+    /// 振り直したキーID(旧ID→新IDの対応表)に合わせて行番号の集合を並べ替えます。
+    fn remap(&mut self, new_key_ids: &[KeyId]) {
+        let mut row_sets = vec![RowSet::new(); new_key_ids.len()];
+        for (key_id, row_set) in self.row_sets.drain(..).enumerate() {
+            row_sets[new_key_ids[key_id]] = row_set;
+        }
+        self.row_sets = row_sets;
+    }
+}
+
+/// RowSetのコンテナ1つが受け持つ行番号の下位ビット数
+const CONTAINER_BITS: u32 = 16;
+/// 下位ビットのマスク
+const CONTAINER_MASK: RowId = (1 << CONTAINER_BITS) - 1;
+/// ビットマップコンテナのワード数
+const BITMAP_WORDS: usize = (1 << CONTAINER_BITS) / 64;
+/// これより要素が多い配列コンテナはビットマップコンテナにする
+const ARRAY_MAX_LEN: usize = 4096;
+
+/// RowSetで上位ビットが同じ行番号の下位ビットを保持するコンテナです。
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Container {
+    /// 昇順の配列(疎な場合)
+    Array(Vec<u16>),
+    /// 65536ビットのビットマップと要素数(密な場合)
+    Bitmap(Box<[u64]>, usize),
+    /// 閉区間`[start, last]`の昇順の列(連続している場合)
+    Run(Vec<(u16, u16)>),
+}
+
+impl Container {
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(_, len) => *len,
+            Container::Run(runs) => runs
+                .iter()
+                .map(|&(start, last)| (last - start) as usize + 1)
+                .sum(),
+        }
+    }
+    fn num_bytes(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len() * 2,
+            Container::Bitmap(words, _) => words.len() * 8,
+            Container::Run(runs) => runs.len() * 4,
+        }
+    }
+    fn contains(&self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&value).is_ok(),
+            Container::Bitmap(words, _) => words[value as usize / 64] >> (value % 64) & 1 == 1,
+            Container::Run(runs) => runs
+                .binary_search_by(|&(start, last)| {
+                    if last < value {
+                        Ordering::Less
+                    } else if start > value {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Equal
+                    }
+                })
+                .is_ok(),
+        }
+    }
+    fn last(&self) -> Option<u16> {
+        match self {
+            Container::Array(values) => values.last().copied(),
+            Container::Bitmap(words, _) => words
+                .iter()
+                .rposition(|&word| word != 0)
+                .map(|i| (i * 64 + 63 - words[i].leading_zeros() as usize) as u16),
+            Container::Run(runs) => runs.last().map(|&(_, last)| last),
+        }
+    }
+    /// 全ての値が自身のどの値よりも大きいコンテナを末尾に繋げます。
+    fn append(&mut self, other: Container) {
+        match (&mut *self, other) {
+            (Container::Run(runs), Container::Run(other)) => {
+                for (start, last) in other {
+                    match runs.last_mut() {
+                        Some((_, prev)) if *prev + 1 == start => *prev = last,
+                        _ => runs.push((start, last)),
+                    }
+                }
             }
-            _ => None,
+            (_, other) => *self = self.union(&other),
         }
     }
-    fn range_from(&self, key: &dyn AsValue) -> Option<BitMap> {
-        match (self, key.as_value_ref()) {
-            (TableColumn::Varchar(column), Value::Varchar(key)) => {
-                Some(column.range_into_bits(key.to_string()..))
+    /// 値を削除します。含まれていなければ`false`を返します。
+    fn remove(&mut self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => match values.binary_search(&value) {
+                Ok(idx) => {
+                    values.remove(idx);
+                    true
+                }
+                Err(_) => false,
+            },
+            Container::Bitmap(words, len) => {
+                let word = &mut words[value as usize / 64];
+                let bit = 1 << (value % 64);
+                if *word & bit == 0 {
+                    return false;
+                }
+                *word &= !bit;
+                *len -= 1;
+                if *len <= ARRAY_MAX_LEN {
+                    *self = Container::from_words(self.to_words());
+                }
+                true
             }
-            (TableColumn::Integer(column), Value::Integer(key)) => {
-                Some(column.range_into_bits(key..))
+            Container::Run(runs) => {
+                let idx = runs.partition_point(|&(_, last)| last < value);
+                match runs.get(idx) {
+                    Some(&(start, last)) if start <= value => {
+                        // [start, value), (value, last] に分割する
+                        let mut pieces = Vec::new();
+                        if start < value {
+                            pieces.push((start, value - 1));
+                        }
+                        if value < last {
+                            pieces.push((value + 1, last));
+                        }
+                        runs.splice(idx..=idx, pieces);
+                        true
+                    }
+                    _ => false,
+                }
             }
-            _ => None,
         }
     }
-    fn range_to(&self, key: &dyn AsValue) -> Option<BitMap> {
-        match (self, key.as_value_ref()) {
-            (TableColumn::Varchar(column), Value::Varchar(key)) => {
-                Some(column.range_into_bits(..key.to_string()))
+    /// 値を追加します。追加済みなら`false`を返します。
+    fn insert(&mut self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                // 昇順に追加されることが多いので末尾を先に調べる
+                let idx = match values.last() {
+                    Some(&last) if last < value => values.len(),
+                    _ => match values.binary_search(&value) {
+                        Ok(_) => return false,
+                        Err(idx) => idx,
+                    },
+                };
+                values.insert(idx, value);
+                if values.len() > ARRAY_MAX_LEN {
+                    *self = Container::from_words(self.to_words());
+                }
+                true
             }
-            (TableColumn::Integer(column), Value::Integer(key)) => {
-                Some(column.range_into_bits(..key))
+            Container::Bitmap(words, len) => {
+                let word = &mut words[value as usize / 64];
+                let bit = 1 << (value % 64);
+                if *word & bit != 0 {
+                    return false;
+                }
+                *word |= bit;
+                *len += 1;
+                true
+            }
+            Container::Run(runs) => {
+                if let Some((_, last)) = runs.last_mut() {
+                    if *last < value {
+                        if *last + 1 == value {
+                            *last = value;
+                        } else {
+                            runs.push((value, value));
+                        }
+                        return true;
+                    }
+                }
+                if self.contains(value) {
+                    return false;
+                }
+                let mut words = self.to_words();
+                words[value as usize / 64] |= 1 << (value % 64);
+                *self = Container::from_words(words);
+                true
             }
-            _ => None,
         }
     }
-    fn key_of(&self, key_id: KeyId) -> Value {
-        if key_id >= self.num_keys() {
-            return NULL.into();
+    fn to_words(&self) -> Box<[u64]> {
+        if let Container::Bitmap(words, _) = self {
+            return words.clone();
         }
-        match self {
-            TableColumn::Varchar(column) => column.key_of(key_id).into(),
-            TableColumn::Integer(column) => (*column.key_of(key_id)).into(),
+        let mut words = vec![0; BITMAP_WORDS].into_boxed_slice();
+        for value in self.iter() {
+            words[value as usize / 64] |= 1 << (value % 64);
         }
+        words
     }
-    fn id_at(&self, row_id: RowId) -> KeyId {
+    /// 要素数に応じて配列かビットマップのコンテナを作ります。
+    fn from_words(words: Box<[u64]>) -> Container {
+        let len = words.iter().map(|word| word.count_ones() as usize).sum();
+        if len <= ARRAY_MAX_LEN {
+            Container::Array(iter_words(&words, 0).collect())
+        } else {
+            Container::Bitmap(words, len)
+        }
+    }
+    fn num_runs(&self) -> usize {
         match self {
-            TableColumn::Varchar(column) => column.id_at(row_id),
-            TableColumn::Integer(column) => column.id_at(row_id),
+            Container::Run(runs) => runs.len(),
+            _ => {
+                let mut num_runs = 0;
+                let mut prev: Option<u16> = None;
+                for value in self.iter() {
+                    if prev.is_none_or(|prev| prev + 1 != value) {
+                        num_runs += 1;
+                    }
+                    prev = Some(value);
+                }
+                num_runs
+            }
         }
     }
-    fn key_at(&self, row_id: RowId) -> Value {
-        if let Some(key) = match self {
-            TableColumn::Varchar(column) => column.key_at(row_id).map(|k| k.into()),
-            TableColumn::Integer(column) => column.key_at(row_id).map(|k| (*k).into()),
-        } {
-            return key;
+    /// 最も小さくなる表現に変換します。
+    fn optimize(&mut self) {
+        let num_runs = self.num_runs();
+        let len = self.len();
+        if num_runs * 4 < cmp::min(len * 2, BITMAP_WORDS * 8) {
+            if let Container::Run(_) = self {
+                return;
+            }
+            let mut runs: Vec<(u16, u16)> = Vec::with_capacity(num_runs);
+            for value in self.iter() {
+                match runs.last_mut() {
+                    Some((_, last)) if *last + 1 == value => *last = value,
+                    _ => runs.push((value, value)),
+                }
+            }
+            *self = Container::Run(runs);
+        } else if let Container::Run(_) = self {
+            *self = Container::from_words(self.to_words());
         }
-        NULL.into()
     }
-}
-
-/// 属性(カラム定義)を表します。
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Attribute {
-    name: String,
-    kind: TypeKind,
-}
-
-impl Attribute {
-    pub fn create(name: &str, kind: TypeKind) -> Attribute {
-        Attribute {
-            name: name.to_string(),
-            kind: kind,
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        self.iter_from(0)
+    }
+    /// 小さい方から`idx`番目以降の値を返すイテレータを返します。
+    fn iter_from(&self, idx: usize) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => {
+                Box::new(values[cmp::min(idx, values.len())..].iter().copied())
+            }
+            Container::Bitmap(words, _) => {
+                // idx番目のビットがあるワードまで読み飛ばす
+                let mut rest = idx;
+                for (i, word) in words.iter().enumerate() {
+                    let n = word.count_ones() as usize;
+                    if rest < n {
+                        let mut word = *word;
+                        for _ in 0..rest {
+                            word &= word - 1;
+                        }
+                        let start = i * 64 + word.trailing_zeros() as usize;
+                        return Box::new(iter_words(words, start));
+                    }
+                    rest -= n;
+                }
+                Box::new(std::iter::empty())
+            }
+            Container::Run(runs) => {
+                let mut rest = idx;
+                for (i, &(start, last)) in runs.iter().enumerate() {
+                    let n = (last - start) as usize + 1;
+                    if rest < n {
+                        let head = (start as usize + rest)..=(last as usize);
+                        let tail = runs[i + 1..]
+                            .iter()
+                            .flat_map(|&(start, last)| (start as usize)..=(last as usize));
+                        return Box::new(head.chain(tail).map(|value| value as u16));
+                    }
+                    rest -= n;
+                }
+                Box::new(std::iter::empty())
+            }
         }
     }
-    pub fn name(&self) -> &String {
-        &self.name
+    fn union(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(lhs), Container::Array(rhs))
+                if lhs.len() + rhs.len() <= ARRAY_MAX_LEN =>
+            {
+                let mut values = Vec::with_capacity(lhs.len() + rhs.len());
+                let (mut i, mut j) = (0, 0);
+                while i < lhs.len() && j < rhs.len() {
+                    match lhs[i].cmp(&rhs[j]) {
+                        Ordering::Less => {
+                            values.push(lhs[i]);
+                            i += 1;
+                        }
+                        Ordering::Greater => {
+                            values.push(rhs[j]);
+                            j += 1;
+                        }
+                        Ordering::Equal => {
+                            values.push(lhs[i]);
+                            i += 1;
+                            j += 1;
+                        }
+                    }
+                }
+                values.extend_from_slice(&lhs[i..]);
+                values.extend_from_slice(&rhs[j..]);
+                Container::Array(values)
+            }
+            _ => {
+                let mut words = self.to_words();
+                for (word, other) in words.iter_mut().zip(other.to_words().iter()) {
+                    *word |= other;
+                }
+                Container::from_words(words)
+            }
+        }
     }
-    pub fn kind(&self) -> TypeKind {
-        self.kind
+    fn intersection(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(values), _) => Container::Array(
+                values
+                    .iter()
+                    .copied()
+                    .filter(|&value| other.contains(value))
+                    .collect(),
+            ),
+            (_, Container::Array(_)) => other.intersection(self),
+            _ => {
+                let mut words = self.to_words();
+                for (word, other) in words.iter_mut().zip(other.to_words().iter()) {
+                    *word &= other;
+                }
+                Container::from_words(words)
+            }
+        }
     }
 }
 
+/// ビットマップの`start`ビット目以降で立っているビットの位置を返すイテレータを返します。
+fn iter_words(words: &[u64], start: usize) -> impl Iterator<Item = u16> + '_ {
+    let mut i = start / 64;
+    let mut word = words.get(i).map_or(0, |word| word & (!0 << (start % 64)));
+    std::iter::from_fn(move || loop {
+        if word != 0 {
+            let bit = word.trailing_zeros() as usize;
+            word &= word - 1;
+            return Some((i * 64 + bit) as u16);
+        }
+        i += 1;
+        word = *words.get(i)?;
+    })
+}
+
 ///
-/// 属性のスライスを簡便に定義します。
+/// 圧縮された行番号の集合です(Roaring Bitmap)。
+///
+/// 行番号を上位ビットで65536行ずつに分け、それぞれを疎なら配列、密ならビットマップ、
+/// 連続していれば区間の列で保持します。
 ///
 /// # Examples
 ///
 /// ```
-/// # use kawaii::*;
-/// # fn main() {
-/// #     let mut table = Table::create(
-/// #         "shohin",
-/// #         attributes![
-/// #             ("shohin_id", TypeKind::Integer),
-/// #             ("shohin_name", TypeKind::Varchar),
-/// #             ("kubun_id", TypeKind::Integer),
-/// #             ("price", TypeKind::Integer)
-/// #         ],
-/// #     );
-/// # }
+/// use kawaii::RowSet;
+/// let lhs: RowSet = (0..100000).filter(|row_id| row_id % 3 == 0).collect();
+/// let rhs = RowSet::from_range(50000..200000);
+/// assert_eq!(lhs.len(), 33334);
+/// assert!(lhs.contains(99999));
+/// assert_eq!((&lhs & &rhs).len(), 16667);
+/// assert_eq!((&lhs | &rhs).len(), 166667);
+/// assert_eq!(lhs.iter_from(2).take(2).collect::<Vec<_>>(), vec![6, 9]);
+/// assert!(rhs.num_bytes() < 1000);
 /// ```
-#[macro_export]
-macro_rules! attributes {
-    ( $( { $name:expr, $($kind:expr),+ } ),* ) => {
-        &[ $( Attribute::create($name.as_ref(), $( $kind ),+ ) ),* ]
-    };
-    ( $( ( $name:expr, $($kind:expr),+ ) ),* ) => {
-        &[ $( Attribute::create($name.as_ref(), $( $kind ),+ ) ),* ]
-    }
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct RowSet {
+    /// 上位ビットとそのコンテナ(上位ビットの昇順、空のコンテナは持たない)
+    containers: Vec<(RowId, Container)>,
+    len: usize,
+    last: Option<RowId>,
 }
 
-/// リレーション定義を表します。
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Definition {
-    name: String,
-    attributes: Vec<Attribute>,
-}
-
-impl Definition {
-    pub fn create(name: &str, attributes: &[Attribute]) -> Definition {
-        Definition {
-            name: name.to_string(),
-            attributes: attributes.to_vec(),
+impl RowSet {
+    pub const fn new() -> Self {
+        RowSet {
+            containers: Vec::new(),
+            len: 0,
+            last: None,
         }
     }
-    pub fn name(&self) -> &String {
-        &self.name
+    /// 範囲内の全ての行番号を含む集合を作成します。
+    pub fn from_range(range: Range<RowId>) -> Self {
+        let mut row_set = RowSet::new();
+        let mut start = range.start;
+        while start < range.end {
+            let high = start >> CONTAINER_BITS;
+            let last = cmp::min(range.end - 1, start | CONTAINER_MASK);
+            let run = (
+                (start & CONTAINER_MASK) as u16,
+                (last & CONTAINER_MASK) as u16,
+            );
+            row_set.containers.push((high, Container::Run(vec![run])));
+            start = last + 1;
+        }
+        row_set.len = range.len();
+        row_set.last = range.end.checked_sub(1).filter(|_| !range.is_empty());
+        row_set
     }
-    pub fn num_columns(&self) -> ColumnId {
-        self.attributes.len()
+    /// ビット列のうち値が`value`であるビットの位置の集合を作成します。
+    fn from_bits(bits: &BitSlice, value: bool) -> Self {
+        let mut containers = Vec::new();
+        for (high, chunk) in bits.chunks(1 << CONTAINER_BITS).enumerate() {
+            let mut words = vec![0; BITMAP_WORDS].into_boxed_slice();
+            let mut set = |low: usize| words[low / 64] |= 1 << (low % 64);
+            if value {
+                chunk.iter_ones().for_each(&mut set);
+            } else {
+                chunk.iter_zeros().for_each(&mut set);
+            }
+            containers.push((high as RowId, Container::from_words(words)));
+        }
+        RowSet::from_containers(containers)
     }
-    pub fn name_to_id(&self, col_name: &str) -> Option<ColumnId> {
-        self.attributes
+    fn from_containers(containers: Vec<(RowId, Container)>) -> Self {
+        let mut containers = containers;
+        containers.retain(|(_, container)| container.len() > 0);
+        let len = containers
             .iter()
-            .position(|column| &column.name == col_name)
+            .map(|(_, container)| container.len())
+            .sum();
+        let last = containers.last().and_then(|(high, container)| {
+            container
+                .last()
+                .map(|low| high << CONTAINER_BITS | low as RowId)
+        });
+        RowSet {
+            containers,
+            len,
+            last,
+        }
     }
-    pub fn select(&self, col_ids: &[ColumnId]) -> Definition {
-        let mut attributes = Vec::new();
-        for col_id in col_ids {
-            if *col_id < self.num_columns() {
-                attributes.push(self.attributes[*col_id].clone());
+    /// 要素数を返します。
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// 最大の行番号を返します。
+    pub fn last(&self) -> Option<RowId> {
+        self.last
+    }
+    /// おおよそのメモリ使用量(バイト数)を返します。
+    pub fn num_bytes(&self) -> usize {
+        self.containers
+            .iter()
+            .map(|(_, container)| container.num_bytes() + std::mem::size_of::<(RowId, Container)>())
+            .sum()
+    }
+    pub fn contains(&self, row_id: RowId) -> bool {
+        match self
+            .containers
+            .binary_search_by_key(&(row_id >> CONTAINER_BITS), |(high, _)| *high)
+        {
+            Ok(idx) => self.containers[idx]
+                .1
+                .contains((row_id & CONTAINER_MASK) as u16),
+            Err(_) => false,
+        }
+    }
+    /// 行番号を追加します。追加済みなら`false`を返します。
+    pub fn insert(&mut self, row_id: RowId) -> bool {
+        let high = row_id >> CONTAINER_BITS;
+        let low = (row_id & CONTAINER_MASK) as u16;
+        // 昇順に追加されることが多いので末尾を先に調べる
+        let idx = match self.containers.last() {
+            Some((last, _)) if *last == high => self.containers.len() - 1,
+            Some((last, _)) if *last < high => {
+                self.containers.push((high, Container::Array(Vec::new())));
+                self.containers.len() - 1
+            }
+            None => {
+                self.containers.push((high, Container::Array(Vec::new())));
+                0
             }
+            _ => match self
+                .containers
+                .binary_search_by_key(&high, |(high, _)| *high)
+            {
+                Ok(idx) => idx,
+                Err(idx) => {
+                    self.containers
+                        .insert(idx, (high, Container::Array(Vec::new())));
+                    idx
+                }
+            },
+        };
+        if !self.containers[idx].1.insert(low) {
+            return false;
         }
-        Definition {
-            name: self.name.clone(),
-            attributes: attributes,
+        self.len += 1;
+        self.last = cmp::max(self.last, Some(row_id));
+        true
+    }
+    /// 行番号を削除します。含まれていなければ`false`を返します。
+    pub fn remove(&mut self, row_id: RowId) -> bool {
+        let idx = match self
+            .containers
+            .binary_search_by_key(&(row_id >> CONTAINER_BITS), |(high, _)| *high)
+        {
+            Ok(idx) => idx,
+            Err(_) => return false,
+        };
+        if !self.containers[idx]
+            .1
+            .remove((row_id & CONTAINER_MASK) as u16)
+        {
+            return false;
+        }
+        if self.containers[idx].1.len() == 0 {
+            self.containers.remove(idx);
+        }
+        self.len -= 1;
+        if self.last == Some(row_id) {
+            self.last = self.containers.last().and_then(|(high, container)| {
+                container
+                    .last()
+                    .map(|low| high << CONTAINER_BITS | low as RowId)
+            });
         }
+        true
     }
-}
-
-impl Index<ColumnId> for Definition {
-    type Output = Attribute;
-    fn index(&self, col_id: ColumnId) -> &Self::Output {
-        &self.attributes[col_id]
+    /// 範囲内の全ての行番号を追加します。
+    pub fn insert_range(&mut self, range: Range<RowId>) {
+        if range.is_empty() {
+            return;
+        }
+        let other = RowSet::from_range(range.clone());
+        if self.last.is_some_and(|last| last >= range.start) {
+            *self = self.union(&other);
+            return;
+        }
+        // 末尾に追加する場合は連続区間のコンテナをそのまま繋げる
+        self.len += other.len;
+        self.last = other.last;
+        let mut containers = other.containers.into_iter();
+        if let Some((high, container)) = containers.next() {
+            match self.containers.last_mut() {
+                Some((last_high, last)) if *last_high == high => last.append(container),
+                _ => self.containers.push((high, container)),
+            }
+        }
+        self.containers.extend(containers);
     }
-}
-
-impl IndexMut<ColumnId> for Definition {
-    fn index_mut(&mut self, col_id: ColumnId) -> &mut Self::Output {
-        &mut self.attributes[col_id]
+    /// 昇順に全ての行番号を返すイテレータを返します。
+    pub fn iter(&self) -> impl Iterator<Item = RowId> + '_ {
+        self.iter_from(0)
     }
-}
-
-/// タプルを表します(カラムナーなのでfetchでしか使われない)
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
-pub struct Tuple<'a> {
-    values: Vec<Value<'a>>,
-}
-
-impl<'a> Tuple<'a> {
-    pub fn new() -> Tuple<'a> {
-        Tuple { values: Vec::new() }
+    /// 小さい方から`rank`番目以降の行番号を昇順に返すイテレータを返します。
+    pub fn iter_from(&self, rank: usize) -> impl Iterator<Item = RowId> + '_ {
+        let mut rest = rank;
+        let mut idx = self.containers.len();
+        for (i, (_, container)) in self.containers.iter().enumerate() {
+            let n = container.len();
+            if rest < n {
+                idx = i;
+                break;
+            }
+            rest -= n;
+        }
+        self.containers[idx..]
+            .iter()
+            .enumerate()
+            .flat_map(move |(i, (high, container))| {
+                let high = *high << CONTAINER_BITS;
+                let skip = if i == 0 { rest } else { 0 };
+                container
+                    .iter_from(skip)
+                    .map(move |low| high | low as RowId)
+            })
     }
-}
-
-impl<'a> Deref for Tuple<'a> {
-    type Target = Vec<Value<'a>>;
-    fn deref(&self) -> &Self::Target {
-        &self.values
+    /// 小さい方から`rank`番目の行番号を返します。
+    pub fn select(&self, rank: usize) -> Option<RowId> {
+        self.iter_from(rank).next()
+    }
+    /// 和集合を返します。
+    pub fn union(&self, other: &RowSet) -> RowSet {
+        let mut containers = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.containers.len() && j < other.containers.len() {
+            let (lhs_high, lhs) = &self.containers[i];
+            let (rhs_high, rhs) = &other.containers[j];
+            match lhs_high.cmp(rhs_high) {
+                Ordering::Less => {
+                    containers.push((*lhs_high, lhs.clone()));
+                    i += 1;
+                }
+                Ordering::Greater => {
+                    containers.push((*rhs_high, rhs.clone()));
+                    j += 1;
+                }
+                Ordering::Equal => {
+                    containers.push((*lhs_high, lhs.union(rhs)));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        containers.extend_from_slice(&self.containers[i..]);
+        containers.extend_from_slice(&other.containers[j..]);
+        RowSet::from_containers(containers)
+    }
+    /// 積集合を返します。
+    pub fn intersection(&self, other: &RowSet) -> RowSet {
+        let mut containers = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.containers.len() && j < other.containers.len() {
+            let (lhs_high, lhs) = &self.containers[i];
+            let (rhs_high, rhs) = &other.containers[j];
+            match lhs_high.cmp(rhs_high) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    containers.push((*lhs_high, lhs.intersection(rhs)));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        RowSet::from_containers(containers)
+    }
+    /// 各コンテナを最も小さくなる表現(連続区間の列を含む)に変換します。
+    pub fn optimize(&mut self) {
+        for (_, container) in &mut self.containers {
+            container.optimize();
+        }
     }
 }
 
-impl<'a> DerefMut for Tuple<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.values
+impl std::iter::FromIterator<RowId> for RowSet {
+    fn from_iter<I: IntoIterator<Item = RowId>>(iter: I) -> Self {
+        let mut row_set = RowSet::new();
+        for row_id in iter {
+            row_set.insert(row_id);
+        }
+        row_set
     }
 }
 
-impl<'a> Index<ColumnId> for Tuple<'a> {
-    type Output = Value<'a>;
-    fn index(&self, col_id: ColumnId) -> &Self::Output {
-        &self.values[col_id]
+impl std::ops::BitAnd for &RowSet {
+    type Output = RowSet;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(rhs)
     }
 }
 
-impl<'a> IndexMut<ColumnId> for Tuple<'a> {
-    fn index_mut(&mut self, col_id: ColumnId) -> &mut Self::Output {
-        &mut self.values[col_id]
+impl std::ops::BitOr for &RowSet {
+    type Output = RowSet;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
     }
 }
 
-/// タプルのリストを表します(カラムナーなのでfetchでしか使われない)
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
-pub struct Tuples<'a> {
-    values: Vec<Tuple<'a>>,
+///
+/// 値とそのIDを管理します。
+///
+/// # Examples
+///
+/// ```
+/// // ちなみにドキュメンテーションコメント内のテストは cargo test で実行されます。
+/// let mut dictionary = kawaii::Dictionary::new();
+/// assert_eq!(dictionary.insert("Alice"), 0);
+/// assert_eq!(dictionary.insert("Bob"), 1);
+/// assert_eq!(dictionary.insert("Alice"), 0);
+/// assert_eq!(dictionary.num_keys(), 2);
+/// assert_eq!(dictionary.id_of("Alice"), Some(0));
+/// assert_eq!(dictionary.id_of("Bob"), Some(1));
+/// assert_eq!(dictionary.id_of("Chris"), None);
+/// let mut iter = dictionary.range("Alice".."Chris");
+/// assert_eq!(iter.next(), Some((&"Alice", &0)));
+/// assert_eq!(iter.next(), Some((&"Bob", &1)));
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(dictionary.key_of(0), &"Alice");
+/// assert_eq!(dictionary.key_of(1), &"Bob");
+/// ```
+///
+#[derive(Debug)]
+pub struct Dictionary<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    key_to_id: BTreeMap<ValuePtr<Key>, KeyId>,
+    keys: PagedArray<Key>,
+    /// IDの順序と値の順序が一致しているかどうか
+    sorted: bool,
 }
 
-impl<'a> Tuples<'a> {
-    pub fn new() -> Tuples<'a> {
-        Tuples { values: Vec::new() }
+/// 登録されている値とその順序だけを比べます(`keys`の未使用の領域は比べない)。
+impl<Key> PartialEq for Dictionary<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.sorted == other.sorted
+            && self.num_keys() == other.num_keys()
+            && (0..self.num_keys()).all(|key_id| self.key_of(key_id) == other.key_of(key_id))
     }
 }
 
-impl<'a> Deref for Tuples<'a> {
-    type Target = Vec<Tuple<'a>>;
-    fn deref(&self) -> &Self::Target {
-        &self.values
-    }
-}
+impl<Key> Eq for Dictionary<Key> where Key: Ord + Clone + Default {}
 
-impl<'a> DerefMut for Tuples<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.values
+/// `key_to_id`は`keys`の値を指しているので、複製した`keys`を指すように組み立て直します。
+impl<Key> Clone for Dictionary<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    fn clone(&self) -> Self {
+        let keys = self.keys.clone();
+        let key_to_id = self
+            .key_to_id
+            .values()
+            .map(|&key_id| {
+                let key = keys.get(key_id).expect("登録された値がありません");
+                (ValuePtr::from(key), key_id)
+            })
+            .collect();
+        Self {
+            key_to_id,
+            keys,
+            sorted: self.sorted,
+        }
     }
 }
 
-impl<'a> Index<RowId> for Tuples<'a> {
-    type Output = Tuple<'a>;
-    fn index(&self, row_id: RowId) -> &Self::Output {
-        &self.values[row_id]
+impl<Key> Default for Dictionary<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<'a> IndexMut<RowId> for Tuples<'a> {
-    fn index_mut(&mut self, row_id: RowId) -> &mut Self::Output {
-        &mut self.values[row_id]
+impl<Key> Dictionary<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    ///
+    /// Dictionaryインスタンスを新規に作成します。
+    ///
+    pub fn new() -> Self {
+        Self {
+            key_to_id: BTreeMap::new(),
+            keys: PagedArray::new(),
+            sorted: true,
+        }
     }
-}
-
-/// リレーションとしての振る舞いを規定するTraitです。
-pub trait Relation: fmt::Display {
-    /// 行数を返します。
-    fn num_rows(&self) -> RowId;
-    /// カラム数を返します。
-    fn num_columns(&self) -> ColumnId;
-    /// 自身の定義を返します。
-    fn definition(&self) -> &Definition;
-    /// col_id番目のカラムを返します。
-    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn;
-    /// 行範囲を指定して、その範囲内の有効な行番号をdestに書き込み、スライスとして返します。
-    fn scan_row_ids<'a>(&self, range: Range<RowId>, dest: &'a mut [RowId]) -> &'a [RowId] {
-        let start = range.start;
-        let end = cmp::min(range.end, self.num_rows());
-        if start >= end {
-            return &dest[0..0];
+    ///
+    /// 値の数を返します。
+    ///
+    pub fn num_keys(&self) -> KeyId {
+        self.key_to_id.len()
+    }
+    ///
+    /// 値を登録します。すでに存在していればそのIDを、新規なら新しいIDを返します。
+    ///
+    /// 最大値より小さい値が新規に登録されると、IDの順序と値の順序が一致しなくなります。
+    ///
+    pub fn insert(&mut self, key: Key) -> KeyId {
+        let new_key_id = self.num_keys();
+        let key = ValuePtr(self.keys.set(new_key_id, key));
+        let is_greatest = self
+            .key_to_id
+            .keys()
+            .next_back()
+            .is_none_or(|max| *max < key);
+        let key_id = *self.key_to_id.entry(key).or_insert(new_key_id);
+        if key_id == new_key_id && !is_greatest {
+            self.sorted = false;
         }
-        let n_range = end - start;
-        if dest.len() < n_range {
-            return &dest[0..0];
+        key_id
+    }
+    ///
+    /// 昇順に並んだ重複のない値をまとめて登録し、それぞれのIDを返します。
+    /// どの値も登録済みの値より大きければ、値ごとに木をたどらずに索引をまとめて組み立てます。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut dictionary = kawaii::Dictionary::new();
+    /// assert_eq!(dictionary.insert_sorted(vec!["Alice", "Bob"]), vec![0, 1]);
+    /// assert_eq!(dictionary.insert_sorted(vec!["Bob", "Chris"]), vec![1, 2]);
+    /// assert!(dictionary.is_sorted());
+    /// ```
+    ///
+    pub fn insert_sorted(&mut self, keys: Vec<Key>) -> Vec<KeyId> {
+        let is_greatest = match (self.key_to_id.keys().next_back(), keys.first()) {
+            (Some(max), Some(first)) => max.as_ref() < first,
+            _ => true,
+        };
+        // 既存の索引の方が大きいと組み立て直す方が高くつく
+        if !is_greatest || self.num_keys() > keys.len() {
+            return keys.into_iter().map(|key| self.insert(key)).collect();
         }
-        let mut i = 0;
-        for row_id in start..end {
-            dest[i] = row_id;
-            i += 1;
+        let start = self.num_keys();
+        let mut entries = Vec::with_capacity(keys.len());
+        for (key_id, key) in (start..).zip(keys) {
+            entries.push((ValuePtr(self.keys.set(key_id, key)), key_id));
         }
-        &dest[0..i]
+        let mut key_to_id: BTreeMap<_, _> = entries.into_iter().collect();
+        self.key_to_id.append(&mut key_to_id);
+        (start..self.num_keys()).collect()
     }
-    /// 指定された範囲をフェッチします。範囲が無効なら`None`を返します。
-    fn fetch(&self, range: Range<RowId>) -> Option<Tuples> {
-        let range = {
-            let start = range.start;
-            let end = cmp::min(range.end, self.num_rows());
-            start..end
-        };
-        if range.start >= range.end {
-            return None;
+    ///
+    /// IDの順序と値の順序が一致しているかどうかを返します。
+    ///
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+    ///
+    /// 値の順にIDを振り直します。戻り値は旧IDから新IDへの対応表です。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut dictionary = kawaii::Dictionary::new();
+    /// dictionary.insert("Bob");
+    /// dictionary.insert("Alice");
+    /// assert!(!dictionary.is_sorted());
+    /// assert_eq!(dictionary.sort_keys(), vec![1, 0]);
+    /// assert!(dictionary.is_sorted());
+    /// assert_eq!(dictionary.id_of("Alice"), Some(0));
+    /// assert_eq!(dictionary.key_of(1), &"Bob");
+    /// ```
+    ///
+    pub fn sort_keys(&mut self) -> Vec<KeyId> {
+        let mut new_key_ids = vec![0; self.num_keys()];
+        let mut key_to_id = BTreeMap::new();
+        let mut keys = PagedArray::new();
+        for (new_key_id, (key, &old_key_id)) in self.key_to_id.iter().enumerate() {
+            new_key_ids[old_key_id] = new_key_id;
+            let key = ValuePtr(keys.set(new_key_id, key.as_ref().clone()));
+            key_to_id.insert(key, new_key_id);
         }
-        let n_fetches = range.end - range.start;
-        let n_cols = self.num_columns();
-        let mut tuples = Tuples::new();
-        tuples.reserve(n_fetches);
-        const STEP: RowId = 64;
-        let mut start = range.start;
-        let mut buffer = [0; STEP];
-        while start < range.end {
-            let step = cmp::min(STEP, range.end - start);
-            let end = start + step;
-            let row_ids = self.scan_row_ids(start..end, &mut buffer);
-            for row_id in row_ids {
-                let mut tuple = Tuple::new();
-                tuple.reserve(n_cols);
-                for col_id in 0..n_cols {
-                    let column = &self.column_at(col_id);
-                    tuple.push(column.key_at(*row_id));
+        self.key_to_id = key_to_id;
+        self.keys = keys;
+        self.sorted = true;
+        new_key_ids
+    }
+    ///
+    /// 値以上となる最小のIDを返します(該当がなければ`num_keys()`)。
+    /// IDの順序と値の順序が一致していない場合は`None`を返します。
+    ///
+    pub fn lower_bound<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.bound_of((Bound::Included(key), Bound::Unbounded))
+    }
+    ///
+    /// 値より大きくなる最小のIDを返します(該当がなければ`num_keys()`)。
+    /// IDの順序と値の順序が一致していない場合は`None`を返します。
+    ///
+    pub fn upper_bound<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.bound_of((Bound::Excluded(key), Bound::Unbounded))
+    }
+    fn bound_of<Q: Ord + ?Sized>(&self, range: (Bound<&Q>, Bound<&Q>)) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        if !self.sorted {
+            return None;
+        }
+        let mut iter = self.key_to_id.range::<Q, _>(range);
+        Some(iter.next().map_or(self.num_keys(), |(_, &key_id)| key_id))
+    }
+    ///
+    /// 値に対応するIDを返します。存在しなければ`None`が返ります。
+    ///
+    pub fn id_of<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.key_to_id.get(key).copied()
+    }
+    ///
+    /// 値の範囲を指定して、対応する範囲のイテレーターを返します。
+    ///
+    pub fn range<T, R>(&self, range: R) -> KeyRange<'_, Key>
+    where
+        ValuePtr<Key>: Borrow<T>,
+        R: RangeBounds<T>,
+        T: Ord + ?Sized,
+    {
+        self.key_to_id.range(range).map(|(k, v)| (k.as_ref(), v))
+    }
+    ///
+    /// 値の範囲を指定して、対応するIDがtrueになったビットマップを返します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut dictionary = kawaii::Dictionary::new();
+    /// dictionary.insert("Alice");
+    /// dictionary.insert("Bob");
+    /// dictionary.insert("Alice");
+    /// let result = dictionary.range_into_bits("Alice".."Bob");
+    /// assert_eq!(result[0], true);
+    /// assert_eq!(result[1], false);
+    /// ```
+    ///
+    pub fn range_into_bits<T, R>(&self, range: R) -> BitMap
+    where
+        ValuePtr<Key>: Borrow<T>,
+        R: RangeBounds<T>,
+        T: Ord + ?Sized,
+    {
+        let mut bitmap = bitvec![0; self.num_keys()];
+        for (&_, &key_id) in self.key_to_id.range(range) {
+            bitmap.set(key_id, true);
+        }
+        bitmap
+    }
+    ///
+    /// IDに対応する値の参照を返します。
+    ///
+    /// # Panics
+    ///
+    /// ``key_id >= self.num_keys()` の場合
+    ///
+    pub fn key_of(&self, key_id: KeyId) -> &Key {
+        self.keys.get(key_id).expect("index out of range")
+    }
+}
+
+///
+/// カラムを表します。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::Column;
+/// let mut column = Column::new();
+/// assert_eq!(column.append("Alice"), 0);
+/// assert_eq!(column.append("Bob"), 1);
+/// assert_eq!(column.append("Alice"), 0);
+/// assert_eq!(column.num_keys(), 2);
+/// assert_eq!(column.num_rows(), 3);
+/// assert_eq!(column.id_of("Alice"), Some(0));
+/// assert_eq!(column.id_of("Bob"), Some(1));
+/// assert_eq!(column.id_of("Chris"), None);
+/// let mut iter = column.range("Alice".."Chris");
+/// assert_eq!(iter.next(), Some((&"Alice", &0)));
+/// assert_eq!(iter.next(), Some((&"Bob", &1)));
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(column.key_of(0), &"Alice");
+/// assert_eq!(column.key_of(1), &"Bob");
+/// assert_eq!(column.key_at(0), Some(&"Alice"));
+/// assert_eq!(column.key_at(1), Some(&"Bob"));
+/// assert_eq!(column.key_at(2), Some(&"Alice"));
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Column<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    dictionary: Dictionary<Key>,
+    key_ids: KeyIds,
+    /// NULLでない行のビットが立ったビットマップ
+    validity: BitMap,
+    /// 作成されていればinsertのたびに更新される
+    index: Option<BitmapIndex>,
+}
+
+impl<Key> Column<Key>
+where
+    Key: Ord + Clone + Default,
+{
+    /// 新しい`Column`インスタンスを作成します。
+    pub fn new() -> Column<Key> {
+        Column {
+            dictionary: Dictionary::new(),
+            key_ids: KeyIds::default(),
+            validity: BitMap::new(),
+            index: None,
+        }
+    }
+    /// 登録されている値の数を返します。
+    pub fn num_keys(&self) -> KeyId {
+        self.dictionary.num_keys()
+    }
+    /// 行数を返します。
+    pub fn num_rows(&self) -> RowId {
+        self.key_ids.len()
+    }
+    /// 値を追記し、その値に割り振られたIDを返します。
+    pub fn append(&mut self, key: Key) -> KeyId {
+        let key_id = self.dictionary.insert(key);
+        if let Some(index) = &mut self.index {
+            index.insert(key_id, self.key_ids.len());
+        }
+        self.key_ids.push(Some(key_id));
+        self.validity.push(true);
+        key_id
+    }
+    /// NULLを追記します。
+    pub fn append_null(&mut self) {
+        self.key_ids.push(None);
+        self.validity.push(false);
+    }
+    ///
+    /// 値をまとめて追記します(`None`はNULL)。
+    /// 値を並べ替えて重複を除いてから辞書に登録するので、辞書を引くのは値の種類の数だけで済み、
+    /// 空の辞書に追記した場合は辞書がソート済みになります。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut column = kawaii::Column::new();
+    /// column.append_all(vec![Some(30), Some(10), None, Some(30), Some(20)]);
+    /// assert_eq!(column.num_rows(), 5);
+    /// assert_eq!(column.num_keys(), 3);
+    /// assert!(column.is_sorted());
+    /// assert_eq!(column.key_at(0), Some(&30));
+    /// assert_eq!(column.key_at(2), None);
+    /// ```
+    ///
+    pub fn append_all(&mut self, mut keys: Vec<Option<Key>>) {
+        let start = self.num_rows();
+        let mut order: Vec<usize> = (0..keys.len()).filter(|&i| keys[i].is_some()).collect();
+        order.sort_unstable_by(|&lhs, &rhs| keys[lhs].cmp(&keys[rhs]));
+        // 重複を除いた値と、各行の値が何番目の値かを求める
+        let mut distinct_keys: Vec<Key> = Vec::new();
+        let mut key_nos = vec![None; keys.len()];
+        for i in order {
+            if let Some(key) = keys[i].take() {
+                if distinct_keys.last() != Some(&key) {
+                    distinct_keys.push(key);
                 }
-                tuples.push(tuple);
+                key_nos[i] = Some(distinct_keys.len() - 1);
             }
-            start += step;
         }
-        tuples.shrink_to_fit();
-        Some(tuples)
+        let distinct_key_ids = self.dictionary.insert_sorted(distinct_keys);
+        let key_ids: Vec<Option<KeyId>> = key_nos
+            .into_iter()
+            .map(|key_no| key_no.map(|key_no| distinct_key_ids[key_no]))
+            .collect();
+        if let Some(index) = &mut self.index {
+            for (i, key_id) in key_ids.iter().enumerate() {
+                if let Some(key_id) = key_id {
+                    index.insert(*key_id, start + i);
+                }
+            }
+        }
+        self.key_ids.extend(&key_ids);
+        self.validity
+            .extend(key_ids.iter().map(|key_id| key_id.is_some()));
+    }
+    /// 末尾の行を削除してその行番号を返します。num_rows() == 0 の場合 `None`を返します。
+    pub fn pop(&mut self) -> Option<RowId> {
+        let row_id = self.num_rows().checked_sub(1)?;
+        let key_id = self.id_at(row_id);
+        if let (Some(index), Some(key_id)) = (&mut self.index, key_id) {
+            index.remove(key_id, row_id);
+        }
+        self.key_ids.pop()?;
+        self.validity.pop();
+        Some(self.num_rows())
+    }
+    ///
+    /// 行の値を置き換え、その値に割り振られたIDを返します。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn set(&mut self, row_id: RowId, key: Key) -> KeyId {
+        assert!(row_id < self.num_rows());
+        let key_id = self.dictionary.insert(key);
+        self.set_id(row_id, Some(key_id));
+        key_id
+    }
+    ///
+    /// 行の値をNULLに置き換えます。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn set_null(&mut self, row_id: RowId) {
+        self.set_id(row_id, None);
+    }
+    fn set_id(&mut self, row_id: RowId, key_id: Option<KeyId>) {
+        let old_key_id = self.id_at(row_id);
+        if let Some(index) = &mut self.index {
+            if let Some(old_key_id) = old_key_id {
+                index.remove(old_key_id, row_id);
+            }
+            if let Some(key_id) = key_id {
+                index.insert(key_id, row_id);
+            }
+        }
+        self.key_ids.set(row_id, key_id);
+        self.validity.set(row_id, key_id.is_some());
+    }
+    /// NULLの行数を返します。
+    pub fn num_nulls(&self) -> RowId {
+        self.validity.count_zeros()
+    }
+    /// NULLでない行のビットが立ったビットマップを返します。
+    pub fn validity(&self) -> &BitSlice {
+        &self.validity
+    }
+    pub fn id_of<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.dictionary.id_of(key)
+    }
+    pub fn range<T, R>(&self, range: R) -> KeyRange<'_, Key>
+    where
+        ValuePtr<Key>: Borrow<T>,
+        R: RangeBounds<T>,
+        T: Ord + ?Sized,
+    {
+        self.dictionary.range(range)
+    }
+    pub fn range_into_bits<T, R>(&self, range: R) -> BitMap
+    where
+        ValuePtr<Key>: Borrow<T>,
+        R: RangeBounds<T>,
+        T: Ord + ?Sized,
+    {
+        self.dictionary.range_into_bits(range)
+    }
+    pub fn key_of(&self, key_id: KeyId) -> &Key {
+        self.dictionary.key_of(key_id)
+    }
+    pub fn is_sorted(&self) -> bool {
+        self.dictionary.is_sorted()
+    }
+    pub fn lower_bound<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.dictionary.lower_bound(key)
+    }
+    pub fn upper_bound<Q: Ord + ?Sized>(&self, key: &Q) -> Option<KeyId>
+    where
+        ValuePtr<Key>: Borrow<Q>,
+    {
+        self.dictionary.upper_bound(key)
+    }
+    ///
+    /// 値の順にIDを振り直し、各行のキーIDを新しいIDに置き換えます。
+    ///
+    pub fn sort_keys(&mut self) {
+        if self.is_sorted() {
+            return;
+        }
+        let new_key_ids = self.dictionary.sort_keys();
+        match &mut self.key_ids {
+            KeyIds::Packed(array) => {
+                for row_id in self.validity.iter_ones() {
+                    let key_id = array.get(row_id);
+                    array.set(row_id, new_key_ids[key_id]);
+                }
+            }
+            KeyIds::RunLength(array) => {
+                array.map_values(|key_id| key_id.map(|key_id| new_key_ids[key_id]))
+            }
+        }
+        if let Some(index) = &mut self.index {
+            index.remap(&new_key_ids);
+        }
+    }
+    ///
+    /// `deleted`のビットが立っている行を取り除き、どの行からも参照されなくなった値を辞書から取り除きます。
+    /// 格納方式やインデックス、辞書が値の順に並んでいるかどうかは引き継ぎます。
+    ///
+    pub fn compact(&mut self, deleted: &BitSlice) {
+        let mut column = Column::new();
+        for row_id in 0..self.num_rows() {
+            if deleted.get(row_id).is_some_and(|bit| *bit) {
+                continue;
+            }
+            match self.key_at(row_id) {
+                Some(key) => {
+                    column.append(key.clone());
+                }
+                None => column.append_null(),
+            }
+        }
+        if self.is_sorted() {
+            column.sort_keys();
+        }
+        column.set_encoding(self.encoding());
+        if self.index.is_some() {
+            column.create_index();
+        }
+        *self = column;
+    }
+    ///
+    /// ビットマップインデックスを作成します。作成済みなら何もしません。
+    ///
+    pub fn create_index(&mut self) {
+        if self.index.is_some() {
+            return;
+        }
+        let mut index = BitmapIndex::new();
+        for row_id in 0..self.num_rows() {
+            if let Some(key_id) = self.id_at(row_id) {
+                index.insert(key_id, row_id);
+            }
+        }
+        self.index = Some(index);
+    }
+    /// ビットマップインデックスを削除します。
+    pub fn drop_index(&mut self) {
+        self.index = None;
+    }
+    /// ビットマップインデックスを返します。作成されていなければ`None`を返します。
+    pub fn index(&self) -> Option<&BitmapIndex> {
+        self.index.as_ref()
+    }
+    /// キーIDの格納方式を返します。
+    pub fn encoding(&self) -> Encoding {
+        match self.key_ids {
+            KeyIds::Packed(_) => Encoding::Packed,
+            KeyIds::RunLength(_) => Encoding::RunLength,
+        }
+    }
+    /// キーIDの格納方式を変更します。
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if self.encoding() == encoding {
+            return;
+        }
+        let mut key_ids = match encoding {
+            Encoding::Packed => KeyIds::Packed(PackedArray::new()),
+            Encoding::RunLength => KeyIds::RunLength(RunLengthArray::new()),
+        };
+        for row_id in 0..self.num_rows() {
+            key_ids.push(self.id_at(row_id));
+        }
+        self.key_ids = key_ids;
+    }
+    /// 同じキーIDが続く区間の数を返します。
+    pub fn num_runs(&self) -> usize {
+        match &self.key_ids {
+            KeyIds::Packed(_) => {
+                let mut num_runs = 0;
+                for row_id in 0..self.num_rows() {
+                    if row_id == 0 || self.id_at(row_id - 1) != self.id_at(row_id) {
+                        num_runs += 1;
+                    }
+                }
+                num_runs
+            }
+            KeyIds::RunLength(array) => array.num_runs(),
+        }
+    }
+    ///
+    /// 格納サイズが小さくなる方の格納方式を選びます。
+    ///
+    pub fn choose_encoding(&mut self) {
+        // ラン1つあたり終端と値で3ワード使う
+        const RUN_BITS: usize = 3 * usize::BITS as usize;
+        let key_bits = PackedArray::bit_width_of(self.num_keys().saturating_sub(1)).max(1) as usize;
+        if self.num_runs() * RUN_BITS < self.num_rows() * key_bits {
+            self.set_encoding(Encoding::RunLength);
+        } else {
+            self.set_encoding(Encoding::Packed);
+        }
+    }
+    ///
+    /// `row_id`と同じキーIDが続く区間の終端(排他的)を返します。
+    /// ラン長圧縮されていないカラムでは`row_id + 1`を返します。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn run_end(&self, row_id: RowId) -> RowId {
+        match &self.key_ids {
+            KeyIds::Packed(_) => {
+                assert!(row_id < self.num_rows());
+                row_id + 1
+            }
+            KeyIds::RunLength(array) => array.run_end(row_id),
+        }
+    }
+    ///
+    /// 行番号を指定してキーIDを取得します。NULLの場合は`None`を返します。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn id_at(&self, row_id: RowId) -> Option<KeyId> {
+        assert!(row_id < self.num_rows());
+        if !self.validity[row_id] {
+            return None;
+        }
+        match &self.key_ids {
+            KeyIds::Packed(array) => Some(array.get(row_id)),
+            KeyIds::RunLength(array) => array.get(row_id),
+        }
+    }
+    ///
+    /// 行番号を指定してキーを取得します。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn key_at(&self, row_id: RowId) -> Option<&Key> {
+        self.id_at(row_id).map(|key_id| self.key_of(key_id))
+    }
+    ///
+    /// 行番号を指定してNULLかどうかを返します。
+    ///
+    /// # Panics
+    ///
+    /// `row_id >= self.num_rows()` の場合
+    ///
+    pub fn is_null_at(&self, row_id: RowId) -> bool {
+        !self.validity[row_id]
+    }
+}
+
+///
+/// カラムとしてのふるまいを規定するTraitです。
+///
+pub trait AsColumn: fmt::Debug {
+    fn kind(&self) -> TypeKind;
+    fn num_keys(&self) -> KeyId;
+    fn num_rows(&self) -> RowId;
+    fn append(&mut self, key: &dyn AsValue) -> Option<RowId>;
+    fn pop(&mut self) -> Option<RowId>;
+    fn set(&mut self, row_id: RowId, key: &dyn AsValue) -> Option<RowId>;
+    fn id_of(&self, key: &dyn AsValue) -> Option<KeyId>;
+    fn range(&self, range: Range<&dyn AsValue>) -> Option<BitMap>;
+    fn range_from(&self, key: &dyn AsValue) -> Option<BitMap>;
+    fn range_to(&self, key: &dyn AsValue) -> Option<BitMap>;
+    fn is_sorted(&self) -> bool;
+    fn lower_bound(&self, key: &dyn AsValue) -> Option<KeyId>;
+    fn upper_bound(&self, key: &dyn AsValue) -> Option<KeyId>;
+    fn key_of(&self, key_id: KeyId) -> Value<'_>;
+    fn id_at(&self, row_id: RowId) -> Option<KeyId>;
+    fn key_at(&self, row_id: RowId) -> Value<'_>;
+    fn is_null_at(&self, row_id: RowId) -> bool;
+    fn validity(&self) -> &BitSlice;
+    fn encoding(&self) -> Encoding;
+    fn run_end(&self, row_id: RowId) -> RowId;
+    fn index(&self) -> Option<&BitmapIndex>;
+}
+
+/// Table用カラム
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TableColumn {
+    Varchar(Column<String>),
+    Integer(Column<i32>),
+}
+
+impl TableColumn {
+    pub fn new(kind: TypeKind) -> TableColumn {
+        match kind {
+            TypeKind::Varchar => TableColumn::Varchar(Column::new()),
+            TypeKind::Integer => TableColumn::Integer(Column::new()),
+        }
+    }
+    pub fn sort_keys(&mut self) {
+        match self {
+            TableColumn::Varchar(column) => column.sort_keys(),
+            TableColumn::Integer(column) => column.sort_keys(),
+        }
+    }
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            TableColumn::Varchar(column) => column.encoding(),
+            TableColumn::Integer(column) => column.encoding(),
+        }
+    }
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        match self {
+            TableColumn::Varchar(column) => column.set_encoding(encoding),
+            TableColumn::Integer(column) => column.set_encoding(encoding),
+        }
+    }
+    pub fn choose_encoding(&mut self) {
+        match self {
+            TableColumn::Varchar(column) => column.choose_encoding(),
+            TableColumn::Integer(column) => column.choose_encoding(),
+        }
+    }
+    pub fn compact(&mut self, deleted: &BitSlice) {
+        match self {
+            TableColumn::Varchar(column) => column.compact(deleted),
+            TableColumn::Integer(column) => column.compact(deleted),
+        }
+    }
+    pub fn create_index(&mut self) {
+        match self {
+            TableColumn::Varchar(column) => column.create_index(),
+            TableColumn::Integer(column) => column.create_index(),
+        }
+    }
+    pub fn drop_index(&mut self) {
+        match self {
+            TableColumn::Varchar(column) => column.drop_index(),
+            TableColumn::Integer(column) => column.drop_index(),
+        }
+    }
+    /// 値をまとめて追記します。型が合わない場合は何もせずに`None`を返します。
+    pub fn append_values(&mut self, values: ColumnValues) -> Option<RowId> {
+        let row_id = self.num_rows();
+        match (self, values) {
+            (TableColumn::Varchar(column), ColumnValues::Varchar(values)) => {
+                column.append_all(values)
+            }
+            (TableColumn::Integer(column), ColumnValues::Integer(values)) => {
+                column.append_all(values)
+            }
+            _ => return None,
+        }
+        Some(row_id)
+    }
+}
+
+///
+/// カラム単位で一括追記する値です(`None`はNULL)。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::{ColumnValues, TypeKind, Value};
+/// let values: ColumnValues = vec!["りんご", "みかん"].into();
+/// assert_eq!(values.kind(), TypeKind::Varchar);
+/// assert_eq!(values.value_at(1), Value::Varchar("みかん"));
+/// let values: ColumnValues = vec![Some(1), None].into();
+/// assert_eq!(values.len(), 2);
+/// assert!(values.value_at(1).is_null());
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ColumnValues {
+    Varchar(Vec<Option<String>>),
+    Integer(Vec<Option<i32>>),
+}
+
+impl ColumnValues {
+    pub fn new(kind: TypeKind) -> ColumnValues {
+        match kind {
+            TypeKind::Varchar => ColumnValues::Varchar(Vec::new()),
+            TypeKind::Integer => ColumnValues::Integer(Vec::new()),
+        }
+    }
+    pub fn kind(&self) -> TypeKind {
+        match self {
+            ColumnValues::Varchar(_) => TypeKind::Varchar,
+            ColumnValues::Integer(_) => TypeKind::Integer,
+        }
+    }
+    pub fn len(&self) -> RowId {
+        match self {
+            ColumnValues::Varchar(values) => values.len(),
+            ColumnValues::Integer(values) => values.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn value_at(&self, row_id: RowId) -> Value<'_> {
+        match self {
+            ColumnValues::Varchar(values) => match &values[row_id] {
+                Some(value) => Value::Varchar(value),
+                None => Value::Null(NULL),
+            },
+            ColumnValues::Integer(values) => match values[row_id] {
+                Some(value) => Value::Integer(value),
+                None => Value::Null(NULL),
+            },
+        }
+    }
+    /// 値を追加します。型が合わない場合は何もせずに`None`を返します。
+    pub fn push(&mut self, value: &dyn AsValue) -> Option<RowId> {
+        let row_id = self.len();
+        match (self, value.as_value_ref()) {
+            (ColumnValues::Varchar(values), Value::Varchar(value)) => {
+                values.push(Some(value.to_string()))
+            }
+            (ColumnValues::Integer(values), Value::Integer(value)) => values.push(Some(value)),
+            (ColumnValues::Varchar(values), Value::Null(_)) => values.push(None),
+            (ColumnValues::Integer(values), Value::Null(_)) => values.push(None),
+            _ => return None,
+        }
+        Some(row_id)
+    }
+}
+
+impl From<Vec<Option<String>>> for ColumnValues {
+    fn from(values: Vec<Option<String>>) -> Self {
+        ColumnValues::Varchar(values)
+    }
+}
+
+impl From<Vec<String>> for ColumnValues {
+    fn from(values: Vec<String>) -> Self {
+        ColumnValues::Varchar(values.into_iter().map(Some).collect())
+    }
+}
+
+impl From<Vec<&str>> for ColumnValues {
+    fn from(values: Vec<&str>) -> Self {
+        ColumnValues::Varchar(
+            values
+                .into_iter()
+                .map(|value| Some(value.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl From<Vec<Option<i32>>> for ColumnValues {
+    fn from(values: Vec<Option<i32>>) -> Self {
+        ColumnValues::Integer(values)
+    }
+}
+
+impl From<Vec<i32>> for ColumnValues {
+    fn from(values: Vec<i32>) -> Self {
+        ColumnValues::Integer(values.into_iter().map(Some).collect())
+    }
+}
+
+impl AsColumn for TableColumn {
+    fn kind(&self) -> TypeKind {
+        match self {
+            TableColumn::Varchar(_) => TypeKind::Varchar,
+            TableColumn::Integer(_) => TypeKind::Integer,
+        }
+    }
+    fn num_keys(&self) -> KeyId {
+        match self {
+            TableColumn::Varchar(column) => column.num_keys(),
+            TableColumn::Integer(column) => column.num_keys(),
+        }
+    }
+    fn num_rows(&self) -> RowId {
+        match self {
+            TableColumn::Varchar(column) => column.num_rows(),
+            TableColumn::Integer(column) => column.num_rows(),
+        }
+    }
+    fn append(&mut self, key: &dyn AsValue) -> Option<RowId> {
+        let row_id = self.num_rows();
+        match self {
+            TableColumn::Varchar(column) => match key.as_value_ref() {
+                Value::Varchar(key) => {
+                    column.append(key.to_string());
+                }
+                Value::Null(_) => column.append_null(),
+                _ => return None,
+            },
+            TableColumn::Integer(column) => match key.as_value_ref() {
+                Value::Integer(key) => {
+                    column.append(key);
+                }
+                Value::Null(_) => column.append_null(),
+                _ => return None,
+            },
+        };
+        Some(row_id)
+    }
+    fn pop(&mut self) -> Option<RowId> {
+        match self {
+            TableColumn::Varchar(column) => column.pop(),
+            TableColumn::Integer(column) => column.pop(),
+        }
+    }
+    fn set(&mut self, row_id: RowId, key: &dyn AsValue) -> Option<RowId> {
+        match self {
+            TableColumn::Varchar(column) => match key.as_value_ref() {
+                Value::Varchar(key) => {
+                    column.set(row_id, key.to_string());
+                }
+                Value::Null(_) => column.set_null(row_id),
+                _ => return None,
+            },
+            TableColumn::Integer(column) => match key.as_value_ref() {
+                Value::Integer(key) => {
+                    column.set(row_id, key);
+                }
+                Value::Null(_) => column.set_null(row_id),
+                _ => return None,
+            },
+        };
+        Some(row_id)
+    }
+    fn id_of(&self, key: &dyn AsValue) -> Option<KeyId> {
+        match (self, key.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(key)) => column.id_of(key),
+            (TableColumn::Integer(column), Value::Integer(key)) => column.id_of(&key),
+            _ => None,
+        }
+    }
+    fn range(&self, range: Range<&dyn AsValue>) -> Option<BitMap> {
+        match (self, range.start.as_value_ref(), range.end.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(start), Value::Varchar(end)) => {
+                Some(column.range_into_bits(start.to_string()..end.to_string()))
+            }
+            (TableColumn::Integer(column), Value::Integer(start), Value::Integer(end)) => {
+                Some(column.range_into_bits(start..end))
+            }
+            _ => None,
+        }
+    }
+    fn range_from(&self, key: &dyn AsValue) -> Option<BitMap> {
+        match (self, key.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(key)) => {
+                Some(column.range_into_bits(key.to_string()..))
+            }
+            (TableColumn::Integer(column), Value::Integer(key)) => {
+                Some(column.range_into_bits(key..))
+            }
+            _ => None,
+        }
+    }
+    fn range_to(&self, key: &dyn AsValue) -> Option<BitMap> {
+        match (self, key.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(key)) => {
+                Some(column.range_into_bits(..key.to_string()))
+            }
+            (TableColumn::Integer(column), Value::Integer(key)) => {
+                Some(column.range_into_bits(..key))
+            }
+            _ => None,
+        }
+    }
+    fn is_sorted(&self) -> bool {
+        match self {
+            TableColumn::Varchar(column) => column.is_sorted(),
+            TableColumn::Integer(column) => column.is_sorted(),
+        }
+    }
+    fn lower_bound(&self, key: &dyn AsValue) -> Option<KeyId> {
+        match (self, key.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(key)) => column.lower_bound(key),
+            (TableColumn::Integer(column), Value::Integer(key)) => column.lower_bound(&key),
+            _ => None,
+        }
+    }
+    fn upper_bound(&self, key: &dyn AsValue) -> Option<KeyId> {
+        match (self, key.as_value_ref()) {
+            (TableColumn::Varchar(column), Value::Varchar(key)) => column.upper_bound(key),
+            (TableColumn::Integer(column), Value::Integer(key)) => column.upper_bound(&key),
+            _ => None,
+        }
+    }
+    fn key_of(&self, key_id: KeyId) -> Value<'_> {
+        match self {
+            TableColumn::Varchar(column) => column.key_of(key_id).into(),
+            TableColumn::Integer(column) => (*column.key_of(key_id)).into(),
+        }
+    }
+    fn id_at(&self, row_id: RowId) -> Option<KeyId> {
+        match self {
+            TableColumn::Varchar(column) => column.id_at(row_id),
+            TableColumn::Integer(column) => column.id_at(row_id),
+        }
+    }
+    fn key_at(&self, row_id: RowId) -> Value<'_> {
+        if let Some(key) = match self {
+            TableColumn::Varchar(column) => column.key_at(row_id).map(|k| k.into()),
+            TableColumn::Integer(column) => column.key_at(row_id).map(|k| (*k).into()),
+        } {
+            return key;
+        }
+        NULL.into()
+    }
+    fn is_null_at(&self, row_id: RowId) -> bool {
+        match self {
+            TableColumn::Varchar(column) => column.is_null_at(row_id),
+            TableColumn::Integer(column) => column.is_null_at(row_id),
+        }
+    }
+    fn validity(&self) -> &BitSlice {
+        match self {
+            TableColumn::Varchar(column) => column.validity(),
+            TableColumn::Integer(column) => column.validity(),
+        }
+    }
+    fn encoding(&self) -> Encoding {
+        match self {
+            TableColumn::Varchar(column) => column.encoding(),
+            TableColumn::Integer(column) => column.encoding(),
+        }
+    }
+    fn run_end(&self, row_id: RowId) -> RowId {
+        match self {
+            TableColumn::Varchar(column) => column.run_end(row_id),
+            TableColumn::Integer(column) => column.run_end(row_id),
+        }
+    }
+    fn index(&self) -> Option<&BitmapIndex> {
+        match self {
+            TableColumn::Varchar(column) => column.index(),
+            TableColumn::Integer(column) => column.index(),
+        }
+    }
+}
+
+/// 属性(カラム定義)を表します。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Attribute {
+    name: String,
+    kind: TypeKind,
+    /// NOT NULL制約
+    not_null: bool,
+    /// 値を省略したときの値
+    default_value: Option<OwnedValue>,
+    /// CHECK制約(FALSEになる値は格納できない)
+    check: Option<Predicate>,
+}
+
+impl Attribute {
+    pub fn create(name: &str, kind: TypeKind) -> Attribute {
+        Attribute {
+            name: name.to_string(),
+            kind,
+            not_null: false,
+            default_value: None,
+            check: None,
+        }
+    }
+    /// NOT NULL制約を付けます。
+    pub fn not_null(mut self) -> Attribute {
+        self.not_null = true;
+        self
+    }
+    /// デフォルト値を設定します。
+    pub fn with_default<V: AsValue>(mut self, value: V) -> Attribute {
+        self.default_value = Some(value.as_value_ref().into());
+        self
+    }
+    /// CHECK制約を付けます。条件式がFALSEになる行は挿入できません(UNKNOWNは許されます)。
+    pub fn check(mut self, predicate: Predicate) -> Attribute {
+        self.check = Some(predicate);
+        self
+    }
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+    pub fn kind(&self) -> TypeKind {
+        self.kind
+    }
+    pub fn is_not_null(&self) -> bool {
+        self.not_null
+    }
+    pub fn default_value(&self) -> Option<&OwnedValue> {
+        self.default_value.as_ref()
+    }
+    pub fn check_predicate(&self) -> Option<&Predicate> {
+        self.check.as_ref()
+    }
+}
+
+///
+/// 属性のスライスを簡便に定義します。
+/// 型の後ろに`not_null`, `with_default(値)`, `check(条件式)`を並べると制約を付けられます。
+///
+/// # Examples
+///
+/// ```
+/// # use kawaii::*;
+/// # fn main() {
+/// #     let mut table = Table::create(
+/// #         "shohin",
+/// #         attributes![
+/// #             ("shohin_id", TypeKind::Integer),
+/// #             ("shohin_name", TypeKind::Varchar, not_null),
+/// #             ("kubun_id", TypeKind::Integer),
+/// #             ("price", TypeKind::Integer, with_default(0), check(Predicate::greater_equal("price", 0)))
+/// #         ],
+/// #     );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! attributes {
+    ( $( { $name:expr, $kind:expr $(, $modifier:ident $( ( $($arg:expr),* ) )? )* } ),* ) => {
+        &[ $( Attribute::create($name.as_ref(), $kind) $( .$modifier( $( $($arg),* )? ) )* ),* ]
+    };
+    ( $( ( $name:expr, $kind:expr $(, $modifier:ident $( ( $($arg:expr),* ) )? )* ) ),* ) => {
+        &[ $( Attribute::create($name.as_ref(), $kind) $( .$modifier( $( $($arg),* )? ) )* ),* ]
+    }
+}
+
+/// 主キーまたはUNIQUE制約を表します。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UniqueKey {
+    col_ids: Vec<ColumnId>,
+    /// 主キーかどうか(主キーのカラムにはNULLを格納できない)
+    primary: bool,
+}
+
+impl UniqueKey {
+    pub fn col_ids(&self) -> &[ColumnId] {
+        &self.col_ids
+    }
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+}
+
+///
+/// 外部キーの参照先の行が削除されたときや、参照されている値が書き換えられたときの動作を表します。
+///
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ReferentialAction {
+    /// 参照している行があれば削除・書き換えしない
+    Restrict,
+    /// 参照している行も削除するか、外部キーを新しい値に書き換える
+    Cascade,
+    /// 参照している行の外部キーをNULLにする
+    SetNull,
+}
+
+/// 外部キー制約を表します。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ForeignKey {
+    col_ids: Vec<ColumnId>,
+    /// 参照先のテーブル名
+    ref_table: String,
+    /// 参照先のカラム名(参照先の主キーまたはUNIQUE制約と同じ並び)
+    ref_col_names: Vec<String>,
+    on_delete: ReferentialAction,
+    on_update: ReferentialAction,
+}
+
+impl ForeignKey {
+    pub fn col_ids(&self) -> &[ColumnId] {
+        &self.col_ids
+    }
+    pub fn ref_table(&self) -> &String {
+        &self.ref_table
+    }
+    pub fn ref_col_names(&self) -> &[String] {
+        &self.ref_col_names
+    }
+    pub fn on_delete(&self) -> ReferentialAction {
+        self.on_delete
+    }
+    pub fn on_update(&self) -> ReferentialAction {
+        self.on_update
+    }
+    ///
+    /// 参照先テーブルでのカラム番号を返します。
+    /// 参照先のカラムが存在しないか、主キーやUNIQUE制約になっていない場合はその理由を返します。
+    ///
+    fn ref_col_ids(&self, parent: &Table) -> Result<Vec<ColumnId>, Error> {
+        let definition = &parent.definition;
+        let mut ref_col_ids = Vec::new();
+        for col_name in &self.ref_col_names {
+            match definition.name_to_id(col_name) {
+                Some(col_id) => ref_col_ids.push(col_id),
+                None => return Err(Error::UnknownColumn(col_name.clone())),
+            }
+        }
+        let unique_keys = &definition.unique_keys;
+        if !unique_keys.iter().any(|key| key.col_ids == ref_col_ids) {
+            return Err(Error::NotUniqueKey {
+                table: definition.name.clone(),
+                columns: self.ref_col_names.clone(),
+            });
+        }
+        Ok(ref_col_ids)
+    }
+}
+
+/// リレーション定義を表します。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Definition {
+    name: String,
+    attributes: Vec<Attribute>,
+    unique_keys: Vec<UniqueKey>,
+    foreign_keys: Vec<ForeignKey>,
+}
+
+impl Definition {
+    pub fn create(name: &str, attributes: &[Attribute]) -> Definition {
+        Definition {
+            name: name.to_string(),
+            attributes: attributes.to_vec(),
+            unique_keys: Vec::new(),
+            foreign_keys: Vec::new(),
+        }
+    }
+    ///
+    /// 主キーを設定します。存在しないカラム名があれば`None`を返します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let definition = Definition::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_id", TypeKind::Integer),
+    ///         ("shohin_name", TypeKind::Varchar)
+    ///     ],
+    /// )
+    /// .primary_key(&["shohin_id"])
+    /// .and_then(|definition| definition.unique(&["shohin_name"]))
+    /// .unwrap();
+    /// let mut shohin = Table::new(definition);
+    /// assert!(shohin.insert(values!(1, "りんご")).is_some());
+    /// assert!(shohin.insert(values!(1, "みかん")).is_none());
+    /// assert!(shohin.insert(values!(2, "りんご")).is_none());
+    /// assert!(shohin.upsert(values!(1, "みかん")).is_some());
+    /// ```
+    ///
+    pub fn primary_key(mut self, col_names: &[&str]) -> Option<Definition> {
+        let col_ids = self.names_to_ids(col_names)?;
+        self.unique_keys.retain(|unique_key| !unique_key.primary);
+        self.unique_keys.insert(
+            0,
+            UniqueKey {
+                col_ids,
+                primary: true,
+            },
+        );
+        Some(self)
+    }
+    ///
+    /// UNIQUE制約を追加します。存在しないカラム名があれば`None`を返します。
+    /// NULLを含む値の組はどれとも重複しないものとして扱います。
+    ///
+    pub fn unique(mut self, col_names: &[&str]) -> Option<Definition> {
+        let col_ids = self.names_to_ids(col_names)?;
+        self.unique_keys.push(UniqueKey {
+            col_ids,
+            primary: false,
+        });
+        Some(self)
+    }
+    ///
+    /// 外部キー制約を追加します。`col_names`の値の組は、テーブル`ref_table`の主キーまたは
+    /// UNIQUE制約のカラム`ref_col_names`に存在しなければなりません(NULLを含む組は検査しません)。
+    /// 制約は[`Database`]を通した挿入・更新・削除で検査されます。
+    /// 参照されている値の書き換えは拒否します(RESTRICT)。
+    /// 存在しないカラム名があるか、カラム数が合わなければ`None`を返します。
+    ///
+    pub fn foreign_key(
+        self,
+        col_names: &[&str],
+        ref_table: &str,
+        ref_col_names: &[&str],
+        on_delete: ReferentialAction,
+    ) -> Option<Definition> {
+        self.foreign_key_with_actions(
+            col_names,
+            ref_table,
+            ref_col_names,
+            on_delete,
+            ReferentialAction::Restrict,
+        )
+    }
+    ///
+    /// 参照されている値が書き換えられたときの動作`on_update`も指定して、外部キー制約を追加します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let kubun = Definition::create("kubun", attributes![("kubun_id", TypeKind::Integer)])
+    ///     .primary_key(&["kubun_id"])
+    ///     .unwrap();
+    /// let shohin = Definition::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_name", TypeKind::Varchar),
+    ///         ("kubun_id", TypeKind::Integer)
+    ///     ],
+    /// )
+    /// .foreign_key_with_actions(
+    ///     &["kubun_id"],
+    ///     "kubun",
+    ///     &["kubun_id"],
+    ///     ReferentialAction::Restrict,
+    ///     ReferentialAction::Cascade,
+    /// )
+    /// .unwrap();
+    /// let mut db = Database::new();
+    /// db.create_table(kubun).unwrap();
+    /// db.create_table(shohin).unwrap();
+    /// db.insert("kubun", values!(1)).unwrap();
+    /// db.insert("shohin", values!("りんご", 1)).unwrap();
+    /// db.update("kubun", &Predicate::equal_to("kubun_id", 1), &[("kubun_id", 10)])
+    ///     .unwrap();
+    /// let shohin = db.table("shohin").unwrap();
+    /// assert_eq!(shohin.equal_to("kubun_id", 10).num_rows(), 1);
+    /// ```
+    ///
+    pub fn foreign_key_with_actions(
+        mut self,
+        col_names: &[&str],
+        ref_table: &str,
+        ref_col_names: &[&str],
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
+    ) -> Option<Definition> {
+        let col_ids = self.names_to_ids(col_names)?;
+        if col_ids.len() != ref_col_names.len() {
+            return None;
+        }
+        self.foreign_keys.push(ForeignKey {
+            col_ids,
+            ref_table: ref_table.to_string(),
+            ref_col_names: ref_col_names.iter().map(|name| name.to_string()).collect(),
+            on_delete,
+            on_update,
+        });
+        Some(self)
+    }
+    fn names_to_ids(&self, col_names: &[&str]) -> Option<Vec<ColumnId>> {
+        if col_names.is_empty() {
+            return None;
+        }
+        col_names
+            .iter()
+            .map(|col_name| self.name_to_id(col_name))
+            .collect()
+    }
+    /// 主キーを含む一意性制約を返します。
+    pub fn unique_keys(&self) -> &[UniqueKey] {
+        &self.unique_keys
+    }
+    /// 外部キー制約を返します。
+    pub fn foreign_keys(&self) -> &[ForeignKey] {
+        &self.foreign_keys
+    }
+    /// カラム番号に対応するカラム名を返します。
+    fn names_of(&self, col_ids: &[ColumnId]) -> Vec<String> {
+        col_ids
+            .iter()
+            .map(|&col_id| self.attributes[col_id].name.clone())
+            .collect()
+    }
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+    pub fn num_columns(&self) -> ColumnId {
+        self.attributes.len()
+    }
+    pub fn name_to_id(&self, col_name: &str) -> Option<ColumnId> {
+        self.attributes
+            .iter()
+            .position(|column| column.name == col_name)
+    }
+    pub fn select(&self, col_ids: &[ColumnId]) -> Definition {
+        let mut attributes = Vec::new();
+        for col_id in col_ids {
+            if *col_id < self.num_columns() {
+                attributes.push(self.attributes[*col_id].clone());
+            }
+        }
+        Definition {
+            name: self.name.clone(),
+            attributes,
+            unique_keys: Vec::new(),
+            foreign_keys: Vec::new(),
+        }
+    }
+}
+
+impl Index<ColumnId> for Definition {
+    type Output = Attribute;
+    fn index(&self, col_id: ColumnId) -> &Self::Output {
+        &self.attributes[col_id]
+    }
+}
+
+impl IndexMut<ColumnId> for Definition {
+    fn index_mut(&mut self, col_id: ColumnId) -> &mut Self::Output {
+        &mut self.attributes[col_id]
+    }
+}
+
+/// タプルを表します(カラムナーなのでfetchでしか使われない)
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub struct Tuple<'a> {
+    values: Vec<Value<'a>>,
+}
+
+impl<'a> Default for Tuple<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Tuple<'a> {
+    pub fn new() -> Tuple<'a> {
+        Tuple { values: Vec::new() }
+    }
+}
+
+impl<'a> Deref for Tuple<'a> {
+    type Target = Vec<Value<'a>>;
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+impl<'a> DerefMut for Tuple<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.values
+    }
+}
+
+impl<'a> Index<ColumnId> for Tuple<'a> {
+    type Output = Value<'a>;
+    fn index(&self, col_id: ColumnId) -> &Self::Output {
+        &self.values[col_id]
+    }
+}
+
+impl<'a> IndexMut<ColumnId> for Tuple<'a> {
+    fn index_mut(&mut self, col_id: ColumnId) -> &mut Self::Output {
+        &mut self.values[col_id]
+    }
+}
+
+/// タプルのリストを表します(カラムナーなのでfetchでしか使われない)
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub struct Tuples<'a> {
+    values: Vec<Tuple<'a>>,
+}
+
+impl<'a> Default for Tuples<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Tuples<'a> {
+    pub fn new() -> Tuples<'a> {
+        Tuples { values: Vec::new() }
+    }
+}
+
+impl<'a> Deref for Tuples<'a> {
+    type Target = Vec<Tuple<'a>>;
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+impl<'a> DerefMut for Tuples<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.values
+    }
+}
+
+impl<'a> Index<RowId> for Tuples<'a> {
+    type Output = Tuple<'a>;
+    fn index(&self, row_id: RowId) -> &Self::Output {
+        &self.values[row_id]
+    }
+}
+
+impl<'a> IndexMut<RowId> for Tuples<'a> {
+    fn index_mut(&mut self, row_id: RowId) -> &mut Self::Output {
+        &mut self.values[row_id]
+    }
+}
+
+/// リレーションとしての振る舞いを規定するTraitです。
+pub trait Relation: fmt::Display {
+    /// 行数を返します。
+    fn num_rows(&self) -> RowId;
+    /// カラム数を返します。
+    fn num_columns(&self) -> ColumnId;
+    /// 自身の定義を返します。
+    fn definition(&self) -> &Definition;
+    /// col_id番目のカラムを返します。
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn;
+    /// 行範囲を指定して、その範囲内の有効な行番号をdestに書き込み、スライスとして返します。
+    fn scan_row_ids<'a>(&self, range: Range<RowId>, dest: &'a mut [RowId]) -> &'a [RowId] {
+        let start = range.start;
+        let end = cmp::min(range.end, self.num_rows());
+        if start >= end {
+            return &dest[0..0];
+        }
+        let n_range = end - start;
+        if dest.len() < n_range {
+            return &dest[0..0];
+        }
+        let mut i = 0;
+        for row_id in start..end {
+            dest[i] = row_id;
+            i += 1;
+        }
+        &dest[0..i]
+    }
+    /// 行範囲を指定して、その範囲内の有効な行番号を連続する区間ごとにfに渡します。
+    fn scan_row_ranges(&self, range: Range<RowId>, f: &mut dyn FnMut(Range<RowId>)) {
+        let end = cmp::min(range.end, self.num_rows());
+        const STEP: RowId = 64;
+        let mut start = range.start;
+        let mut buffer = [0; STEP];
+        let mut current: Option<Range<RowId>> = None;
+        while start < end {
+            let step = cmp::min(STEP, end - start);
+            for &row_id in self.scan_row_ids(start..start + step, &mut buffer) {
+                match &mut current {
+                    Some(rows) if rows.end == row_id => rows.end += 1,
+                    _ => {
+                        if let Some(rows) = current.replace(row_id..row_id + 1) {
+                            f(rows);
+                        }
+                    }
+                }
+            }
+            start += step;
+        }
+        if let Some(rows) = current {
+            f(rows);
+        }
+    }
+    /// 指定された範囲をフェッチします。範囲が無効なら`None`を返します。
+    fn fetch(&self, range: Range<RowId>) -> Option<Tuples<'_>> {
+        let range = {
+            let start = range.start;
+            let end = cmp::min(range.end, self.num_rows());
+            start..end
+        };
+        if range.start >= range.end {
+            return None;
+        }
+        let n_fetches = range.end - range.start;
+        let n_cols = self.num_columns();
+        let mut tuples = Tuples::new();
+        tuples.reserve(n_fetches);
+        const STEP: RowId = 64;
+        let mut start = range.start;
+        let mut buffer = [0; STEP];
+        while start < range.end {
+            let step = cmp::min(STEP, range.end - start);
+            let end = start + step;
+            let row_ids = self.scan_row_ids(start..end, &mut buffer);
+            for row_id in row_ids {
+                let mut tuple = Tuple::new();
+                tuple.reserve(n_cols);
+                for col_id in 0..n_cols {
+                    let column = &self.column_at(col_id);
+                    tuple.push(column.key_at(*row_id));
+                }
+                tuples.push(tuple);
+            }
+            start += step;
+        }
+        tuples.shrink_to_fit();
+        Some(tuples)
+    }
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(tuples) = self.fetch(0..self.num_rows()) {
+            for col_id in 0..self.num_columns() {
+                let name = &self.definition()[col_id].name();
+                if let fmt::Result::Err(err) = write!(f, "│{}", name) {
+                    return fmt::Result::Err(err);
+                }
+            }
+            if let fmt::Result::Err(err) = writeln!(f, "│") {
+                return fmt::Result::Err(err);
+            }
+            for tuple in tuples.deref() {
+                for field in tuple.deref() {
+                    if let fmt::Result::Err(err) = write!(f, "│{}", field) {
+                        return fmt::Result::Err(err);
+                    }
+                }
+                if let fmt::Result::Err(err) = writeln!(f, "│") {
+                    return fmt::Result::Err(err);
+                }
+            }
+        }
+        write!(f, "")
+    }
+}
+
+/// テーブルを表します。
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Table {
+    /// 削除済みの行も含めた行数
+    num_rows: RowId,
+    definition: Definition,
+    columns: Vec<TableColumn>,
+    /// 削除済みの行の墓標
+    tombstones: Tombstones,
+    /// 一意性制約ごとの、値のキーIDの組から行番号への索引
+    unique_indexes: Vec<HashMap<Vec<KeyId>, RowId>>,
+    /// 他のテーブルの外部キーから参照されているカラム名([`Database::alter_table`]の間だけ設定する)
+    referenced_columns: Vec<String>,
+}
+
+/// 一意性制約に違反したときの動作を表します(INSERT ... ON CONFLICT)。
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OnConflict {
+    /// 挿入しない
+    DoNothing,
+    /// 衝突した行を挿入しようとした値で書き換える
+    DoUpdate,
+}
+
+impl Table {
+    pub fn new(definition: Definition) -> Table {
+        let mut columns = Vec::new();
+        for attribute in &definition.attributes {
+            columns.push(TableColumn::new(attribute.kind));
+        }
+        let unique_indexes = vec![HashMap::new(); definition.unique_keys.len()];
+        Table {
+            num_rows: 0,
+            definition,
+            columns,
+            tombstones: Tombstones::default(),
+            unique_indexes,
+            referenced_columns: Vec::new(),
+        }
+    }
+    pub fn create(name: &str, attributes: &[Attribute]) -> Table {
+        Table::new(Definition::create(name, attributes))
+    }
+    ///
+    /// 全カラムの辞書を値の順に並べ直します。
+    /// 範囲検索やORDER BYがキーIDの比較だけで済むようになります。
+    ///
+    pub fn sort_keys(&mut self) {
+        for column in &mut self.columns {
+            column.sort_keys();
+        }
+        // 行の値は変わらないので失敗しない
+        let _ = self.rebuild_unique_indexes();
+    }
+    ///
+    /// カラム名を指定してキーIDの格納方式を変更します。カラムが存在しなければ`None`を返します。
+    ///
+    pub fn set_encoding(&mut self, col_name: &str, encoding: Encoding) -> Option<&mut Table> {
+        let col_id = self.definition.name_to_id(col_name)?;
+        self.columns[col_id].set_encoding(encoding);
+        Some(self)
+    }
+    ///
+    /// 全カラムについて、格納サイズが小さくなる方の格納方式を選びます。
+    /// ソート済みやクラスタ化されたカラムはラン長圧縮されます。
+    ///
+    pub fn choose_encodings(&mut self) {
+        for column in &mut self.columns {
+            column.choose_encoding();
+        }
+    }
+    ///
+    /// カラム名を指定してビットマップインデックスを作成します。カラムが存在しなければ`None`を返します。
+    /// 以降のinsertでインデックスも更新され、等値・IN・範囲条件の抽出に使われます。
+    ///
+    pub fn create_index(&mut self, col_name: &str) -> Option<&mut Table> {
+        let col_id = self.definition.name_to_id(col_name)?;
+        self.columns[col_id].create_index();
+        Some(self)
+    }
+    ///
+    /// カラム名を指定してビットマップインデックスを削除します。カラムが存在しなければ`None`を返します。
+    ///
+    pub fn drop_index(&mut self, col_name: &str) -> Option<&mut Table> {
+        let col_id = self.definition.name_to_id(col_name)?;
+        self.columns[col_id].drop_index();
+        Some(self)
+    }
+    ///
+    /// カラムを末尾に追加します(ALTER TABLE ... ADD COLUMN)。
+    /// 既存の行にはデフォルト値か、なければNULLを入れます。同じ名前のカラムがある場合や、
+    /// 入れる値が型・NOT NULL・CHECK制約を満たさない場合は、何もせずにその理由を返します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create("shohin", attributes![("shohin_name", TypeKind::Varchar)]);
+    /// shohin.insert(values!("りんご"));
+    /// shohin
+    ///     .add_column(Attribute::create("price", TypeKind::Integer).with_default(100))
+    ///     .unwrap()
+    ///     .rename_column("shohin_name", "name")
+    ///     .unwrap()
+    ///     .alter_column_type("price", TypeKind::Varchar)
+    ///     .unwrap();
+    /// assert_eq!(shohin.fetch(0..1).unwrap()[0][1], Value::Varchar("100"));
+    /// assert_eq!(
+    ///     shohin.add_column(Attribute::create("kubun_id", TypeKind::Integer).not_null()).err(),
+    ///     Some(Error::NotNull("kubun_id".to_string()))
+    /// );
+    /// shohin.drop_column("price").unwrap();
+    /// assert_eq!(shohin.num_columns(), 1);
+    /// ```
+    ///
+    pub fn add_column(&mut self, attribute: Attribute) -> Result<&mut Table, Error> {
+        if self.definition.name_to_id(&attribute.name).is_some() {
+            return Err(Error::ColumnExists(attribute.name));
+        }
+        let value = attribute
+            .default_value
+            .clone()
+            .unwrap_or_else(|| NULL.into());
+        if value.is_null() {
+            if attribute.not_null && self.num_rows() > 0 {
+                return Err(Error::NotNull(attribute.name));
+            }
+        } else if value.as_value_ref().kind() != Some(attribute.kind) {
+            return Err(Error::TypeMismatch {
+                column: attribute.name,
+                expected: attribute.kind,
+                value,
+            });
+        }
+        let mut values = ColumnValues::new(attribute.kind);
+        for _ in 0..self.num_rows {
+            values.push(&value);
+        }
+        let mut column = TableColumn::new(attribute.kind);
+        column.append_values(values);
+        self.columns.push(column);
+        self.definition.attributes.push(attribute);
+        if let Err(err) = self.check_violation() {
+            self.columns.pop();
+            self.definition.attributes.pop();
+            return Err(err);
+        }
+        Ok(self)
+    }
+    ///
+    /// カラムを削除します(ALTER TABLE ... DROP COLUMN)。
+    /// 一意性制約・外部キー制約や他のカラムのCHECK制約で使われているカラムは削除できません。
+    ///
+    pub fn drop_column(&mut self, col_name: &str) -> Result<&mut Table, Error> {
+        let col_id = self.column_id(col_name)?;
+        let definition = &self.definition;
+        let in_use = definition
+            .unique_keys
+            .iter()
+            .any(|key| key.col_ids.contains(&col_id))
+            || self.is_foreign_key_column(col_id)
+            || definition
+                .attributes
+                .iter()
+                .enumerate()
+                .any(|(id, attribute)| {
+                    id != col_id
+                        && attribute
+                            .check
+                            .as_ref()
+                            .is_some_and(|check| check.refers_to(col_name))
+                });
+        if in_use {
+            return Err(Error::ColumnInUse(col_name.to_string()));
+        }
+        self.columns.remove(col_id);
+        self.definition.attributes.remove(col_id);
+        // 後ろのカラムの番号を詰める
+        let unique_keys = self
+            .definition
+            .unique_keys
+            .iter_mut()
+            .map(|key| &mut key.col_ids);
+        let foreign_keys = self
+            .definition
+            .foreign_keys
+            .iter_mut()
+            .map(|key| &mut key.col_ids);
+        for col_ids in unique_keys.chain(foreign_keys) {
+            for id in col_ids.iter_mut().filter(|id| **id > col_id) {
+                *id -= 1;
+            }
+        }
+        Ok(self)
+    }
+    ///
+    /// カラム名を変更します(ALTER TABLE ... RENAME COLUMN)。CHECK制約の条件式も書き換えます。
+    /// 外部キー制約で使われているカラムの名前は変更できません。
+    ///
+    pub fn rename_column(&mut self, col_name: &str, new_name: &str) -> Result<&mut Table, Error> {
+        let col_id = self.column_id(col_name)?;
+        if self.is_foreign_key_column(col_id) {
+            return Err(Error::ColumnInUse(col_name.to_string()));
+        }
+        if self.definition.name_to_id(new_name).is_some() {
+            return Err(Error::ColumnExists(new_name.to_string()));
+        }
+        self.definition[col_id].name = new_name.to_string();
+        for attribute in &mut self.definition.attributes {
+            if let Some(check) = &mut attribute.check {
+                check.rename_column(col_name, new_name);
+            }
+        }
+        Ok(self)
+    }
+    ///
+    /// カラムの型を変更します(ALTER TABLE ... ALTER COLUMN ... TYPE)。
+    /// 値とデフォルト値は[`Value::cast`]で変換します。変換できない値がある場合や、
+    /// 変換で一意性制約に違反する場合、CHECK制約や外部キー制約で使われている場合は、
+    /// 何もせずにその理由を返します。
+    ///
+    pub fn alter_column_type(
+        &mut self,
+        col_name: &str,
+        kind: TypeKind,
+    ) -> Result<&mut Table, Error> {
+        let col_id = self.column_id(col_name)?;
+        if self.definition[col_id].kind == kind {
+            return Ok(self);
+        }
+        let attributes = &self.definition.attributes;
+        if self.is_foreign_key_column(col_id)
+            || attributes.iter().any(|attribute| {
+                attribute
+                    .check
+                    .as_ref()
+                    .is_some_and(|check| check.refers_to(col_name))
+            })
+        {
+            return Err(Error::ColumnInUse(col_name.to_string()));
+        }
+        let cast = |value: Value| {
+            value.cast(kind).ok_or_else(|| Error::TypeMismatch {
+                column: col_name.to_string(),
+                expected: kind,
+                value: value.into(),
+            })
+        };
+        let default_value = match &self.definition[col_id].default_value {
+            Some(value) => Some(cast(value.as_value_ref())?),
+            None => None,
+        };
+        let old_column = &self.columns[col_id];
+        let mut values = ColumnValues::new(kind);
+        for row_id in 0..self.num_rows {
+            // 削除済みの行の値は変換しない
+            let value = if self.tombstones.is_deleted(row_id) {
+                NULL.into()
+            } else {
+                cast(old_column.key_at(row_id))?
+            };
+            values.push(&value);
+        }
+        let mut column = TableColumn::new(kind);
+        column.set_encoding(old_column.encoding());
+        column.append_values(values);
+        if old_column.index().is_some() {
+            column.create_index();
+        }
+        let old_column = std::mem::replace(&mut self.columns[col_id], column);
+        let old_kind = std::mem::replace(&mut self.definition[col_id].kind, kind);
+        if let Err(err) = self.rebuild_unique_indexes() {
+            self.columns[col_id] = old_column;
+            self.definition[col_id].kind = old_kind;
+            // 元に戻した状態は制約を満たしている
+            let _ = self.rebuild_unique_indexes();
+            return Err(err);
+        }
+        self.definition[col_id].default_value = default_value;
+        Ok(self)
+    }
+    fn column_id(&self, col_name: &str) -> Result<ColumnId, Error> {
+        self.definition
+            .name_to_id(col_name)
+            .ok_or_else(|| Error::UnknownColumn(col_name.to_string()))
+    }
+    ///
+    /// 行を挿入します。失敗した場合は何もせずに`None`を返します。
+    /// 失敗した理由が知りたい場合は[`Table::try_insert`]を使います。
+    ///
+    pub fn insert<T: AsValue>(&mut self, tuple: &[T]) -> Option<&mut Table> {
+        self.try_insert(tuple).ok()
+    }
+    ///
+    /// 行を挿入します。カラム数や型が合わない場合や、NOT NULL・CHECK・一意性制約に違反する場合は、
+    /// 何もせずにその理由を返します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_name", TypeKind::Varchar, not_null),
+    ///         ("price", TypeKind::Integer, check(Predicate::greater_equal("price", 0)))
+    ///     ],
+    /// );
+    /// assert!(shohin.try_insert(values!("りんご", 300)).is_ok());
+    /// assert_eq!(
+    ///     shohin.try_insert(values!(NULL, 300)).err(),
+    ///     Some(Error::NotNull("shohin_name".to_string()))
+    /// );
+    /// let err = shohin.try_insert(values!("みかん", -1)).err().unwrap();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "カラムpriceの値-1はCHECK制約(price >= 0)を満たしません"
+    /// );
+    /// ```
+    ///
+    pub fn try_insert<T: AsValue>(&mut self, tuple: &[T]) -> Result<&mut Table, Error> {
+        self.check_insert(tuple)?;
+        for (col_id, value) in tuple.iter().enumerate() {
+            // 型は検査済みなので失敗しない
+            self.columns[col_id].append(value);
+        }
+        let row_id = self.num_rows;
+        self.num_rows += 1;
+        self.tombstones.resize(self.num_rows);
+        self.register_unique_keys(row_id);
+        Ok(self)
+    }
+    ///
+    /// カラムごとの値の配列をまとめて追記します。1行ずつ[`Table::insert`]するより速く、
+    /// 値の辞書への登録も値の種類の数だけで済みます。
+    /// カラム数・行数・型が合わない場合や、NOT NULL・CHECK・一意性制約に違反する場合は、
+    /// 何もせずにその理由を返します。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_id", TypeKind::Integer),
+    ///         ("shohin_name", TypeKind::Varchar),
+    ///         ("kubun_id", TypeKind::Integer)
+    ///     ],
+    /// );
+    /// shohin
+    ///     .append_columns(vec![
+    ///         vec![1, 2, 3].into(),
+    ///         vec!["りんご", "みかん", "キャベツ"].into(),
+    ///         vec![Some(1), Some(1), None].into(),
+    ///     ])
+    ///     .unwrap();
+    /// assert_eq!(shohin.num_rows(), 3);
+    /// let err = shohin
+    ///     .append_columns(vec![vec![4].into(), vec!["さんま"].into(), vec![3, 4].into()])
+    ///     .err()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "カラムkubun_idの行数が合いません(他のカラムは1行、このカラムは2行)"
+    /// );
+    /// ```
+    ///
+    pub fn append_columns(&mut self, columns: Vec<ColumnValues>) -> Result<&mut Table, Error> {
+        self.validate_columns(&columns)?;
+        let start = self.num_rows;
+        let num_rows = columns.first().map_or(0, ColumnValues::len);
+        for (column, values) in self.columns.iter_mut().zip(columns) {
+            // 型は検査済みなので失敗しない
+            column.append_values(values);
+        }
+        self.num_rows += num_rows;
+        self.tombstones.resize(self.num_rows);
+        if let Err(err) = self.register_unique_keys_from(start) {
+            for column in &mut self.columns {
+                while column.num_rows() > start {
+                    column.pop();
+                }
+            }
+            self.num_rows = start;
+            self.tombstones.resize(start);
+            // 追記する前の状態は制約を満たしている
+            let _ = self.rebuild_unique_indexes();
+            return Err(err);
+        }
+        Ok(self)
+    }
+    ///
+    /// 行の配列をカラムごとの値の配列に組み替えて、[`Table::append_columns`]で追記します。
+    ///
+    pub fn insert_rows<R: AsRef<[T]>, T: AsValue>(
+        &mut self,
+        rows: &[R],
+    ) -> Result<&mut Table, Error> {
+        let mut columns: Vec<ColumnValues> = self
+            .definition
+            .attributes
+            .iter()
+            .map(|attribute| ColumnValues::new(attribute.kind))
+            .collect();
+        for row in rows {
+            let row = row.as_ref();
+            if row.len() != columns.len() {
+                return Err(Error::ColumnCount {
+                    expected: columns.len(),
+                    actual: row.len(),
+                });
+            }
+            for (col_id, (values, value)) in columns.iter_mut().zip(row).enumerate() {
+                if values.push(value).is_none() {
+                    let attribute = &self.definition[col_id];
+                    return Err(Error::TypeMismatch {
+                        column: attribute.name.clone(),
+                        expected: attribute.kind,
+                        value: value.as_value_ref().into(),
+                    });
+                }
+            }
+        }
+        self.append_columns(columns)
+    }
+    ///
+    /// カラムごとの値の配列がカラム数・行数・型・NOT NULL制約・CHECK制約を満たすか検査します。
+    ///
+    fn validate_columns(&self, columns: &[ColumnValues]) -> Result<(), Error> {
+        let n_cols = self.num_columns();
+        if n_cols != columns.len() {
+            return Err(Error::ColumnCount {
+                expected: n_cols,
+                actual: columns.len(),
+            });
+        }
+        let num_rows = columns.first().map_or(0, ColumnValues::len);
+        for (col_id, values) in columns.iter().enumerate() {
+            let attribute = &self.definition[col_id];
+            if values.len() != num_rows {
+                return Err(Error::RowCount {
+                    column: attribute.name.clone(),
+                    expected: num_rows,
+                    actual: values.len(),
+                });
+            }
+            if values.kind() != attribute.kind {
+                let value = (0..num_rows)
+                    .map(|row_id| values.value_at(row_id))
+                    .find(|value| !value.is_null());
+                return Err(Error::TypeMismatch {
+                    column: attribute.name.clone(),
+                    expected: attribute.kind,
+                    value: value.unwrap_or(Value::Null(NULL)).into(),
+                });
+            }
+            let nullable = !attribute.not_null && !self.is_primary_key_column(col_id);
+            if !nullable && (0..num_rows).any(|row_id| values.value_at(row_id).is_null()) {
+                return Err(Error::NotNull(attribute.name.clone()));
+            }
+        }
+        if self
+            .definition
+            .attributes
+            .iter()
+            .all(|attribute| attribute.check.is_none())
+        {
+            return Ok(());
+        }
+        for row_id in 0..num_rows {
+            let tuple: Vec<Value> = columns
+                .iter()
+                .map(|values| values.value_at(row_id))
+                .collect();
+            self.validate(&tuple)?;
+        }
+        Ok(())
+    }
+    ///
+    /// `start`行目以降の行を一意性制約の索引に登録します。重複があればその理由を返します。
+    ///
+    fn register_unique_keys_from(&mut self, start: RowId) -> Result<(), Error> {
+        for key_no in 0..self.unique_indexes.len() {
+            let unique_key = &self.definition.unique_keys[key_no];
+            for row_id in start..self.num_rows {
+                if let Some(key_ids) = self.unique_key_ids(unique_key, row_id) {
+                    if self.unique_indexes[key_no]
+                        .insert(key_ids, row_id)
+                        .is_some()
+                    {
+                        return Err(Error::Unique {
+                            columns: self.definition.names_of(&unique_key.col_ids),
+                            values: self.values_at(row_id, &unique_key.col_ids),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    ///
+    /// カラム名と値の組で行を挿入します。失敗した場合は何もせずに`None`を返します。
+    /// 失敗した理由が知りたい場合は[`Table::try_insert_named`]を使います。
+    ///
+    pub fn insert_named<T: AsValue>(&mut self, pairs: &[(&str, T)]) -> Option<&mut Table> {
+        self.try_insert_named(pairs).ok()
+    }
+    ///
+    /// カラム名と値の組で行を挿入します。指定しなかったカラムにはデフォルト値か、なければNULLを入れます。
+    /// 存在しないカラム名がある場合や、[`Table::try_insert`]と同じ検査に失敗した場合は、
+    /// 何もせずにその理由を返します。同じカラム名が複数あれば後の値を使います。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_name", TypeKind::Varchar),
+    ///         ("kubun_id", TypeKind::Integer),
+    ///         ("price", TypeKind::Integer, with_default(0))
+    ///     ],
+    /// );
+    /// shohin.try_insert_named(named_values!("shohin_name" => "りんご")).unwrap();
+    /// let tuples = shohin.fetch(0..1).unwrap();
+    /// assert_eq!(tuples[0][1], Value::Null(NULL));
+    /// assert_eq!(tuples[0][2], Value::Integer(0));
+    /// assert_eq!(
+    ///     shohin.try_insert_named(named_values!("name" => "みかん")).err(),
+    ///     Some(Error::UnknownColumn("name".to_string()))
+    /// );
+    /// ```
+    ///
+    pub fn try_insert_named<T: AsValue>(
+        &mut self,
+        pairs: &[(&str, T)],
+    ) -> Result<&mut Table, Error> {
+        let tuple = self.complete_tuple(pairs)?;
+        self.try_insert(&tuple)
+    }
+    ///
+    /// カラム名と値の組から、指定しなかったカラムをデフォルト値かNULLで埋めた値の組を作ります。
+    ///
+    fn complete_tuple<T: AsValue>(&self, pairs: &[(&str, T)]) -> Result<Vec<OwnedValue>, Error> {
+        let mut tuple: Vec<OwnedValue> = self
+            .definition
+            .attributes
+            .iter()
+            .map(|attribute| {
+                attribute
+                    .default_value
+                    .clone()
+                    .unwrap_or_else(|| NULL.into())
+            })
+            .collect();
+        for (col_name, value) in pairs {
+            let col_id = self.column_id(col_name)?;
+            tuple[col_id] = value.as_value_ref().into();
+        }
+        Ok(tuple)
+    }
+    ///
+    /// 値の組がカラム数・型・NOT NULL制約・CHECK制約を満たすか検査します。
+    ///
+    fn validate<T: AsValue>(&self, tuple: &[T]) -> Result<(), Error> {
+        let n_cols = self.num_columns();
+        if n_cols != tuple.len() {
+            return Err(Error::ColumnCount {
+                expected: n_cols,
+                actual: tuple.len(),
+            });
+        }
+        let values: Vec<Value> = tuple.iter().map(|value| value.as_value_ref()).collect();
+        for (col_id, value) in values.iter().enumerate() {
+            let attribute = &self.definition[col_id];
+            if value.is_null() {
+                if attribute.not_null || self.is_primary_key_column(col_id) {
+                    return Err(Error::NotNull(attribute.name.clone()));
+                }
+            } else if value.kind() != Some(attribute.kind) {
+                return Err(Error::TypeMismatch {
+                    column: attribute.name.clone(),
+                    expected: attribute.kind,
+                    value: value.clone().into(),
+                });
+            }
+        }
+        for (attribute, value) in self.definition.attributes.iter().zip(&values) {
+            if let Some(check) = &attribute.check {
+                // UNKNOWNは許される
+                if check.evaluate_tuple(&self.definition, &values) == Truth::False {
+                    return Err(Error::Check {
+                        column: attribute.name.clone(),
+                        check: check.clone(),
+                        value: value.clone().into(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+    ///
+    /// 外部キー制約のカラムか、他のテーブルの外部キーから参照されているカラムかどうかを返します。
+    /// 名前や型を変えると外部キーの参照が成り立たなくなります。
+    ///
+    fn is_foreign_key_column(&self, col_id: ColumnId) -> bool {
+        let definition = &self.definition;
+        definition
+            .foreign_keys
+            .iter()
+            .any(|foreign_key| foreign_key.col_ids.contains(&col_id))
+            || self.referenced_columns.contains(&definition[col_id].name)
+    }
+    /// 行を挿入できるか、[`Table::try_insert`]と同じ検査をします。
+    fn check_insert<T: AsValue>(&self, tuple: &[T]) -> Result<(), Error> {
+        self.validate(tuple)?;
+        if let Some(&(key_no, _)) = self.conflicting_row_ids(tuple).first() {
+            return Err(self.unique_error(key_no, tuple));
+        }
+        Ok(())
+    }
+    fn is_primary_key_column(&self, col_id: ColumnId) -> bool {
+        self.definition
+            .unique_keys
+            .iter()
+            .any(|unique_key| unique_key.primary && unique_key.col_ids.contains(&col_id))
+    }
+    fn unique_error<T: AsValue>(&self, key_no: usize, tuple: &[T]) -> Error {
+        let col_ids = &self.definition.unique_keys[key_no].col_ids;
+        Error::Unique {
+            columns: self.definition.names_of(col_ids),
+            values: col_ids
+                .iter()
+                .map(|&col_id| tuple[col_id].as_value_ref().into())
+                .collect(),
+        }
+    }
+    ///
+    /// 一意性制約に違反する行があればその行を`tuple`の値で書き換え、なければ挿入します。
+    /// `insert_on_conflict(tuple, OnConflict::DoUpdate)`と同じです。
+    ///
+    pub fn upsert<T: AsValue>(&mut self, tuple: &[T]) -> Option<&mut Table> {
+        self.insert_on_conflict(tuple, OnConflict::DoUpdate)
+    }
+    ///
+    /// 行を挿入します。一意性制約に違反する行があれば`on_conflict`に従います。
+    /// `DoUpdate`で複数の行と衝突する場合や、カラム数や型が合わない場合は`None`を返します。
+    ///
+    pub fn insert_on_conflict<T: AsValue>(
+        &mut self,
+        tuple: &[T],
+        on_conflict: OnConflict,
+    ) -> Option<&mut Table> {
+        self.validate(tuple).ok()?;
+        let mut conflicts: Vec<RowId> = self
+            .conflicting_row_ids(tuple)
+            .into_iter()
+            .map(|(_, row_id)| row_id)
+            .collect();
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        match (conflicts.as_slice(), on_conflict) {
+            ([], _) => self.insert(tuple),
+            (_, OnConflict::DoNothing) => Some(self),
+            (&[row_id], OnConflict::DoUpdate) => {
+                self.unregister_unique_keys(row_id);
+                for (col_id, value) in tuple.iter().enumerate() {
+                    self.columns[col_id].set(row_id, value);
+                }
+                self.register_unique_keys(row_id);
+                Some(self)
+            }
+            _ => None,
+        }
+    }
+    ///
+    /// 値の組が`tuple`と一致する既存の行を、一意性制約の番号とともに返します。
+    ///
+    fn conflicting_row_ids<T: AsValue>(&self, tuple: &[T]) -> Vec<(usize, RowId)> {
+        let mut row_ids = Vec::new();
+        let unique_keys = self.definition.unique_keys.iter();
+        for (key_no, (unique_key, index)) in unique_keys.zip(&self.unique_indexes).enumerate() {
+            let values: Vec<Value> = unique_key
+                .col_ids
+                .iter()
+                .map(|&col_id| tuple[col_id].as_value_ref())
+                .collect();
+            if values.iter().any(Value::is_null) {
+                continue;
+            }
+            // 辞書にない値があれば衝突しようがない
+            let key_ids: Option<Vec<KeyId>> = unique_key
+                .col_ids
+                .iter()
+                .zip(&values)
+                .map(|(&col_id, value)| self.columns[col_id].id_of(value))
+                .collect();
+            if let Some(&row_id) = key_ids.and_then(|key_ids| index.get(&key_ids)) {
+                row_ids.push((key_no, row_id));
+            }
+        }
+        row_ids
+    }
+    /// 行の値のキーIDの組を返します。NULLを含む場合は`None`を返します。
+    fn unique_key_ids(&self, unique_key: &UniqueKey, row_id: RowId) -> Option<Vec<KeyId>> {
+        unique_key
+            .col_ids
+            .iter()
+            .map(|&col_id| self.columns[col_id].id_at(row_id))
+            .collect()
+    }
+    fn register_unique_keys(&mut self, row_id: RowId) {
+        for i in 0..self.unique_indexes.len() {
+            if let Some(key_ids) = self.unique_key_ids(&self.definition.unique_keys[i], row_id) {
+                self.unique_indexes[i].insert(key_ids, row_id);
+            }
+        }
+    }
+    fn unregister_unique_keys(&mut self, row_id: RowId) {
+        for i in 0..self.unique_indexes.len() {
+            if let Some(key_ids) = self.unique_key_ids(&self.definition.unique_keys[i], row_id) {
+                self.unique_indexes[i].remove(&key_ids);
+            }
+        }
+    }
+    ///
+    /// 行を一意性制約の索引に登録します。同じ値の組の行が既にあれば、
+    /// この呼び出しで登録した分を取り消してその理由を返します。
+    ///
+    fn try_register_unique_keys(&mut self, row_ids: &[RowId]) -> Result<(), Error> {
+        let mut registered: Vec<(usize, Vec<KeyId>)> = Vec::new();
+        for &row_id in row_ids {
+            for key_no in 0..self.unique_indexes.len() {
+                let unique_key = &self.definition.unique_keys[key_no];
+                let key_ids = match self.unique_key_ids(unique_key, row_id) {
+                    Some(key_ids) => key_ids,
+                    None => continue,
+                };
+                if self.unique_indexes[key_no].contains_key(&key_ids) {
+                    let err = Error::Unique {
+                        columns: self.definition.names_of(&unique_key.col_ids),
+                        values: self.values_at(row_id, &unique_key.col_ids),
+                    };
+                    for (key_no, key_ids) in registered {
+                        self.unique_indexes[key_no].remove(&key_ids);
+                    }
+                    return Err(err);
+                }
+                self.unique_indexes[key_no].insert(key_ids.clone(), row_id);
+                registered.push((key_no, key_ids));
+            }
+        }
+        Ok(())
+    }
+    ///
+    /// 一意性制約の索引を作り直します。重複する行や主キーがNULLの行があればその理由を返します。
+    ///
+    fn rebuild_unique_indexes(&mut self) -> Result<(), Error> {
+        let mut unique_indexes = Vec::new();
+        for unique_key in &self.definition.unique_keys {
+            let mut index = HashMap::new();
+            for row_id in self.tombstones.bits().iter_zeros() {
+                match self.unique_key_ids(unique_key, row_id) {
+                    Some(key_ids) if index.contains_key(&key_ids) => {
+                        return Err(Error::Unique {
+                            columns: self.definition.names_of(&unique_key.col_ids),
+                            values: self.values_at(row_id, &unique_key.col_ids),
+                        });
+                    }
+                    Some(key_ids) => {
+                        index.insert(key_ids, row_id);
+                    }
+                    None if unique_key.primary => {
+                        let mut col_ids = unique_key.col_ids.iter().copied();
+                        let col_id = col_ids
+                            .find(|&col_id| self.columns[col_id].is_null_at(row_id))
+                            .unwrap_or_default();
+                        return Err(Error::NotNull(self.definition[col_id].name.clone()));
+                    }
+                    None => {}
+                }
+            }
+            unique_indexes.push(index);
+        }
+        self.unique_indexes = unique_indexes;
+        Ok(())
+    }
+    /// 指定したカラムの値を返します。
+    fn values_at(&self, row_id: RowId, col_ids: &[ColumnId]) -> Vec<OwnedValue> {
+        col_ids
+            .iter()
+            .map(|&col_id| self.columns[col_id].key_at(row_id).into())
+            .collect()
+    }
+    ///
+    /// 一意性制約のカラム`col_ids`の値の組が`values`である行があるか調べます。
+    /// `col_ids`と同じ並びの一意性制約がなければ`false`を返します。
+    ///
+    fn contains_unique(&self, col_ids: &[ColumnId], values: &[Value]) -> bool {
+        let unique_keys = &self.definition.unique_keys;
+        let key_no = match unique_keys.iter().position(|key| key.col_ids == col_ids) {
+            Some(key_no) => key_no,
+            None => return false,
+        };
+        let key_ids: Option<Vec<KeyId>> = col_ids
+            .iter()
+            .zip(values)
+            .map(|(&col_id, value)| self.columns[col_id].id_of(value))
+            .collect();
+        key_ids.is_some_and(|key_ids| self.unique_indexes[key_no].contains_key(&key_ids))
+    }
+    ///
+    /// カラム`col_ids`の値の組が`keys`のどれかと一致する行の行番号を返します。
+    /// NULLを含む行はどれとも一致しません。
+    ///
+    fn referencing_row_ids(
+        &self,
+        col_ids: &[ColumnId],
+        keys: &BTreeSet<Vec<OwnedValue>>,
+    ) -> Vec<RowId> {
+        self.tombstones
+            .bits()
+            .iter_zeros()
+            .filter(|&row_id| {
+                let values = self.values_at(row_id, col_ids);
+                !values.iter().any(OwnedValue::is_null) && keys.contains(&values)
+            })
+            .collect()
+    }
+    ///
+    /// 条件式が真になる行を削除し、削除した行数を返します。
+    /// 行は墓標を立てるだけで残っているので、領域を回収するには[`Table::compact`]を呼びます。
+    ///
+    pub fn delete_where(&mut self, predicate: &Predicate) -> RowId {
+        let row_ids = self.matching_row_ids(predicate);
+        self.delete_row_ids(&row_ids);
+        row_ids.len()
+    }
+    /// 行番号を指定して行を削除します。削除済みの行は無視します。
+    fn delete_row_ids(&mut self, row_ids: &[RowId]) {
+        let mut row_ids: Vec<RowId> = row_ids
+            .iter()
+            .copied()
+            .filter(|&row_id| !self.tombstones.is_deleted(row_id))
+            .collect();
+        row_ids.sort_unstable();
+        row_ids.dedup();
+        for &row_id in &row_ids {
+            self.unregister_unique_keys(row_id);
+        }
+        self.tombstones.delete(&row_ids);
+    }
+    ///
+    /// 条件式が真になる行について、カラム名と値の組で指定したカラムを書き換え、書き換えた行数を返します。
+    /// 存在しないカラム名や型の合わない値があるか、NOT NULL・CHECK・一意性制約に違反する場合は、
+    /// 何も書き換えずに`None`を返します。
+    /// 失敗した理由が知りたい場合は[`Table::try_update`]を使います。
+    ///
+    pub fn update<T: AsValue>(
+        &mut self,
+        predicate: &Predicate,
+        assignments: &[(&str, T)],
+    ) -> Option<RowId> {
+        self.try_update(predicate, assignments).ok()
+    }
+    ///
+    /// 条件式が真になる行について、カラム名と値の組で指定したカラムを書き換え、書き換えた行数を返します。
+    /// 存在しないカラム名や型の合わない値があるか、NOT NULL・CHECK・一意性制約に違反する場合は、
+    /// 何も書き換えずにその理由を返します。
+    ///
+    pub fn try_update<T: AsValue>(
+        &mut self,
+        predicate: &Predicate,
+        assignments: &[(&str, T)],
+    ) -> Result<RowId, Error> {
+        let targets = self.resolve_assignments(assignments)?;
+        let row_ids = self.matching_row_ids(predicate);
+        self.update_row_ids(&row_ids, &targets)?;
+        Ok(row_ids.len())
+    }
+    ///
+    /// 行番号を指定して、カラム番号と値の組で指定したカラムを書き換え、書き換える前の値を返します。
+    /// 値の型とNOT NULL制約は検査済みでなければなりません。
+    /// CHECK制約・一意性制約に違反する場合は、何も書き換えずにその理由を返します。
+    ///
+    fn update_row_ids<T: AsValue>(
+        &mut self,
+        row_ids: &[RowId],
+        targets: &[(ColumnId, T)],
+    ) -> Result<Vec<(ColumnId, RowId, OwnedValue)>, Error> {
+        let unique_keys = &self.definition.unique_keys;
+        let touches_unique_keys = targets.iter().any(|(col_id, _)| {
+            unique_keys
+                .iter()
+                .any(|unique_key| unique_key.col_ids.contains(col_id))
+        });
+        // 書き換える行は一意性制約の索引から外しておき、書き換えた後の値で登録し直す
+        if touches_unique_keys {
+            for &row_id in row_ids {
+                self.unregister_unique_keys(row_id);
+            }
+        }
+        // 制約に違反したら書き戻すために元の値を取っておく
+        let mut old_values = Vec::new();
+        for (col_id, value) in targets {
+            for &row_id in row_ids {
+                let old_value = OwnedValue::from(self.columns[*col_id].key_at(row_id));
+                old_values.push((*col_id, row_id, old_value));
+                // 型は検査済みなので失敗しない
+                self.columns[*col_id].set(row_id, value);
+            }
+        }
+        let mut result = self.check_rows(row_ids);
+        if result.is_ok() && touches_unique_keys {
+            result = self.try_register_unique_keys(row_ids);
+        }
+        if let Err(err) = result {
+            for (col_id, row_id, old_value) in old_values.iter().rev() {
+                self.columns[*col_id].set(*row_id, old_value);
+            }
+            // 元の値は制約を満たしていたので重複しない
+            if touches_unique_keys {
+                for &row_id in row_ids {
+                    self.register_unique_keys(row_id);
+                }
+            }
+            return Err(err);
+        }
+        Ok(old_values)
+    }
+    /// [`Table::update_row_ids`]で書き換えた行を、書き換える前の値に戻します。
+    fn restore_values(&mut self, row_ids: &[RowId], old_values: &[(ColumnId, RowId, OwnedValue)]) {
+        for &row_id in row_ids {
+            self.unregister_unique_keys(row_id);
+        }
+        for (col_id, row_id, old_value) in old_values.iter().rev() {
+            self.columns[*col_id].set(*row_id, old_value);
+        }
+        for &row_id in row_ids {
+            self.register_unique_keys(row_id);
+        }
+    }
+    ///
+    /// 更新のカラム名と値の組を、カラム番号と値の組にします。
+    /// 存在しないカラム名や型の合わない値、NOT NULL制約に違反するNULLがあればその理由を返します。
+    ///
+    fn resolve_assignments<'a, T: AsValue>(
+        &self,
+        assignments: &'a [(&str, T)],
+    ) -> Result<Vec<(ColumnId, Value<'a>)>, Error> {
+        let mut targets = Vec::new();
+        for (col_name, value) in assignments {
+            targets.push((self.column_id(col_name)?, value.as_value_ref()));
+        }
+        self.check_targets(&targets)?;
+        Ok(targets)
+    }
+    ///
+    /// 更新のカラム番号と値の組を検査します。
+    /// 型の合わない値や、NOT NULL制約に違反するNULLがあればその理由を返します。
+    ///
+    fn check_targets<T: AsValue>(&self, targets: &[(ColumnId, T)]) -> Result<(), Error> {
+        for (col_id, value) in targets {
+            let attribute = &self.definition[*col_id];
+            let value = value.as_value_ref();
+            if value.is_null() {
+                if attribute.not_null || self.is_primary_key_column(*col_id) {
+                    return Err(Error::NotNull(attribute.name.clone()));
+                }
+            } else if value.kind() != Some(attribute.kind) {
+                return Err(Error::TypeMismatch {
+                    column: attribute.name.clone(),
+                    expected: attribute.kind,
+                    value: value.into(),
+                });
+            }
+        }
+        Ok(())
+    }
+    /// 行番号を指定して、CHECK制約がFALSEになる行があれば、最初のカラムと値を返します。
+    fn check_rows(&self, row_ids: &[RowId]) -> Result<(), Error> {
+        if self
+            .definition
+            .attributes
+            .iter()
+            .all(|attribute| attribute.check.is_none())
+        {
+            return Ok(());
+        }
+        for &row_id in row_ids {
+            let tuple: Vec<Value> = self
+                .columns
+                .iter()
+                .map(|column| column.key_at(row_id))
+                .collect();
+            self.validate(&tuple)?;
+        }
+        Ok(())
+    }
+    /// CHECK制約がFALSEになる行があれば、最初のカラムと値を返します。
+    fn check_violation(&self) -> Result<(), Error> {
+        for (col_id, attribute) in self.definition.attributes.iter().enumerate() {
+            if let Some(check) = &attribute.check {
+                let violations = self.filter(&!check.clone());
+                if violations.num_rows() > 0 {
+                    return Err(Error::Check {
+                        column: attribute.name.clone(),
+                        check: check.clone(),
+                        value: violations
+                            .fetch(0..1)
+                            .map(|tuples| tuples[0][col_id].clone().into())
+                            .unwrap_or_else(|| NULL.into()),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+    /// 条件式が真になる行の行番号を返します。
+    fn matching_row_ids(&self, predicate: &Predicate) -> Vec<RowId> {
+        match self.filter(predicate).row_set() {
+            Some(row_set) => row_set.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+    /// 削除済みで領域が回収されていない行数を返します。
+    pub fn num_deleted(&self) -> RowId {
+        self.tombstones.num_deleted()
+    }
+    ///
+    /// 削除済みの行を取り除き、どの行からも参照されなくなった値を辞書から取り除きます。
+    /// 行番号は詰め直されます。
+    ///
+    pub fn compact(&mut self) {
+        if self.tombstones.num_deleted() == 0 {
+            return;
+        }
+        for column in &mut self.columns {
+            column.compact(self.tombstones.bits());
+        }
+        self.num_rows -= self.tombstones.num_deleted();
+        self.tombstones = Tombstones::new(self.num_rows);
+        // 行の値は変わらないので失敗しない
+        let _ = self.rebuild_unique_indexes();
+    }
+}
+
+/// 生きている行数の累積を持っておく間隔(行数)
+const LIVE_RANK_BLOCK: usize = 512;
+
+///
+/// 削除済みの行のビットが立った墓標のビットマップです。
+/// 生きている行を先頭から何番目かで引けるように、ブロックごとにそれより前の生きている行数を持ちます。
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+struct Tombstones {
+    deleted: BitMap,
+    num_deleted: RowId,
+    /// `LIVE_RANK_BLOCK`行ごとの、そのブロックより前にある生きている行数
+    live_ranks: Vec<RowId>,
+}
+
+impl Tombstones {
+    /// 全ての行が生きている墓標を作成します。
+    fn new(num_rows: RowId) -> Self {
+        Tombstones::from_bits(bitvec![0; num_rows])
+    }
+    /// 削除済みの行のビットが立ったビットマップから作成します。
+    fn from_bits(deleted: BitMap) -> Self {
+        Tombstones {
+            num_deleted: deleted.count_ones(),
+            live_ranks: live_ranks_of(&deleted),
+            deleted,
+        }
+    }
+    fn bits(&self) -> &BitSlice {
+        &self.deleted
+    }
+    fn num_deleted(&self) -> RowId {
+        self.num_deleted
+    }
+    fn is_deleted(&self, row_id: RowId) -> bool {
+        self.deleted[row_id]
+    }
+    /// 行数を変えます。増えた行は生きている行です。
+    fn resize(&mut self, num_rows: RowId) {
+        if num_rows < self.deleted.len() {
+            self.num_deleted -= self.deleted[num_rows..].count_ones();
+            self.deleted.truncate(num_rows);
+            self.live_ranks.truncate(num_rows.div_ceil(LIVE_RANK_BLOCK));
+        }
+        // 増えた行は全て生きているので、新しいブロックより前の生きている行数は削除済みの行数から求まる
+        for block in self.live_ranks.len()..num_rows.div_ceil(LIVE_RANK_BLOCK) {
+            self.live_ranks
+                .push(block * LIVE_RANK_BLOCK - self.num_deleted);
+        }
+        self.deleted.resize(num_rows, false);
+    }
+    /// 行に墓標を立てます。削除済みの行は無視します。
+    fn delete(&mut self, row_ids: &[RowId]) {
+        let mut first = self.deleted.len();
+        for &row_id in row_ids {
+            if !self.deleted[row_id] {
+                self.deleted.set(row_id, true);
+                self.num_deleted += 1;
+                first = cmp::min(first, row_id);
+            }
+        }
+        // 削除した行より後ろのブロックの累積だけ数え直す
+        let from = first / LIVE_RANK_BLOCK;
+        if let Some(&live) = self.live_ranks.get(from) {
+            let mut live = live;
+            let blocks = self.deleted.chunks(LIVE_RANK_BLOCK).skip(from);
+            for (live_rank, block) in self.live_ranks[from..].iter_mut().zip(blocks) {
+                *live_rank = live;
+                live += block.count_zeros();
+            }
+        }
+    }
+}
+
+/// 墓標のビットマップの`LIVE_RANK_BLOCK`行ごとに、そのブロックより前にある生きている行数を求めます。
+fn live_ranks_of(deleted: &BitSlice) -> Vec<RowId> {
+    let mut live = 0;
+    deleted
+        .chunks(LIVE_RANK_BLOCK)
+        .map(|block| {
+            let live_rank = live;
+            live += block.count_zeros();
+            live_rank
+        })
+        .collect()
+}
+
+///
+/// 墓標のビットマップで削除されていない行のうち`rank`番目の行番号を返します。
+/// なければビットマップの長さを返します。
+///
+fn nth_live_row_id(deleted: &BitSlice, live_ranks: &[RowId], rank: RowId) -> RowId {
+    // 目的の行を含むブロックまで二分探索で飛んでから、ワード単位で読み飛ばして1ビットずつ数える
+    const WORD_BITS: usize = usize::BITS as usize;
+    let block = live_ranks
+        .partition_point(|&live_rank| live_rank <= rank)
+        .saturating_sub(1);
+    let start = cmp::min(block * LIVE_RANK_BLOCK, deleted.len());
+    let mut rest = rank - live_ranks.get(block).copied().unwrap_or(0);
+    for (i, word) in deleted[start..].chunks(WORD_BITS).enumerate() {
+        let n_live = word.count_zeros();
+        if rest < n_live {
+            let offset = word.iter_zeros().nth(rest).unwrap();
+            return start + i * WORD_BITS + offset;
+        }
+        rest -= n_live;
+    }
+    deleted.len()
+}
+
+///
+/// 墓標のビットマップで削除されていない行について、行範囲内の行番号をdestに書き込み、スライスとして返します。
+///
+fn scan_live_row_ids<'a>(
+    deleted: &BitSlice,
+    num_deleted: RowId,
+    live_ranks: &[RowId],
+    range: Range<RowId>,
+    dest: &'a mut [RowId],
+) -> &'a [RowId] {
+    let start = range.start;
+    let end = cmp::min(range.end, deleted.len() - num_deleted);
+    if start >= end {
+        return &dest[0..0];
+    }
+    let n_range = end - start;
+    if dest.len() < n_range {
+        return &dest[0..0];
+    }
+    if num_deleted == 0 {
+        for (i, row_id) in (start..end).enumerate() {
+            dest[i] = row_id;
+        }
+        return &dest[0..n_range];
+    }
+    // 削除済みの行を飛ばす
+    let first = nth_live_row_id(deleted, live_ranks, start);
+    let row_ids = deleted[first..].iter_zeros().map(|i| first + i);
+    for (dest, row_id) in dest.iter_mut().zip(row_ids.take(n_range)) {
+        *dest = row_id;
+    }
+    &dest[0..n_range]
+}
+
+///
+/// 墓標のビットマップで削除されていない行について、行範囲内の行番号を連続する区間ごとにfに渡します。
+///
+fn scan_live_row_ranges(
+    deleted: &BitSlice,
+    num_deleted: RowId,
+    live_ranks: &[RowId],
+    range: Range<RowId>,
+    f: &mut dyn FnMut(Range<RowId>),
+) {
+    let end = cmp::min(range.end, deleted.len() - num_deleted);
+    if range.start >= end {
+        return;
+    }
+    if num_deleted == 0 {
+        f(range.start..end);
+        return;
+    }
+    // 削除済みの行の区間を飛ばしながら、生きている行の区間を渡す
+    let mut rest = end - range.start;
+    let mut row_id = nth_live_row_id(deleted, live_ranks, range.start);
+    while rest > 0 && row_id < deleted.len() {
+        let n_live = deleted[row_id..]
+            .first_one()
+            .unwrap_or(deleted.len() - row_id);
+        let n = cmp::min(n_live, rest);
+        f(row_id..row_id + n);
+        rest -= n;
+        row_id += n;
+        row_id += deleted[row_id..]
+            .first_zero()
+            .unwrap_or(deleted.len() - row_id);
+    }
+}
+
+impl Relation for Table {
+    fn num_columns(&self) -> ColumnId {
+        self.columns.len()
+    }
+    fn num_rows(&self) -> RowId {
+        self.num_rows - self.tombstones.num_deleted()
+    }
+    fn definition(&self) -> &Definition {
+        &self.definition
+    }
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
+        &self.columns[col_id]
+    }
+    fn scan_row_ids<'a>(&self, range: Range<RowId>, dest: &'a mut [RowId]) -> &'a [RowId] {
+        let tombstones = &self.tombstones;
+        scan_live_row_ids(
+            &tombstones.deleted,
+            tombstones.num_deleted,
+            &tombstones.live_ranks,
+            range,
+            dest,
+        )
+    }
+    fn scan_row_ranges(&self, range: Range<RowId>, f: &mut dyn FnMut(Range<RowId>)) {
+        let tombstones = &self.tombstones;
+        scan_live_row_ranges(
+            &tombstones.deleted,
+            tombstones.num_deleted,
+            &tombstones.live_ranks,
+            range,
+            f,
+        )
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Relation::fmt(self, f)
+    }
+}
+
+pub trait Insertable<'a> {
+    fn insert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table>;
+    fn insert_named<T: AsValue>(self, pairs: &[(&str, T)]) -> Option<&'a mut Table>;
+    fn upsert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table>;
+}
+
+impl<'a> Insertable<'a> for Option<&'a mut Table> {
+    fn insert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table> {
+        self.and_then(|table| table.insert(tuple))
+    }
+    fn insert_named<T: AsValue>(self, pairs: &[(&str, T)]) -> Option<&'a mut Table> {
+        self.and_then(|table| table.insert_named(pairs))
+    }
+    fn upsert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table> {
+        self.and_then(|table| table.upsert(tuple))
+    }
+}
+
+/// 書き戻すためのテーブル名、書き換えた行番号と、書き換える前のカラム番号・行番号・値の組
+type UpdateUndo = (String, Vec<RowId>, Vec<(ColumnId, RowId, OwnedValue)>);
+
+///
+/// テーブルを名前で管理するカタログです。
+/// 外部キー制約はテーブルをまたぐので、Databaseを通した挿入・更新・削除でだけ検査されます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let kubun = Definition::create(
+///     "kubun",
+///     attributes![
+///         ("kubun_id", TypeKind::Integer),
+///         ("kubun_name", TypeKind::Varchar)
+///     ],
+/// )
+/// .primary_key(&["kubun_id"])
+/// .unwrap();
+/// let shohin = Definition::create(
+///     "shohin",
+///     attributes![
+///         ("shohin_name", TypeKind::Varchar),
+///         ("kubun_id", TypeKind::Integer)
+///     ],
+/// )
+/// .foreign_key(&["kubun_id"], "kubun", &["kubun_id"], ReferentialAction::Cascade)
+/// .unwrap();
+/// let mut db = Database::new();
+/// db.create_table(kubun).unwrap();
+/// db.create_table(shohin).unwrap();
+/// db.insert("kubun", values!(1, "くだもの")).unwrap();
+/// db.insert("shohin", values!("りんご", 1)).unwrap();
+/// db.insert("shohin", values!("わかめ", NULL)).unwrap();
+/// let err = db.insert("shohin", values!("しいたけ", 4)).err().unwrap();
+/// assert_eq!(err.to_string(), "外部キー(kubun_id)の値(4)はテーブルkubunに存在しません");
+/// db.delete_where("kubun", &Predicate::equal_to("kubun_id", 1)).unwrap();
+/// assert_eq!(db.table("shohin").unwrap().num_rows(), 1);
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Database {
+    tables: BTreeMap<String, Table>,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+    /// 名前を指定してテーブルを返します。
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+    /// テーブル名を名前順に返します。
+    pub fn table_names(&self) -> impl Iterator<Item = &String> {
+        self.tables.keys()
+    }
+    fn find_table(&self, name: &str) -> Result<&Table, Error> {
+        self.table(name)
+            .ok_or_else(|| Error::UnknownTable(name.to_string()))
+    }
+    ///
+    /// 空のテーブルを作成します。失敗する場合は[`Database::add_table`]と同じ理由を返します。
+    ///
+    pub fn create_table(&mut self, definition: Definition) -> Result<&mut Database, Error> {
+        self.add_table(Table::new(definition))
+    }
+    ///
+    /// テーブルを追加します。同じ名前のテーブルがある場合や、外部キーの参照先が見つからない場合、
+    /// 既存の行が外部キー制約を満たさない場合は、何もせずにその理由を返します。
+    ///
+    pub fn add_table(&mut self, table: Table) -> Result<&mut Database, Error> {
+        let name = table.definition.name.clone();
+        if self.tables.contains_key(&name) {
+            return Err(Error::TableExists(name));
+        }
+        // 自分自身を参照する外部キーもあるので、いったん登録してから検査する
+        self.tables.insert(name.clone(), table);
+        if let Err(err) = self.check_table(&self.tables[&name]) {
+            self.tables.remove(&name);
+            return Err(err);
+        }
+        Ok(self)
+    }
+    ///
+    /// テーブルを取り除いて返します。他のテーブルから外部キーで参照されている場合は取り除けません。
+    ///
+    pub fn drop_table(&mut self, name: &str) -> Result<Table, Error> {
+        self.find_table(name)?;
+        for child in self.tables.values() {
+            let definition = &child.definition;
+            if definition.name == name {
+                continue;
+            }
+            if let Some(foreign_key) = definition
+                .foreign_keys
+                .iter()
+                .find(|foreign_key| foreign_key.ref_table == name)
+            {
+                return Err(Error::Referenced {
+                    table: definition.name.clone(),
+                    columns: definition.names_of(&foreign_key.col_ids),
+                    values: Vec::new(),
+                });
+            }
+        }
+        self.tables
+            .remove(name)
+            .ok_or_else(|| Error::UnknownTable(name.to_string()))
+    }
+    ///
+    /// テーブル名を指定して、`alter`でテーブルの定義を変更します([`AlterTable::add_column`]など)。
+    /// `alter`には定義を変更する操作だけを渡し、行の挿入・更新・削除はできません。
+    /// 外部キー制約のカラムと、他のテーブルの外部キーから参照されているカラムは、
+    /// 削除も名前や型の変更もできず、変更する前にその理由を返します。
+    /// `alter`が途中で失敗した場合は、それまでの変更は残ります。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let kubun = Definition::create("kubun", attributes![("kubun_id", TypeKind::Integer)]);
+    /// let mut db = Database::new();
+    /// db.create_table(kubun).unwrap();
+    /// db.alter_table("kubun", |kubun| {
+    ///     kubun.add_column(Attribute::create("kubun_name", TypeKind::Varchar))
+    /// })
+    /// .unwrap();
+    /// assert_eq!(db.table("kubun").unwrap().num_columns(), 2);
+    /// ```
+    ///
+    pub fn alter_table<F>(&mut self, table_name: &str, alter: F) -> Result<&mut Database, Error>
+    where
+        F: FnOnce(&mut AlterTable) -> Result<&mut AlterTable, Error>,
+    {
+        self.find_table(table_name)?;
+        // 参照されているカラムをテーブルに伝えて、変更する前に拒否させる
+        let mut referenced_columns = Vec::new();
+        for child in self.tables.values() {
+            for foreign_key in &child.definition.foreign_keys {
+                if foreign_key.ref_table == table_name {
+                    referenced_columns.extend(foreign_key.ref_col_names.iter().cloned());
+                }
+            }
+        }
+        let mut table = self
+            .tables
+            .remove(table_name)
+            .ok_or_else(|| Error::UnknownTable(table_name.to_string()))?;
+        table.referenced_columns = referenced_columns;
+        let mut alter_table = AlterTable { table };
+        let result = alter(&mut alter_table).map(|_| ());
+        let mut table = alter_table.table;
+        table.referenced_columns.clear();
+        self.tables.insert(table_name.to_string(), table);
+        result.map(|_| self)
+    }
+    ///
+    /// テーブルの外部キーの参照先の定義と、全ての行の外部キーの値を検査します。
+    ///
+    fn check_table(&self, table: &Table) -> Result<(), Error> {
+        // 行がなくても参照先の定義は検査する
+        self.check_references(table, &[])?;
+        for row_id in table.tombstones.bits().iter_zeros() {
+            let values: Vec<Value> = table
+                .columns
+                .iter()
+                .map(|column| column.key_at(row_id))
+                .collect();
+            self.check_references(table, &values)?;
+        }
+        Ok(())
+    }
+    ///
+    /// 行の外部キーの値が参照先に存在するか検査します。
+    /// `values`が空の場合は参照先の定義だけを検査します。
+    ///
+    fn check_references(&self, table: &Table, values: &[Value]) -> Result<(), Error> {
+        for foreign_key in &table.definition.foreign_keys {
+            let parent = self.find_table(&foreign_key.ref_table)?;
+            let ref_col_ids = foreign_key.ref_col_ids(parent)?;
+            if values.is_empty() {
+                continue;
+            }
+            let key: Vec<Value> = foreign_key
+                .col_ids
+                .iter()
+                .map(|&col_id| values[col_id].clone())
+                .collect();
+            if !key.iter().any(Value::is_null) && !parent.contains_unique(&ref_col_ids, &key) {
+                return Err(Error::ForeignKey {
+                    columns: table.definition.names_of(&foreign_key.col_ids),
+                    table: parent.definition.name.clone(),
+                    values: key.into_iter().map(OwnedValue::from).collect(),
+                });
+            }
+        }
+        Ok(())
+    }
+    ///
+    /// テーブル名を指定して行を挿入します。[`Table::try_insert`]の検査に加えて、
+    /// 外部キーの値が参照先に存在しなければ、何もせずにその理由を返します。
+    ///
+    pub fn insert<T: AsValue>(
+        &mut self,
+        table_name: &str,
+        tuple: &[T],
+    ) -> Result<&mut Database, Error> {
+        let table = self.find_table(table_name)?;
+        table.validate(tuple)?;
+        let values: Vec<Value> = tuple.iter().map(|value| value.as_value_ref()).collect();
+        self.check_references(table, &values)?;
+        if let Some(table) = self.tables.get_mut(table_name) {
+            table.try_insert(tuple)?;
+        }
+        Ok(self)
+    }
+    ///
+    /// テーブル名を指定して、カラム名と値の組で行を挿入します。
+    /// 指定しなかったカラムは[`Table::try_insert_named`]と同じように埋めます。
+    ///
+    pub fn insert_named<T: AsValue>(
+        &mut self,
+        table_name: &str,
+        pairs: &[(&str, T)],
+    ) -> Result<&mut Database, Error> {
+        let tuple = self.find_table(table_name)?.complete_tuple(pairs)?;
+        self.insert(table_name, &tuple)
+    }
+    ///
+    /// テーブル名を指定して行を挿入します。一意性制約に違反する行があれば`on_conflict`に従います。
+    /// 挿入する場合は[`Database::insert`]と同じく外部キーの値を検査し、書き換える場合は
+    /// [`Database::update`]と同じく参照している行に外部キーの動作を連鎖させます。
+    /// `DoUpdate`で複数の行と衝突する場合は、何もせずに一意性制約違反を返します。
+    ///
+    pub fn insert_on_conflict<T: AsValue>(
+        &mut self,
+        table_name: &str,
+        tuple: &[T],
+        on_conflict: OnConflict,
+    ) -> Result<&mut Database, Error> {
+        let table = self.find_table(table_name)?;
+        table.validate(tuple)?;
+        let conflicts = table.conflicting_row_ids(tuple);
+        let row_id = match (conflicts.first(), on_conflict) {
+            (None, _) => return self.insert(table_name, tuple),
+            (Some(_), OnConflict::DoNothing) => return Ok(self),
+            (Some(&(_, row_id)), OnConflict::DoUpdate) => row_id,
+        };
+        if let Some(&(key_no, _)) = conflicts.iter().find(|&&(_, other)| other != row_id) {
+            return Err(table.unique_error(key_no, tuple));
+        }
+        let targets: Vec<(ColumnId, OwnedValue)> = tuple
+            .iter()
+            .enumerate()
+            .map(|(col_id, value)| (col_id, value.as_value_ref().into()))
+            .collect();
+        let mut undo = Vec::new();
+        if let Err(err) = self.update_row_ids(table_name, &[row_id], &targets, &mut undo) {
+            self.undo_updates(undo);
+            return Err(err);
+        }
+        Ok(self)
+    }
+    ///
+    /// テーブル名を指定して、条件式が真になる行を書き換え、書き換えた行数を返します。
+    /// 他の行から参照されている値を書き換える場合は、参照している行を外部キーの
+    /// [`ReferentialAction`]に従って書き換えるかNULLにします。
+    /// [`Table::try_update`]の検査に加えて、書き換えた外部キーの値が参照先に存在しない場合や、
+    /// RESTRICTの外部キーから参照されている値を書き換える場合は、何もせずにその理由を返します。
+    ///
+    pub fn update<T: AsValue>(
+        &mut self,
+        table_name: &str,
+        predicate: &Predicate,
+        assignments: &[(&str, T)],
+    ) -> Result<RowId, Error> {
+        let table = self.find_table(table_name)?;
+        let targets: Vec<(ColumnId, OwnedValue)> = table
+            .resolve_assignments(assignments)?
+            .into_iter()
+            .map(|(col_id, value)| (col_id, value.into()))
+            .collect();
+        let row_ids = table.matching_row_ids(predicate);
+        // 連鎖して書き換えた後で失敗したら、書き換えた順と逆に書き戻す
+        let mut undo = Vec::new();
+        if let Err(err) = self.update_row_ids(table_name, &row_ids, &targets, &mut undo) {
+            self.undo_updates(undo);
+            return Err(err);
+        }
+        Ok(row_ids.len())
+    }
+    ///
+    /// テーブル名と行番号を指定して行を書き換え、参照している行に外部キーの動作を連鎖させます。
+    /// 書き換えた行と書き換える前の値を`undo`に追加します。
+    ///
+    fn update_row_ids(
+        &mut self,
+        table_name: &str,
+        row_ids: &[RowId],
+        targets: &[(ColumnId, OwnedValue)],
+        undo: &mut Vec<UpdateUndo>,
+    ) -> Result<(), Error> {
+        let table = self.find_table(table_name)?;
+        table.check_targets(targets)?;
+        // 書き換えた後の値の組
+        let new_values = |row_id: RowId, col_ids: &[ColumnId]| -> Vec<OwnedValue> {
+            let mut values = table.values_at(row_id, col_ids);
+            for (value, col_id) in values.iter_mut().zip(col_ids) {
+                if let Some((_, new_value)) = targets.iter().find(|(id, _)| id == col_id) {
+                    *value = new_value.clone();
+                }
+            }
+            values
+        };
+        let is_target =
+            |col_ids: &[ColumnId]| targets.iter().any(|(col_id, _)| col_ids.contains(col_id));
+        // 参照する側として、書き換えた外部キーの値が参照先に存在するか
+        for foreign_key in &table.definition.foreign_keys {
+            if !is_target(&foreign_key.col_ids) {
+                continue;
+            }
+            let parent = self.find_table(&foreign_key.ref_table)?;
+            let ref_col_ids = foreign_key.ref_col_ids(parent)?;
+            for &row_id in row_ids {
+                let key = new_values(row_id, &foreign_key.col_ids);
+                let values: Vec<Value> = key.iter().map(|value| value.as_value_ref()).collect();
+                if !key.iter().any(OwnedValue::is_null)
+                    && !parent.contains_unique(&ref_col_ids, &values)
+                {
+                    return Err(Error::ForeignKey {
+                        columns: table.definition.names_of(&foreign_key.col_ids),
+                        table: parent.definition.name.clone(),
+                        values: key,
+                    });
+                }
+            }
+        }
+        // 参照される側として、書き換える値を参照している行をどうするか
+        let mut cascades = Vec::new();
+        for child in self.tables.values() {
+            for foreign_key in &child.definition.foreign_keys {
+                if foreign_key.ref_table != *table_name {
+                    continue;
+                }
+                let ref_col_ids = foreign_key.ref_col_ids(table)?;
+                if !is_target(&ref_col_ids) {
+                    continue;
+                }
+                let mut keys = BTreeSet::new();
+                for &row_id in row_ids {
+                    let key = table.values_at(row_id, &ref_col_ids);
+                    if key != new_values(row_id, &ref_col_ids) {
+                        keys.insert(key);
+                    }
+                }
+                let referencing = child.referencing_row_ids(&foreign_key.col_ids, &keys);
+                if referencing.is_empty() {
+                    continue;
+                }
+                let col_ids = foreign_key.col_ids.iter().zip(&ref_col_ids);
+                let child_targets: Vec<(ColumnId, OwnedValue)> = match foreign_key.on_update {
+                    ReferentialAction::Restrict => {
+                        return Err(Error::Referenced {
+                            table: child.definition.name.clone(),
+                            columns: child.definition.names_of(&foreign_key.col_ids),
+                            values: child.values_at(referencing[0], &foreign_key.col_ids),
+                        });
+                    }
+                    // 書き換えるカラムに対応する外部キーのカラムだけを同じ値にする
+                    ReferentialAction::Cascade => col_ids
+                        .filter_map(|(&col_id, ref_col_id)| {
+                            let target = targets.iter().find(|(id, _)| id == ref_col_id);
+                            target.map(|(_, value)| (col_id, value.clone()))
+                        })
+                        .collect(),
+                    ReferentialAction::SetNull => {
+                        col_ids.map(|(&col_id, _)| (col_id, NULL.into())).collect()
+                    }
+                };
+                cascades.push((child.definition.name.clone(), referencing, child_targets));
+            }
+        }
+        if let Some(table) = self.tables.get_mut(table_name) {
+            let old_values = table.update_row_ids(row_ids, targets)?;
+            undo.push((table_name.to_string(), row_ids.to_vec(), old_values));
+        }
+        // 参照先を書き換えてから、参照している行を書き換える
+        for (name, row_ids, targets) in cascades {
+            self.update_row_ids(&name, &row_ids, &targets, undo)?;
+        }
+        Ok(())
+    }
+    /// [`Database::update_row_ids`]で書き換えた行を、書き換えた順と逆に書き戻します。
+    fn undo_updates(&mut self, undo: Vec<UpdateUndo>) {
+        for (name, row_ids, old_values) in undo.into_iter().rev() {
+            if let Some(table) = self.tables.get_mut(&name) {
+                table.restore_values(&row_ids, &old_values);
+            }
+        }
+    }
+    ///
+    /// テーブル名を指定して条件式が真になる行を削除し、削除した行数を返します。
+    /// 削除する行を参照している行は、外部キーの[`ReferentialAction`]に従って削除するかNULLにします。
+    /// NULLにした値を参照している行には、[`Database::update`]と同じように外部キーの動作を連鎖させます。
+    /// RESTRICTの外部キーから参照されている場合や、NULLにするとNOT NULL・CHECK制約に違反する場合は、
+    /// 何もせずにその理由を返します。
+    ///
+    pub fn delete_where(
+        &mut self,
+        table_name: &str,
+        predicate: &Predicate,
+    ) -> Result<RowId, Error> {
+        let row_ids = self.find_table(table_name)?.matching_row_ids(predicate);
+        let num_deleted = row_ids.len();
+        // 連鎖して削除する行と、NULLにする行をテーブルごとに集めてから一度に反映する
+        let mut deletions: BTreeMap<String, BTreeSet<RowId>> = BTreeMap::new();
+        let mut set_nulls: Vec<(String, Vec<ColumnId>, Vec<RowId>)> = Vec::new();
+        let mut pending = vec![(table_name.to_string(), row_ids)];
+        while let Some((name, row_ids)) = pending.pop() {
+            let deleted = deletions.entry(name.clone()).or_default();
+            let row_ids: Vec<RowId> = row_ids
+                .into_iter()
+                .filter(|&row_id| deleted.insert(row_id))
+                .collect();
+            if row_ids.is_empty() {
+                continue;
+            }
+            let parent = &self.tables[&name];
+            for child in self.tables.values() {
+                let definition = &child.definition;
+                for foreign_key in &definition.foreign_keys {
+                    if foreign_key.ref_table != name {
+                        continue;
+                    }
+                    let ref_col_ids = foreign_key.ref_col_ids(parent)?;
+                    let keys: BTreeSet<Vec<OwnedValue>> = row_ids
+                        .iter()
+                        .map(|&row_id| parent.values_at(row_id, &ref_col_ids))
+                        .collect();
+                    let referencing = child.referencing_row_ids(&foreign_key.col_ids, &keys);
+                    if referencing.is_empty() {
+                        continue;
+                    }
+                    match foreign_key.on_delete {
+                        ReferentialAction::Restrict => {
+                            return Err(Error::Referenced {
+                                table: definition.name.clone(),
+                                columns: definition.names_of(&foreign_key.col_ids),
+                                values: child.values_at(referencing[0], &foreign_key.col_ids),
+                            });
+                        }
+                        ReferentialAction::Cascade => {
+                            pending.push((definition.name.clone(), referencing));
+                        }
+                        ReferentialAction::SetNull => {
+                            for &col_id in &foreign_key.col_ids {
+                                if definition[col_id].not_null
+                                    || child.is_primary_key_column(col_id)
+                                {
+                                    return Err(Error::NotNull(definition[col_id].name.clone()));
+                                }
+                            }
+                            let col_ids = foreign_key.col_ids.clone();
+                            set_nulls.push((definition.name.clone(), col_ids, referencing));
+                        }
+                    }
+                }
+            }
+        }
+        // NULLにした値を参照している行にも外部キーの動作を連鎖させ、
+        // 制約に違反したら、それまでにNULLにした行を書き戻す
+        let mut undo = Vec::new();
+        for (name, col_ids, row_ids) in set_nulls {
+            let deleted = deletions.get(&name);
+            let row_ids: Vec<RowId> = row_ids
+                .into_iter()
+                .filter(|row_id| !deleted.is_some_and(|deleted| deleted.contains(row_id)))
+                .collect();
+            let targets: Vec<(ColumnId, OwnedValue)> = col_ids
+                .into_iter()
+                .map(|col_id| (col_id, NULL.into()))
+                .collect();
+            if let Err(err) = self.update_row_ids(&name, &row_ids, &targets, &mut undo) {
+                self.undo_updates(undo);
+                return Err(err);
+            }
+        }
+        for (name, row_ids) in deletions {
+            if let Some(table) = self.tables.get_mut(&name) {
+                let row_ids: Vec<RowId> = row_ids.into_iter().collect();
+                table.delete_row_ids(&row_ids);
+            }
+        }
+        Ok(num_deleted)
+    }
+}
+
+///
+/// [`Database::alter_table`]で、テーブルの定義を変更する操作だけを提供します。
+/// 操作は[`Table`]の同じ名前のメソッドと同じです。
+///
+#[derive(Debug)]
+pub struct AlterTable {
+    table: Table,
+}
+
+impl AlterTable {
+    /// 変更中のテーブルを返します。
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+    /// [`Table::add_column`]でカラムを追加します。
+    pub fn add_column(&mut self, attribute: Attribute) -> Result<&mut AlterTable, Error> {
+        self.table.add_column(attribute)?;
+        Ok(self)
+    }
+    /// [`Table::drop_column`]でカラムを削除します。
+    pub fn drop_column(&mut self, col_name: &str) -> Result<&mut AlterTable, Error> {
+        self.table.drop_column(col_name)?;
+        Ok(self)
+    }
+    /// [`Table::rename_column`]でカラム名を変更します。
+    pub fn rename_column(
+        &mut self,
+        col_name: &str,
+        new_name: &str,
+    ) -> Result<&mut AlterTable, Error> {
+        self.table.rename_column(col_name, new_name)?;
+        Ok(self)
+    }
+    /// [`Table::alter_column_type`]でカラムの型を変更します。
+    pub fn alter_column_type(
+        &mut self,
+        col_name: &str,
+        kind: TypeKind,
+    ) -> Result<&mut AlterTable, Error> {
+        self.table.alter_column_type(col_name, kind)?;
+        Ok(self)
+    }
+}
+
+///
+/// 射影結果リレーションを表します。
+///
+#[derive(Clone)]
+pub struct SelectedRelation<'a> {
+    definition: Definition,
+    relation: &'a dyn Relation,
+    col_ids: Vec<ColumnId>,
+}
+
+impl<'a> Relation for SelectedRelation<'a> {
+    fn num_columns(&self) -> ColumnId {
+        self.col_ids.len()
+    }
+    fn num_rows(&self) -> RowId {
+        self.relation.num_rows()
+    }
+    fn definition(&self) -> &Definition {
+        &self.definition
+    }
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
+        let col_id = self.col_ids[col_id];
+        self.relation.column_at(col_id)
+    }
+    fn scan_row_ids<'b>(&self, range: Range<RowId>, dest: &'b mut [RowId]) -> &'b [RowId] {
+        self.relation.scan_row_ids(range, dest)
+    }
+}
+
+impl fmt::Display for SelectedRelation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Relation::fmt(self, f)
+    }
+}
+
+///
+/// リレーションに対してカラムを指定して射影します。
+///
+pub trait Select: Relation {
+    fn select(&self, col_names: &[&str]) -> SelectedRelation<'_>;
+}
+
+impl<T> Select for T
+where
+    T: Relation,
+{
+    fn select(&self, col_names: &[&str]) -> SelectedRelation<'_> {
+        let mut col_ids = Vec::new();
+        for col_name in col_names {
+            if let Some(col_id) = self.definition().name_to_id(col_name) {
+                col_ids.push(col_id);
+            }
+        }
+        let definition = self.definition().select(&col_ids);
+        SelectedRelation {
+            definition,
+            relation: self,
+            col_ids: col_ids.to_vec(),
+        }
+    }
+}
+
+///
+/// 抽出結果リレーションを表します。
+///
+#[derive(Clone)]
+pub struct FilteredRelation<'a> {
+    relation: &'a dyn Relation,
+    valid_row_ids: ValidRowIds,
+}
+
+/// 抽出した行番号を保持します。
+#[derive(Clone)]
+enum ValidRowIds {
+    /// 元のリレーションが行番号の昇順に並んでいる場合(通常はこちら)
+    Set(RowSet),
+    /// 並べ替え済みのリレーションから抽出した場合は順序を保つ
+    List(Vec<RowId>),
+}
+
+impl ValidRowIds {
+    fn len(&self) -> RowId {
+        match self {
+            ValidRowIds::Set(row_set) => row_set.len(),
+            ValidRowIds::List(row_ids) => row_ids.len(),
+        }
+    }
+    fn push(&mut self, row_id: RowId) {
+        match self {
+            ValidRowIds::Set(row_set) if row_set.last().is_none_or(|last| last < row_id) => {
+                row_set.insert(row_id);
+            }
+            ValidRowIds::Set(row_set) => {
+                let mut row_ids: Vec<RowId> = row_set.iter().collect();
+                row_ids.push(row_id);
+                *self = ValidRowIds::List(row_ids);
+            }
+            ValidRowIds::List(row_ids) => row_ids.push(row_id),
+        }
+    }
+    fn push_range(&mut self, range: Range<RowId>) {
+        match self {
+            ValidRowIds::Set(row_set) if row_set.last().is_none_or(|last| last < range.start) => {
+                row_set.insert_range(range);
+            }
+            _ => {
+                for row_id in range {
+                    self.push(row_id);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> FilteredRelation<'a> {
+    ///
+    /// 抽出した行番号の集合を返します。並べ替え済みのリレーションから抽出した場合は`None`を返します。
+    ///
+    pub fn row_set(&self) -> Option<&RowSet> {
+        match &self.valid_row_ids {
+            ValidRowIds::Set(row_set) => Some(row_set),
+            ValidRowIds::List(_) => None,
+        }
+    }
+    ///
+    /// 同じリレーションから抽出した結果どうしの積集合(AND)を返します。
+    /// 元のリレーションが異なる場合や、並べ替え済みのリレーションから抽出した場合は`None`を返します。
+    ///
+    pub fn intersection(&self, other: &FilteredRelation<'a>) -> Option<FilteredRelation<'a>> {
+        self.combine(other, RowSet::intersection)
+    }
+    ///
+    /// 同じリレーションから抽出した結果どうしの和集合(OR)を返します。
+    /// 元のリレーションが異なる場合や、並べ替え済みのリレーションから抽出した場合は`None`を返します。
+    ///
+    pub fn union(&self, other: &FilteredRelation<'a>) -> Option<FilteredRelation<'a>> {
+        self.combine(other, RowSet::union)
+    }
+    fn combine<F>(&self, other: &FilteredRelation<'a>, f: F) -> Option<FilteredRelation<'a>>
+    where
+        F: Fn(&RowSet, &RowSet) -> RowSet,
+    {
+        let relation = self.relation as *const dyn Relation as *const ();
+        let other_relation = other.relation as *const dyn Relation as *const ();
+        if relation != other_relation {
+            return None;
+        }
+        let row_set = f(self.row_set()?, other.row_set()?);
+        Some(FilteredRelation {
+            relation: self.relation,
+            valid_row_ids: ValidRowIds::Set(row_set),
+        })
+    }
+}
+
+impl<'a> Relation for FilteredRelation<'a> {
+    fn num_columns(&self) -> ColumnId {
+        self.relation.num_columns()
+    }
+    fn num_rows(&self) -> RowId {
+        self.valid_row_ids.len()
+    }
+    fn definition(&self) -> &Definition {
+        self.relation.definition()
+    }
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
+        self.relation.column_at(col_id)
+    }
+    fn scan_row_ids<'b>(&self, range: Range<RowId>, dest: &'b mut [RowId]) -> &'b [RowId] {
+        let start = range.start;
+        let end = cmp::min(range.end, self.num_rows());
+        if start >= end {
+            return &dest[0..0];
+        }
+        let n_range = end - start;
+        if dest.len() < n_range {
+            return &dest[0..0];
+        }
+        match &self.valid_row_ids {
+            ValidRowIds::Set(row_set) => {
+                for (dest, row_id) in dest.iter_mut().zip(row_set.iter_from(start).take(n_range)) {
+                    *dest = row_id;
+                }
+            }
+            ValidRowIds::List(row_ids) => dest[0..n_range].copy_from_slice(&row_ids[start..end]),
+        }
+        &dest[0..n_range]
+    }
+}
+
+impl fmt::Display for FilteredRelation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Relation::fmt(self, f)
+    }
+}
+
+///
+/// SQLの三値論理における真偽値を表します。
+///
+/// NULLとの比較は`Unknown`になり、`NOT`, `AND`, `OR`はKleeneの三値論理に従います。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::Truth;
+/// assert_eq!(!Truth::Unknown, Truth::Unknown);
+/// assert_eq!(Truth::Unknown & Truth::False, Truth::False);
+/// assert_eq!(Truth::Unknown | Truth::True, Truth::True);
+/// assert_eq!(Truth::Unknown | Truth::False, Truth::Unknown);
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Truth {
+    /// 真
+    True,
+    /// 偽
+    False,
+    /// 不明(NULLが絡む比較の結果)
+    Unknown,
+}
+
+impl Truth {
+    /// `True`のときだけ`true`を返します。WHERE句で行を残すかどうかの判定です。
+    pub fn is_true(self) -> bool {
+        self == Truth::True
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(item: bool) -> Self {
+        if item {
+            Truth::True
+        } else {
+            Truth::False
+        }
+    }
+}
+
+impl std::ops::Not for Truth {
+    type Output = Truth;
+    fn not(self) -> Self::Output {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl std::ops::BitAnd for Truth {
+    type Output = Truth;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Truth::False, _) | (_, Truth::False) => Truth::False,
+            (Truth::True, Truth::True) => Truth::True,
+            _ => Truth::Unknown,
+        }
+    }
+}
+
+impl std::ops::BitOr for Truth {
+    type Output = Truth;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Truth::True, _) | (_, Truth::True) => Truth::True,
+            (Truth::False, Truth::False) => Truth::False,
+            _ => Truth::Unknown,
+        }
+    }
+}
+
+/// 比較演算子を表します。
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CompareOp {
+    /// `=`
+    Equal,
+    /// `<>`
+    NotEqual,
+    /// `<`
+    LessThan,
+    /// `<=`
+    LessEqual,
+    /// `>`
+    GreaterThan,
+    /// `>=`
+    GreaterEqual,
+}
+
+///
+/// WHERE句の条件式を表します。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::Predicate;
+/// // kubun_id = 1 OR kubun_id IS NULL
+/// let p = Predicate::equal_to("kubun_id", 1).or(Predicate::is_null("kubun_id"));
+/// // NOT (price < 200)
+/// let q = !Predicate::less_than("price", 200);
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Predicate {
+    /// カラムと定数の比較
+    Compare(String, CompareOp, OwnedValue),
+    /// IN (定数のリスト)
+    In(String, Vec<OwnedValue>),
+    /// IS NULL
+    IsNull(String),
+    /// IS NOT NULL
+    IsNotNull(String),
+    /// NOT
+    Not(Box<Predicate>),
+    /// AND
+    And(Box<Predicate>, Box<Predicate>),
+    /// OR
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn compare<Key: AsValue>(col_name: &str, op: CompareOp, key: Key) -> Predicate {
+        Predicate::Compare(col_name.to_string(), op, key.as_value_ref().into())
+    }
+    pub fn equal_to<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::Equal, key)
+    }
+    pub fn not_equal_to<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::NotEqual, key)
+    }
+    pub fn less_than<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::LessThan, key)
+    }
+    pub fn less_equal<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::LessEqual, key)
+    }
+    pub fn greater_than<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::GreaterThan, key)
+    }
+    pub fn greater_equal<Key: AsValue>(col_name: &str, key: Key) -> Predicate {
+        Predicate::compare(col_name, CompareOp::GreaterEqual, key)
+    }
+    pub fn is_in<Key: AsValue>(col_name: &str, keys: &[Key]) -> Predicate {
+        let keys = keys.iter().map(|key| key.as_value_ref().into()).collect();
+        Predicate::In(col_name.to_string(), keys)
+    }
+    pub fn is_null(col_name: &str) -> Predicate {
+        Predicate::IsNull(col_name.to_string())
+    }
+    pub fn is_not_null(col_name: &str) -> Predicate {
+        Predicate::IsNotNull(col_name.to_string())
+    }
+    pub fn and(self, rhs: Predicate) -> Predicate {
+        Predicate::And(Box::new(self), Box::new(rhs))
+    }
+    pub fn or(self, rhs: Predicate) -> Predicate {
+        Predicate::Or(Box::new(self), Box::new(rhs))
+    }
+    /// 条件式がカラムを参照しているかどうかを返します。
+    fn refers_to(&self, col_name: &str) -> bool {
+        match self {
+            Predicate::Compare(name, _, _)
+            | Predicate::In(name, _)
+            | Predicate::IsNull(name)
+            | Predicate::IsNotNull(name) => name == col_name,
+            Predicate::Not(p) => p.refers_to(col_name),
+            Predicate::And(lhs, rhs) | Predicate::Or(lhs, rhs) => {
+                lhs.refers_to(col_name) || rhs.refers_to(col_name)
+            }
+        }
+    }
+    /// 条件式が参照しているカラム名を書き換えます。
+    fn rename_column(&mut self, old_name: &str, new_name: &str) {
+        match self {
+            Predicate::Compare(name, _, _)
+            | Predicate::In(name, _)
+            | Predicate::IsNull(name)
+            | Predicate::IsNotNull(name) => {
+                if name == old_name {
+                    *name = new_name.to_string();
+                }
+            }
+            Predicate::Not(p) => p.rename_column(old_name, new_name),
+            Predicate::And(lhs, rhs) | Predicate::Or(lhs, rhs) => {
+                lhs.rename_column(old_name, new_name);
+                rhs.rename_column(old_name, new_name);
+            }
+        }
+    }
+    ///
+    /// 定義に沿った値の組(まだテーブルにない行)に対して評価します。
+    /// 存在しないカラム名との比較はUNKNOWNになります。
+    ///
+    fn evaluate_tuple(&self, definition: &Definition, tuple: &[Value<'_>]) -> Truth {
+        let value_of = |col_name: &str| {
+            definition
+                .name_to_id(col_name)
+                .map(|col_id| &tuple[col_id])
+                .filter(|value| !value.is_null())
+        };
+        let compare = |value: &Value<'_>, op: CompareOp, key: &OwnedValue| {
+            let key = key.as_value_ref();
+            if key.kind() != value.kind() {
+                return Truth::Unknown;
+            }
+            let ordering = value.cmp(&key);
+            Truth::from(match op {
+                CompareOp::Equal => ordering == Ordering::Equal,
+                CompareOp::NotEqual => ordering != Ordering::Equal,
+                CompareOp::LessThan => ordering == Ordering::Less,
+                CompareOp::LessEqual => ordering != Ordering::Greater,
+                CompareOp::GreaterThan => ordering == Ordering::Greater,
+                CompareOp::GreaterEqual => ordering != Ordering::Less,
+            })
+        };
+        match self {
+            Predicate::Compare(col_name, op, key) => match value_of(col_name) {
+                Some(value) => compare(value, *op, key),
+                None => Truth::Unknown,
+            },
+            Predicate::In(col_name, keys) => match value_of(col_name) {
+                Some(value) => keys
+                    .iter()
+                    .map(|key| compare(value, CompareOp::Equal, key))
+                    .fold(Truth::False, |acc, truth| acc | truth),
+                None => Truth::Unknown,
+            },
+            Predicate::IsNull(col_name) | Predicate::IsNotNull(col_name) => {
+                match definition.name_to_id(col_name) {
+                    Some(col_id) => {
+                        let is_null = tuple[col_id].is_null();
+                        Truth::from(is_null == matches!(self, Predicate::IsNull(_)))
+                    }
+                    None => Truth::Unknown,
+                }
+            }
+            Predicate::Not(p) => !p.evaluate_tuple(definition, tuple),
+            Predicate::And(lhs, rhs) => {
+                lhs.evaluate_tuple(definition, tuple) & rhs.evaluate_tuple(definition, tuple)
+            }
+            Predicate::Or(lhs, rhs) => {
+                lhs.evaluate_tuple(definition, tuple) | rhs.evaluate_tuple(definition, tuple)
+            }
+        }
+    }
+    ///
+    /// リレーションのカラムに束縛します。存在しないカラム名があれば`None`を返します。
+    ///
+    fn bind<'a>(&self, relation: &'a dyn Relation) -> Option<BoundPredicate<'a>> {
+        let column_of = |col_name: &str| {
+            let col_id = relation.definition().name_to_id(col_name)?;
+            Some(relation.column_at(col_id))
+        };
+        Some(match self {
+            Predicate::Compare(col_name, op, key) => {
+                Predicate::bind_compare(column_of(col_name)?, *op, key)
+            }
+            Predicate::In(col_name, keys) => Predicate::bind_in(column_of(col_name)?, keys),
+            Predicate::IsNull(col_name) => BoundPredicate::IsNull(column_of(col_name)?),
+            Predicate::IsNotNull(col_name) => {
+                BoundPredicate::Not(Box::new(BoundPredicate::IsNull(column_of(col_name)?)))
+            }
+            Predicate::Not(p) => BoundPredicate::Not(Box::new(p.bind(relation)?)),
+            Predicate::And(lhs, rhs) => {
+                BoundPredicate::And(Box::new(lhs.bind(relation)?), Box::new(rhs.bind(relation)?))
+            }
+            Predicate::Or(lhs, rhs) => {
+                BoundPredicate::Or(Box::new(lhs.bind(relation)?), Box::new(rhs.bind(relation)?))
+            }
+        })
+    }
+    fn bind_compare<'a>(
+        column: &'a dyn AsColumn,
+        op: CompareOp,
+        key: &OwnedValue,
+    ) -> BoundPredicate<'a> {
+        if key.as_value_ref().kind() != Some(column.kind()) {
+            return BoundPredicate::Unknown;
+        }
+        if let (Some(lower), Some(upper)) = (column.lower_bound(key), column.upper_bound(key)) {
+            // IDの順序と値の順序が一致しているので、比較はIDの範囲判定で済む
+            let n_keys = column.num_keys();
+            let key_ids = match op {
+                CompareOp::Equal | CompareOp::NotEqual => lower..upper,
+                CompareOp::LessThan => 0..lower,
+                CompareOp::LessEqual => 0..upper,
+                CompareOp::GreaterThan => upper..n_keys,
+                CompareOp::GreaterEqual => lower..n_keys,
+            };
+            let bound = BoundPredicate::KeyIdRange(column, key_ids);
+            if op == CompareOp::NotEqual {
+                return BoundPredicate::Not(Box::new(bound));
+            }
+            return bound;
+        }
+        match Predicate::key_ids_of(column, op, key) {
+            Some(key_ids) => BoundPredicate::KeyIds(column, key_ids),
+            None => BoundPredicate::Unknown,
+        }
+    }
+    ///
+    /// いずれかの定数と等しければTRUE、そうでなくNULLや型の異なる定数を含む場合はUNKNOWNにします。
+    ///
+    fn bind_in<'a>(column: &'a dyn AsColumn, keys: &[OwnedValue]) -> BoundPredicate<'a> {
+        let mut key_ids = bitvec![0; column.num_keys()];
+        let mut has_unknown = false;
+        for key in keys {
+            if key.as_value_ref().kind() != Some(column.kind()) {
+                has_unknown = true;
+            } else if let Some(key_id) = column.id_of(key) {
+                key_ids.set(key_id, true);
+            }
+        }
+        let bound = BoundPredicate::KeyIds(column, key_ids);
+        if has_unknown {
+            return BoundPredicate::Or(Box::new(bound), Box::new(BoundPredicate::Unknown));
+        }
+        bound
+    }
+    ///
+    /// 比較結果が真になるKeyIdのビットマップを返します。
+    /// 定数がNULLまたは型が一致しない場合は比較結果が常にUNKNOWNなので`None`を返します。
+    ///
+    fn key_ids_of(column: &dyn AsColumn, op: CompareOp, key: &OwnedValue) -> Option<BitMap> {
+        if key.as_value_ref().kind() != Some(column.kind()) {
+            return None;
+        }
+        let equal_to = || {
+            let mut bitmap = bitvec![0; column.num_keys()];
+            if let Some(key_id) = column.id_of(key) {
+                bitmap.set(key_id, true);
+            }
+            bitmap
+        };
+        match op {
+            CompareOp::Equal => Some(equal_to()),
+            CompareOp::NotEqual => Some(!equal_to()),
+            CompareOp::LessThan => column.range_to(key),
+            CompareOp::LessEqual => column.range_to(key).map(|bitmap| bitmap | equal_to()),
+            CompareOp::GreaterThan => column.range_from(key).map(|bitmap| bitmap & !equal_to()),
+            CompareOp::GreaterEqual => column.range_from(key),
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CompareOp::Equal => "=",
+            CompareOp::NotEqual => "<>",
+            CompareOp::LessThan => "<",
+            CompareOp::LessEqual => "<=",
+            CompareOp::GreaterThan => ">",
+            CompareOp::GreaterEqual => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::Compare(col_name, op, key) => {
+                write!(f, "{} {} ", col_name, op)?;
+                key.fmt_literal(f)
+            }
+            Predicate::In(col_name, keys) => {
+                write!(f, "{} IN (", col_name)?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_literal(f)?;
+                }
+                write!(f, ")")
+            }
+            Predicate::IsNull(col_name) => write!(f, "{} IS NULL", col_name),
+            Predicate::IsNotNull(col_name) => write!(f, "{} IS NOT NULL", col_name),
+            Predicate::Not(p) => write!(f, "NOT ({})", p),
+            Predicate::And(lhs, rhs) => write!(f, "({} AND {})", lhs, rhs),
+            Predicate::Or(lhs, rhs) => write!(f, "({} OR {})", lhs, rhs),
+        }
+    }
+}
+
+impl std::ops::Not for Predicate {
+    type Output = Predicate;
+    fn not(self) -> Self::Output {
+        Predicate::Not(Box::new(self))
+    }
+}
+
+/// カラムに束縛済みの条件式です。
+enum BoundPredicate<'a> {
+    /// 値がビットマップに含まれるKeyIdならTRUE(NULLならUNKNOWN)
+    KeyIds(&'a dyn AsColumn, BitMap),
+    /// 値が範囲内のKeyIdならTRUE(NULLならUNKNOWN)
+    KeyIdRange(&'a dyn AsColumn, Range<KeyId>),
+    /// 常にUNKNOWN
+    Unknown,
+    /// IS NULL
+    IsNull(&'a dyn AsColumn),
+    /// NOT
+    Not(Box<BoundPredicate<'a>>),
+    /// AND
+    And(Box<BoundPredicate<'a>>, Box<BoundPredicate<'a>>),
+    /// OR
+    Or(Box<BoundPredicate<'a>>, Box<BoundPredicate<'a>>),
+}
+
+impl<'a> BoundPredicate<'a> {
+    fn evaluate(&self, row_id: RowId) -> Truth {
+        match self {
+            BoundPredicate::KeyIds(column, key_ids) => match column.id_at(row_id) {
+                Some(key_id) => key_ids[key_id].into(),
+                None => Truth::Unknown,
+            },
+            BoundPredicate::KeyIdRange(column, key_ids) => match column.id_at(row_id) {
+                Some(key_id) => key_ids.contains(&key_id).into(),
+                None => Truth::Unknown,
+            },
+            BoundPredicate::Unknown => Truth::Unknown,
+            BoundPredicate::IsNull(column) => (!column.validity()[row_id]).into(),
+            BoundPredicate::Not(p) => !p.evaluate(row_id),
+            BoundPredicate::And(lhs, rhs) => lhs.evaluate(row_id) & rhs.evaluate(row_id),
+            BoundPredicate::Or(lhs, rhs) => lhs.evaluate(row_id) | rhs.evaluate(row_id),
+        }
+    }
+    ///
+    /// 行を走査せずにビットマップインデックスで評価できる場合、結果が`value`
+    /// (`true`ならTRUE、`false`ならFALSE)になる行の集合を返します。
+    ///
+    fn truth_rows(&self, value: bool) -> Option<RowSet> {
+        match self {
+            BoundPredicate::KeyIds(column, key_ids) => {
+                let index = column.index()?;
+                Some(if value {
+                    index.union(key_ids.iter_ones())
+                } else {
+                    index.union(key_ids.iter_zeros())
+                })
+            }
+            BoundPredicate::KeyIdRange(column, key_ids) => {
+                let index = column.index()?;
+                Some(if value {
+                    index.union(key_ids.clone())
+                } else {
+                    index.union((0..key_ids.start).chain(key_ids.end..column.num_keys()))
+                })
+            }
+            BoundPredicate::Unknown => Some(RowSet::new()),
+            BoundPredicate::IsNull(column) => Some(RowSet::from_bits(column.validity(), !value)),
+            BoundPredicate::Not(p) => p.truth_rows(!value),
+            BoundPredicate::And(lhs, rhs) => {
+                let lhs = lhs.truth_rows(value)?;
+                let rhs = rhs.truth_rows(value)?;
+                Some(if value { &lhs & &rhs } else { &lhs | &rhs })
+            }
+            BoundPredicate::Or(lhs, rhs) => {
+                let lhs = lhs.truth_rows(value)?;
+                let rhs = rhs.truth_rows(value)?;
+                Some(if value { &lhs | &rhs } else { &lhs & &rhs })
+            }
+        }
+    }
+    /// 全てのカラムがラン長圧縮されていて、ランごとにまとめて評価できるかどうかを返します。
+    fn is_run_length(&self) -> bool {
+        match self {
+            BoundPredicate::KeyIds(column, _)
+            | BoundPredicate::KeyIdRange(column, _)
+            | BoundPredicate::IsNull(column) => column.encoding() == Encoding::RunLength,
+            BoundPredicate::Unknown => true,
+            BoundPredicate::Not(p) => p.is_run_length(),
+            BoundPredicate::And(lhs, rhs) | BoundPredicate::Or(lhs, rhs) => {
+                lhs.is_run_length() && rhs.is_run_length()
+            }
+        }
+    }
+    /// `row_id`から評価結果が変わらない区間の終端(排他的)を返します。
+    fn run_end(&self, row_id: RowId) -> RowId {
+        match self {
+            BoundPredicate::KeyIds(column, _)
+            | BoundPredicate::KeyIdRange(column, _)
+            | BoundPredicate::IsNull(column) => column.run_end(row_id),
+            BoundPredicate::Unknown => RowId::MAX,
+            BoundPredicate::Not(p) => p.run_end(row_id),
+            BoundPredicate::And(lhs, rhs) | BoundPredicate::Or(lhs, rhs) => {
+                cmp::min(lhs.run_end(row_id), rhs.run_end(row_id))
+            }
+        }
+    }
+}
+
+///
+/// リレーションの有効な行のうち`true_rows`に含まれる行を、リレーションの行の順に`dest`に追加します。
+///
+fn extend_true_rows(relation: &dyn Relation, dest: &mut ValidRowIds, true_rows: &RowSet) {
+    // 行番号の昇順に走査している間は、true_rowsも先頭から一緒に読み進める
+    let mut iter = true_rows.iter().peekable();
+    let mut scanned = 0;
+    relation.scan_row_ranges(0..relation.num_rows(), &mut |rows| {
+        if rows.start < scanned {
+            for row_id in rows {
+                if true_rows.contains(row_id) {
+                    dest.push(row_id);
+                }
+            }
+            return;
+        }
+        scanned = rows.end;
+        while let Some(row_id) = iter.next_if(|&row_id| row_id < rows.end) {
+            if row_id >= rows.start {
+                dest.push(row_id);
+            }
+        }
+    });
+}
+
+///
+/// リレーションに対して条件式が真になる行を抽出します。
+/// 条件式がUNKNOWNになる行は抽出されません。
+///
+pub trait Filter: Relation {
+    fn filter(&self, predicate: &Predicate) -> FilteredRelation<'_>;
+}
+
+impl<T> Filter for T
+where
+    T: Relation,
+{
+    fn filter(&self, predicate: &Predicate) -> FilteredRelation<'_> {
+        let valid_row_ids = {
+            let mut valid_row_ids = ValidRowIds::Set(RowSet::new());
+            if let Some(predicate) = predicate.bind(self) {
+                let n_rows = self.num_rows();
+                if let Some(true_rows) = predicate.truth_rows(true) {
+                    // インデックスで求めた行と突き合わせるだけで済む
+                    extend_true_rows(self, &mut valid_row_ids, &true_rows);
+                } else if predicate.is_run_length() {
+                    // 同じランに含まれる行は評価結果も同じなので、ランごとに1回だけ評価する
+                    self.scan_row_ranges(0..n_rows, &mut |rows| {
+                        let mut row_id = rows.start;
+                        while row_id < rows.end {
+                            let end = cmp::min(predicate.run_end(row_id), rows.end);
+                            if predicate.evaluate(row_id).is_true() {
+                                valid_row_ids.push_range(row_id..end);
+                            }
+                            row_id = end;
+                        }
+                    });
+                } else {
+                    const STEP: RowId = 64;
+                    let mut start = 0;
+                    let mut buffer = [0; STEP];
+                    while start < n_rows {
+                        let step = cmp::min(STEP, n_rows - start);
+                        let end = start + step;
+                        let row_ids = self.scan_row_ids(start..end, &mut buffer);
+                        for &row_id in row_ids {
+                            if predicate.evaluate(row_id).is_true() {
+                                valid_row_ids.push(row_id);
+                            }
+                        }
+                        start += step;
+                    }
+                }
+            }
+            match &mut valid_row_ids {
+                ValidRowIds::Set(row_set) => row_set.optimize(),
+                ValidRowIds::List(row_ids) => row_ids.shrink_to_fit(),
+            }
+            valid_row_ids
+        };
+        FilteredRelation {
+            relation: self,
+            valid_row_ids,
+        }
+    }
+}
+
+///
+/// リレーションに対してカラム名と値を指定して '<' 比較します。
+///
+pub trait LessThan: Relation {
+    fn less_than<Key: AsValue>(&self, col_id: &str, key: Key) -> FilteredRelation<'_>;
+}
+
+impl<T> LessThan for T
+where
+    T: Relation,
+{
+    fn less_than<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation<'_> {
+        self.filter(&Predicate::less_than(col_name, key))
+    }
+}
+
+///
+/// リレーションに対してカラム名と値を指定して '==' 比較します。
+///
+pub trait EqualTo: Relation {
+    fn equal_to<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation<'_>;
+}
+
+impl<T> EqualTo for T
+where
+    T: Relation,
+{
+    fn equal_to<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation<'_> {
+        self.filter(&Predicate::equal_to(col_name, key))
+    }
+}
+
+///
+/// リレーションに対してカラム名を指定して IS NULL / IS NOT NULL で抽出します。
+///
+pub trait IsNull: Relation {
+    fn is_null(&self, col_name: &str) -> FilteredRelation<'_>;
+    fn is_not_null(&self, col_name: &str) -> FilteredRelation<'_>;
+}
+
+impl<T> IsNull for T
+where
+    T: Relation,
+{
+    fn is_null(&self, col_name: &str) -> FilteredRelation<'_> {
+        self.filter(&Predicate::is_null(col_name))
+    }
+    fn is_not_null(&self, col_name: &str) -> FilteredRelation<'_> {
+        self.filter(&Predicate::is_not_null(col_name))
+    }
+}
+
+///
+/// 並べ替え結果リレーションを表します。
+///
+#[derive(Clone)]
+pub struct OrderedRelation<'a> {
+    relation: &'a dyn Relation,
+    row_ids: Vec<RowId>,
+}
+
+impl<'a> Relation for OrderedRelation<'a> {
+    fn num_columns(&self) -> ColumnId {
+        self.relation.num_columns()
+    }
+    fn num_rows(&self) -> RowId {
+        self.row_ids.len()
+    }
+    fn definition(&self) -> &Definition {
+        self.relation.definition()
+    }
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
+        self.relation.column_at(col_id)
+    }
+    fn scan_row_ids<'b>(&self, range: Range<RowId>, dest: &'b mut [RowId]) -> &'b [RowId] {
+        let start = range.start;
+        let end = cmp::min(range.end, self.num_rows());
+        if start >= end {
+            return &dest[0..0];
+        }
+        let n_range = end - start;
+        if dest.len() < n_range {
+            return &dest[0..0];
+        }
+        dest[0..n_range].copy_from_slice(&self.row_ids[start..end]);
+        &dest[0..n_range]
+    }
+}
+
+impl fmt::Display for OrderedRelation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Relation::fmt(self, f)
+    }
+}
+
+/// 並べ替えのキーを表します。NULLは昇順では最後、降順では最初になります。
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    /// 対象カラム名
+    name: &'static str,
+    /// 昇順かどうか
+    ascending: bool,
+}
+
+impl SortKey {
+    pub fn asc(name: &'static str) -> SortKey {
+        SortKey {
+            name,
+            ascending: true,
+        }
+    }
+    pub fn desc(name: &'static str) -> SortKey {
+        SortKey {
+            name,
+            ascending: false,
+        }
+    }
+}
+
+///
+/// リレーションを指定したカラムの順に並べ替えます。
+///
+/// 辞書が値の順に並んでいるカラム([`Table::sort_keys`])はキーIDだけで比較します。
+///
+pub trait OrderBy: Relation {
+    fn order_by(&self, sort_keys: &[SortKey]) -> OrderedRelation<'_>;
+}
+
+impl<T> OrderBy for T
+where
+    T: Relation,
+{
+    fn order_by(&self, sort_keys: &[SortKey]) -> OrderedRelation<'_> {
+        let mut sort_columns: Vec<(&dyn AsColumn, bool)> = Vec::new();
+        // TODO: 不正なカラム名の処置(Optionで返すように変更?)
+        for sort_key in sort_keys {
+            if let Some(col_id) = self.definition().name_to_id(sort_key.name) {
+                sort_columns.push((self.column_at(col_id), sort_key.ascending));
+            }
+        }
+        let n_rows = self.num_rows();
+        let mut row_ids = Vec::with_capacity(n_rows);
+        const STEP: RowId = 64;
+        let mut start = 0;
+        let mut buffer = [0; STEP];
+        while start < n_rows {
+            let step = cmp::min(STEP, n_rows - start);
+            let end = start + step;
+            row_ids.extend_from_slice(self.scan_row_ids(start..end, &mut buffer));
+            start += step;
+        }
+        row_ids.sort_by(|&lhs, &rhs| {
+            for (column, ascending) in &sort_columns {
+                let ordering = if column.is_sorted() {
+                    match (column.id_at(lhs), column.id_at(rhs)) {
+                        (Some(lhs), Some(rhs)) => lhs.cmp(&rhs),
+                        (lhs, rhs) => lhs.is_none().cmp(&rhs.is_none()),
+                    }
+                } else {
+                    // Value::NullはVarcharやIntegerより大きい
+                    column.key_at(lhs).cmp(&column.key_at(rhs))
+                };
+                let ordering = if *ascending {
+                    ordering
+                } else {
+                    ordering.reverse()
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        OrderedRelation {
+            relation: self,
+            row_ids,
+        }
+    }
+}
+
+/// Count集計用の構造体です。
+#[derive(Debug, Clone, Copy)]
+pub struct Count {
+    result: i32,
+}
+
+impl Count {
+    fn calculate<T: AsValue>(&mut self, value: T, n: i32) {
+        if let Value::Null(_) = value.as_value_ref() {
+            return;
+        }
+        self.result += n;
+    }
+    fn get_result(&self) -> i32 {
+        self.result
+    }
+}
+
+/// Average集計用の構造体です。
+#[derive(Debug, Clone, Copy)]
+pub struct Average {
+    count: i32,
+    sum: i32,
+}
+
+impl Average {
+    fn calculate<T: AsValue>(&mut self, value: T, n: i32) {
+        if let Value::Integer(val) = value.as_value_ref() {
+            self.sum += val * n;
+            self.count += n;
+        }
+    }
+    fn get_result(&self) -> i32 {
+        if self.count == 0 {
+            return 0;
+        }
+        self.sum / self.count
+    }
+}
+
+/// 集計関数をまとめたEnumです。
+#[derive(Debug, Clone, Copy)]
+pub enum AggFunc {
+    /// COUNT
+    Count(Count),
+    /// AVG
+    Average(Average),
+}
+
+impl AggFunc {
+    /// 同じ値が`n`行続いたものとして集計します。
+    fn calculate<T: AsValue>(&mut self, value: T, n: i32) {
+        match self {
+            AggFunc::Count(func) => func.calculate(value, n),
+            AggFunc::Average(func) => func.calculate(value, n),
+        }
+    }
+    fn get_result(&self) -> i32 {
+        match self {
+            AggFunc::Count(func) => func.get_result(),
+            AggFunc::Average(func) => func.get_result(),
+        }
+    }
+}
+
+/// 集計パラメーターを表します。
+#[derive(Debug, Clone)]
+pub struct Agg {
+    /// 対象カラム名
+    name: &'static str,
+    /// 集計関数
+    func: AggFunc,
+}
+
+impl Agg {
+    pub fn count(name: &'static str) -> Agg {
+        Agg {
+            name,
+            func: AggFunc::Count(Count { result: 0 }),
+        }
+    }
+    pub fn average(name: &'static str) -> Agg {
+        Agg {
+            name,
+            func: AggFunc::Average(Average { sum: 0, count: 0 }),
+        }
+    }
+}
+
+///
+/// リレーションに対して集計集約を行います。
+///
+pub trait GroupBy: Relation {
+    fn group_by(&self, group_col_names: &[&str], agg_params: &[Agg]) -> Table;
+}
+
+impl<T> GroupBy for T
+where
+    T: Relation,
+{
+    fn group_by(&self, group_col_names: &[&str], agg_params: &[Agg]) -> Table {
+        // 集約列IDリスト
+        let mut group_col_ids = Vec::new();
+        // TODO: 不正なカラム名の処置(Optionで返すように変更?)
+        for group_col_name in group_col_names {
+            if let Some(col_id) = self.definition().name_to_id(group_col_name) {
+                group_col_ids.push(col_id);
+            }
+        }
+        // イミュータブルに再束縛
+        let group_col_ids = group_col_ids;
+        // 集計列IDリスト
+        let mut agg_col_ids = Vec::new();
+        // 集計関数リスト
+        let mut master_agg_funcs = Vec::new();
+        // TODO: 不正なカラム名の処置(Optionで返すように変更?)
+        for agg_param in agg_params {
+            if let Some(col_id) = self.definition().name_to_id(agg_param.name) {
+                agg_col_ids.push(col_id);
+                master_agg_funcs.push(agg_param.func);
+            }
+        }
+        let agg_col_ids = agg_col_ids;
+        let master_agg_funcs = master_agg_funcs;
+        let n_group_cols = group_col_ids.len();
+        let n_agg_cols = agg_col_ids.len();
+        let mut group_columns: Vec<&dyn AsColumn> = Vec::new();
+        for col_id in &group_col_ids {
+            group_columns.push(self.column_at(*col_id));
+        }
+        let group_columns = group_columns;
+        let mut agg_columns: Vec<&dyn AsColumn> = Vec::new();
+        for col_id in &agg_col_ids {
+            agg_columns.push(self.column_at(*col_id));
+        }
+        let agg_columns = agg_columns;
+        let mut group_map = HashMap::new();
+        // row_idから同じ値がn行続くものとして集計する
+        let mut calculate = |row_id: RowId, n: usize| {
+            let mut group_key_ids = Vec::with_capacity(group_columns.len());
+            for column in &group_columns {
+                group_key_ids.push(column.id_at(row_id));
+            }
+            let group_key_ids = group_key_ids;
+            let grouped_values = group_map
+                .entry(group_key_ids)
+                .or_insert(master_agg_funcs.clone());
+            for (func, column) in grouped_values.iter_mut().zip(&agg_columns) {
+                func.calculate(column.key_at(row_id), n as i32);
+            }
+        };
+        let n_rows = self.num_rows();
+        let run_length = group_columns
+            .iter()
+            .chain(&agg_columns)
+            .all(|column| column.encoding() == Encoding::RunLength);
+        if run_length {
+            // 全カラムのランに収まる行は同じ値なので、ランごとにまとめて集計する
+            self.scan_row_ranges(0..n_rows, &mut |rows| {
+                let mut row_id = rows.start;
+                while row_id < rows.end {
+                    let end = group_columns
+                        .iter()
+                        .chain(&agg_columns)
+                        .map(|column| column.run_end(row_id))
+                        .fold(rows.end, cmp::min);
+                    calculate(row_id, end - row_id);
+                    row_id = end;
+                }
+            });
+        } else {
+            const STEP: RowId = 64;
+            let mut start = 0;
+            let mut buffer = [0; STEP];
+            while start < n_rows {
+                let step = cmp::min(STEP, n_rows - start);
+                let end = start + step;
+                let row_ids = self.scan_row_ids(start..end, &mut buffer);
+                for &row_id in row_ids {
+                    calculate(row_id, 1);
+                }
+                start += step;
+            }
+        }
+        let mut attributes = Vec::new();
+        for col_id in group_col_ids {
+            // 集約結果には制約を引き継がない
+            let attribute = &self.definition()[col_id];
+            attributes.push(Attribute::create(attribute.name(), attribute.kind()));
+        }
+        for agg_func in &master_agg_funcs {
+            let name = match agg_func {
+                AggFunc::Count(_) => "count",
+                AggFunc::Average(_) => "average",
+            };
+            attributes.push(Attribute::create(name, TypeKind::Integer));
+        }
+        let attributes = attributes;
+        let mut table = Table::create(self.definition().name(), &attributes);
+        assert!(table.num_columns() == n_group_cols + n_agg_cols);
+        let mut num_rows = 0;
+        for (group_key_ids, grouped_values) in group_map.iter() {
+            for group_i in 0..n_group_cols {
+                let col_id = group_i;
+                let datum = match group_key_ids[group_i] {
+                    Some(key_id) => group_columns[group_i].key_of(key_id),
+                    None => NULL.into(),
+                };
+                table.columns[col_id].append(&datum);
+            }
+            for (agg_i, grouped_value) in grouped_values.iter().enumerate() {
+                let col_id = agg_i + n_group_cols;
+                let result = grouped_value.get_result();
+                table.columns[col_id].append(&result);
+            }
+            num_rows += 1;
+        }
+        table.num_rows = num_rows;
+        table.tombstones = Tombstones::new(num_rows);
+        table
+    }
+}
+
+/// CRC-32(IEEE)の表です。
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// バイト列のCRC-32を計算します。
+fn crc32(bytes: &[u8]) -> u32 {
+    crc32_of(&[bytes])
+}
+
+/// 連結したバイト列のCRC-32を計算します。
+fn crc32_of(parts: &[&[u8]]) -> u32 {
+    let bytes = parts.iter().flat_map(|part| part.iter());
+    !bytes.fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+///
+/// 保存したファイルを区切ったブロックです。チェックサムが一致しない場所を示すのに使います。
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Block {
+    /// ファイルの先頭(マジックナンバー・バージョン・行数など)
+    Header,
+    /// 削除済みの行のビットマップ
+    Deleted,
+    /// テーブルの定義
+    Definition,
+    /// カラムの辞書の値
+    Dictionary(String),
+    /// カラムの値の順に並べたKeyId
+    SortedKeyIds(String),
+    /// カラムのNULLでない行のビットマップ
+    Validity(String),
+    /// カラムの各行のキーID
+    KeyIds(String),
+    /// ログの記録(ファイルの先頭からの位置)
+    LogRecord(u64),
+}
+
+impl Block {
+    /// ファイルに書くブロックの種類の番号です。
+    fn tag(&self) -> u32 {
+        match self {
+            Block::Header => 0,
+            Block::Deleted => 1,
+            Block::Definition => 2,
+            Block::Dictionary(_) => 3,
+            Block::SortedKeyIds(_) => 4,
+            Block::Validity(_) => 5,
+            Block::KeyIds(_) => 6,
+            Block::LogRecord(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Block::Header => write!(f, "ファイルの先頭"),
+            Block::Deleted => write!(f, "削除済みの行のビットマップ"),
+            Block::Definition => write!(f, "テーブルの定義"),
+            Block::Dictionary(column) => write!(f, "カラム{}の辞書", column),
+            Block::SortedKeyIds(column) => write!(f, "カラム{}の値の順のKeyId", column),
+            Block::Validity(column) => write!(f, "カラム{}のNULLのビットマップ", column),
+            Block::KeyIds(column) => write!(f, "カラム{}のキーID", column),
+            Block::LogRecord(offset) => write!(f, "ログの{}バイト目からの記録", offset),
+        }
+    }
+}
+
+/// ファイルの先頭に置くマジックナンバー
+const FILE_MAGIC: &[u8; 8] = b"KAWAIIDB";
+/// ファイル形式のバージョン
+const FILE_VERSION: u32 = 3;
+/// ブロックの先頭(長さ u64, CRC-32 u32, 種類 u32)の長さ
+const BLOCK_HEADER_LEN: usize = 16;
+/// 読み込む条件式の入れ子の深さの上限
+const MAX_PREDICATE_DEPTH: usize = 256;
+
+///
+/// バイナリ形式で書き出すためのバッファです。整数はリトルエンディアンで書きます。
+///
+/// 配列は8バイト境界に揃えて書くので、mmapしたファイルからそのまま参照できます。
+///
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+    fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+    fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+    fn put_usize(&mut self, value: usize) {
+        self.put_u64(value as u64);
+    }
+    fn put_i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+    fn put_str(&mut self, value: &str) {
+        self.put_usize(value.len());
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+    /// 8バイト境界まで0で埋めます。
+    fn align(&mut self) {
+        let padding = (8 - self.buf.len() % 8) % 8;
+        self.buf.resize(self.buf.len() + padding, 0);
+    }
+    /// 8バイト境界に揃えて64ビット整数の配列を書きます。
+    fn put_words<I: IntoIterator<Item = u64>>(&mut self, words: I) {
+        self.align();
+        for word in words {
+            self.put_u64(word);
+        }
+    }
+    /// ビットマップを64ビットずつ書きます。長さを超える部分のビットは0にします。
+    fn put_bitmap(&mut self, bitmap: &BitSlice) {
+        self.put_words(bitmap.chunks(64).map(|chunk| chunk.load_le::<u64>()));
+    }
+    ///
+    /// ファイルの先頭(マジックナンバー, バージョン u32, CRC-32 u32, 続く64ビット整数の項目)を書きます。
+    /// CRCはCRC自身を除く先頭全体から計算します。
+    ///
+    fn put_header(&mut self, magic: &[u8; 8], version: u32, fields: &[u64]) {
+        let fields: Vec<u8> = fields
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect();
+        self.buf.extend_from_slice(magic);
+        self.put_u32(version);
+        self.put_u32(crc32_of(&[magic, &version.to_le_bytes(), &fields]));
+        self.buf.extend_from_slice(&fields);
+    }
+    ///
+    /// 8バイト境界からブロックの先頭(長さ u64, CRC-32 u32, 種類 u32)と`f`で書いた中身を書きます。
+    /// CRCはCRC自身を除くブロック全体から計算します。
+    ///
+    fn put_block<F: FnOnce(&mut Self)>(&mut self, block: &Block, f: F) {
+        self.align();
+        let start = self.buf.len();
+        self.put_u64(0);
+        self.put_u32(0);
+        self.put_u32(block.tag());
+        f(self);
+        self.align();
+        let len = (self.buf.len() - start - BLOCK_HEADER_LEN) as u64;
+        self.buf[start..start + 8].copy_from_slice(&len.to_le_bytes());
+        let crc = crc32_of(&[&self.buf[start..start + 8], &self.buf[start + 12..]]);
+        self.buf[start + 8..start + 12].copy_from_slice(&crc.to_le_bytes());
+    }
+    fn put_kind(&mut self, kind: TypeKind) {
+        self.put_u8(match kind {
+            TypeKind::Varchar => 0,
+            TypeKind::Integer => 1,
+        });
+    }
+    fn put_value(&mut self, value: &OwnedValue) {
+        match value {
+            OwnedValue::Null(_) => self.put_u8(0),
+            OwnedValue::Integer(value) => {
+                self.put_u8(1);
+                self.put_i32(*value);
+            }
+            OwnedValue::Varchar(value) => {
+                self.put_u8(2);
+                self.put_str(value);
+            }
+        }
+    }
+    fn put_predicate(&mut self, predicate: &Predicate) {
+        match predicate {
+            Predicate::Compare(col_name, op, value) => {
+                self.put_u8(0);
+                self.put_str(col_name);
+                self.put_u8(match op {
+                    CompareOp::Equal => 0,
+                    CompareOp::NotEqual => 1,
+                    CompareOp::LessThan => 2,
+                    CompareOp::LessEqual => 3,
+                    CompareOp::GreaterThan => 4,
+                    CompareOp::GreaterEqual => 5,
+                });
+                self.put_value(value);
+            }
+            Predicate::In(col_name, values) => {
+                self.put_u8(1);
+                self.put_str(col_name);
+                self.put_usize(values.len());
+                for value in values {
+                    self.put_value(value);
+                }
+            }
+            Predicate::IsNull(col_name) => {
+                self.put_u8(2);
+                self.put_str(col_name);
+            }
+            Predicate::IsNotNull(col_name) => {
+                self.put_u8(3);
+                self.put_str(col_name);
+            }
+            Predicate::Not(p) => {
+                self.put_u8(4);
+                self.put_predicate(p);
+            }
+            Predicate::And(lhs, rhs) => {
+                self.put_u8(5);
+                self.put_predicate(lhs);
+                self.put_predicate(rhs);
+            }
+            Predicate::Or(lhs, rhs) => {
+                self.put_u8(6);
+                self.put_predicate(lhs);
+                self.put_predicate(rhs);
+            }
+        }
+    }
+}
+
+///
+/// [`Encoder`]で書いたバイト列を読むカーソルです。
+/// 壊れたデータを読んでもパニックせず、[`Error::InvalidFormat`]を返します。
+///
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf, pos: 0 }
+    }
+    fn invalid(message: &str) -> Error {
+        Error::InvalidFormat(message.to_string())
+    }
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+    fn get_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(Self::invalid("データが途中で切れています"));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn get_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.get_bytes(N)?);
+        Ok(array)
+    }
+    fn get_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.get_bytes(1)?[0])
+    }
+    fn get_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.get_array()?))
+    }
+    fn get_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.get_array()?))
+    }
+    fn get_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.get_array()?))
+    }
+    fn get_usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.get_u64()?).map_err(|_| Self::invalid("値が大きすぎます"))
+    }
+    /// 要素数を読みます。残りのデータに収まらない要素数はエラーにします。
+    fn get_len(&mut self, elem_size: usize) -> Result<usize, Error> {
+        let len = self.get_usize()?;
+        match len.checked_mul(elem_size) {
+            Some(size) if size <= self.remaining() => Ok(len),
+            _ => Err(Self::invalid("要素数が大きすぎます")),
+        }
+    }
+    fn get_str(&mut self) -> Result<&'a str, Error> {
+        let len = self.get_len(1)?;
+        std::str::from_utf8(self.get_bytes(len)?)
+            .map_err(|_| Self::invalid("文字列がUTF-8ではありません"))
+    }
+    fn align(&mut self) -> Result<(), Error> {
+        let padding = (8 - self.pos % 8) % 8;
+        self.get_bytes(padding).map(|_| ())
+    }
+    /// 8バイト境界に揃えて、64ビット整数`len`個分のバイト列を読みます。
+    fn get_word_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        self.align()?;
+        let size = len
+            .checked_mul(8)
+            .ok_or_else(|| Self::invalid("要素数が大きすぎます"))?;
+        self.get_bytes(size)
+    }
+    /// 8バイト境界に揃えて、64ビット整数`len`個分のバイト列の位置を返します。
+    fn get_word_range(&mut self, len: usize) -> Result<Range<usize>, Error> {
+        self.align()?;
+        let start = self.pos;
+        self.get_word_bytes(len)?;
+        Ok(start..self.pos)
+    }
+    fn get_words(&mut self, len: usize) -> Result<Vec<u64>, Error> {
+        let bytes = self.get_word_bytes(len)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()))
+            .collect())
+    }
+    fn get_bitmap(&mut self, len: usize) -> Result<BitMap, Error> {
+        let words = self.get_words(len.div_ceil(64))?;
+        let mut bitmap = BitMap::from_vec(words.iter().map(|&word| word as usize).collect());
+        bitmap.truncate(len);
+        Ok(bitmap)
+    }
+    ///
+    /// [`Encoder::put_header`]で書いたファイルの先頭を読み、64ビット整数の項目を返します。
+    ///
+    fn get_header<const N: usize>(
+        &mut self,
+        magic: &[u8; 8],
+        version: u32,
+        file_name: &str,
+    ) -> Result<[u64; N], Error> {
+        if self.get_bytes(magic.len()) != Ok(magic) {
+            let message = format!("{}のファイルではありません", file_name);
+            return Err(Error::InvalidFormat(message));
+        }
+        let actual_version = self.get_u32()?;
+        if actual_version != version {
+            let message = format!("バージョン{}には対応していません", actual_version);
+            return Err(Error::InvalidFormat(message));
+        }
+        let crc = self.get_u32()?;
+        let fields = self.get_bytes(N * 8)?;
+        if crc32_of(&[magic, &version.to_le_bytes(), fields]) != crc {
+            return Err(Error::Corrupted(Block::Header));
+        }
+        let mut values = [0; N];
+        for (value, bytes) in values.iter_mut().zip(fields.chunks_exact(8)) {
+            *value = u64::from_le_bytes(bytes.try_into().unwrap_or_default());
+        }
+        Ok(values)
+    }
+    ///
+    /// [`Encoder::put_block`]で書いたブロックのCRCを検査し、中身を`f`で読みます。
+    /// 途中で切れていたりCRCが一致しなかったりする場合は[`Error::Corrupted`]を返します。
+    ///
+    fn get_block<T, F>(&mut self, block: Block, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        self.align()?;
+        let start = self.pos;
+        if self.remaining() < BLOCK_HEADER_LEN {
+            return Err(Error::Corrupted(block));
+        }
+        let len = self.get_u64()?;
+        let crc = self.get_u32()?;
+        let tag = self.get_u32()?;
+        let end = match usize::try_from(len) {
+            Ok(len) if len <= self.remaining() => self.pos + len,
+            _ => return Err(Error::Corrupted(block)),
+        };
+        if crc32_of(&[&self.buf[start..start + 8], &self.buf[start + 12..end]]) != crc {
+            return Err(Error::Corrupted(block));
+        }
+        if tag != block.tag() {
+            return Err(Self::invalid("ブロックの種類が正しくありません"));
+        }
+        let value = f(self)?;
+        self.align()?;
+        if self.pos != end {
+            return Err(Self::invalid("ブロックの長さが正しくありません"));
+        }
+        Ok(value)
+    }
+    ///
+    /// ブロックの中身を読まずにチェックサムだけを検査して、ブロックの後ろに進みます。
+    /// チェックサムが一致すれば`true`を返します。
+    /// ブロックの長さが範囲外で、次のブロックの位置がわからない場合は[`Error::Corrupted`]を返します。
+    ///
+    fn check_block(&mut self, block: &Block) -> Result<bool, Error> {
+        self.align()?;
+        let start = self.pos;
+        if self.remaining() < BLOCK_HEADER_LEN {
+            return Err(Error::Corrupted(block.clone()));
+        }
+        let len = self.get_u64()?;
+        let crc = self.get_u32()?;
+        let tag = self.get_u32()?;
+        let end = match usize::try_from(len) {
+            Ok(len) if len <= self.remaining() => self.pos + len,
+            _ => return Err(Error::Corrupted(block.clone())),
+        };
+        self.pos = end;
+        if crc32_of(&[&self.buf[start..start + 8], &self.buf[start + 12..end]]) != crc {
+            return Ok(false);
+        }
+        if tag != block.tag() {
+            return Err(Self::invalid("ブロックの種類が正しくありません"));
+        }
+        Ok(true)
+    }
+    fn get_kind(&mut self) -> Result<TypeKind, Error> {
+        match self.get_u8()? {
+            0 => Ok(TypeKind::Varchar),
+            1 => Ok(TypeKind::Integer),
+            _ => Err(Self::invalid("不明な型です")),
+        }
+    }
+    fn get_value(&mut self) -> Result<OwnedValue, Error> {
+        match self.get_u8()? {
+            0 => Ok(NULL.into()),
+            1 => Ok(self.get_i32()?.into()),
+            2 => Ok(self.get_str()?.into()),
+            _ => Err(Self::invalid("不明な値の種類です")),
+        }
+    }
+    fn get_predicate(&mut self, depth: usize) -> Result<Predicate, Error> {
+        if depth > MAX_PREDICATE_DEPTH {
+            return Err(Self::invalid("条件式の入れ子が深すぎます"));
+        }
+        let tag = self.get_u8()?;
+        let predicate = match tag {
+            0 => {
+                let col_name = self.get_str()?.to_string();
+                let op = match self.get_u8()? {
+                    0 => CompareOp::Equal,
+                    1 => CompareOp::NotEqual,
+                    2 => CompareOp::LessThan,
+                    3 => CompareOp::LessEqual,
+                    4 => CompareOp::GreaterThan,
+                    5 => CompareOp::GreaterEqual,
+                    _ => return Err(Self::invalid("不明な比較演算子です")),
+                };
+                Predicate::Compare(col_name, op, self.get_value()?)
+            }
+            1 => {
+                let col_name = self.get_str()?.to_string();
+                let len = self.get_len(1)?;
+                let values = (0..len)
+                    .map(|_| self.get_value())
+                    .collect::<Result<_, _>>()?;
+                Predicate::In(col_name, values)
+            }
+            2 => Predicate::IsNull(self.get_str()?.to_string()),
+            3 => Predicate::IsNotNull(self.get_str()?.to_string()),
+            4 => !self.get_predicate(depth + 1)?,
+            5 | 6 => {
+                let lhs = self.get_predicate(depth + 1)?;
+                let rhs = self.get_predicate(depth + 1)?;
+                if tag == 5 {
+                    lhs.and(rhs)
+                } else {
+                    lhs.or(rhs)
+                }
+            }
+            _ => return Err(Self::invalid("不明な条件式です")),
+        };
+        Ok(predicate)
+    }
+}
+
+///
+/// ファイルに書き出せる辞書の値の型です。
+///
+trait StoredKey: Ord + Clone + Default + Sized {
+    /// 値の型
+    const KIND: TypeKind;
+    /// KeyIdの順に並んだ値を書きます。
+    fn put_keys<'k, I>(enc: &mut Encoder, keys: I)
+    where
+        I: ExactSizeIterator<Item = &'k Self>,
+        Self: 'k;
+    /// KeyIdの順に並んだ値を`len`個読みます。
+    fn get_keys(dec: &mut Decoder<'_>, len: usize) -> Result<Vec<Self>, Error>;
+}
+
+impl StoredKey for i32 {
+    const KIND: TypeKind = TypeKind::Integer;
+    fn put_keys<'k, I>(enc: &mut Encoder, keys: I)
+    where
+        I: ExactSizeIterator<Item = &'k Self>,
+    {
+        enc.align();
+        for key in keys {
+            enc.put_i32(*key);
+        }
+        enc.align();
+    }
+    fn get_keys(dec: &mut Decoder<'_>, len: usize) -> Result<Vec<Self>, Error> {
+        dec.align()?;
+        let size = len
+            .checked_mul(4)
+            .ok_or_else(|| Decoder::invalid("要素数が大きすぎます"))?;
+        let bytes = dec.get_bytes(size)?;
+        dec.align()?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| i32::from_le_bytes(chunk.try_into().unwrap_or_default()))
+            .collect())
+    }
+}
+
+impl StoredKey for String {
+    const KIND: TypeKind = TypeKind::Varchar;
+    /// 各値の開始位置(値の数+1個)と、値を連結したバイト列を書きます。
+    fn put_keys<'k, I>(enc: &mut Encoder, keys: I)
+    where
+        I: ExactSizeIterator<Item = &'k Self>,
+    {
+        let keys: Vec<&String> = keys.collect();
+        let mut offset = 0;
+        let mut offsets = vec![0];
+        for key in &keys {
+            offset += key.len() as u64;
+            offsets.push(offset);
+        }
+        enc.put_words(offsets);
+        for key in keys {
+            enc.buf.extend_from_slice(key.as_bytes());
+        }
+        enc.align();
+    }
+    fn get_keys(dec: &mut Decoder<'_>, len: usize) -> Result<Vec<Self>, Error> {
+        let offsets = dec.get_words(len + 1)?;
+        let is_ascending = offsets.windows(2).all(|pair| pair[0] <= pair[1]);
+        if offsets[0] != 0 || !is_ascending {
+            return Err(Decoder::invalid("文字列の位置が正しくありません"));
+        }
+        let size =
+            usize::try_from(offsets[len]).map_err(|_| Decoder::invalid("値が大きすぎます"))?;
+        let bytes = dec.get_bytes(size)?;
+        dec.align()?;
+        offsets
+            .windows(2)
+            .map(|pair| {
+                let key = &bytes[pair[0] as usize..pair[1] as usize];
+                String::from_utf8(key.to_vec())
+                    .map_err(|_| Decoder::invalid("文字列がUTF-8ではありません"))
+            })
+            .collect()
+    }
+}
+
+///
+/// カラムを、辞書(型・格納方式・インデックスの有無・KeyId順の値)、値の順に並べたKeyId、
+/// NULLでない行のビットマップ、キーIDの4つのブロックに分けて書きます。
+///
+fn encode_column<Key: StoredKey>(column: &Column<Key>, name: &str, enc: &mut Encoder) {
+    let num_keys = column.num_keys();
+    enc.put_block(&Block::Dictionary(name.to_string()), |enc| {
+        enc.put_kind(Key::KIND);
+        enc.put_u8(match column.encoding() {
+            Encoding::Packed => 0,
+            Encoding::RunLength => 1,
+        });
+        enc.put_u8(column.index.is_some() as u8);
+        enc.put_usize(num_keys);
+        Key::put_keys(
+            enc,
+            (0..num_keys).map(|key_id| column.dictionary.key_of(key_id)),
+        );
+    });
+    // mmapしたときに値からKeyIdを二分探索で引けるようにする
+    enc.put_block(&Block::SortedKeyIds(name.to_string()), |enc| {
+        let key_to_id = &column.dictionary.key_to_id;
+        enc.put_words(key_to_id.values().map(|&key_id| key_id as u64));
+    });
+    enc.put_block(&Block::Validity(name.to_string()), |enc| {
+        enc.put_bitmap(&column.validity);
+    });
+    enc.put_block(&Block::KeyIds(name.to_string()), |enc| {
+        match &column.key_ids {
+            KeyIds::Packed(array) => {
+                enc.put_u64(array.bit_width as u64);
+                enc.put_words(array.words.iter().copied());
+            }
+            KeyIds::RunLength(array) => {
+                enc.put_usize(array.num_runs());
+                enc.put_words(array.ends.iter().map(|&end| end as u64));
+                let key_ids = array.values.iter();
+                enc.put_words(
+                    key_ids.map(|key_id| key_id.map_or(u64::MAX, |key_id| key_id as u64)),
+                );
+            }
+        }
+    });
+}
+
+/// 辞書のブロックの先頭に書いた、カラムの型と格納方式、インデックスの有無を読みます。
+fn decode_column_header(dec: &mut Decoder<'_>, kind: TypeKind) -> Result<(Encoding, bool), Error> {
+    if dec.get_kind()? != kind {
+        return Err(Decoder::invalid("カラムの型が定義と一致しません"));
+    }
+    let encoding = match dec.get_u8()? {
+        0 => Encoding::Packed,
+        1 => Encoding::RunLength,
+        _ => return Err(Decoder::invalid("不明な格納方式です")),
+    };
+    let has_index = dec.get_u8()? != 0;
+    Ok((encoding, has_index))
+}
+
+///
+/// [`encode_column`]で書いた`num_rows`行のカラムを読みます。
+///
+fn decode_column<Key: StoredKey>(
+    dec: &mut Decoder<'_>,
+    name: &str,
+    num_rows: RowId,
+) -> Result<Column<Key>, Error> {
+    let (encoding, has_index, dictionary) =
+        dec.get_block(Block::Dictionary(name.to_string()), |dec| {
+            let (encoding, has_index) = decode_column_header(dec, Key::KIND)?;
+            let num_keys = dec.get_len(4)?;
+            let mut dictionary = Dictionary::new();
+            for (key_id, key) in Key::get_keys(dec, num_keys)?.into_iter().enumerate() {
+                if dictionary.insert(key) != key_id {
+                    return Err(Decoder::invalid("辞書の値が重複しています"));
+                }
+            }
+            Ok((encoding, has_index, dictionary))
+        })?;
+    let num_keys = dictionary.num_keys();
+    // 値の順に並べたKeyIdは辞書を作り直せば分かる
+    dec.get_block(Block::SortedKeyIds(name.to_string()), |dec| {
+        dec.get_word_bytes(num_keys).map(|_| ())
+    })?;
+    let validity = dec.get_block(Block::Validity(name.to_string()), |dec| {
+        dec.get_bitmap(num_rows)
+    })?;
+    let key_ids = dec.get_block(Block::KeyIds(name.to_string()), |dec| match encoding {
+        Encoding::Packed => {
+            let bit_width = dec.get_u64()?;
+            if bit_width > 64 {
+                return Err(Decoder::invalid("キーIDのビット幅が正しくありません"));
+            }
+            let bit_width = bit_width as u32;
+            let words = dec.get_words(PackedArray::num_words(num_rows, bit_width))?;
+            Ok(KeyIds::Packed(PackedArray {
+                words,
+                bit_width,
+                len: num_rows,
+            }))
+        }
+        Encoding::RunLength => {
+            let num_runs = dec.get_len(16)?;
+            let ends = dec.get_words(num_runs)?;
+            let values = dec.get_words(num_runs)?;
+            let mut array = RunLengthArray::new();
+            let mut start = 0;
+            for (&end, &key_id) in ends.iter().zip(&values) {
+                let end = usize::try_from(end).unwrap_or(usize::MAX);
+                if end <= start || end > num_rows {
+                    return Err(Decoder::invalid("ランの終端が正しくありません"));
+                }
+                let key_id = (key_id != u64::MAX).then_some(key_id as KeyId);
+                if validity[start..end].any() != key_id.is_some()
+                    || validity[start..end].not_all() == key_id.is_some()
+                {
+                    return Err(Decoder::invalid("ランとNULLのビットマップが一致しません"));
+                }
+                array.ends.push(end);
+                array.values.push(key_id);
+                start = end;
+            }
+            if start != num_rows {
+                return Err(Decoder::invalid("ランの長さが行数と一致しません"));
+            }
+            Ok(KeyIds::RunLength(array))
+        }
+    })?;
+    let mut column = Column {
+        dictionary,
+        key_ids,
+        validity,
+        index: None,
+    };
+    for row_id in 0..num_rows {
+        if column
+            .id_at(row_id)
+            .is_some_and(|key_id| key_id >= num_keys)
+        {
+            return Err(Decoder::invalid("キーIDが辞書の範囲外です"));
+        }
+    }
+    if has_index {
+        column.create_index();
+    }
+    Ok(column)
+}
+
+impl TableColumn {
+    fn encode(&self, name: &str, enc: &mut Encoder) {
+        match self {
+            TableColumn::Varchar(column) => encode_column(column, name, enc),
+            TableColumn::Integer(column) => encode_column(column, name, enc),
+        }
+    }
+    fn decode(
+        dec: &mut Decoder<'_>,
+        attribute: &Attribute,
+        num_rows: RowId,
+    ) -> Result<TableColumn, Error> {
+        let name = &attribute.name;
+        match attribute.kind {
+            TypeKind::Varchar => Ok(TableColumn::Varchar(decode_column(dec, name, num_rows)?)),
+            TypeKind::Integer => Ok(TableColumn::Integer(decode_column(dec, name, num_rows)?)),
+        }
+    }
+}
+
+impl Definition {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.name);
+        enc.put_usize(self.attributes.len());
+        for attribute in &self.attributes {
+            enc.put_str(&attribute.name);
+            enc.put_kind(attribute.kind);
+            enc.put_u8(attribute.not_null as u8);
+            match &attribute.default_value {
+                Some(value) => {
+                    enc.put_u8(1);
+                    enc.put_value(value);
+                }
+                None => enc.put_u8(0),
+            }
+            match &attribute.check {
+                Some(check) => {
+                    enc.put_u8(1);
+                    enc.put_predicate(check);
+                }
+                None => enc.put_u8(0),
+            }
+        }
+        enc.put_usize(self.unique_keys.len());
+        for unique_key in &self.unique_keys {
+            enc.put_u8(unique_key.primary as u8);
+            enc.put_usize(unique_key.col_ids.len());
+            enc.put_words(unique_key.col_ids.iter().map(|&col_id| col_id as u64));
+        }
+        enc.put_usize(self.foreign_keys.len());
+        for foreign_key in &self.foreign_keys {
+            enc.put_usize(foreign_key.col_ids.len());
+            enc.put_words(foreign_key.col_ids.iter().map(|&col_id| col_id as u64));
+            enc.put_str(&foreign_key.ref_table);
+            for col_name in &foreign_key.ref_col_names {
+                enc.put_str(col_name);
+            }
+            for action in [foreign_key.on_delete, foreign_key.on_update] {
+                enc.put_u8(match action {
+                    ReferentialAction::Restrict => 0,
+                    ReferentialAction::Cascade => 1,
+                    ReferentialAction::SetNull => 2,
+                });
+            }
+        }
+        enc.align();
+    }
+    fn decode(dec: &mut Decoder<'_>) -> Result<Definition, Error> {
+        let name = dec.get_str()?;
+        let num_columns = dec.get_len(1)?;
+        let mut attributes = Vec::new();
+        for _ in 0..num_columns {
+            let mut attribute = Attribute::create(dec.get_str()?, dec.get_kind()?);
+            attribute.not_null = dec.get_u8()? != 0;
+            if dec.get_u8()? != 0 {
+                attribute.default_value = Some(dec.get_value()?);
+            }
+            if dec.get_u8()? != 0 {
+                attribute.check = Some(dec.get_predicate(0)?);
+            }
+            attributes.push(attribute);
+        }
+        let mut definition = Definition::create(name, &attributes);
+        let get_col_ids = |dec: &mut Decoder<'_>, len: usize| -> Result<Vec<ColumnId>, Error> {
+            let col_ids = dec.get_words(len)?;
+            col_ids
+                .into_iter()
+                .map(|col_id| match usize::try_from(col_id) {
+                    Ok(col_id) if col_id < num_columns => Ok(col_id),
+                    _ => Err(Decoder::invalid("カラム番号が範囲外です")),
+                })
+                .collect()
+        };
+        let num_unique_keys = dec.get_len(1)?;
+        for _ in 0..num_unique_keys {
+            let primary = dec.get_u8()? != 0;
+            let len = dec.get_len(8)?;
+            let col_ids = get_col_ids(dec, len)?;
+            definition.unique_keys.push(UniqueKey { col_ids, primary });
+        }
+        let num_foreign_keys = dec.get_len(1)?;
+        for _ in 0..num_foreign_keys {
+            let len = dec.get_len(8)?;
+            let col_ids = get_col_ids(dec, len)?;
+            let ref_table = dec.get_str()?.to_string();
+            let ref_col_names = (0..len)
+                .map(|_| dec.get_str().map(str::to_string))
+                .collect::<Result<_, _>>()?;
+            let mut get_action = || match dec.get_u8()? {
+                0 => Ok(ReferentialAction::Restrict),
+                1 => Ok(ReferentialAction::Cascade),
+                2 => Ok(ReferentialAction::SetNull),
+                _ => Err(Decoder::invalid("不明な参照動作です")),
+            };
+            let on_delete = get_action()?;
+            let on_update = get_action()?;
+            definition.foreign_keys.push(ForeignKey {
+                col_ids,
+                ref_table,
+                ref_col_names,
+                on_delete,
+                on_update,
+            });
+        }
+        dec.align()?;
+        Ok(definition)
+    }
+}
+
+impl Table {
+    /// ファイルの先頭を読み、削除済みを含む行数を返します。
+    fn get_header(dec: &mut Decoder<'_>) -> Result<RowId, Error> {
+        let [num_rows] = dec.get_header(FILE_MAGIC, FILE_VERSION, "テーブル")?;
+        usize::try_from(num_rows).map_err(|_| Decoder::invalid("値が大きすぎます"))
+    }
+    ///
+    /// テーブルをファイルに書き出します。
+    /// 書き終えるまでは一時ファイルに書くので、途中で失敗しても元のファイルは壊れません。
+    ///
+    /// ファイル形式は[`Table::to_bytes`]を参照してください。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_name", TypeKind::Varchar),
+    ///         ("kubun_id", TypeKind::Integer)
+    ///     ],
+    /// );
+    /// shohin.insert(values!("りんご", 1)).insert(values!("わかめ", NULL));
+    /// let path = std::env::temp_dir().join("kawaii_doctest_shohin.kwi");
+    /// shohin.save(&path).unwrap();
+    /// assert_eq!(Table::load(&path).unwrap(), shohin);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+    ///
+    /// [`Table::save`]で書き出したファイルからテーブルを読み込みます。
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Table, Error> {
+        Table::from_bytes(&fs::read(path)?)
+    }
+    ///
+    /// テーブルをバイト列にします。整数はリトルエンディアンで、配列は8バイト境界に揃えます。
+    ///
+    /// 先頭の後ろは、長さ u64, CRC-32 u32, 種類 u32 で始まるブロックに分かれていて、
+    /// 読み込むときにブロックごとにCRCを検査します。
+    ///
+    /// | 内容 | 形式 |
+    /// |------|------|
+    /// | 先頭 | マジックナンバー`KAWAIIDB`, バージョン u32, CRC-32 u32, 削除済みを含む行数 u64 |
+    /// | 削除済みの行のビットマップ | u64の配列 |
+    /// | 定義 | 名前、属性、一意性制約、外部キー制約 |
+    /// | カラムごとに | 辞書(型、格納方式、KeyId順の値)、値の順のKeyId、NULLでない行のビットマップ、キーID |
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.put_header(FILE_MAGIC, FILE_VERSION, &[self.num_rows as u64]);
+        enc.put_block(&Block::Deleted, |enc| {
+            enc.put_bitmap(self.tombstones.bits())
+        });
+        enc.put_block(&Block::Definition, |enc| self.definition.encode(enc));
+        for (column, attribute) in self.columns.iter().zip(&self.definition.attributes) {
+            column.encode(&attribute.name, &mut enc);
+        }
+        enc.buf
+    }
+    ///
+    /// [`Table::to_bytes`]で作ったバイト列からテーブルを作ります。
+    /// チェックサムが一致しないブロックがある場合は[`Error::Corrupted`]を、
+    /// 形式が正しくない場合は[`Error::InvalidFormat`]を返します。
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Table, Error> {
+        let mut dec = Decoder::new(bytes);
+        let num_rows = Table::get_header(&mut dec)?;
+        let deleted = dec.get_block(Block::Deleted, |dec| dec.get_bitmap(num_rows))?;
+        let definition = dec.get_block(Block::Definition, Definition::decode)?;
+        let mut table = Table::new(definition);
+        for col_id in 0..table.num_columns() {
+            let attribute = &table.definition[col_id];
+            table.columns[col_id] = TableColumn::decode(&mut dec, attribute, num_rows)?;
+        }
+        if dec.remaining() > 0 {
+            return Err(Decoder::invalid("余分なデータがあります"));
+        }
+        table.num_rows = num_rows;
+        table.tombstones = Tombstones::from_bits(deleted);
+        table.rebuild_unique_indexes()?;
+        Ok(table)
+    }
+    ///
+    /// [`Table::save`]で書き出したファイルを、テーブルを組み立てずにブロックごとに検査して、
+    /// チェックサムが一致しないブロックを全て返します(壊れていなければ空)。
+    /// 定義が壊れているか、ブロックの長さが範囲外で次のブロックの位置がわからない場合は、
+    /// そこまでに見つかったブロックを返します。
+    /// ブロックの長さが範囲内で壊れている場合は、後ろのブロックも壊れているように見えることがあります。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create("shohin", attributes![("price", TypeKind::Integer)]);
+    /// shohin.insert(values!(300));
+    /// let path = std::env::temp_dir().join("kawaii_doctest_verify.kwi");
+    /// shohin.save(&path).unwrap();
+    /// assert_eq!(Table::verify(&path).unwrap(), vec![]);
+    ///
+    /// let mut bytes = std::fs::read(&path).unwrap();
+    /// let last = bytes.len() - 1;
+    /// bytes[last] ^= 1;
+    /// std::fs::write(&path, &bytes).unwrap();
+    /// assert_eq!(
+    ///     Table::verify(&path).unwrap(),
+    ///     vec![Block::KeyIds("price".to_string())]
+    /// );
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<Vec<Block>, Error> {
+        let bytes = fs::read(path)?;
+        let mut corrupted = Vec::new();
+        match Table::check_blocks(&bytes, &mut corrupted) {
+            Ok(()) => Ok(corrupted),
+            Err(Error::Corrupted(block)) => {
+                corrupted.push(block);
+                Ok(corrupted)
+            }
+            Err(err) => Err(err),
+        }
+    }
+    ///
+    /// ブロックを順に検査して、チェックサムが一致しないブロックを`corrupted`に追加します。
+    /// 次のブロックの位置やカラム名がわからなくなったら、そのブロックを[`Error::Corrupted`]で返します。
+    ///
+    fn check_blocks(bytes: &[u8], corrupted: &mut Vec<Block>) -> Result<(), Error> {
+        let mut dec = Decoder::new(bytes);
+        // 先頭は長さが決まっているので、壊れていても続きを検査できる
+        match Table::get_header(&mut dec) {
+            Err(Error::Corrupted(block)) => corrupted.push(block),
+            result => {
+                result?;
+            }
+        }
+        if !dec.check_block(&Block::Deleted)? {
+            corrupted.push(Block::Deleted);
+        }
+        let start = dec.pos;
+        if !dec.check_block(&Block::Definition)? {
+            return Err(Error::Corrupted(Block::Definition));
+        }
+        dec.pos = start;
+        let definition = dec.get_block(Block::Definition, Definition::decode)?;
+        for attribute in &definition.attributes {
+            let name = &attribute.name;
+            for block in [
+                Block::Dictionary(name.clone()),
+                Block::SortedKeyIds(name.clone()),
+                Block::Validity(name.clone()),
+                Block::KeyIds(name.clone()),
+            ] {
+                if !dec.check_block(&block)? {
+                    corrupted.push(block);
+                }
+            }
+        }
+        if dec.remaining() > 0 {
+            return Err(Decoder::invalid("余分なデータがあります"));
+        }
+        Ok(())
+    }
+}
+
+/// ログファイルの先頭に置くマジックナンバー
+const LOG_MAGIC: &[u8; 8] = b"KAWAIWAL";
+/// ログファイルの形式のバージョン
+const LOG_VERSION: u32 = 2;
+/// ログファイルのヘッダーの長さ
+const LOG_HEADER_LEN: u64 = 16;
+/// チェックポイントファイルの先頭に置くマジックナンバー
+const CHECKPOINT_MAGIC: &[u8; 8] = b"KAWAIICP";
+/// チェックポイントファイルのヘッダーの長さ(この後ろにテーブルが続く)
+const CHECKPOINT_HEADER_LEN: usize = 24;
+
+///
+/// 先行書き込みログに記録する操作です。
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum LogRecord {
+    /// 行の挿入
+    Insert(Vec<OwnedValue>),
+    /// 条件式による行の更新
+    Update {
+        predicate: Predicate,
+        assignments: Vec<(String, OwnedValue)>,
+    },
+    /// 条件式による行の削除
+    Delete(Predicate),
+}
+
+impl LogRecord {
+    ///
+    /// 操作をテーブルに適用し、挿入・更新・削除した行数を返します。
+    ///
+    pub fn apply(&self, table: &mut Table) -> Result<RowId, Error> {
+        match self {
+            LogRecord::Insert(tuple) => table.try_insert(tuple).map(|_| 1),
+            LogRecord::Update {
+                predicate,
+                assignments,
+            } => {
+                let assignments: Vec<(&str, Value)> = assignments
+                    .iter()
+                    .map(|(col_name, value)| (col_name.as_str(), value.as_value_ref()))
+                    .collect();
+                table.try_update(predicate, &assignments)
+            }
+            LogRecord::Delete(predicate) => Ok(table.delete_where(predicate)),
+        }
+    }
+    fn encode(&self, enc: &mut Encoder) {
+        match self {
+            LogRecord::Insert(tuple) => {
+                enc.put_u8(0);
+                enc.put_usize(tuple.len());
+                for value in tuple {
+                    enc.put_value(value);
+                }
+            }
+            LogRecord::Update {
+                predicate,
+                assignments,
+            } => {
+                enc.put_u8(1);
+                enc.put_predicate(predicate);
+                enc.put_usize(assignments.len());
+                for (col_name, value) in assignments {
+                    enc.put_str(col_name);
+                    enc.put_value(value);
+                }
+            }
+            LogRecord::Delete(predicate) => {
+                enc.put_u8(2);
+                enc.put_predicate(predicate);
+            }
+        }
+    }
+    fn decode(dec: &mut Decoder) -> Result<LogRecord, Error> {
+        match dec.get_u8()? {
+            0 => {
+                let len = dec.get_len(1)?;
+                let tuple = (0..len)
+                    .map(|_| dec.get_value())
+                    .collect::<Result<_, _>>()?;
+                Ok(LogRecord::Insert(tuple))
+            }
+            1 => {
+                let predicate = dec.get_predicate(0)?;
+                let len = dec.get_len(9)?;
+                let assignments = (0..len)
+                    .map(|_| Ok((dec.get_str()?.to_string(), dec.get_value()?)))
+                    .collect::<Result<_, Error>>()?;
+                Ok(LogRecord::Update {
+                    predicate,
+                    assignments,
+                })
+            }
+            2 => Ok(LogRecord::Delete(dec.get_predicate(0)?)),
+            _ => Err(Decoder::invalid("不明なログの種類です")),
+        }
+    }
+}
+
+///
+/// 先行書き込みログをいつディスクに同期(fsync)するかを表します。
+///
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SyncPolicy {
+    /// 記録のたびに同期する。電源が落ちても記録した操作は失われない
+    Always,
+    /// 指定した件数の記録ごとに同期する。電源が落ちると最後に同期した後の操作が失われることがある
+    Every(usize),
+    /// 同期はOSに任せる。プロセスが落ちても失われないが、電源が落ちると失われることがある
+    Never,
+}
+
+///
+/// 先行書き込みログ(WAL)です。操作を適用する前にファイルの末尾に記録します。
+///
+/// ファイルはヘッダー(マジックナンバー`KAWAIWAL`, バージョン u32, CRC-32 u32)の後に、
+/// 記録(長さ u32, CRC-32 u32, ログ番号 u64, 操作)を並べたものです。
+/// 書き込み途中で落ちて最後の記録が欠けたり壊れたりしている場合は、その記録を捨てます。
+///
+pub struct WriteAheadLog {
+    file: fs::File,
+    policy: SyncPolicy,
+    /// 同期していない記録の数
+    unsynced: usize,
+    /// 次に記録する操作のログ番号
+    next_lsn: u64,
+}
+
+impl WriteAheadLog {
+    ///
+    /// 空のログファイルを作ります。既にあるファイルは上書きします。
+    ///
+    pub fn create<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> Result<WriteAheadLog, Error> {
+        let mut file = fs::File::create(path)?;
+        let mut enc = Encoder::default();
+        enc.put_header(LOG_MAGIC, LOG_VERSION, &[]);
+        file.write_all(&enc.buf)?;
+        file.sync_all()?;
+        Ok(WriteAheadLog {
+            file,
+            policy,
+            unsynced: 0,
+            next_lsn: 1,
+        })
+    }
+    ///
+    /// ログファイルを開き、ログと、記録されている操作をログ番号と組にして返します。
+    /// 書きかけの最後の記録はファイルから切り詰めます。
+    /// それより前の記録が壊れている場合は[`Error::Corrupted`]を返します。
+    ///
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        policy: SyncPolicy,
+    ) -> Result<(WriteAheadLog, Vec<(u64, LogRecord)>), Error> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        if bytes.len() < LOG_HEADER_LEN as usize {
+            // ヘッダーを書き終える前に落ちた
+            return Ok((WriteAheadLog::create(path, policy)?, Vec::new()));
+        }
+        let (records, valid_len) = WriteAheadLog::read_records(&bytes)?;
+        let last_lsn = records.last().map_or(0, |&(lsn, _)| lsn);
+        let file = fs::OpenOptions::new().append(true).open(path)?;
+        if valid_len < bytes.len() {
+            file.set_len(valid_len as u64)?;
+            file.sync_all()?;
+        }
+        let log = WriteAheadLog {
+            file,
+            policy,
+            unsynced: 0,
+            next_lsn: last_lsn + 1,
+        };
+        Ok((log, records))
+    }
+    ///
+    /// ログファイルを読み込んで、壊れていないか検査します。
+    /// 書きかけの記録や壊れた記録がある場合は、その記録を[`Error::Corrupted`]で返します。
+    ///
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let bytes = fs::read(path)?;
+        let (_, valid_len) = WriteAheadLog::read_records(&bytes)?;
+        if valid_len < bytes.len() {
+            return Err(Error::Corrupted(Block::LogRecord(valid_len as u64)));
+        }
+        Ok(())
+    }
+    ///
+    /// ログファイルの中身から記録を読み、記録と、書きかけの記録を除いた長さを返します。
+    ///
+    /// CRCが一致しない記録のうち、ファイルの末尾まで届くものは書きかけとみなして捨てます。
+    /// 後ろに続きがある場合は書きかけではないので、壊れているとみなしてエラーを返します。
+    ///
+    fn read_records(bytes: &[u8]) -> Result<(Vec<(u64, LogRecord)>, usize), Error> {
+        let mut dec = Decoder::new(bytes);
+        dec.get_header::<0>(LOG_MAGIC, LOG_VERSION, "ログ")?;
+        let mut records: Vec<(u64, LogRecord)> = Vec::new();
+        while dec.remaining() > 0 {
+            let start = dec.pos;
+            match WriteAheadLog::read_record(&mut dec) {
+                Ok((lsn, record)) => {
+                    if records.last().is_some_and(|&(last_lsn, _)| lsn <= last_lsn) {
+                        return Err(Decoder::invalid("ログ番号が増えていません"));
+                    }
+                    records.push((lsn, record));
+                }
+                Err(Error::Corrupted(_)) if WriteAheadLog::reaches_end(bytes, start) => {
+                    return Ok((records, start));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok((records, bytes.len()))
+    }
+    /// `start`から始まる記録がファイルの末尾まで届くか(書きかけの可能性があるか)を返します。
+    fn reaches_end(bytes: &[u8], start: usize) -> bool {
+        match bytes.get(start..start + 4) {
+            Some(len) => {
+                let len = u32::from_le_bytes(len.try_into().unwrap_or_default()) as usize;
+                start + 8 + len >= bytes.len()
+            }
+            None => true,
+        }
+    }
+    ///
+    /// 記録を1つ読みます。途中で切れているかCRCが一致しなければ[`Error::Corrupted`]を返します。
+    ///
+    fn read_record(dec: &mut Decoder) -> Result<(u64, LogRecord), Error> {
+        let corrupted = Error::Corrupted(Block::LogRecord(dec.pos as u64));
+        if dec.remaining() < 8 {
+            return Err(corrupted);
+        }
+        let len = dec.get_u32()? as usize;
+        let checksum = dec.get_u32()?;
+        let payload = dec.get_bytes(len).map_err(|_| corrupted.clone())?;
+        if crc32(payload) != checksum {
+            return Err(corrupted);
+        }
+        let mut payload = Decoder::new(payload);
+        let lsn = payload.get_u64()?;
+        let record = LogRecord::decode(&mut payload)?;
+        if payload.remaining() > 0 {
+            return Err(Decoder::invalid("余分なデータがあります"));
+        }
+        Ok((lsn, record))
+    }
+    ///
+    /// 操作をログの末尾に記録し、そのログ番号を返します。
+    /// 同期するかどうかは[`SyncPolicy`]に従います。
+    ///
+    pub fn append(&mut self, record: &LogRecord) -> Result<u64, Error> {
+        let lsn = self.next_lsn;
+        let mut payload = Encoder::default();
+        payload.put_u64(lsn);
+        record.encode(&mut payload);
+        let mut enc = Encoder::default();
+        enc.put_u32(payload.buf.len() as u32);
+        enc.put_u32(crc32(&payload.buf));
+        enc.buf.extend_from_slice(&payload.buf);
+        // 1回で書くので、落ちても欠けるのは最後の記録だけ
+        self.file.write_all(&enc.buf)?;
+        self.next_lsn += 1;
+        self.unsynced += 1;
+        let needs_sync = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(n) => self.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if needs_sync {
+            self.sync()?;
+        }
+        Ok(lsn)
+    }
+    ///
+    /// 記録した操作をディスクに同期します。
+    ///
+    pub fn sync(&mut self) -> Result<(), Error> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+    ///
+    /// 記録をすべて捨てます。ログ番号は続きから振ります。
+    ///
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.file.set_len(LOG_HEADER_LEN)?;
+        self.file.sync_all()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+    /// 次に記録する操作のログ番号を返します。
+    pub fn next_lsn(&self) -> u64 {
+        self.next_lsn
+    }
+}
+
+///
+/// 先行書き込みログで更新を永続化するテーブルです。
+///
+/// 更新はログ(`<path>.wal`)に記録してからテーブルに適用し、
+/// [`DurableTable::checkpoint`]でテーブル全体をチェックポイントファイル(`<path>`)に書き出してログを空にします。
+/// 開くときはチェックポイントを読み込み、その後に記録された操作をやり直して落ちる前の状態に戻します。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let path = std::env::temp_dir().join("kawaii_doctest_durable.kwi");
+/// let definition = Definition::create(
+///     "kubun",
+///     attributes![
+///         ("kubun_id", TypeKind::Integer),
+///         ("kubun_name", TypeKind::Varchar)
+///     ],
+/// );
+/// # let _ = std::fs::remove_file(&path);
+/// let mut kubun = DurableTable::create(&path, definition, SyncPolicy::Always).unwrap();
+/// kubun.insert(values!(1, "くだもの")).unwrap();
+/// kubun.insert(values!(2, "野菜")).unwrap();
+/// kubun.delete_where(&Predicate::equal_to("kubun_id", 2)).unwrap();
+/// drop(kubun);
+///
+/// // チェックポイントの後の操作はログからやり直される
+/// let kubun = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+/// assert_eq!(kubun.num_rows(), 1);
+/// # std::fs::remove_file(&path).unwrap();
+/// # std::fs::remove_file(DurableTable::log_path(&path)).unwrap();
+/// ```
+///
+pub struct DurableTable {
+    table: Table,
+    path: std::path::PathBuf,
+    log: WriteAheadLog,
+}
+
+impl DurableTable {
+    ///
+    /// 空のテーブルを作り、チェックポイントファイルと空のログファイルを作ります。
+    /// チェックポイントファイルが既にある場合は[`Error::TableExists`]を返します。
+    ///
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        definition: Definition,
+        policy: SyncPolicy,
+    ) -> Result<DurableTable, Error> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(Error::TableExists(definition.name.clone()));
+        }
+        let table = Table::new(definition);
+        let log = WriteAheadLog::create(DurableTable::log_path(path), policy)?;
+        DurableTable::write_checkpoint(path, &table, 0)?;
+        Ok(DurableTable {
+            table,
+            path: path.to_path_buf(),
+            log,
+        })
+    }
+    ///
+    /// チェックポイントを読み込み、ログに記録された操作をやり直してテーブルを開きます。
+    ///
+    pub fn open<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> Result<DurableTable, Error> {
+        let path = path.as_ref();
+        let (mut table, checkpoint_lsn) = DurableTable::read_checkpoint(path)?;
+        let (mut log, records) = WriteAheadLog::open(DurableTable::log_path(path), policy)?;
+        for (lsn, record) in &records {
+            // チェックポイントの後でログを空にする前に落ちた場合は、適用済みの記録が残っている
+            if *lsn > checkpoint_lsn {
+                // 成功する操作だけを記録しているので、失敗するならログとチェックポイントが食い違っている
+                record.apply(&mut table)?;
+            }
+        }
+        log.next_lsn = cmp::max(log.next_lsn, checkpoint_lsn + 1);
+        Ok(DurableTable {
+            table,
+            path: path.to_path_buf(),
+            log,
+        })
+    }
+    ///
+    /// チェックポイントファイルとログファイルを読み込んで、壊れていないか検査します。
+    ///
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        DurableTable::read_checkpoint(path)?;
+        WriteAheadLog::verify(DurableTable::log_path(path))
+    }
+    /// チェックポイントファイルのパスからログファイルのパスを返します。
+    pub fn log_path<P: AsRef<Path>>(path: P) -> std::path::PathBuf {
+        let mut log_path = path.as_ref().as_os_str().to_owned();
+        log_path.push(".wal");
+        log_path.into()
+    }
+    ///
+    /// チェックポイントファイルを書きます。中身はヘッダー(マジックナンバー`KAWAIICP`,
+    /// バージョン u32, CRC-32 u32, 反映済みのログ番号 u64)の後に[`Table::to_bytes`]が続きます。
+    ///
+    fn write_checkpoint(path: &Path, table: &Table, lsn: u64) -> Result<(), Error> {
+        let mut enc = Encoder::default();
+        enc.put_header(CHECKPOINT_MAGIC, LOG_VERSION, &[lsn]);
+        enc.buf.extend_from_slice(&table.to_bytes());
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&enc.buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+    /// チェックポイントファイルを読み、テーブルと反映済みのログ番号を返します。
+    fn read_checkpoint(path: &Path) -> Result<(Table, u64), Error> {
+        let bytes = fs::read(path)?;
+        let mut dec = Decoder::new(&bytes);
+        let [lsn] = dec.get_header(CHECKPOINT_MAGIC, LOG_VERSION, "チェックポイント")?;
+        let table = Table::from_bytes(&bytes[CHECKPOINT_HEADER_LEN..])?;
+        Ok((table, lsn))
+    }
+    ///
+    /// 行を挿入します。ログに記録してから挿入します。
+    ///
+    pub fn insert<T: AsValue>(&mut self, tuple: &[T]) -> Result<&mut DurableTable, Error> {
+        let tuple = tuple
+            .iter()
+            .map(|value| value.as_value_ref().into())
+            .collect();
+        self.apply(LogRecord::Insert(tuple))?;
+        Ok(self)
+    }
+    ///
+    /// カラム名と値の組で行を挿入します。省略したカラムはデフォルト値かNULLになります。
+    ///
+    pub fn insert_named<T: AsValue>(
+        &mut self,
+        pairs: &[(&str, T)],
+    ) -> Result<&mut DurableTable, Error> {
+        let tuple = self.table.complete_tuple(pairs)?;
+        self.apply(LogRecord::Insert(tuple))?;
+        Ok(self)
+    }
+    ///
+    /// 条件式が真になる行を書き換え、書き換えた行数を返します。
+    ///
+    pub fn update<T: AsValue>(
+        &mut self,
+        predicate: &Predicate,
+        assignments: &[(&str, T)],
+    ) -> Result<RowId, Error> {
+        let assignments = assignments
+            .iter()
+            .map(|(col_name, value)| (col_name.to_string(), value.as_value_ref().into()))
+            .collect();
+        self.apply(LogRecord::Update {
+            predicate: predicate.clone(),
+            assignments,
+        })
+    }
+    ///
+    /// 条件式が真になる行を削除し、削除した行数を返します。
+    ///
+    pub fn delete_where(&mut self, predicate: &Predicate) -> Result<RowId, Error> {
+        self.apply(LogRecord::Delete(predicate.clone()))
+    }
+    ///
+    /// 操作をログに記録してからテーブルに適用し、変更した行数を返します。
+    /// 失敗する操作は記録しないので、開くときのやり直しは失敗しません。
+    ///
+    fn apply(&mut self, record: LogRecord) -> Result<RowId, Error> {
+        match &record {
+            LogRecord::Insert(tuple) => self.table.check_insert(tuple)?,
+            LogRecord::Update {
+                predicate,
+                assignments,
+            } => {
+                // 書き換えてみないと制約を検査できないので、記録できなければ書き戻す
+                let assignments: Vec<(&str, Value)> = assignments
+                    .iter()
+                    .map(|(col_name, value)| (col_name.as_str(), value.as_value_ref()))
+                    .collect();
+                let targets = self.table.resolve_assignments(&assignments)?;
+                let row_ids = self.table.matching_row_ids(predicate);
+                let old_values = self.table.update_row_ids(&row_ids, &targets)?;
+                if let Err(err) = self.log.append(&record) {
+                    self.table.restore_values(&row_ids, &old_values);
+                    return Err(err);
+                }
+                return Ok(row_ids.len());
+            }
+            LogRecord::Delete(_) => {}
+        }
+        self.log.append(&record)?;
+        record.apply(&mut self.table)
+    }
+    ///
+    /// テーブル全体をチェックポイントファイルに書き出し、ログを空にします。
+    ///
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        self.log.sync()?;
+        let lsn = self.log.next_lsn() - 1;
+        DurableTable::write_checkpoint(&self.path, &self.table, lsn)?;
+        self.log.clear()
+    }
+    ///
+    /// ログをディスクに同期します。[`SyncPolicy::Always`]以外で、区切りのよいところで呼びます。
+    ///
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.log.sync()
+    }
+}
+
+impl Deref for DurableTable {
+    type Target = Table;
+    fn deref(&self) -> &Table {
+        &self.table
+    }
+}
+
+/// mmapしたカラムのキーIDの格納場所です。範囲はファイルの先頭からのバイト位置です。
+#[derive(Debug, Clone)]
+enum MappedKeyIds {
+    /// ビット幅と、詰めたキーIDのワード列
+    Packed { bit_width: u32, words: Range<usize> },
+    /// 各ランの終端(排他的)と、キーID(`u64::MAX`はNULL)
+    RunLength {
+        ends: Range<usize>,
+        key_ids: Range<usize>,
+    },
+}
+
+///
+/// mmapしたテーブルファイルのカラムです。辞書の値・キーID・NULLのビットマップをファイルの領域から直接読みます。
+///
+/// 値からキーIDを引くときは、ファイルに書かれた値の順のKeyIdを二分探索します。
+/// 読み取り専用なので`append`・`pop`・`set`は何もせずに`None`を返します。
+///
+#[derive(Debug)]
+pub struct MappedColumn {
+    map: Arc<Mmap>,
+    kind: TypeKind,
+    num_rows: RowId,
+    num_keys: KeyId,
+    /// 辞書の値(整数ならi32の配列、文字列なら値を連結したバイト列)
+    keys: Range<usize>,
+    /// 文字列の各値の開始位置(値の数+1個)
+    offsets: Range<usize>,
+    /// 値の順に並べたKeyId
+    sorted_ids: Range<usize>,
+    /// NULLでない行のビットマップ
+    validity: Range<usize>,
+    key_ids: MappedKeyIds,
+    /// KeyIdの順序と値の順序が一致しているか
+    sorted: bool,
+    /// ビットマップインデックス(開くときに作り直す)
+    index: Option<BitmapIndex>,
+}
+
+impl MappedColumn {
+    /// [`encode_column`]で書いた`num_rows`行のカラムの位置を読み、中身を検査します。
+    fn decode(
+        dec: &mut Decoder<'_>,
+        map: &Arc<Mmap>,
+        attribute: &Attribute,
+        num_rows: RowId,
+    ) -> Result<MappedColumn, Error> {
+        let (kind, name) = (attribute.kind, &attribute.name);
+        let (encoding, has_index, num_keys, keys, offsets) =
+            dec.get_block(Block::Dictionary(name.to_string()), |dec| {
+                let (encoding, has_index) = decode_column_header(dec, kind)?;
+                let num_keys = dec.get_len(4)?;
+                let (keys, offsets) = match kind {
+                    TypeKind::Integer => {
+                        dec.align()?;
+                        let start = dec.pos;
+                        dec.get_bytes(num_keys * 4)?;
+                        (start..dec.pos, 0..0)
+                    }
+                    TypeKind::Varchar => {
+                        let offsets = dec.get_word_range(num_keys + 1)?;
+                        let words: &[u64] = bytemuck::cast_slice(&map[offsets.clone()]);
+                        let is_ascending = words.windows(2).all(|pair| pair[0] <= pair[1]);
+                        if words[0] != 0 || !is_ascending {
+                            return Err(Decoder::invalid("文字列の位置が正しくありません"));
+                        }
+                        let size = usize::try_from(words[num_keys])
+                            .map_err(|_| Decoder::invalid("値が大きすぎます"))?;
+                        let start = dec.pos;
+                        let bytes = dec.get_bytes(size)?;
+                        for pair in words.windows(2) {
+                            let key = &bytes[pair[0] as usize..pair[1] as usize];
+                            if std::str::from_utf8(key).is_err() {
+                                return Err(Decoder::invalid("文字列がUTF-8ではありません"));
+                            }
+                        }
+                        (start..start + size, offsets)
+                    }
+                };
+                Ok((encoding, has_index, num_keys, keys, offsets))
+            })?;
+        let sorted_ids = dec.get_block(Block::SortedKeyIds(name.to_string()), |dec| {
+            dec.get_word_range(num_keys)
+        })?;
+        let validity = dec.get_block(Block::Validity(name.to_string()), |dec| {
+            dec.get_word_range(num_rows.div_ceil(64))
+        })?;
+        let key_ids = dec.get_block(Block::KeyIds(name.to_string()), |dec| match encoding {
+            Encoding::Packed => {
+                let bit_width = dec.get_u64()?;
+                if bit_width > 64 {
+                    return Err(Decoder::invalid("キーIDのビット幅が正しくありません"));
+                }
+                let bit_width = bit_width as u32;
+                let words = dec.get_word_range(PackedArray::num_words(num_rows, bit_width))?;
+                Ok(MappedKeyIds::Packed { bit_width, words })
+            }
+            Encoding::RunLength => {
+                let num_runs = dec.get_len(16)?;
+                let ends = dec.get_word_range(num_runs)?;
+                let key_ids = dec.get_word_range(num_runs)?;
+                Ok(MappedKeyIds::RunLength { ends, key_ids })
+            }
+        })?;
+        let mut column = MappedColumn {
+            map: Arc::clone(map),
+            kind,
+            num_rows,
+            num_keys,
+            keys,
+            offsets,
+            sorted_ids,
+            validity,
+            key_ids,
+            sorted: false,
+            index: None,
+        };
+        column.check_sorted_ids()?;
+        column.check_key_ids()?;
+        column.sorted = column
+            .words(&column.sorted_ids)
+            .iter()
+            .enumerate()
+            .all(|(i, &key_id)| key_id == i as u64);
+        if has_index {
+            let mut index = BitmapIndex::new();
+            for row_id in 0..num_rows {
+                if let Some(key_id) = column.id_at(row_id) {
+                    index.insert(key_id, row_id);
+                }
+            }
+            column.index = Some(index);
+        }
+        Ok(column)
+    }
+    /// 値の順に並べたKeyIdが、辞書のKeyIdを1つずつ値の昇順に並べたものか検査します。
+    fn check_sorted_ids(&self) -> Result<(), Error> {
+        let mut seen = bitvec![0; self.num_keys];
+        for &key_id in self.words(&self.sorted_ids) {
+            match usize::try_from(key_id) {
+                Ok(key_id) if key_id < self.num_keys && !seen[key_id] => seen.set(key_id, true),
+                _ => return Err(Decoder::invalid("値の順のKeyIdが正しくありません")),
+            }
+        }
+        let sorted_ids = self.words(&self.sorted_ids);
+        for pair in sorted_ids.windows(2) {
+            if self.key_of(pair[0] as KeyId) >= self.key_of(pair[1] as KeyId) {
+                return Err(Decoder::invalid("値の順のKeyIdが値の順に並んでいません"));
+            }
+        }
+        Ok(())
+    }
+    /// 各行のキーIDが辞書の範囲内で、NULLのビットマップと一致しているか検査します。
+    fn check_key_ids(&self) -> Result<(), Error> {
+        if let MappedKeyIds::RunLength { ends, key_ids } = &self.key_ids {
+            let validity = self.validity();
+            let mut start = 0;
+            for (&end, &key_id) in self.words(ends).iter().zip(self.words(key_ids)) {
+                let end = usize::try_from(end).unwrap_or(usize::MAX);
+                if end <= start || end > self.num_rows {
+                    return Err(Decoder::invalid("ランの終端が正しくありません"));
+                }
+                let is_valid = key_id != u64::MAX;
+                if validity[start..end].any() != is_valid
+                    || validity[start..end].not_all() == is_valid
+                {
+                    return Err(Decoder::invalid("ランとNULLのビットマップが一致しません"));
+                }
+                start = end;
+            }
+            if start != self.num_rows {
+                return Err(Decoder::invalid("ランの長さが行数と一致しません"));
+            }
+        }
+        for row_id in self.validity().iter_ones() {
+            if self
+                .id_at(row_id)
+                .is_some_and(|key_id| key_id >= self.num_keys)
+            {
+                return Err(Decoder::invalid("キーIDが辞書の範囲外です"));
+            }
+        }
+        Ok(())
+    }
+    /// ファイルの領域を64ビット整数の配列として返します。
+    fn words(&self, range: &Range<usize>) -> &[u64] {
+        bytemuck::cast_slice(&self.map[range.clone()])
+    }
+    /// `row_id`を含むランの番号を返します。
+    fn run_index(&self, ends: &Range<usize>, row_id: RowId) -> usize {
+        self.words(ends)
+            .partition_point(|&end| end as usize <= row_id)
+    }
+    ///
+    /// 値の順で、`key`より小さい(`inclusive`なら`key`以下の)値の数を返します。
+    /// 型が合わない場合は`None`を返します。
+    ///
+    fn rank_of(&self, key: &dyn AsValue, inclusive: bool) -> Option<usize> {
+        let key = key.as_value_ref();
+        if key.kind() != Some(self.kind) {
+            return None;
+        }
+        let sorted_ids = self.words(&self.sorted_ids);
+        Some(sorted_ids.partition_point(|&key_id| {
+            let value = self.key_of(key_id as KeyId);
+            value < key || (inclusive && value == key)
+        }))
+    }
+    /// 値の順で`ranks`番目の値のKeyIdのビットが立ったビットマップを返します。
+    fn key_ids_in(&self, ranks: Range<usize>) -> BitMap {
+        let mut bitmap = bitvec![0; self.num_keys];
+        if ranks.start < ranks.end {
+            for &key_id in &self.words(&self.sorted_ids)[ranks] {
+                bitmap.set(key_id as KeyId, true);
+            }
+        }
+        bitmap
+    }
+}
+
+impl AsColumn for MappedColumn {
+    fn kind(&self) -> TypeKind {
+        self.kind
+    }
+    fn num_keys(&self) -> KeyId {
+        self.num_keys
+    }
+    fn num_rows(&self) -> RowId {
+        self.num_rows
+    }
+    fn append(&mut self, _key: &dyn AsValue) -> Option<RowId> {
+        None
+    }
+    fn pop(&mut self) -> Option<RowId> {
+        None
+    }
+    fn set(&mut self, _row_id: RowId, _key: &dyn AsValue) -> Option<RowId> {
+        None
+    }
+    fn id_of(&self, key: &dyn AsValue) -> Option<KeyId> {
+        let rank = self.rank_of(key, false)?;
+        let key_id = *self.words(&self.sorted_ids).get(rank)? as KeyId;
+        (self.key_of(key_id) == key.as_value_ref()).then_some(key_id)
+    }
+    fn range(&self, range: Range<&dyn AsValue>) -> Option<BitMap> {
+        let start = self.rank_of(range.start, false)?;
+        let end = self.rank_of(range.end, false)?;
+        Some(self.key_ids_in(start..end))
+    }
+    fn range_from(&self, key: &dyn AsValue) -> Option<BitMap> {
+        let start = self.rank_of(key, false)?;
+        Some(self.key_ids_in(start..self.num_keys))
+    }
+    fn range_to(&self, key: &dyn AsValue) -> Option<BitMap> {
+        let end = self.rank_of(key, false)?;
+        Some(self.key_ids_in(0..end))
+    }
+    fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+    fn lower_bound(&self, key: &dyn AsValue) -> Option<KeyId> {
+        if !self.sorted {
+            return None;
+        }
+        self.rank_of(key, false)
+    }
+    fn upper_bound(&self, key: &dyn AsValue) -> Option<KeyId> {
+        if !self.sorted {
+            return None;
+        }
+        self.rank_of(key, true)
+    }
+    fn key_of(&self, key_id: KeyId) -> Value<'_> {
+        match self.kind {
+            TypeKind::Integer => {
+                let keys: &[i32] = bytemuck::cast_slice(&self.map[self.keys.clone()]);
+                Value::Integer(keys[key_id])
+            }
+            TypeKind::Varchar => {
+                let offsets = self.words(&self.offsets);
+                let start = self.keys.start + offsets[key_id] as usize;
+                let end = self.keys.start + offsets[key_id + 1] as usize;
+                // 開くときにUTF-8であることを検査済み
+                Value::Varchar(std::str::from_utf8(&self.map[start..end]).unwrap_or_default())
+            }
+        }
+    }
+    fn id_at(&self, row_id: RowId) -> Option<KeyId> {
+        assert!(row_id < self.num_rows);
+        if !self.validity()[row_id] {
+            return None;
+        }
+        match &self.key_ids {
+            MappedKeyIds::Packed { bit_width, words } => {
+                Some(PackedArray::get_in(self.words(words), *bit_width, row_id))
+            }
+            MappedKeyIds::RunLength { ends, key_ids } => {
+                let run = self.run_index(ends, row_id);
+                Some(self.words(key_ids)[run] as KeyId)
+            }
+        }
+    }
+    fn key_at(&self, row_id: RowId) -> Value<'_> {
+        match self.id_at(row_id) {
+            Some(key_id) => self.key_of(key_id),
+            None => NULL.into(),
+        }
+    }
+    fn is_null_at(&self, row_id: RowId) -> bool {
+        !self.validity()[row_id]
+    }
+    fn validity(&self) -> &BitSlice {
+        let words: &[usize] = bytemuck::cast_slice(&self.map[self.validity.clone()]);
+        &BitSlice::from_slice(words)[..self.num_rows]
+    }
+    fn encoding(&self) -> Encoding {
+        match &self.key_ids {
+            MappedKeyIds::Packed { .. } => Encoding::Packed,
+            MappedKeyIds::RunLength { .. } => Encoding::RunLength,
+        }
+    }
+    fn run_end(&self, row_id: RowId) -> RowId {
+        assert!(row_id < self.num_rows);
+        match &self.key_ids {
+            MappedKeyIds::Packed { .. } => row_id + 1,
+            MappedKeyIds::RunLength { ends, .. } => {
+                self.words(ends)[self.run_index(ends, row_id)] as RowId
+            }
+        }
+    }
+    fn index(&self) -> Option<&BitmapIndex> {
+        self.index.as_ref()
+    }
+}
+
+///
+/// [`Table::save`]で書き出したファイルをmmapした、読み取り専用のテーブルです。
+///
+/// 辞書の値やキーIDはページキャッシュ上のファイルの領域をそのまま使うので、
+/// [`Table::load`]のように読み込んで辞書を作り直す必要がありません。
+/// 開いている間はファイルを書き換えないでください([`Table::save`]は別のファイルに書いてから置き換えるので大丈夫です)。
+/// 64ビットのリトルエンディアン環境でだけ開けます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let mut shohin = Table::create(
+///     "shohin",
+///     attributes![
+///         ("shohin_name", TypeKind::Varchar),
+///         ("price", TypeKind::Integer)
+///     ],
+/// );
+/// shohin.insert(values!("りんご", 300)).insert(values!("みかん", 130));
+/// let path = std::env::temp_dir().join("kawaii_doctest_mapped.kwi");
+/// shohin.save(&path).unwrap();
+///
+/// let mapped = MappedTable::open(&path).unwrap();
+/// let cheap = mapped.filter(&Predicate::less_than("price", 200));
+/// assert_eq!(cheap.fetch(0..1).unwrap()[0][0], Value::Varchar("みかん"));
+/// # drop(cheap);
+/// # drop(mapped);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+///
+#[derive(Debug)]
+pub struct MappedTable {
+    map: Arc<Mmap>,
+    /// 削除済みの行も含めた行数
+    num_rows: RowId,
+    /// 削除済みの行のビットマップの位置
+    deleted: Range<usize>,
+    num_deleted: RowId,
+    /// 墓標のビットマップのブロックごとの、それより前にある生きている行数
+    live_ranks: Vec<RowId>,
+    definition: Definition,
+    columns: Vec<MappedColumn>,
+}
+
+impl MappedTable {
+    ///
+    /// テーブルのファイルをmmapして開きます。開くときに各ブロックのチェックサムと中身を検査し、
+    /// 壊れている場合は[`Error::Corrupted`]を、形式が正しくない場合は[`Error::InvalidFormat`]を返します。
+    ///
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedTable, Error> {
+        if cfg!(target_endian = "big") || usize::BITS != 64 {
+            let message = "mmapで開けるのは64ビットのリトルエンディアン環境だけです";
+            return Err(Error::InvalidFormat(message.to_string()));
+        }
+        let file = fs::File::open(path)?;
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let mut dec = Decoder::new(&map);
+        let num_rows = Table::get_header(&mut dec)?;
+        let deleted = dec.get_block(Block::Deleted, |dec| {
+            dec.get_word_range(num_rows.div_ceil(64))
+        })?;
+        let definition = dec.get_block(Block::Definition, Definition::decode)?;
+        let mut columns = Vec::new();
+        for attribute in &definition.attributes {
+            columns.push(MappedColumn::decode(&mut dec, &map, attribute, num_rows)?);
+        }
+        if dec.remaining() > 0 {
+            return Err(Decoder::invalid("余分なデータがあります"));
+        }
+        let mut table = MappedTable {
+            map: Arc::clone(&map),
+            num_rows,
+            deleted,
+            num_deleted: 0,
+            live_ranks: Vec::new(),
+            definition,
+            columns,
+        };
+        table.num_deleted = table.deleted().count_ones();
+        table.live_ranks = live_ranks_of(table.deleted());
+        Ok(table)
+    }
+    /// 削除済みの行のビットが立ったビットマップを返します。
+    fn deleted(&self) -> &BitSlice {
+        let words: &[usize] = bytemuck::cast_slice(&self.map[self.deleted.clone()]);
+        &BitSlice::from_slice(words)[..self.num_rows]
+    }
+    /// 削除済みで領域が回収されていない行数を返します。
+    pub fn num_deleted(&self) -> RowId {
+        self.num_deleted
+    }
+}
+
+impl Relation for MappedTable {
+    fn num_columns(&self) -> ColumnId {
+        self.columns.len()
+    }
+    fn num_rows(&self) -> RowId {
+        self.num_rows - self.num_deleted
+    }
+    fn definition(&self) -> &Definition {
+        &self.definition
+    }
+    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
+        &self.columns[col_id]
+    }
+    fn scan_row_ids<'a>(&self, range: Range<RowId>, dest: &'a mut [RowId]) -> &'a [RowId] {
+        scan_live_row_ids(
+            self.deleted(),
+            self.num_deleted,
+            &self.live_ranks,
+            range,
+            dest,
+        )
+    }
+    fn scan_row_ranges(&self, range: Range<RowId>, f: &mut dyn FnMut(Range<RowId>)) {
+        scan_live_row_ranges(self.deleted(), self.num_deleted, &self.live_ranks, range, f)
+    }
+}
+
+impl fmt::Display for MappedTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Relation::fmt(self, f)
+    }
+}
+
+///
+/// リレーションの有効な行番号を先頭から少しずつ取り出して、順に`f`を呼びます。
+/// `f`がエラーを返したらそこで止めます。
+///
+fn for_each_row_id<F>(relation: &dyn Relation, mut f: F) -> std::io::Result<()>
+where
+    F: FnMut(RowId) -> std::io::Result<()>,
+{
+    const STEP: RowId = 64;
+    let mut buffer = [0; STEP];
+    let num_rows = relation.num_rows();
+    let mut start = 0;
+    while start < num_rows {
+        let end = cmp::min(start + STEP, num_rows);
+        for &row_id in relation.scan_row_ids(start..end, &mut buffer) {
+            f(row_id)?;
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// CSVの1行分の記録(記録が始まる行番号と、各欄の値。引用符で囲まれていない空欄は`None`)
+type CsvRecord = (usize, Vec<Option<String>>);
+
+///
+/// CSVのテキストを記録ごとに区切ります。
+/// 引用符で囲んだ欄には区切り文字・改行・`""`(引用符そのもの)を含められます。
+/// 空行は欄のない記録にします。
+///
+struct CsvRecords<'a> {
+    text: &'a str,
+    pos: usize,
+    /// 次に読む位置の行番号(1から数える)
+    line: usize,
+    delimiter: char,
+}
+
+impl<'a> CsvRecords<'a> {
+    fn new(text: &'a str, delimiter: char) -> CsvRecords<'a> {
+        CsvRecords {
+            // Excelなどが付けるBOMは読み飛ばす
+            text: text.strip_prefix('\u{feff}').unwrap_or(text),
+            pos: 0,
+            line: 1,
+            delimiter,
+        }
+    }
+    /// 改行があれば読み進めて`true`を返します。
+    fn skip_newline(&mut self) -> bool {
+        let rest = &self.text[self.pos..];
+        let len = if rest.starts_with("\r\n") {
+            2
+        } else if rest.starts_with('\n') {
+            1
+        } else {
+            return false;
+        };
+        self.pos += len;
+        self.line += 1;
+        true
+    }
+    /// 引用符で囲まれた欄を、開始の引用符の次から読みます。
+    fn read_quoted(&mut self, field: &mut String, column: usize) -> Result<(), Error> {
+        let line = self.line;
+        loop {
+            let rest = &self.text[self.pos..];
+            let len = rest
+                .find('"')
+                .ok_or_else(|| csv_error(line, column, "引用符が閉じられていません"))?;
+            field.push_str(&rest[..len]);
+            self.line += rest[..len].matches('\n').count();
+            self.pos += len + 1;
+            if !self.text[self.pos..].starts_with('"') {
+                return Ok(());
+            }
+            field.push('"');
+            self.pos += 1;
+        }
+    }
+    /// 読み終えた欄の値を取り出します。引用符で囲まれていない空欄は`None`です。
+    fn take_field(field: &mut String, quoted: bool) -> Option<String> {
+        if quoted || !field.is_empty() {
+            Some(std::mem::take(field))
+        } else {
+            None
+        }
+    }
+    fn read_record(&mut self) -> Result<CsvRecord, Error> {
+        let line = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let column = fields.len() + 1;
+            let end_of_field = match self.text[self.pos..].chars().next() {
+                None => true,
+                Some('\r') | Some('\n') if self.skip_newline() => true,
+                Some(c) if c == self.delimiter => {
+                    self.pos += c.len_utf8();
+                    fields.push(CsvRecords::take_field(&mut field, quoted));
+                    quoted = false;
+                    false
+                }
+                Some('"') if !quoted && field.is_empty() => {
+                    self.pos += 1;
+                    self.read_quoted(&mut field, column)?;
+                    quoted = true;
+                    false
+                }
+                Some(_) if quoted => {
+                    let message = "閉じた引用符の後に区切り文字がありません";
+                    return Err(csv_error(self.line, column, message));
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    field.push(c);
+                    false
+                }
+            };
+            if end_of_field {
+                fields.push(CsvRecords::take_field(&mut field, quoted));
+                return Ok((line, fields));
+            }
+        }
+    }
+}
+
+impl Iterator for CsvRecords<'_> {
+    type Item = Result<CsvRecord, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.text.len() {
+            return None;
+        }
+        let line = self.line;
+        if self.skip_newline() {
+            return Some(Ok((line, Vec::new())));
+        }
+        let record = self.read_record();
+        if record.is_err() {
+            // 壊れた記録から後ろは読まない
+            self.pos = self.text.len();
+        }
+        Some(record)
+    }
+}
+
+fn csv_error(line: usize, column: usize, message: &str) -> Error {
+    Error::Csv {
+        line,
+        column,
+        message: message.to_string(),
+    }
+}
+
+///
+/// CSVを読み込んでテーブルを作ります。
+///
+/// スキーマを指定する場合は[`CsvReader::parse`]を、各カラムの型を先頭の何行かから推測する場合は
+/// [`CsvReader::parse_inferred`]を使います。ヘッダー行がある場合はカラム名で対応付けるので、
+/// カラムの順序は定義と違っていても構いません。CSVにないカラムにはデフォルト値かNULLを入れます。
+/// 引用符で囲まれていない空欄はNULLになり、`""`は空文字列になります。
+/// 空行は読み飛ばしますが、1カラムだけのCSVでは空欄が1つの行としてNULLになります。
+///
+/// 型の合わない値や壊れた引用符は、記録が始まる行と列の番号(1から数える)と一緒に
+/// [`Error::Csv`]で返します。制約の検査は[`Table::append_columns`]と同じで、
+/// どこかで失敗した場合はテーブルに何も追加しません。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let csv = "shohin_id,shohin_name,price\n\
+///            1,りんご,300\n\
+///            2,\"みかん, 愛媛産\",\n";
+/// let shohin = CsvReader::new()
+///     .parse(
+///         "shohin",
+///         attributes![
+///             ("shohin_id", TypeKind::Integer),
+///             ("shohin_name", TypeKind::Varchar),
+///             ("kubun_id", TypeKind::Integer, with_default(1)),
+///             ("price", TypeKind::Integer)
+///         ],
+///         csv,
+///     )
+///     .unwrap();
+/// let tuples = shohin.fetch(0..2).unwrap();
+/// assert_eq!(tuples[1][1], Value::Varchar("みかん, 愛媛産"));
+/// assert_eq!(tuples[1][2], Value::Integer(1));
+/// assert_eq!(tuples[1][3], Value::Null(NULL));
+///
+/// let shohin = CsvReader::new().parse_inferred("shohin", csv).unwrap();
+/// assert_eq!(shohin.definition()[2].kind(), TypeKind::Integer);
+/// let err = CsvReader::new()
+///     .parse(
+///         "shohin",
+///         attributes![("shohin_id", TypeKind::Integer), ("price", TypeKind::Integer)],
+///         "shohin_id,price\n1,300\n2,たかい\n",
+///     )
+///     .err()
+///     .unwrap();
+/// assert_eq!(err.to_string(), "CSVの3行2列目: 整数ではありません(たかい)");
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CsvReader {
+    delimiter: char,
+    has_header: bool,
+    /// 型の推測に使う行数
+    num_samples: usize,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        CsvReader::new()
+    }
+}
+
+impl CsvReader {
+    /// 区切り文字が`,`で、1行目がヘッダーのCSVを読む設定で作ります。
+    pub fn new() -> CsvReader {
+        CsvReader {
+            delimiter: ',',
+            has_header: true,
+            num_samples: 100,
+        }
+    }
+    /// 区切り文字を設定します。TSVなら`'\t'`です。
+    pub fn delimiter(mut self, delimiter: char) -> CsvReader {
+        self.delimiter = delimiter;
+        self
+    }
+    /// 1行目がヘッダーかどうかを設定します。ヘッダーがなければ定義の順に対応付けます。
+    pub fn has_header(mut self, has_header: bool) -> CsvReader {
+        self.has_header = has_header;
+        self
+    }
+    /// 型の推測に使う行数を設定します。
+    pub fn num_samples(mut self, num_samples: usize) -> CsvReader {
+        self.num_samples = num_samples;
+        self
+    }
+    ///
+    /// CSVのファイルを読み込んで、指定したスキーマのテーブルを作ります。
+    ///
+    pub fn read<P: AsRef<Path>>(
+        &self,
+        name: &str,
+        attributes: &[Attribute],
+        path: P,
+    ) -> Result<Table, Error> {
+        self.parse(name, attributes, &fs::read_to_string(path)?)
+    }
+    ///
+    /// CSVのファイルを読み込んで、型を推測したテーブルを作ります。
+    ///
+    pub fn read_inferred<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<Table, Error> {
+        self.parse_inferred(name, &fs::read_to_string(path)?)
+    }
+    ///
+    /// CSVのテキストから、指定したスキーマのテーブルを作ります。
+    ///
+    pub fn parse(&self, name: &str, attributes: &[Attribute], text: &str) -> Result<Table, Error> {
+        let mut table = Table::create(name, attributes);
+        self.parse_into(&mut table, text)?;
+        Ok(table)
+    }
+    ///
+    /// CSVのテキストから、型を推測したテーブルを作ります。
+    ///
+    pub fn parse_inferred(&self, name: &str, text: &str) -> Result<Table, Error> {
+        let (header, records) = self.records(text)?;
+        let attributes = self.infer(header.as_deref(), &records);
+        let mut table = Table::create(name, &attributes);
+        self.append_records(&mut table, header.as_deref(), &records)?;
+        Ok(table)
+    }
+    ///
+    /// CSVのテキストから各カラムの名前と型を推測します。
+    /// 先頭の[`CsvReader::num_samples`]行の空欄でない値がすべて整数ならInteger、
+    /// そうでなければVarcharとします。ヘッダーがなければ`column1`, `column2`, ...と名付けます。
+    ///
+    pub fn infer_attributes(&self, text: &str) -> Result<Vec<Attribute>, Error> {
+        let (header, records) = self.records(text)?;
+        Ok(self.infer(header.as_deref(), &records))
+    }
+    ///
+    /// CSVのテキストを読み込んで、既存のテーブルに追記します。追記した行数を返します。
+    ///
+    pub fn parse_into(&self, table: &mut Table, text: &str) -> Result<RowId, Error> {
+        let (header, records) = self.records(text)?;
+        self.append_records(table, header.as_deref(), &records)
+    }
+    /// テキストを記録に区切り、ヘッダーのカラム名と残りの記録に分けます。
+    fn records(&self, text: &str) -> Result<(Option<Vec<String>>, Vec<CsvRecord>), Error> {
+        let mut records = CsvRecords::new(text, self.delimiter).collect::<Result<Vec<_>, _>>()?;
+        let first = records.iter().position(|(_, fields)| !fields.is_empty());
+        let first = match first {
+            Some(first) if self.has_header => first,
+            _ => return Ok((None, records)),
+        };
+        let (line, fields) = records.drain(..=first).next_back().unwrap_or_default();
+        let mut names = Vec::new();
+        for (col_no, field) in fields.into_iter().enumerate() {
+            match field {
+                Some(name) if !name.is_empty() => names.push(name),
+                _ => return Err(csv_error(line, col_no + 1, "カラム名が空です")),
+            }
+        }
+        Ok((Some(names), records))
+    }
+    fn infer(&self, header: Option<&[String]>, records: &[CsvRecord]) -> Vec<Attribute> {
+        let names: Vec<String> = match header {
+            Some(names) => names.to_vec(),
+            None => {
+                let num_columns = records
+                    .iter()
+                    .map(|(_, fields)| fields.len())
+                    .find(|&len| len > 0)
+                    .unwrap_or(0);
+                (1..=num_columns)
+                    .map(|no| format!("column{}", no))
+                    .collect()
+            }
+        };
+        let samples = &records[..cmp::min(records.len(), self.num_samples)];
+        names
+            .iter()
+            .enumerate()
+            .map(|(col_no, name)| {
+                let mut values = samples
+                    .iter()
+                    .filter_map(|(_, fields)| fields.get(col_no).and_then(Option::as_ref))
+                    .peekable();
+                let is_integer =
+                    values.peek().is_some() && values.all(|value| value.parse::<i32>().is_ok());
+                let kind = if is_integer {
+                    TypeKind::Integer
+                } else {
+                    TypeKind::Varchar
+                };
+                Attribute::create(name, kind)
+            })
+            .collect()
+    }
+    /// 記録をカラムごとの値に組み替えて、[`Table::append_columns`]で追記します。
+    fn append_records(
+        &self,
+        table: &mut Table,
+        header: Option<&[String]>,
+        records: &[CsvRecord],
+    ) -> Result<RowId, Error> {
+        let col_ids: Vec<ColumnId> = match header {
+            Some(names) => {
+                let mut col_ids = Vec::new();
+                for name in names {
+                    let col_id = table.column_id(name)?;
+                    if col_ids.contains(&col_id) {
+                        return Err(Error::ColumnExists(name.clone()));
+                    }
+                    col_ids.push(col_id);
+                }
+                col_ids
+            }
+            None => (0..table.num_columns()).collect(),
+        };
+        // 空行は、1カラムだけならNULLの行、そうでなければ読み飛ばす
+        let null_fields = [None];
+        let records: Vec<(usize, &[Option<String>])> = records
+            .iter()
+            .filter_map(|(line, fields)| match fields.is_empty() {
+                false => Some((*line, &fields[..])),
+                true if col_ids.len() == 1 => Some((*line, &null_fields[..])),
+                true => None,
+            })
+            .collect();
+        let definition = table.definition();
+        let mut columns: Vec<ColumnValues> = definition
+            .attributes
+            .iter()
+            .map(|attribute| ColumnValues::new(attribute.kind))
+            .collect();
+        for (line, fields) in &records {
+            if fields.len() != col_ids.len() {
+                let message = format!(
+                    "列の数が合いません({}列のはずが{}列あります)",
+                    col_ids.len(),
+                    fields.len()
+                );
+                let column = cmp::min(fields.len(), col_ids.len()) + 1;
+                return Err(csv_error(*line, column, &message));
+            }
+            for (col_no, (field, &col_id)) in fields.iter().zip(&col_ids).enumerate() {
+                let values = &mut columns[col_id];
+                match (values, field) {
+                    (ColumnValues::Varchar(values), field) => values.push(field.clone()),
+                    (ColumnValues::Integer(values), None) => values.push(None),
+                    (ColumnValues::Integer(values), Some(field)) => match field.parse() {
+                        Ok(value) => values.push(Some(value)),
+                        Err(_) => {
+                            let message = format!("整数ではありません({})", field);
+                            return Err(csv_error(*line, col_no + 1, &message));
+                        }
+                    },
+                }
+            }
+        }
+        for (col_id, values) in columns.iter_mut().enumerate() {
+            if col_ids.contains(&col_id) {
+                continue;
+            }
+            let attribute = &definition[col_id];
+            let value = attribute
+                .default_value
+                .clone()
+                .unwrap_or_else(|| NULL.into());
+            for _ in 0..records.len() {
+                if values.push(&value).is_none() {
+                    return Err(Error::TypeMismatch {
+                        column: attribute.name.clone(),
+                        expected: attribute.kind,
+                        value,
+                    });
+                }
+            }
+        }
+        table.append_columns(columns)?;
+        Ok(records.len())
+    }
+}
+
+///
+/// リレーションをCSV(RFC 4180)やTSVとして書き出します。
+///
+/// どのリレーション(抽出・射影・集約の結果など)でも、先頭にカラム名のヘッダー行を付けて
+/// 1行ずつ書き出します。区切り文字・引用符・改行を含む値と、空文字列やNULLの表現と同じ文字列は
+/// 引用符で囲むので、NULLの表現が空欄なら[`CsvReader`]で読み戻すと元の値になります。
+/// 日本語はUTF-8のまま書き出します。Excelで開く場合は[`CsvWriter::bom`]でBOMを付けます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let mut shohin = Table::create(
+///     "shohin",
+///     attributes![("shohin_name", TypeKind::Varchar), ("price", TypeKind::Integer)],
+/// );
+/// shohin
+///     .insert(values!("りんご", 300))
+///     .insert(values!("みかん, 愛媛産", 130))
+///     .insert(values!("ドリアン", NULL));
+/// let cheap = shohin.filter(&Predicate::less_than("price", 200).or(Predicate::is_null("price")));
+/// assert_eq!(
+///     CsvWriter::new().format(&cheap),
+///     "shohin_name,price\r\n\"みかん, 愛媛産\",130\r\nドリアン,\r\n"
+/// );
+/// let tsv = CsvWriter::new().delimiter('\t').null("\\N").line_terminator("\n");
+/// assert_eq!(tsv.format(&cheap), "shohin_name\tprice\nみかん, 愛媛産\t130\nドリアン\t\\N\n");
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CsvWriter {
+    delimiter: char,
+    has_header: bool,
+    /// NULLの表現
+    null: String,
+    line_terminator: String,
+    bom: bool,
+}
+
+impl Default for CsvWriter {
+    fn default() -> Self {
+        CsvWriter::new()
+    }
+}
+
+impl CsvWriter {
+    /// 区切り文字が`,`、改行がCRLF、NULLが空欄で、ヘッダー行を付けて書き出す設定で作ります。
+    pub fn new() -> CsvWriter {
+        CsvWriter {
+            delimiter: ',',
+            has_header: true,
+            null: String::new(),
+            line_terminator: "\r\n".to_string(),
+            bom: false,
+        }
+    }
+    /// 区切り文字を設定します。TSVなら`'\t'`です。
+    pub fn delimiter(mut self, delimiter: char) -> CsvWriter {
+        self.delimiter = delimiter;
+        self
+    }
+    /// ヘッダー行を付けるかどうかを設定します。
+    pub fn has_header(mut self, has_header: bool) -> CsvWriter {
+        self.has_header = has_header;
+        self
+    }
+    /// NULLの表現を設定します。
+    pub fn null(mut self, null: &str) -> CsvWriter {
+        self.null = null.to_string();
+        self
+    }
+    /// 行の区切りを設定します。
+    pub fn line_terminator(mut self, line_terminator: &str) -> CsvWriter {
+        self.line_terminator = line_terminator.to_string();
+        self
+    }
+    /// 先頭にBOMを付けるかどうかを設定します。
+    pub fn bom(mut self, bom: bool) -> CsvWriter {
+        self.bom = bom;
+        self
+    }
+    ///
+    /// リレーションを書き出します。行はまとめずに少しずつ取り出して書きます。
+    ///
+    pub fn write<W: std::io::Write>(
+        &self,
+        relation: &dyn Relation,
+        mut out: W,
+    ) -> Result<(), Error> {
+        let mut line = String::new();
+        if self.bom {
+            line.push('\u{feff}');
+        }
+        if self.has_header {
+            for col_id in 0..relation.num_columns() {
+                self.push_field(&mut line, col_id, relation.definition()[col_id].name());
+            }
+            line.push_str(&self.line_terminator);
+        }
+        out.write_all(line.as_bytes())?;
+        for_each_row_id(relation, |row_id| {
+            line.clear();
+            for col_id in 0..relation.num_columns() {
+                match relation.column_at(col_id).key_at(row_id) {
+                    Value::Null(_) => {
+                        if col_id > 0 {
+                            line.push(self.delimiter);
+                        }
+                        line.push_str(&self.null);
+                    }
+                    Value::Integer(value) => self.push_field(&mut line, col_id, &value.to_string()),
+                    Value::Varchar(value) => self.push_field(&mut line, col_id, value),
+                }
+            }
+            line.push_str(&self.line_terminator);
+            out.write_all(line.as_bytes())
+        })?;
+        out.flush()?;
+        Ok(())
+    }
+    ///
+    /// リレーションをファイルに書き出します。既にあるファイルは上書きします。
+    ///
+    pub fn write_file<P: AsRef<Path>>(
+        &self,
+        relation: &dyn Relation,
+        path: P,
+    ) -> Result<(), Error> {
+        let file = fs::File::create(path)?;
+        self.write(relation, std::io::BufWriter::new(file))
+    }
+    ///
+    /// リレーションを書き出した文字列を返します。
+    ///
+    pub fn format(&self, relation: &dyn Relation) -> String {
+        let mut bytes = Vec::new();
+        // Vecへの書き込みは失敗しない
+        let _ = self.write(relation, &mut bytes);
+        String::from_utf8(bytes).unwrap_or_default()
+    }
+    /// 必要なら引用符で囲んで、値を1欄分書きます。
+    fn push_field(&self, line: &mut String, col_id: ColumnId, field: &str) {
+        if col_id > 0 {
+            line.push(self.delimiter);
+        }
+        // 引用符で囲まない空欄はNULLとして読まれるので、空文字列も囲む
+        let needs_quote = field.is_empty()
+            || *field == self.null
+            || field.contains([self.delimiter, '"', '\r', '\n']);
+        if needs_quote {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(field);
+        }
+    }
+}
+
+///
+/// リレーションを1行1オブジェクトのJSON Lines形式で書き出します。
+///
+/// 各行はカラム名をキーにしたオブジェクトで、キーはカラムの順に並びます。NULLは`null`になります。
+/// どのリレーション(抽出・射影・集約の結果など)でも、行はまとめずに少しずつ取り出して書きます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let mut shohin = Table::create(
+///     "shohin",
+///     attributes![("shohin_name", TypeKind::Varchar), ("price", TypeKind::Integer)],
+/// );
+/// shohin.insert(values!("りんご", 300)).insert(values!("\"特選\"メロン", NULL));
+/// assert_eq!(
+///     JsonLinesWriter::new().format(&shohin),
+///     "{\"shohin_name\":\"りんご\",\"price\":300}\n\
+///      {\"shohin_name\":\"\\\"特選\\\"メロン\",\"price\":null}\n"
+/// );
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct JsonLinesWriter {}
+
+impl JsonLinesWriter {
+    /// JsonLinesWriterを作ります。書き出しの設定はありません。
+    pub fn new() -> JsonLinesWriter {
+        JsonLinesWriter {}
+    }
+    ///
+    /// リレーションを書き出します。
+    ///
+    pub fn write<W: std::io::Write>(
+        &self,
+        relation: &dyn Relation,
+        mut out: W,
+    ) -> Result<(), Error> {
+        let keys: Vec<String> = relation
+            .definition()
+            .attributes
+            .iter()
+            .map(|attribute| serde_json::Value::from(attribute.name.as_str()).to_string())
+            .collect();
+        let mut line = String::new();
+        for_each_row_id(relation, |row_id| {
+            line.clear();
+            for (col_id, key) in keys.iter().enumerate() {
+                line.push(if col_id == 0 { '{' } else { ',' });
+                line.push_str(key);
+                line.push(':');
+                match relation.column_at(col_id).key_at(row_id) {
+                    Value::Null(_) => line.push_str("null"),
+                    Value::Integer(value) => line.push_str(&value.to_string()),
+                    Value::Varchar(value) => {
+                        line.push_str(&serde_json::Value::from(value).to_string())
+                    }
+                }
+            }
+            if keys.is_empty() {
+                line.push('{');
+            }
+            line.push_str("}\n");
+            out.write_all(line.as_bytes())
+        })?;
+        out.flush()?;
+        Ok(())
+    }
+    ///
+    /// リレーションをファイルに書き出します。既にあるファイルは上書きします。
+    ///
+    pub fn write_file<P: AsRef<Path>>(
+        &self,
+        relation: &dyn Relation,
+        path: P,
+    ) -> Result<(), Error> {
+        let file = fs::File::create(path)?;
+        self.write(relation, std::io::BufWriter::new(file))
+    }
+    ///
+    /// リレーションを書き出した文字列を返します。
+    ///
+    pub fn format(&self, relation: &dyn Relation) -> String {
+        let mut bytes = Vec::new();
+        // Vecへの書き込みは失敗しない
+        let _ = self.write(relation, &mut bytes);
+        String::from_utf8(bytes).unwrap_or_default()
+    }
+}
+
+impl Table {
+    ///
+    /// JSON Lines形式のファイルを読み込んで、テーブルに追記します。追記した行数を返します。
+    ///
+    pub fn read_json_lines<P: AsRef<Path>>(&mut self, path: P) -> Result<RowId, Error> {
+        self.parse_json_lines(&fs::read_to_string(path)?)
+    }
+    ///
+    /// JSON Lines形式のテキストを読み込んで、テーブルに追記します。追記した行数を返します。
+    ///
+    /// 各行のオブジェクトのキーをカラム名として対応付け、ないキーのカラムにはデフォルト値か
+    /// NULLを入れます。空行は読み飛ばします。JSONとして正しくない行や、存在しないカラム名、
+    /// カラムの型と合わない値(INTEGERのカラムに文字列や小数など)は、行番号(1から数える)と一緒に
+    /// [`Error::JsonLines`]で返します。制約の検査は[`Table::append_columns`]と同じで、
+    /// どこかで失敗した場合はテーブルに何も追加しません。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kawaii::*;
+    /// let mut shohin = Table::create(
+    ///     "shohin",
+    ///     attributes![
+    ///         ("shohin_name", TypeKind::Varchar),
+    ///         ("kubun_id", TypeKind::Integer, with_default(1)),
+    ///         ("price", TypeKind::Integer)
+    ///     ],
+    /// );
+    /// let jsonl = "{\"shohin_name\": \"りんご\", \"price\": 300}\n\
+    ///              {\"price\": null, \"shohin_name\": \"ドリアン\", \"kubun_id\": 2}\n";
+    /// assert_eq!(shohin.parse_json_lines(jsonl), Ok(2));
+    /// assert_eq!(shohin.fetch(0..1).unwrap()[0][1], Value::Integer(1));
+    /// let err = shohin.parse_json_lines("{\"price\": \"たかい\"}").err().unwrap();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "JSON Linesの1行目: カラムpriceはINTEGER型ですが、値\"たかい\"が指定されました"
+    /// );
+    /// ```
+    ///
+    pub fn parse_json_lines(&mut self, text: &str) -> Result<RowId, Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let defaults: Vec<OwnedValue> = self
+            .definition
+            .attributes
+            .iter()
+            .map(|attribute| {
+                attribute
+                    .default_value
+                    .clone()
+                    .unwrap_or_else(|| NULL.into())
+            })
+            .collect();
+        let mut columns: Vec<ColumnValues> = self
+            .definition
+            .attributes
+            .iter()
+            .map(|attribute| ColumnValues::new(attribute.kind))
+            .collect();
+        let mut num_rows = 0;
+        for (line_no, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json_error = |message: String| Error::JsonLines {
+                line: line_no + 1,
+                message,
+            };
+            let object: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(line).map_err(|err| json_error(err.to_string()))?;
+            let mut tuple = defaults.clone();
+            for (key, json) in &object {
+                let col_id = self
+                    .column_id(key)
+                    .map_err(|err| json_error(err.to_string()))?;
+                let attribute = &self.definition[col_id];
+                let value = match (attribute.kind, json) {
+                    (_, serde_json::Value::Null) => Some(NULL.into()),
+                    (TypeKind::Varchar, serde_json::Value::String(value)) => {
+                        Some(value.as_str().into())
+                    }
+                    (TypeKind::Integer, serde_json::Value::Number(value)) => value
+                        .as_i64()
+                        .and_then(|value| i32::try_from(value).ok())
+                        .map(OwnedValue::from),
+                    _ => None,
+                };
+                tuple[col_id] = value.ok_or_else(|| {
+                    json_error(format!(
+                        "カラム{}は{}型ですが、値{}が指定されました",
+                        attribute.name, attribute.kind, json
+                    ))
+                })?;
+            }
+            for (col_id, (values, value)) in columns.iter_mut().zip(&tuple).enumerate() {
+                if values.push(value).is_none() {
+                    // 型の合わないデフォルト値
+                    let attribute = &self.definition[col_id];
+                    return Err(Error::TypeMismatch {
+                        column: attribute.name.clone(),
+                        expected: attribute.kind,
+                        value: value.clone(),
+                    });
+                }
+            }
+            num_rows += 1;
+        }
+        self.append_columns(columns)?;
+        Ok(num_rows)
+    }
+}
+
+///
+/// リレーションをApache Arrowのレコードバッチに変換し、IPCファイル形式で書き出します。
+///
+/// VARCHARのカラムは辞書(キーIDの順の値)とキーIDの配列をそのまま使って
+/// `Dictionary(Int32, Utf8)`の配列にし、INTEGERのカラムは`Int32`の配列にします。
+/// NOT NULL制約や主キーのカラムはNULLを許さないフィールドになります。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// use kawaii::arrow_array::Array;
+/// let mut shohin = Table::create(
+///     "shohin",
+///     attributes![("shohin_name", TypeKind::Varchar), ("price", TypeKind::Integer)],
+/// );
+/// shohin
+///     .insert(values!("りんご", 300))
+///     .insert(values!("みかん", 130))
+///     .insert(values!("りんご", NULL));
+/// let writer = ArrowIpcWriter::new();
+/// let batch = writer.record_batch(&shohin).unwrap();
+/// assert_eq!(batch.num_rows(), 3);
+/// assert_eq!(batch.column(1).null_count(), 1);
+///
+/// let path = std::env::temp_dir().join("kawaii_doctest.arrow");
+/// writer.write_file(&shohin, &path).unwrap();
+/// let loaded = Table::read_arrow_ipc("shohin", &path).unwrap();
+/// # std::fs::remove_file(&path).unwrap();
+/// assert_eq!(loaded.fetch(2..3).unwrap()[0][0], Value::Varchar("りんご"));
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct ArrowIpcWriter {}
+
+impl ArrowIpcWriter {
+    /// ArrowIpcWriterを作ります。書き出しの設定はありません。
+    pub fn new() -> ArrowIpcWriter {
+        ArrowIpcWriter {}
+    }
+    ///
+    /// リレーションを1つのレコードバッチに変換します。
+    /// VARCHARのカラムの辞書が`i32`で数えられないほど大きい場合は[`Error::InvalidFormat`]を返します。
+    ///
+    pub fn record_batch(&self, relation: &dyn Relation) -> Result<RecordBatch, Error> {
+        record_batch_of(relation)
+    }
+    ///
+    /// リレーションをIPCファイル形式で書き出します。
+    ///
+    pub fn write<W: std::io::Write>(&self, relation: &dyn Relation, out: W) -> Result<(), Error> {
+        let batch = self.record_batch(relation)?;
+        let mut writer = FileWriter::try_new(out, &batch.schema())?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        Ok(())
+    }
+    ///
+    /// リレーションをIPCファイル形式のファイルに書き出します。既にあるファイルは上書きします。
+    ///
+    pub fn write_file<P: AsRef<Path>>(
+        &self,
+        relation: &dyn Relation,
+        path: P,
+    ) -> Result<(), Error> {
+        let file = std::io::BufWriter::new(fs::File::create(path)?);
+        self.write(relation, file)
+    }
+}
+
+/// [`ArrowIpcWriter::record_batch`]の本体です。
+fn record_batch_of(relation: &dyn Relation) -> Result<RecordBatch, Error> {
+    let mut row_ids = Vec::with_capacity(relation.num_rows());
+    for_each_row_id(relation, |row_id| {
+        row_ids.push(row_id);
+        Ok(())
+    })?;
+    let definition = relation.definition();
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for col_id in 0..relation.num_columns() {
+        let attribute = &definition[col_id];
+        let column = relation.column_at(col_id);
+        let (data_type, array): (DataType, ArrayRef) = match column.kind() {
+            TypeKind::Varchar => {
+                if i32::try_from(column.num_keys()).is_err() {
+                    let message = format!("カラム{}の辞書が大きすぎます", attribute.name);
+                    return Err(Error::InvalidFormat(message));
+                }
+                let values: StringArray = (0..column.num_keys())
+                    .map(|key_id| match column.key_of(key_id) {
+                        Value::Varchar(value) => Some(value),
+                        _ => None,
+                    })
+                    .collect();
+                let keys: Int32Array = row_ids
+                    .iter()
+                    .map(|&row_id| column.id_at(row_id).map(|key_id| key_id as i32))
+                    .collect();
+                let array = DictionaryArray::try_new(keys, Arc::new(values))?;
+                let data_type =
+                    DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+                (data_type, Arc::new(array))
+            }
+            TypeKind::Integer => {
+                let array: Int32Array = row_ids
+                    .iter()
+                    .map(|&row_id| match column.key_at(row_id) {
+                        Value::Integer(value) => Some(value),
+                        _ => None,
+                    })
+                    .collect();
+                (DataType::Int32, Arc::new(array))
+            }
+        };
+        let nullable = is_nullable(definition, col_id);
+        fields.push(Field::new(attribute.name.as_str(), data_type, nullable));
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(row_ids.len()));
+    let schema = Arc::new(Schema::new(fields));
+    Ok(RecordBatch::try_new_with_options(schema, arrays, &options)?)
+}
+
+/// NOT NULL制約も主キーもないカラムかどうかを返します。
+fn is_nullable(definition: &Definition, col_id: ColumnId) -> bool {
+    !definition[col_id].not_null
+        && !definition
+            .unique_keys
+            .iter()
+            .any(|unique_key| unique_key.primary && unique_key.col_ids.contains(&col_id))
+}
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Self {
+        match err {
+            ArrowError::IoError(message, _) => Error::Io(message),
+            err => Error::InvalidFormat(err.to_string()),
+        }
+    }
+}
+
+/// Arrowの型に対応するカラムの型を返します。辞書は値の型で決めます。
+fn kind_of_arrow(data_type: &DataType) -> Option<TypeKind> {
+    match data_type {
+        DataType::Int32 => Some(TypeKind::Integer),
+        DataType::Utf8 | DataType::LargeUtf8 => Some(TypeKind::Varchar),
+        DataType::Dictionary(_, value_type) => kind_of_arrow(value_type),
+        _ => None,
+    }
+}
+
+/// Arrowの配列の値を追加します。型が合わない場合は`None`を返します。
+fn push_arrow_array(values: &mut ColumnValues, array: &dyn Array) -> Option<()> {
+    match (&mut *values, array.data_type()) {
+        (ColumnValues::Integer(values), DataType::Int32) => {
+            values.extend(array.as_primitive::<Int32Type>().iter());
+        }
+        (ColumnValues::Varchar(values), DataType::Utf8) => {
+            let array = array.as_string::<i32>().iter();
+            values.extend(array.map(|value| value.map(str::to_string)));
+        }
+        (ColumnValues::Varchar(values), DataType::LargeUtf8) => {
+            let array = array.as_string::<i64>().iter();
+            values.extend(array.map(|value| value.map(str::to_string)));
+        }
+        (_, DataType::Dictionary(..)) => {
+            // 辞書の値を先に変換しておき、キーで引く
+            let dictionary = array.as_any_dictionary();
+            let mut keys = ColumnValues::new(values.kind());
+            push_arrow_array(&mut keys, dictionary.values().as_ref())?;
+            for (idx, key_id) in dictionary.normalized_keys().into_iter().enumerate() {
+                if array.is_null(idx) {
+                    values.push(&NULL)?;
+                } else {
+                    values.push(&keys.value_at(key_id))?;
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+impl Table {
+    ///
+    /// Apache Arrowのレコードバッチからテーブルを作ります。
+    ///
+    /// `Int32`のフィールドはINTEGER、`Utf8`と`LargeUtf8`はVARCHARのカラムになり、
+    /// 辞書型は値の型で決めます。NULLを許さないフィールドにはNOT NULL制約を付けます。
+    /// それ以外の型のフィールドがある場合は[`Error::InvalidFormat`]を返します。
+    ///
+    pub fn from_record_batches(
+        name: &str,
+        schema: &Schema,
+        batches: &[RecordBatch],
+    ) -> Result<Table, Error> {
+        let mut attributes = Vec::new();
+        for field in schema.fields() {
+            let kind = kind_of_arrow(field.data_type()).ok_or_else(|| {
+                let message = format!(
+                    "カラム{}の型{}には対応していません",
+                    field.name(),
+                    field.data_type()
+                );
+                Error::InvalidFormat(message)
+            })?;
+            let attribute = Attribute::create(field.name(), kind);
+            attributes.push(if field.is_nullable() {
+                attribute
+            } else {
+                attribute.not_null()
+            });
+        }
+        let mut columns: Vec<ColumnValues> = attributes
+            .iter()
+            .map(|attribute| ColumnValues::new(attribute.kind))
+            .collect();
+        for batch in batches {
+            if batch.num_columns() != columns.len() {
+                return Err(Error::ColumnCount {
+                    expected: columns.len(),
+                    actual: batch.num_columns(),
+                });
+            }
+            for (col_id, values) in columns.iter_mut().enumerate() {
+                let array = batch.column(col_id);
+                if push_arrow_array(values, array.as_ref()).is_none() {
+                    let message = format!(
+                        "カラム{}の型{}がスキーマと合いません",
+                        attributes[col_id].name,
+                        array.data_type()
+                    );
+                    return Err(Error::InvalidFormat(message));
+                }
+            }
+        }
+        let mut table = Table::create(name, &attributes);
+        table.append_columns(columns)?;
+        Ok(table)
+    }
+    ///
+    /// Apache ArrowのIPCファイル形式のファイルを読み込んで、テーブルを作ります。
+    ///
+    pub fn read_arrow_ipc<P: AsRef<Path>>(name: &str, path: P) -> Result<Table, Error> {
+        let reader = FileReader::try_new(fs::File::open(path)?, None)?;
+        let schema = reader.schema();
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        Table::from_record_batches(name, &schema, &batches)
+    }
+}
+
+///
+/// リレーションをParquetファイルに書き出します。
+///
+/// INTEGERのカラムは`INT32`、VARCHARのカラムはUTF-8の`BYTE_ARRAY`として書きます。
+/// カラムの辞書のうち行グループで使われている値をキーIDの順に並べて辞書ページにし、
+/// 各行のキーIDをその番号に振り直したデータページ(辞書エンコーディング)で書きます。
+/// 行グループごとにカラムの最小値・最大値の統計情報を書くので、読む側で行グループを読み飛ばせます。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// let mut shohin = Table::create(
+///     "shohin",
+///     attributes![("shohin_name", TypeKind::Varchar), ("price", TypeKind::Integer)],
+/// );
+/// shohin
+///     .insert(values!("りんご", 300))
+///     .insert(values!("みかん", 130))
+///     .insert(values!("りんご", NULL));
+/// let path = std::env::temp_dir().join("kawaii_doctest.parquet");
+/// ParquetWriter::new().row_group_size(2).write_file(&shohin, &path).unwrap();
+/// let loaded = Table::read_parquet("shohin", &path).unwrap();
+/// # std::fs::remove_file(&path).unwrap();
+/// assert_eq!(loaded.num_rows(), 3);
+/// assert_eq!(loaded.fetch(1..2).unwrap()[0][1], Value::Integer(130));
+/// ```
+///
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ParquetWriter {
+    /// 行グループの最大行数
+    row_group_size: usize,
+}
+
+impl Default for ParquetWriter {
+    fn default() -> Self {
+        ParquetWriter::new()
+    }
+}
+
+impl ParquetWriter {
+    /// 行グループの最大行数を1024 * 1024行にする設定で作ります。
+    pub fn new() -> ParquetWriter {
+        ParquetWriter {
+            row_group_size: 1024 * 1024,
+        }
+    }
+    /// 行グループの最大行数を設定します。
+    pub fn row_group_size(mut self, row_group_size: usize) -> ParquetWriter {
+        self.row_group_size = row_group_size;
+        self
+    }
+    ///
+    /// リレーションをParquet形式で書き出します。
+    ///
+    pub fn write<W: std::io::Write + Send>(
+        &self,
+        relation: &dyn Relation,
+        out: W,
+    ) -> Result<(), Error> {
+        let mut row_ids = Vec::with_capacity(relation.num_rows());
+        for_each_row_id(relation, |row_id| {
+            row_ids.push(row_id);
+            Ok(())
+        })?;
+        let definition = relation.definition();
+        let mut fields = Vec::new();
+        for (col_id, attribute) in definition.attributes.iter().enumerate() {
+            let (physical_type, logical_type) = match attribute.kind {
+                TypeKind::Integer => (PhysicalType::INT32, None),
+                TypeKind::Varchar => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            };
+            let repetition = if is_nullable(definition, col_id) {
+                Repetition::OPTIONAL
+            } else {
+                Repetition::REQUIRED
+            };
+            let field = ParquetType::primitive_type_builder(&attribute.name, physical_type)
+                .with_repetition(repetition)
+                .with_logical_type(logical_type)
+                .build()?;
+            fields.push(Arc::new(field));
+        }
+        let schema = ParquetType::group_type_builder(&definition.name)
+            .with_fields(fields)
+            .build()?;
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(out, Arc::new(schema), properties)?;
+        let descriptors = writer.schema_descr().columns().to_vec();
+        for row_ids in row_ids.chunks(cmp::max(self.row_group_size, 1)) {
+            let mut row_group = writer.next_row_group()?;
+            for (col_id, descriptor) in descriptors.iter().enumerate() {
+                let column = relation.column_at(col_id);
+                let (bytes, close) = parquet_column_chunk(column, row_ids, descriptor)?;
+                row_group.append_column(&bytes, close)?;
+            }
+            row_group.close()?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+    ///
+    /// リレーションをParquetファイルに書き出します。既にあるファイルは上書きします。
+    ///
+    pub fn write_file<P: AsRef<Path>>(
+        &self,
+        relation: &dyn Relation,
+        path: P,
+    ) -> Result<(), Error> {
+        let file = std::io::BufWriter::new(fs::File::create(path)?);
+        self.write(relation, file)
+    }
+}
+
+/// Parquetのデータページ1つに書く最大行数
+const PARQUET_PAGE_ROWS: usize = 20_000;
+
+///
+/// 行グループ1つ分のカラムのチャンクを、辞書ページとデータページにして返します。
+/// 辞書ページにはカラムの辞書のうち`row_ids`の行で使われている値をキーIDの順に並べ、
+/// データページには各行のキーIDを辞書ページでの番号に振り直して書きます。
+///
+fn parquet_column_chunk(
+    column: &dyn AsColumn,
+    row_ids: &[RowId],
+    descriptor: &ColumnDescPtr,
+) -> Result<(Bytes, ColumnCloseResult), Error> {
+    let name = descriptor.name();
+    let too_large = || Error::InvalidFormat(format!("カラム{}の行グループが大きすぎます", name));
+    let num_rows = u32::try_from(row_ids.len()).map_err(|_| too_large())?;
+    let key_ids: Vec<Option<KeyId>> = row_ids.iter().map(|&row_id| column.id_at(row_id)).collect();
+    let nullable = descriptor.max_def_level() > 0;
+    let num_nulls = key_ids.iter().filter(|key_id| key_id.is_none()).count();
+    if !nullable && num_nulls > 0 {
+        return Err(Error::NotNull(name.to_string()));
+    }
+    // 使われているキーIDをキーIDの順に番号付けする
+    let mut used = bitvec![0; column.num_keys()];
+    for &key_id in key_ids.iter().flatten() {
+        used.set(key_id, true);
+    }
+    let dictionary: Vec<KeyId> = used.iter_ones().collect();
+    let mut indices = vec![0; column.num_keys()];
+    for (index, &key_id) in dictionary.iter().enumerate() {
+        indices[key_id] = index as u32;
+    }
+    let mut plain = Vec::new();
+    for &key_id in &dictionary {
+        match column.key_of(key_id) {
+            Value::Integer(value) => plain.extend_from_slice(&value.to_le_bytes()),
+            Value::Varchar(value) => {
+                let len = u32::try_from(value.len()).map_err(|_| too_large())?;
+                plain.extend_from_slice(&len.to_le_bytes());
+                plain.extend_from_slice(value.as_bytes());
+            }
+            _ => {}
+        }
+    }
+    let mut sink = TrackedWrite::new(Vec::new());
+    let mut page_writer = SerializedPageWriter::new(&mut sink);
+    let uncompressed_size = plain.len();
+    let dictionary_page = Page::DictionaryPage {
+        buf: Bytes::from(plain),
+        num_values: dictionary.len() as u32,
+        encoding: ParquetEncoding::PLAIN,
+        is_sorted: column.is_sorted(),
+    };
+    page_writer.write_page(CompressedPage::new(dictionary_page, uncompressed_size))?;
+    let bit_width = cmp::max(
+        usize::BITS - dictionary.len().saturating_sub(1).leading_zeros(),
+        1,
+    );
+    let mut data_page_offset = None;
+    for key_ids in key_ids.chunks(PARQUET_PAGE_ROWS) {
+        let mut buf = Vec::new();
+        // 定義レベル(NULLでなければ1)は長さを前に付けて書く
+        if nullable {
+            let levels: Vec<u32> = key_ids
+                .iter()
+                .map(|key_id| key_id.is_some() as u32)
+                .collect();
+            let mut encoded = Vec::new();
+            encode_rle_hybrid(&levels, 1, &mut encoded);
+            buf.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&encoded);
+        }
+        buf.push(bit_width as u8);
+        let values: Vec<u32> = key_ids
+            .iter()
+            .flatten()
+            .map(|&key_id| indices[key_id])
+            .collect();
+        encode_rle_hybrid(&values, bit_width, &mut buf);
+        let uncompressed_size = buf.len();
+        let data_page = Page::DataPage {
+            buf: Bytes::from(buf),
+            num_values: key_ids.len() as u32,
+            encoding: ParquetEncoding::RLE_DICTIONARY,
+            def_level_encoding: ParquetEncoding::RLE,
+            rep_level_encoding: ParquetEncoding::RLE,
+            statistics: None,
+        };
+        let spec = page_writer.write_page(CompressedPage::new(data_page, uncompressed_size))?;
+        data_page_offset.get_or_insert(spec.offset as i64);
+    }
+    page_writer.close()?;
+    let bytes = Bytes::from(sink.into_inner()?);
+    // 辞書の値の最小値と最大値が、行グループの最小値と最大値になる
+    let values = dictionary.iter().map(|&key_id| column.key_of(key_id));
+    let (min, max) = match (values.clone().min(), values.max()) {
+        (Some(min), Some(max)) => (Some(min), Some(max)),
+        _ => (None, None),
+    };
+    let num_nulls = Some(num_nulls as u64);
+    let statistics = match column.kind() {
+        TypeKind::Integer => {
+            let as_i32 = |value: Option<Value>| match value {
+                Some(Value::Integer(value)) => Some(value),
+                _ => None,
+            };
+            ParquetStatistics::int32(as_i32(min), as_i32(max), None, num_nulls, false)
+        }
+        TypeKind::Varchar => {
+            let as_bytes = |value: Option<Value>| match value {
+                Some(Value::Varchar(value)) => Some(ByteArray::from(value.as_bytes().to_vec())),
+                _ => None,
+            };
+            ParquetStatistics::byte_array(as_bytes(min), as_bytes(max), None, num_nulls, false)
+        }
+    };
+    let metadata = ColumnChunkMetaData::builder(Arc::clone(descriptor))
+        .set_encodings(vec![
+            ParquetEncoding::PLAIN,
+            ParquetEncoding::RLE,
+            ParquetEncoding::RLE_DICTIONARY,
+        ])
+        .set_compression(Compression::UNCOMPRESSED)
+        .set_num_values(i64::from(num_rows))
+        .set_total_compressed_size(bytes.len() as i64)
+        .set_total_uncompressed_size(bytes.len() as i64)
+        .set_dictionary_page_offset(Some(0))
+        .set_data_page_offset(data_page_offset.unwrap_or(0))
+        .set_statistics(statistics)
+        .build()?;
+    let close = ColumnCloseResult {
+        bytes_written: bytes.len() as u64,
+        rows_written: u64::from(num_rows),
+        metadata,
+        bloom_filter: None,
+        column_index: None,
+        offset_index: None,
+    };
+    Ok((bytes, close))
+}
+
+///
+/// 値をParquetのRLEとビットパッキングの混成(RLE/Bit-Packing Hybrid)で符号化します。
+/// 同じ値が8個以上続くところはRLEで、それ以外は8個ずつのグループにしてビットパッキングで書きます。
+///
+fn encode_rle_hybrid(values: &[u32], bit_width: u32, out: &mut Vec<u8>) {
+    let push_uleb128 = |out: &mut Vec<u8>, mut value: usize| {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    };
+    let is_long_run = |start: usize| {
+        values.len() - start >= 8 && values[start..start + 8].iter().all(|&v| v == values[start])
+    };
+    let mut start = 0;
+    while start < values.len() {
+        if is_long_run(start) {
+            let run = values[start..]
+                .iter()
+                .take_while(|&&value| value == values[start])
+                .count();
+            push_uleb128(out, run << 1);
+            let bytes = values[start].to_le_bytes();
+            out.extend_from_slice(&bytes[..(bit_width as usize).div_ceil(8)]);
+            start += run;
+            continue;
+        }
+        // ヘッダーの1バイトに収まる63グループまで、長いランが始まる手前までをまとめる
+        let mut end = start;
+        let mut num_groups = 0;
+        loop {
+            end = cmp::min(end + 8, values.len());
+            num_groups += 1;
+            if end == values.len() || num_groups == 63 || is_long_run(end) {
+                break;
+            }
+        }
+        push_uleb128(out, num_groups << 1 | 1);
+        let mut bits: u64 = 0;
+        let mut num_bits = 0;
+        for i in start..start + num_groups * 8 {
+            bits |= u64::from(values.get(i).copied().unwrap_or(0)) << num_bits;
+            num_bits += bit_width;
+            while num_bits >= 8 {
+                out.push(bits as u8);
+                bits >>= 8;
+                num_bits -= 8;
+            }
+        }
+        start = end;
+    }
+}
+
+impl From<ParquetError> for Error {
+    fn from(err: ParquetError) -> Self {
+        Error::InvalidFormat(err.to_string())
+    }
+}
+
+impl Table {
+    ///
+    /// Parquetファイルを読み込んで、テーブルを作ります。
+    /// カラムの型の対応は[`Table::from_record_batches`]と同じです。
+    ///
+    pub fn read_parquet<P: AsRef<Path>>(name: &str, path: P) -> Result<Table, Error> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(path)?)?;
+        let schema = Arc::clone(builder.schema());
+        let batches = builder.build()?.collect::<Result<Vec<_>, _>>()?;
+        Table::from_record_batches(name, &schema, &batches)
+    }
+}
+
+///
+/// 構造体とテーブルの行を対応付けます。`#[derive(Row)]`で実装します。
+///
+/// 値の変換はserdeの`Serialize`・`Deserialize`で行うので、構造体にはそれらも導出します。
+/// `#[derive(Row)]`はフィールドに対応するカラムの定義だけを作ります。カラム名はフィールド名か、
+/// `#[serde(rename = "名前")]`で指定した名前です。
+///
+/// フィールドの型は[`RowField`]を実装した`i32`(INTEGER)・`String`(VARCHAR)と、
+/// それらの`Option`(NULLを許すカラム)が使えます。`Option`でないフィールドのカラムには
+/// NOT NULL制約が付きます。それ以外の型のフィールドはコンパイルエラーになります。
+///
+/// # Examples
+///
+/// ```
+/// use kawaii::*;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Row, Serialize, Deserialize, Debug, PartialEq)]
+/// struct Shohin {
+///     shohin_id: i32,
+///     #[serde(rename = "shohin_name")]
+///     name: String,
+///     kubun_id: Option<i32>,
+///     price: i32,
+/// }
+///
+/// let mut shohin = Table::create_for::<Shohin>("shohin");
+/// assert!(shohin.definition()[1].is_not_null());
+/// assert!(!shohin.definition()[2].is_not_null());
+/// let wakame = Shohin {
+///     shohin_id: 5,
+///     name: "わかめ".to_string(),
+///     kubun_id: None,
+///     price: 250,
+/// };
+/// shohin.insert_row(&wakame).unwrap();
+/// let rows: Vec<Shohin> = shohin.fetch_rows(0..1).unwrap();
+/// assert_eq!(rows, vec![wakame]);
+/// ```
+///
+/// 対応するカラムの型がないフィールドはコンパイルできません。
+///
+/// ```compile_fail
+/// use kawaii::*;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Row, Serialize, Deserialize)]
+/// struct Shohin {
+///     price: f64,
+/// }
+/// ```
+///
+pub trait Row: Serialize + DeserializeOwned {
+    /// フィールドに対応するカラムの定義を、フィールドの順に返します。
+    fn attributes() -> Vec<Attribute>;
+    /// フィールドの順に、定義の中で対応するカラムのIDを返します。
+    fn column_ids(definition: &Definition) -> Result<Vec<ColumnId>, Error> {
+        Self::attributes()
+            .iter()
+            .map(|attribute| {
+                definition
+                    .name_to_id(attribute.name())
+                    .ok_or_else(|| Error::UnknownColumn(attribute.name().clone()))
+            })
+            .collect()
+    }
+    /// 定義と値の組から、カラム名で対応付けて構造体を作ります。
+    fn from_tuple(definition: &Definition, tuple: &[Value<'_>]) -> Result<Self, Error> {
+        let attributes = Self::attributes();
+        let col_ids = Self::column_ids(definition)?;
+        Self::deserialize(RowDeserializer {
+            tuple,
+            col_ids: &col_ids,
+            attributes: &attributes,
+        })
+    }
+}
+
+///
+/// [`Row`]を導出した構造体のフィールドとして使える型です。
+///
+pub trait RowField {
+    /// 対応するカラムの型
+    const KIND: TypeKind;
+    /// NULLを許すか
+    const NULLABLE: bool;
+    /// カラム名から、このフィールドに対応するカラムの定義を作ります。
+    fn attribute(name: &str) -> Attribute {
+        let attribute = Attribute::create(name, Self::KIND);
+        if Self::NULLABLE {
+            attribute
+        } else {
+            attribute.not_null()
+        }
+    }
+}
+
+impl RowField for i32 {
+    const KIND: TypeKind = TypeKind::Integer;
+    const NULLABLE: bool = false;
+}
+
+impl RowField for String {
+    const KIND: TypeKind = TypeKind::Varchar;
+    const NULLABLE: bool = false;
+}
+
+impl<T: RowField> RowField for Option<T> {
+    const KIND: TypeKind = T::KIND;
+    const NULLABLE: bool = true;
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Serde(msg.to_string())
+    }
+}
+
+///
+/// 行の値を、[`Row::column_ids`]で求めたカラムのIDでフィールドに渡すデシリアライザーです。
+/// フィールド名と値の組の並び(マップ)として渡します。
+///
+struct RowDeserializer<'a, 'b> {
+    tuple: &'a [Value<'b>],
+    col_ids: &'a [ColumnId],
+    attributes: &'a [Attribute],
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'_, '_> {
+    type Error = Error;
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(RowAccess {
+            row: self,
+            field_no: 0,
+        })
+    }
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// [`RowDeserializer`]のフィールドを順に渡します。
+struct RowAccess<'a, 'b> {
+    row: RowDeserializer<'a, 'b>,
+    field_no: usize,
+}
+
+impl<'de> MapAccess<'de> for RowAccess<'_, '_> {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.row.attributes.get(self.field_no) {
+            Some(attribute) => seed
+                .deserialize(attribute.name.as_str().into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let attribute = &self.row.attributes[self.field_no];
+        let value = self
+            .row
+            .col_ids
+            .get(self.field_no)
+            .and_then(|&col_id| self.row.tuple.get(col_id))
+            .ok_or_else(|| Error::UnknownColumn(attribute.name.clone()))?;
+        self.field_no += 1;
+        if value.is_null() {
+            if attribute.not_null {
+                return Err(Error::NotNull(attribute.name.clone()));
+            }
+        } else if value.kind() != Some(attribute.kind) {
+            return Err(Error::TypeMismatch {
+                column: attribute.name.clone(),
+                expected: attribute.kind,
+                value: value.clone().into(),
+            });
+        }
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+/// 1つの値をフィールドに渡します。NULLは`None`になります。
+struct ValueDeserializer<'a, 'b>(&'a Value<'b>);
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_, '_> {
+    type Error = Error;
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Integer(value) => visitor.visit_i32(*value),
+            Value::Varchar(value) => visitor.visit_str(value),
+            _ => visitor.visit_none(),
+        }
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.0.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+///
+/// 構造体をserdeでJSONの値にシリアライズして、フィールドの順のカラム名と値の組にします。
+///
+fn named_values_of<R: Row>(row: &R) -> Result<Vec<(String, OwnedValue)>, Error> {
+    let mut object = match serde_json::to_value(row) {
+        Ok(serde_json::Value::Object(object)) => object,
+        Ok(json) => return Err(Error::Serde(format!("行が構造体ではありません: {}", json))),
+        Err(err) => return Err(Error::Serde(err.to_string())),
+    };
+    // フィールドの順に取り出す
+    R::attributes()
+        .into_iter()
+        .map(|attribute| {
+            let name = attribute.name;
+            let json = object
+                .remove(&name)
+                .ok_or_else(|| Error::Serde(format!("フィールド{}がありません", name)))?;
+            let value = match &json {
+                serde_json::Value::Null => Some(NULL.into()),
+                serde_json::Value::String(value) => Some(value.as_str().into()),
+                serde_json::Value::Number(value) => value
+                    .as_i64()
+                    .and_then(|value| i32::try_from(value).ok())
+                    .map(OwnedValue::from),
+                _ => None,
+            };
+            match value {
+                Some(value) => Ok((name, value)),
+                None => Err(Error::Serde(format!(
+                    "フィールド{}の値{}はカラムの値にできません",
+                    name, json
+                ))),
+            }
+        })
+        .collect()
+}
+
+impl Table {
+    ///
+    /// [`Row`]を実装した構造体のフィールドをカラムにしたテーブルを作ります。
+    ///
+    pub fn create_for<R: Row>(name: &str) -> Table {
+        Table::create(name, &R::attributes())
+    }
+    ///
+    /// 構造体を1行として挿入します。フィールドとカラムは名前で対応付け、
+    /// 構造体にないカラムにはデフォルト値かNULLを入れます。
+    /// 検査は[`Table::try_insert_named`]と同じです。
+    ///
+    pub fn insert_row<R: Row>(&mut self, row: &R) -> Result<&mut Table, Error> {
+        let tuple = self.complete_row(row)?;
+        self.try_insert(&tuple)
+    }
+    ///
+    /// 構造体の配列をまとめて追記します。検査は[`Table::insert_rows`]と同じで、
+    /// どれかの行が失敗した場合は何も追加しません。
+    ///
+    pub fn append_rows<R: Row>(&mut self, rows: &[R]) -> Result<&mut Table, Error> {
+        let tuples = rows
+            .iter()
+            .map(|row| self.complete_row(row))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.insert_rows(&tuples)
+    }
+    /// 構造体のフィールドを[`Table::complete_tuple`]と同じようにカラムの順の値の組にします。
+    fn complete_row<R: Row>(&self, row: &R) -> Result<Vec<OwnedValue>, Error> {
+        let pairs = named_values_of(row)?;
+        let pairs: Vec<(&str, Value)> = pairs
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_value_ref()))
+            .collect();
+        self.complete_tuple(&pairs)
+    }
+}
+
+///
+/// リレーションの行を[`Row`]を実装した構造体として取り出します。
+///
+pub trait FetchRows: Relation {
+    /// 指定された範囲をフェッチして、カラム名でフィールドに対応付けた構造体にします。
+    /// カラム名の対応は呼び出しごとに一度だけ求めます。
+    fn fetch_rows<R: Row>(&self, range: Range<RowId>) -> Result<Vec<R>, Error>;
+}
+
+impl<T> FetchRows for T
+where
+    T: Relation,
+{
+    fn fetch_rows<R: Row>(&self, range: Range<RowId>) -> Result<Vec<R>, Error> {
+        match self.fetch(range) {
+            Some(tuples) => {
+                let attributes = R::attributes();
+                let col_ids = R::column_ids(self.definition())?;
+                tuples
+                    .iter()
+                    .map(|tuple| {
+                        R::deserialize(RowDeserializer {
+                            tuple,
+                            col_ids: &col_ids,
+                            attributes: &attributes,
+                        })
+                    })
+                    .collect()
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+///
+/// テーブルinser時のパラメーターを簡便にします。
+///
+/// # Examples
+///
+/// ```
+/// # use kawaii::*;
+/// # fn main() {
+/// #     let mut table = Table::create(
+/// #         "shohin",
+/// #         attributes![
+/// #             ("shohin_id", TypeKind::Integer),
+/// #             ("shohin_name", TypeKind::Varchar),
+/// #             ("kubun_id", TypeKind::Integer),
+/// #             ("price", TypeKind::Integer)
+/// #         ],
+/// #     );
+/// #     table
+/// #         .insert(values!(1, "りんご", 1, 300))
+/// #         .insert(values!(2, "みかん", 1, 130))
+/// #         .insert(values!(3, "キャベツ", 2, 200))
+/// #         .insert(values!(4, "さんま", 3, 220))
+/// #         .insert(values!(5, "わかめ", NULL, 250)) //区分がNULL
+/// #         .insert(values!(6, "しいたけ", 4, 180)) //該当区分なし
+/// #         .insert(values!(7, "ドリアン", 1, NULL));
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! values {
+    ( $( $x:expr ),* ) => ( &[ $( Value::from($x) ),* ] )
+}
+
+///
+/// カラム名と値の組のスライスを簡便に定義します。
+/// [`Table::insert_named`]や[`Table::update`]に渡せます。
+///
+/// # Examples
+///
+/// ```
+/// # use kawaii::*;
+/// let pairs = named_values!("shohin_id" => 1, "shohin_name" => "りんご", "kubun_id" => NULL);
+/// assert_eq!(pairs[1], ("shohin_name", Value::Varchar("りんご")));
+/// ```
+///
+#[macro_export]
+macro_rules! named_values {
+    ( $( $name:expr => $x:expr ),* ) => ( &[ $( ($name, Value::from($x)) ),* ] )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_dictionary_varchar() {
+        let mut dictionary = Dictionary::new();
+        assert_eq!(dictionary.insert("Alice".to_string()), 0);
+        assert_eq!(dictionary.insert("Bob".to_string()), 1);
+        assert_eq!(dictionary.insert("Alice".to_string()), 0);
+        assert_eq!(dictionary.num_keys(), 2);
+        assert_eq!(dictionary.id_of("Alice"), Some(0));
+        assert_eq!(dictionary.id_of("Bob"), Some(1));
+        assert_eq!(dictionary.id_of("Chris"), None);
+        let mut iter = dictionary.range("Alice".to_string().."Chris".to_string());
+        assert_eq!(iter.next(), Some((&"Alice".to_string(), &0)));
+        assert_eq!(iter.next(), Some((&"Bob".to_string(), &1)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(dictionary.key_of(0), &"Alice".to_string());
+        assert_eq!(dictionary.key_of(1), &"Bob".to_string());
+        // 複製は元の辞書を捨てた後も自分の値で引ける
+        let cloned = dictionary.clone();
+        drop(dictionary);
+        assert_eq!(cloned.id_of("Bob"), Some(1));
+        assert_eq!(cloned.range::<str, _>(..).count(), 2);
+    }
+
+    #[test]
+    fn test_column_varchar() {
+        let mut column = Column::new();
+        assert_eq!(column.append("Alice".to_string()), 0);
+        assert_eq!(column.append("Bob".to_string()), 1);
+        assert_eq!(column.append("Alice".to_string()), 0);
+        assert_eq!(column.num_keys(), 2);
+        assert_eq!(column.num_rows(), 3);
+        assert_eq!(column.id_of("Alice"), Some(0));
+        assert_eq!(column.id_of("Bob"), Some(1));
+        assert_eq!(column.id_of("Chris"), None);
+        let mut iter = column.range("Alice".to_string().."Chris".to_string());
+        assert_eq!(iter.next(), Some((&"Alice".to_string(), &0)));
+        assert_eq!(iter.next(), Some((&"Bob".to_string(), &1)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(column.key_of(0), &"Alice".to_string());
+        assert_eq!(column.key_of(1), &"Bob".to_string());
+        assert_eq!(column.key_at(0), Some(&"Alice".to_string()));
+        assert_eq!(column.key_at(1), Some(&"Bob".to_string()));
+        assert_eq!(column.key_at(2), Some(&"Alice".to_string()));
+    }
+
+    #[test]
+    fn test_dictionary_integer() {
+        let mut dictionary = Dictionary::new();
+        assert_eq!(dictionary.insert(10), 0);
+        assert_eq!(dictionary.insert(20), 1);
+        assert_eq!(dictionary.insert(10), 0);
+        assert_eq!(dictionary.num_keys(), 2);
+        assert_eq!(dictionary.id_of(&10), Some(0));
+        assert_eq!(dictionary.id_of(&20), Some(1));
+        assert_eq!(dictionary.id_of(&30), None);
+        let mut iter = dictionary.range(10..30);
+        assert_eq!(iter.next(), Some((&10, &0)));
+        assert_eq!(iter.next(), Some((&20, &1)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(dictionary.key_of(0), &10);
+        assert_eq!(dictionary.key_of(1), &20);
+    }
+
+    #[test]
+    fn test_column_integer() {
+        let mut column = Column::new();
+        assert_eq!(column.append(10), 0);
+        assert_eq!(column.append(20), 1);
+        assert_eq!(column.append(10), 0);
+        assert_eq!(column.num_keys(), 2);
+        assert_eq!(column.num_rows(), 3);
+        assert_eq!(column.id_of(&10), Some(0));
+        assert_eq!(column.id_of(&20), Some(1));
+        assert_eq!(column.id_of(&30), None);
+        let mut iter = column.range(10..30);
+        assert_eq!(iter.next(), Some((&10, &0)));
+        assert_eq!(iter.next(), Some((&20, &1)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(column.key_of(0), &10);
+        assert_eq!(column.key_of(1), &20);
+        assert_eq!(column.key_at(0), Some(&10));
+        assert_eq!(column.key_at(1), Some(&20));
+        assert_eq!(column.key_at(2), Some(&10));
+    }
+
+    #[test]
+    fn test_packed_array() {
+        let mut array = PackedArray::new();
+        let mut expected = Vec::new();
+        for i in 0..1000 {
+            let value = (i * 7919) % (1 << (i % 20));
+            array.push(value);
+            expected.push(value);
+        }
+        assert_eq!(array.bit_width(), 19);
+        assert_eq!(array.iter().collect::<Vec<_>>(), expected);
+        array.set(500, usize::MAX);
+        expected[500] = usize::MAX;
+        assert_eq!(array.bit_width(), 64);
+        assert_eq!(array.iter().collect::<Vec<_>>(), expected);
+        while let Some(value) = array.pop() {
+            assert_eq!(Some(value), expected.pop());
+        }
+        assert!(array.is_empty());
+        assert_eq!(array.num_bytes(), 0);
+        // 値の種類が少なければ1行あたりのサイズも小さい
+        let mut array = PackedArray::new();
+        for i in 0..1024 {
+            array.push(i % 10);
+        }
+        assert_eq!(array.bit_width(), 4);
+        assert_eq!(array.num_bytes(), 1024 / 2);
     }
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(tuples) = self.fetch(0..self.num_rows()) {
-            for col_id in 0..self.num_columns() {
-                let name = &self.definition()[col_id].name();
-                if let fmt::Result::Err(err) = write!(f, "│{}", name) {
-                    return fmt::Result::Err(err);
-                }
-            }
-            if let fmt::Result::Err(err) = write!(f, "│\n") {
-                return fmt::Result::Err(err);
+
+    #[test]
+    fn test_column_null() {
+        let mut column = Column::new();
+        assert_eq!(column.append(10), 0);
+        column.append_null();
+        assert_eq!(column.append(20), 1);
+        assert_eq!(column.num_keys(), 2);
+        assert_eq!(column.num_rows(), 3);
+        assert_eq!(column.num_nulls(), 1);
+        assert_eq!(column.id_at(0), Some(0));
+        assert_eq!(column.id_at(1), None);
+        assert_eq!(column.id_at(2), Some(1));
+        assert_eq!(column.key_at(1), None);
+        assert!(column.is_null_at(1));
+        assert_eq!(column.validity(), bits![1, 0, 1]);
+        assert_eq!(column.pop(), Some(2));
+        assert_eq!(column.pop(), Some(1));
+        assert_eq!(column.num_nulls(), 0);
+        assert_eq!(column.pop(), Some(0));
+        assert_eq!(column.pop(), None);
+    }
+
+    /// リレーションの全行を所有権を持つ値に変換します。
+    fn owned_rows(relation: &dyn Relation) -> Vec<Vec<OwnedValue>> {
+        let mut rows = Vec::new();
+        if let Some(tuples) = relation.fetch(0..relation.num_rows()) {
+            for tuple in tuples.iter() {
+                rows.push(tuple.iter().map(|value| value.clone().into()).collect());
             }
-            for tuple in tuples.deref() {
-                for field in tuple.deref() {
-                    if let fmt::Result::Err(err) = write!(f, "│{}", field) {
-                        return fmt::Result::Err(err);
+        }
+        rows
+    }
+
+    fn create_shohin_table() -> Table {
+        let mut table = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        table
+            .insert(values!(1, "りんご", 1, 300))
+            .insert(values!(2, "みかん", 1, 130))
+            .insert(values!(3, "キャベツ", 2, 200))
+            .insert(values!(4, "さんま", 3, 220))
+            .insert(values!(5, "わかめ", NULL, 250)) //区分がNULL
+            .insert(values!(6, "しいたけ", 4, 180)) //該当区分なし
+            .insert(values!(7, "ドリアン", 1, NULL));
+        table
+    }
+
+    fn create_kubun_table() -> Table {
+        let mut table = Table::create(
+            "kubun",
+            attributes![
+                ("kubun_id", TypeKind::Integer),
+                ("kubun_name", TypeKind::Varchar)
+            ],
+        );
+        table
+            .insert(values!(1, "くだもの"))
+            .insert(values!(2, "野菜"))
+            .insert(values!(3, "魚"));
+        table
+    }
+
+    #[test]
+    fn test_table() {
+        let shohin1 = create_shohin_table();
+        let shohin2 = create_shohin_table();
+        assert_eq!(shohin1, shohin2);
+        let kubun1 = create_kubun_table();
+        let kubun2 = create_kubun_table();
+        assert_eq!(kubun1, kubun2);
+    }
+
+    #[test]
+    fn test_select() {
+        let shohin = create_shohin_table();
+        let actual = shohin.select(&["shohin_id", "shohin_name"]);
+        let mut expected = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar)
+            ],
+        );
+        expected
+            .insert(values!(1, "りんご"))
+            .insert(values!(2, "みかん"))
+            .insert(values!(3, "キャベツ"))
+            .insert(values!(4, "さんま"))
+            .insert(values!(5, "わかめ")) //区分がNULL
+            .insert(values!(6, "しいたけ")) //該当区分なし
+            .insert(values!(7, "ドリアン"));
+        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+    }
+
+    #[test]
+    fn test_less_than() {
+        let shohin = create_shohin_table();
+        let actual = shohin.less_than("shohin_id", 4);
+        let mut expected = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        expected
+            .insert(values!(1, "りんご", 1, 300))
+            .insert(values!(2, "みかん", 1, 130))
+            .insert(values!(3, "キャベツ", 2, 200));
+        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+    }
+
+    #[test]
+    fn test_equal_to() {
+        let shohin = create_shohin_table();
+        let actual = shohin.equal_to("shohin_id", 4);
+        let mut expected = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        expected.insert(values!(4, "さんま", 3, 220));
+        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+    }
+
+    #[test]
+    fn test_is_null() {
+        let shohin = create_shohin_table();
+        let actual = shohin.is_null("kubun_id");
+        let mut expected = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        expected.insert(values!(5, "わかめ", NULL, 250));
+        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+        assert_eq!(shohin.is_not_null("kubun_id").num_rows(), 6);
+        // '= NULL' はUNKNOWNなので何も抽出されない
+        assert_eq!(shohin.equal_to("kubun_id", NULL).num_rows(), 0);
+    }
+
+    #[test]
+    fn test_filter_three_valued_logic() {
+        let shohin = create_shohin_table();
+        let shohin_ids = |relation: &dyn Relation| -> Vec<i32> {
+            let mut ids = Vec::new();
+            if let Some(tuples) = relation.fetch(0..relation.num_rows()) {
+                for tuple in tuples.iter() {
+                    if let Value::Integer(id) = tuple[0] {
+                        ids.push(id);
                     }
                 }
-                if let fmt::Result::Err(err) = write!(f, "│\n") {
-                    return fmt::Result::Err(err);
-                }
             }
+            ids
+        };
+        // NOT (price < 200) はpriceがNULLのドリアンを含まない
+        let actual = shohin.filter(&!Predicate::less_than("price", 200));
+        assert_eq!(shohin_ids(&actual), vec![1, 3, 4, 5]);
+        let actual = shohin.filter(&Predicate::greater_equal("price", 200));
+        assert_eq!(shohin_ids(&actual), vec![1, 3, 4, 5]);
+        let actual =
+            shohin.filter(&Predicate::less_than("price", 200).or(Predicate::is_null("price")));
+        assert_eq!(shohin_ids(&actual), vec![2, 6, 7]);
+        // NOT (kubun_id = 1) は区分がNULLのわかめを含まない
+        let actual = shohin.filter(&!Predicate::equal_to("kubun_id", 1));
+        assert_eq!(shohin_ids(&actual), vec![3, 4, 6]);
+        let actual = shohin.filter(&Predicate::not_equal_to("kubun_id", 1));
+        assert_eq!(shohin_ids(&actual), vec![3, 4, 6]);
+        // UNKNOWN OR TRUE は TRUE
+        let actual = shohin.filter(
+            &Predicate::equal_to("kubun_id", NULL).or(Predicate::less_equal("shohin_id", 2)),
+        );
+        assert_eq!(shohin_ids(&actual), vec![1, 2]);
+        // UNKNOWN AND FALSE は FALSE なので NOT すると TRUE
+        let actual = shohin.filter(
+            &!(Predicate::greater_than("kubun_id", 2).and(Predicate::less_than("price", 0))),
+        );
+        assert_eq!(shohin_ids(&actual), vec![1, 2, 3, 4, 5, 6, 7]);
+        // 存在しないカラム名は何も抽出しない
+        let actual = shohin.filter(&Predicate::is_null("no_such_column"));
+        assert_eq!(actual.num_rows(), 0);
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let mut shohin = create_shohin_table();
+        shohin.sort_keys();
+        assert_eq!(shohin.fetch(0..10), create_shohin_table().fetch(0..10));
+        let kubun_ids = shohin.column_at(2);
+        assert!(kubun_ids.is_sorted());
+        assert_eq!(kubun_ids.lower_bound(&2), Some(1));
+        assert_eq!(kubun_ids.upper_bound(&2), Some(2));
+        assert_eq!(kubun_ids.lower_bound(&5), Some(4));
+        assert_eq!(shohin.less_than("price", 200).num_rows(), 2);
+        assert_eq!(
+            shohin
+                .filter(&Predicate::less_equal("price", 200))
+                .num_rows(),
+            3
+        );
+        assert_eq!(
+            shohin
+                .filter(&Predicate::greater_than("price", 200))
+                .num_rows(),
+            3
+        );
+        assert_eq!(
+            shohin
+                .filter(&Predicate::not_equal_to("kubun_id", 1))
+                .num_rows(),
+            3
+        );
+        // 最大値より小さい値が追加されると範囲判定は使えなくなる
+        shohin.insert(values!(8, "すいか", 1, 100));
+        assert!(!shohin.column_at(3).is_sorted());
+        assert_eq!(shohin.less_than("price", 200).num_rows(), 3);
+    }
+
+    #[test]
+    fn test_order_by() {
+        let mut shohin = create_shohin_table();
+        let expected = [2, 6, 3, 4, 5, 1, 7];
+        for sort in &[false, true] {
+            if *sort {
+                shohin.sort_keys();
+            }
+            let actual = shohin.order_by(&[SortKey::asc("price")]);
+            let ids: Vec<_> = actual
+                .fetch(0..10)
+                .unwrap()
+                .iter()
+                .map(|tuple| tuple[0].clone())
+                .collect();
+            let expected: Vec<Value> = expected.iter().map(|id| (*id).into()).collect();
+            assert_eq!(ids, expected);
+            let actual = shohin.order_by(&[SortKey::desc("kubun_id"), SortKey::asc("shohin_name")]);
+            let names: Vec<_> = actual
+                .fetch(0..10)
+                .unwrap()
+                .iter()
+                .map(|tuple| tuple[1].clone())
+                .collect();
+            let expected: Vec<Value> = vec![
+                "わかめ",
+                "しいたけ",
+                "さんま",
+                "キャベツ",
+                "みかん",
+                "りんご",
+                "ドリアン",
+            ]
+            .into_iter()
+            .map(|name| name.into())
+            .collect();
+            assert_eq!(names, expected);
         }
-        write!(f, "")
     }
-}
 
-/// テーブルを表します。
-#[derive(PartialEq, Eq, Debug)]
-pub struct Table {
-    num_rows: RowId,
-    definition: Definition,
-    columns: Vec<TableColumn>,
-}
+    #[test]
+    fn test_run_length_encoding() {
+        let mut table = Table::create(
+            "uriage",
+            attributes![
+                ("uriage_id", TypeKind::Integer),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        for i in 0..3000 {
+            let kubun_id: Value = if i < 1000 {
+                NULL.into()
+            } else {
+                (i / 1000).into()
+            };
+            table.insert(values!(i, kubun_id, 100 * (i / 1500)));
+        }
+        let predicate =
+            Predicate::greater_equal("kubun_id", 2).or(Predicate::less_than("price", 100));
+        let aggs = [Agg::count("uriage_id"), Agg::average("uriage_id")];
+        let filtered = owned_rows(&table.filter(&predicate));
+        let mut grouped = owned_rows(&table.group_by(&["kubun_id", "price"], &aggs));
+        grouped.sort();
+        table.choose_encodings();
+        assert_eq!(table.columns[0].encoding(), Encoding::Packed);
+        assert_eq!(table.columns[1].encoding(), Encoding::RunLength);
+        assert_eq!(table.columns[2].encoding(), Encoding::RunLength);
+        assert_eq!(table.column_at(1).run_end(0), 1000);
+        assert_eq!(table.column_at(1).run_end(1500), 2000);
+        assert_eq!(table.column_at(1).id_at(1500), Some(0));
+        assert_eq!(owned_rows(&table.filter(&predicate)), filtered);
+        let mut actual = owned_rows(&table.group_by(&["kubun_id", "price"], &aggs));
+        actual.sort();
+        assert_eq!(actual, grouped);
+        // ラン長圧縮されたカラムにも追記や並べ替えができる
+        table.insert(values!(3000, 1, NULL));
+        table.sort_keys();
+        assert_eq!(table.is_null("price").num_rows(), 1);
+        assert_eq!(table.equal_to("kubun_id", 1).num_rows(), 1001);
+        table.set_encoding("kubun_id", Encoding::Packed);
+        assert_eq!(table.equal_to("kubun_id", 1).num_rows(), 1001);
+        // 削除済みの行があってもランごとの評価と集計が行ごとの場合と一致する
+        table.delete_where(&Predicate::less_than("uriage_id", 10));
+        table.delete_where(&Predicate::equal_to("uriage_id", 1999));
+        let filtered = owned_rows(&table.filter(&predicate));
+        let mut grouped = owned_rows(&table.group_by(&["kubun_id", "price"], &aggs));
+        grouped.sort();
+        table.set_encoding("uriage_id", Encoding::RunLength);
+        table.set_encoding("kubun_id", Encoding::RunLength);
+        assert_eq!(owned_rows(&table.filter(&predicate)), filtered);
+        let mut actual = owned_rows(&table.group_by(&["kubun_id", "price"], &aggs));
+        actual.sort();
+        assert_eq!(actual, grouped);
+        let filtered = table.filter(&predicate);
+        assert!(filtered.row_set().unwrap().num_bytes() < 100);
+    }
 
-impl Table {
-    pub fn new(definition: Definition) -> Table {
-        let mut columns = Vec::new();
-        for attribute in &definition.attributes {
-            columns.push(TableColumn::new(attribute.kind));
+    #[test]
+    fn test_bitmap_index() {
+        let mut shohin = create_shohin_table();
+        shohin.create_index("kubun_id");
+        shohin.create_index("price");
+        assert!(shohin.create_index("no_such_column").is_none());
+        let plain = create_shohin_table();
+        let predicates = [
+            Predicate::equal_to("kubun_id", 1),
+            !Predicate::equal_to("kubun_id", 1),
+            Predicate::is_in("kubun_id", values!(2, 3, 5)),
+            // IN (..., NULL) は一致しなければUNKNOWN
+            !Predicate::is_in("kubun_id", values!(1, NULL)),
+            Predicate::less_than("price", 200).or(Predicate::is_null("kubun_id")),
+            !(Predicate::greater_than("kubun_id", 2).and(Predicate::less_than("price", 0))),
+            Predicate::equal_to("kubun_id", 1).and(Predicate::greater_equal("shohin_id", 2)),
+            !(Predicate::is_not_null("kubun_id").and(Predicate::equal_to("price", 100))),
+        ];
+        let order = [SortKey::desc("price")];
+        for predicate in &predicates {
+            assert_eq!(
+                owned_rows(&shohin.filter(predicate)),
+                owned_rows(&plain.filter(predicate)),
+                "{:?}",
+                predicate
+            );
+            // 並べ替え済みのリレーションでも順序を保って突き合わせる
+            assert_eq!(
+                owned_rows(&shohin.order_by(&order).filter(predicate)),
+                owned_rows(&plain.order_by(&order).filter(predicate)),
+                "{:?}",
+                predicate
+            );
         }
-        Table {
-            num_rows: 0,
-            definition: definition,
-            columns: columns,
+        // 値ごとの行番号の集合は圧縮して持つ
+        let index = shohin.column_at(3).index().unwrap();
+        assert_eq!(index.rows_of(0).len(), 1);
+        assert!(index.rows_of(0).num_bytes() < 100);
+        assert_eq!(
+            owned_rows(&shohin.filter(&Predicate::is_in("kubun_id", values!(2, 3))))[1][0],
+            OwnedValue::Integer(4)
+        );
+        // insertとその巻き戻し、キーIDの振り直しでもインデックスが追従する
+        shohin.insert(values!(8, "すいか", 5, 1000));
+        assert!(shohin.insert(values!(9, "メロン", 5, "高い")).is_none());
+        shohin.sort_keys();
+        assert_eq!(shohin.equal_to("kubun_id", 5).num_rows(), 1);
+        assert_eq!(
+            shohin
+                .filter(&Predicate::greater_equal("price", 250))
+                .num_rows(),
+            3
+        );
+        // 抽出済みのリレーションに重ねてもよい
+        let filtered = shohin.filter(&Predicate::is_not_null("price"));
+        assert_eq!(filtered.equal_to("kubun_id", 1).num_rows(), 2);
+        shohin.drop_index("kubun_id");
+        assert_eq!(shohin.column_at(2).index(), None);
+        assert_eq!(shohin.equal_to("kubun_id", 1).num_rows(), 3);
+    }
+
+    #[test]
+    fn test_row_set() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::collections::BTreeSet;
+        let mut rng = StdRng::seed_from_u64(32);
+        // 疎な区間、密な区間、連続した区間を混ぜる
+        let mut expected = BTreeSet::new();
+        let mut row_set = RowSet::new();
+        for i in 0..20000 {
+            let row_id = match rng.gen_range(0, 3) {
+                0 => rng.gen_range(0, 1 << 20),
+                1 => rng.gen_range(70000, 71000),
+                _ => rng.gen_range(200000, 200100),
+            };
+            assert_eq!(
+                row_set.insert(row_id),
+                expected.insert(row_id),
+                "{}回目の追加: {}",
+                i,
+                row_id
+            );
         }
+        let check = |row_set: &RowSet, expected: &BTreeSet<RowId>| {
+            assert_eq!(row_set.len(), expected.len());
+            assert_eq!(row_set.last(), expected.iter().next_back().copied());
+            assert!(row_set.iter().eq(expected.iter().copied()));
+            for rank in (0..expected.len()).step_by(997) {
+                assert!(
+                    row_set.iter_from(rank).take(100).eq(expected
+                        .iter()
+                        .copied()
+                        .skip(rank)
+                        .take(100)),
+                    "{}番目から",
+                    rank
+                );
+            }
+        };
+        check(&row_set, &expected);
+        let before = row_set.num_bytes();
+        row_set.optimize();
+        assert!(row_set.num_bytes() < before);
+        check(&row_set, &expected);
+        assert!(row_set.num_bytes() < expected.len() * 2);
+        let other = RowSet::from_range(75000..250000);
+        let range: BTreeSet<RowId> = (75000..250000).collect();
+        check(
+            &(&row_set & &other),
+            &expected.intersection(&range).copied().collect(),
+        );
+        check(
+            &(&row_set | &other),
+            &expected.union(&range).copied().collect(),
+        );
+        assert!(!row_set.contains(1 << 21));
+        assert_eq!(RowSet::from_range(0..0), RowSet::new());
     }
-    pub fn create(name: &str, attributes: &[Attribute]) -> Table {
-        Table::new(Definition::create(name, attributes))
+
+    #[test]
+    fn test_filtered_relation_row_set() {
+        let shohin = create_shohin_table();
+        let cheap = shohin.less_than("price", 200);
+        let kubun = shohin.equal_to("kubun_id", 1);
+        let both = cheap.intersection(&kubun).unwrap();
+        assert_eq!(
+            owned_rows(&both),
+            owned_rows(&shohin.equal_to("shohin_id", 2))
+        );
+        let either = cheap.union(&kubun).unwrap();
+        assert_eq!(either.num_rows(), 4);
+        // 絞り込んだ結果にさらに条件を重ねられる
+        assert_eq!(either.equal_to("kubun_id", 1).num_rows(), 3);
+        let kubun_table = create_kubun_table();
+        assert!(cheap
+            .intersection(&kubun_table.equal_to("kubun_id", 1))
+            .is_none());
+        // 並べ替え済みのリレーションから抽出しても順序を保つ
+        let ordered = shohin.order_by(&[SortKey::desc("shohin_id")]);
+        let filtered = ordered.filter(&Predicate::less_than("shohin_id", 4));
+        assert!(filtered.row_set().is_none());
+        let ids: Vec<OwnedValue> = owned_rows(&filtered)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(ids, vec![3.into(), 2.into(), 1.into()]);
     }
-    pub fn insert<T: AsValue>(&mut self, tuple: &[T]) -> Option<&mut Table> {
-        let n_cols = self.num_columns();
-        if n_cols != tuple.len() {
-            return None;
+
+    #[test]
+    fn test_delete_where() {
+        let mut shohin = create_shohin_table();
+        shohin.create_index("kubun_id");
+        assert_eq!(shohin.delete_where(&Predicate::equal_to("kubun_id", 1)), 3);
+        assert_eq!(shohin.delete_where(&Predicate::equal_to("kubun_id", 1)), 0);
+        assert_eq!(shohin.num_rows(), 4);
+        assert_eq!(shohin.num_deleted(), 3);
+        let mut expected = create_shohin_table();
+        expected.delete_where(&Predicate::equal_to("shohin_id", 1));
+        assert_eq!(
+            expected.delete_where(&Predicate::is_in("shohin_id", values!(2, 7))),
+            2
+        );
+        assert_eq!(owned_rows(&shohin), owned_rows(&expected));
+        // 削除済みの行は抽出や射影にも現れない
+        assert_eq!(shohin.is_not_null("kubun_id").num_rows(), 3);
+        assert_eq!(shohin.equal_to("kubun_id", 1).num_rows(), 0);
+        assert_eq!(
+            shohin.select(&["shohin_name"]).fetch(0..1).unwrap()[0][0],
+            Value::Varchar("キャベツ")
+        );
+        let rows = owned_rows(&shohin);
+        shohin.insert(values!(8, "すいか", 1, 1000));
+        assert_eq!(shohin.equal_to("kubun_id", 1).num_rows(), 1);
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 8));
+        // compactすると行と参照されない値が取り除かれる
+        assert_eq!(shohin.column_at(1).num_keys(), 8);
+        shohin.compact();
+        assert_eq!(shohin.num_deleted(), 0);
+        assert_eq!(owned_rows(&shohin), rows);
+        assert_eq!(shohin.column_at(0).num_rows(), 4);
+        assert_eq!(shohin.column_at(1).num_keys(), 4);
+        assert_eq!(shohin.column_at(2).id_of(&1), None);
+        assert!(shohin.column_at(2).index().is_some());
+        // 64行を超えるテーブルでも削除済みの行を飛ばして走査できる
+        let mut table = Table::create("t", attributes![("id", TypeKind::Integer)]);
+        for i in 0..300 {
+            table.insert(values!(i));
         }
-        for col_id in 0..n_cols {
-            // appendに失敗したらappend済みのカラムをもとに戻す
-            if self.columns[col_id].append(&tuple[col_id]).is_none() {
-                for j in 0..col_id {
-                    self.columns[j].pop();
-                }
-                return None;
+        assert_eq!(table.delete_where(&Predicate::less_than("id", 100)), 100);
+        table.delete_where(&Predicate::is_in("id", values!(150, 299)));
+        let ids: Vec<OwnedValue> = (100..299)
+            .filter(|&i| i != 150)
+            .map(OwnedValue::from)
+            .collect();
+        let rows: Vec<OwnedValue> = owned_rows(&table)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(rows, ids);
+        // ブロックをまたいで削除したり、削除後に行を追加・巻き戻ししたりしても途中から走査できる
+        let definition = Definition::create("t", attributes![("id", TypeKind::Integer)]);
+        let mut table = Table::new(definition.primary_key(&["id"]).unwrap());
+        table
+            .append_columns(vec![(0..3000).collect::<Vec<i32>>().into()])
+            .unwrap();
+        table.delete_where(
+            &Predicate::greater_equal("id", 600).and(Predicate::less_than("id", 1300)),
+        );
+        table.delete_where(&Predicate::equal_to("id", 2000));
+        table
+            .append_columns(vec![(3000..4000).collect::<Vec<i32>>().into()])
+            .unwrap();
+        assert!(table.append_columns(vec![vec![4000, 0].into()]).is_err());
+        let ids: Vec<i32> = (0..4000)
+            .filter(|&i| !(600..1300).contains(&i) && i != 2000)
+            .collect();
+        assert_eq!(table.num_rows(), ids.len());
+        for start in (0..ids.len()).step_by(333) {
+            let rows = table.fetch(start..start + 100).unwrap();
+            for (i, row) in rows.iter().enumerate() {
+                assert_eq!(row[0], Value::Integer(ids[start + i]), "{}行目", start + i);
             }
         }
-        self.num_rows += 1;
-        Some(self)
+        assert_eq!(
+            shohin
+                .filter(&Predicate::is_in("kubun_id", values!(2, 3)))
+                .num_rows(),
+            2
+        );
     }
-}
 
-impl Relation for Table {
-    fn num_columns(&self) -> ColumnId {
-        self.columns.len()
-    }
-    fn num_rows(&self) -> RowId {
-        self.num_rows
-    }
-    fn definition(&self) -> &Definition {
-        &self.definition
-    }
-    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
-        &self.columns[col_id]
+    #[test]
+    fn test_update() {
+        let mut shohin = create_shohin_table();
+        shohin.create_index("price");
+        shohin.sort_keys();
+        let rows = owned_rows(&shohin);
+        // 型が合わない値や存在しないカラムがあれば何も書き換えない
+        let predicate = Predicate::equal_to("kubun_id", 1);
+        assert_eq!(
+            shohin.update(
+                &predicate,
+                &[
+                    ("price", Value::from(100)),
+                    ("kubun_id", Value::from("果物"))
+                ]
+            ),
+            None
+        );
+        assert_eq!(shohin.update(&predicate, &[("no_such_column", 0)]), None);
+        assert_eq!(owned_rows(&shohin), rows);
+        assert_eq!(shohin.update(&predicate, &[("price", 100)]), Some(3));
+        assert_eq!(
+            shohin.update(&Predicate::equal_to("shohin_id", 4), &[("kubun_id", NULL)]),
+            Some(1)
+        );
+        assert_eq!(
+            shohin.update(&Predicate::equal_to("shohin_id", 99), &[("price", 0)]),
+            Some(0)
+        );
+        assert_eq!(shohin.equal_to("price", 100).num_rows(), 3);
+        assert_eq!(shohin.less_than("price", 200).num_rows(), 4);
+        assert_eq!(shohin.is_null("kubun_id").num_rows(), 2);
+        let row = &owned_rows(&shohin.equal_to("shohin_id", 7))[0];
+        assert_eq!(row[3], OwnedValue::Integer(100));
+        // ラン長圧縮されたカラムでもランを分割・結合して書き換える
+        let mut array = RunLengthArray::new();
+        for value in &[1, 1, 1, 2, 2] {
+            array.push(*value);
+        }
+        array.set(1, 3);
+        assert_eq!(
+            array.runs().collect::<Vec<_>>(),
+            vec![(0..1, 1), (1..2, 3), (2..3, 1), (3..5, 2)]
+        );
+        array.set(1, 1);
+        array.set(2, 2);
+        assert_eq!(array.runs().collect::<Vec<_>>(), vec![(0..2, 1), (2..5, 2)]);
+        shohin.set_encoding("price", Encoding::RunLength);
+        shohin.update(&Predicate::greater_than("price", 200), &[("price", 200)]);
+        assert_eq!(shohin.equal_to("price", 200).num_rows(), 3);
+        assert_eq!(shohin.column_at(3).num_keys(), 7);
     }
-}
 
-impl fmt::Display for Table {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Relation::fmt(self, f)
+    #[test]
+    fn test_unique_keys() {
+        let definition = Definition::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        assert!(definition
+            .clone()
+            .primary_key(&["no_such_column"])
+            .is_none());
+        let definition = definition
+            .primary_key(&["shohin_id"])
+            .and_then(|definition| definition.unique(&["shohin_name", "kubun_id"]))
+            .unwrap();
+        assert!(definition.unique_keys()[0].is_primary());
+        assert_eq!(definition.unique_keys()[1].col_ids(), &[1, 2]);
+        let mut shohin = Table::new(definition);
+        shohin
+            .insert(values!(1, "りんご", 1, 300))
+            .insert(values!(2, "みかん", 1, 130))
+            .insert(values!(3, "わかめ", NULL, 250))
+            .insert(values!(4, "わかめ", NULL, 200)) // NULLを含む組は重複しない
+            .unwrap();
+        // 主キーの重複とNULL、UNIQUE制約の重複
+        assert!(shohin.insert(values!(1, "ドリアン", 1, NULL)).is_none());
+        assert!(shohin.insert(values!(NULL, "ドリアン", 1, NULL)).is_none());
+        assert!(shohin.insert(values!(5, "りんご", 1, 100)).is_none());
+        assert!(shohin.insert(values!(5, "りんご", 2, 100)).is_some());
+        assert_eq!(shohin.num_rows(), 5);
+        // ON CONFLICT
+        shohin.insert_on_conflict(values!(1, "ドリアン", 1, NULL), OnConflict::DoNothing);
+        assert_eq!(owned_rows(&shohin)[0][1], OwnedValue::from("りんご"));
+        shohin
+            .upsert(values!(1, "ドリアン", 1, NULL))
+            .upsert(values!(6, "すいか", 3, 1000));
+        assert_eq!(shohin.num_rows(), 6);
+        assert_eq!(owned_rows(&shohin)[0][1], OwnedValue::from("ドリアン"));
+        assert!(shohin.insert(values!(7, "ドリアン", 1, 500)).is_none());
+        assert!(shohin.insert(values!(7, "りんご", 1, 500)).is_some());
+        // 2つの行と衝突する場合は書き換えない
+        assert!(shohin.upsert(values!(2, "りんご", 1, 0)).is_none());
+        // 削除した行の値は再び使える
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 7));
+        assert!(shohin.insert(values!(7, "りんご", 1, 500)).is_some());
+        // 一意性制約に違反する更新は何も書き換えない
+        let rows = owned_rows(&shohin);
+        assert_eq!(
+            shohin.update(&Predicate::less_than("shohin_id", 3), &[("shohin_id", 10)]),
+            None
+        );
+        assert_eq!(
+            shohin.update(&Predicate::equal_to("shohin_id", 1), &[("shohin_id", NULL)]),
+            None
+        );
+        assert_eq!(owned_rows(&shohin), rows);
+        // 失敗した更新の後も、書き換えようとした行と衝突した行の索引は残っている
+        assert_eq!(
+            shohin.update(&Predicate::equal_to("shohin_id", 2), &[("shohin_id", 6)]),
+            None
+        );
+        assert!(shohin.insert(values!(2, "ぶどう", 1, 500)).is_none());
+        assert!(shohin.insert(values!(6, "ぶどう", 1, 500)).is_none());
+        assert_eq!(
+            shohin.update(&Predicate::equal_to("shohin_id", 1), &[("shohin_id", 10)]),
+            Some(1)
+        );
+        shohin.compact();
+        shohin.sort_keys();
+        assert!(shohin.insert(values!(10, "もも", 1, 500)).is_none());
+        assert!(shohin.insert(values!(1, "もも", 1, 500)).is_some());
     }
-}
 
-pub trait Insertable<'a> {
-    fn insert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table>;
-}
+    #[test]
+    fn test_column_constraints() {
+        let definition = Definition::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar, not_null),
+                ("kubun_id", TypeKind::Integer, with_default(1)),
+                (
+                    "price",
+                    TypeKind::Integer,
+                    with_default(0),
+                    check(
+                        Predicate::greater_equal("price", 0)
+                            .and(Predicate::less_than("price", 10000))
+                    )
+                )
+            ],
+        )
+        .primary_key(&["shohin_id"])
+        .unwrap();
+        assert!(!definition[0].is_not_null());
+        assert!(definition[1].is_not_null());
+        assert_eq!(definition[2].default_value(), Some(&OwnedValue::Integer(1)));
+        assert_eq!(definition[0].default_value(), None);
+        assert!(definition[3].check_predicate().is_some());
+        let mut shohin = Table::new(definition);
+        shohin
+            .try_insert(values!(1, "りんご", 1, 300))
+            .and_then(|shohin| shohin.try_insert(values!(2, "ドリアン", 1, NULL)))
+            .unwrap();
+        let errors: [(&[Value], &str); 6] = [
+            (
+                values!(3, "みかん", 1),
+                "カラム数が合いません(定義は4個、値は3個)",
+            ),
+            (
+                values!(3, "みかん", "果物", 130),
+                "カラムkubun_idはINTEGER型ですが、値果物が指定されました",
+            ),
+            (
+                values!(3, NULL, 1, 130),
+                "カラムshohin_nameにNULLは格納できません",
+            ),
+            (
+                values!(NULL, "みかん", 1, 130),
+                "カラムshohin_idにNULLは格納できません",
+            ),
+            (
+                values!(3, "みかん", 1, 10000),
+                "カラムpriceの値10000はCHECK制約((price >= 0 AND price < 10000))を満たしません",
+            ),
+            (
+                values!(1, "みかん", 1, 130),
+                "一意性制約(shohin_id)に違反しています: (1)",
+            ),
+        ];
+        for (tuple, message) in errors.iter() {
+            let err = shohin.try_insert(tuple).err().unwrap();
+            assert_eq!(err.to_string(), *message);
+        }
+        assert_eq!(shohin.num_rows(), 2);
+        // 更新でも制約を検査し、違反したら何も書き換えない
+        let rows = owned_rows(&shohin);
+        let all = Predicate::is_not_null("shohin_id");
+        assert_eq!(shohin.update(&all, &[("shohin_name", NULL)]), None);
+        assert_eq!(shohin.update(&all, &[("price", -1)]), None);
+        assert_eq!(owned_rows(&shohin), rows);
+        assert_eq!(shohin.update(&all, &[("price", 9999)]), Some(2));
+        // 条件式は表示用にSQL風に書き出せる
+        let p =
+            !Predicate::is_in("shohin_name", values!("it's", NULL)).or(Predicate::is_null("price"));
+        assert_eq!(
+            p.to_string(),
+            "NOT ((shohin_name IN ('it''s', NULL) OR price IS NULL))"
+        );
+    }
 
-impl<'a> Insertable<'a> for Option<&'a mut Table> {
-    fn insert<T: AsValue>(self, tuple: &[T]) -> Option<&'a mut Table> {
-        self.and_then(|table| table.insert(tuple))
+    #[test]
+    fn test_insert_named() {
+        let mut shohin = Table::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer, not_null),
+                ("shohin_name", TypeKind::Varchar),
+                ("kubun_id", TypeKind::Integer, with_default(1)),
+                ("price", TypeKind::Integer)
+            ],
+        );
+        shohin
+            .insert_named(
+                named_values!("shohin_id" => 1, "shohin_name" => "りんご", "price" => 300),
+            )
+            .insert_named(named_values!("price" => 250, "shohin_id" => 5, "kubun_id" => NULL))
+            .insert_named(named_values!("shohin_id" => 7, "shohin_name" => "ドリアン"));
+        assert_eq!(
+            owned_rows(&shohin),
+            vec![
+                vec![1.into(), "りんご".into(), 1.into(), 300.into()],
+                vec![5.into(), NULL.into(), NULL.into(), 250.into()],
+                vec![7.into(), "ドリアン".into(), 1.into(), NULL.into()],
+            ]
+        );
+        assert_eq!(
+            shohin
+                .try_insert_named(named_values!("shohin_name" => "みかん"))
+                .err(),
+            Some(Error::NotNull("shohin_id".to_string()))
+        );
+        assert_eq!(
+            shohin
+                .try_insert_named(named_values!("shohin_id" => 2, "kubun" => 1))
+                .err(),
+            Some(Error::UnknownColumn("kubun".to_string()))
+        );
+        assert_eq!(shohin.num_rows(), 3);
+        let durian = Predicate::equal_to("shohin_id", 7);
+        assert_eq!(
+            shohin.update(&durian, named_values!("price" => 2000)),
+            Some(1)
+        );
     }
-}
 
-///
-/// 射影結果リレーションを表します。
-///
-#[derive(Clone)]
-pub struct SelectedRelation<'a> {
-    definition: Definition,
-    relation: &'a dyn Relation,
-    col_ids: Vec<ColumnId>,
-}
+    #[test]
+    fn test_append_columns() {
+        let expected = create_shohin_table();
+        let rows = owned_rows(&expected);
+        let mut shohin = Table::new(expected.definition().clone());
+        shohin.insert_rows(&rows[..3]).unwrap();
+        shohin
+            .append_columns(vec![
+                vec![4, 5, 6, 7].into(),
+                vec!["さんま", "わかめ", "しいたけ", "ドリアン"].into(),
+                vec![Some(3), None, Some(4), Some(1)].into(),
+                vec![Some(220), Some(250), Some(180), None].into(),
+            ])
+            .unwrap();
+        assert_eq!(owned_rows(&shohin), rows);
+        assert_eq!(shohin.less_than("price", 200).num_rows(), 2);
+        // 空のテーブルに一括で追記すると辞書がソート済みになる
+        let mut sorted = Table::new(expected.definition().clone());
+        sorted.insert_rows(&rows).unwrap();
+        assert!(sorted.columns.iter().all(|column| column.is_sorted()));
+        assert!(!expected.columns[1].is_sorted());
 
-impl<'a> Relation for SelectedRelation<'a> {
-    fn num_columns(&self) -> ColumnId {
-        self.col_ids.len()
-    }
-    fn num_rows(&self) -> RowId {
-        self.relation.num_rows()
-    }
-    fn definition(&self) -> &Definition {
-        &self.definition
-    }
-    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
-        let col_id = self.col_ids[col_id];
-        self.relation.column_at(col_id)
+        // 制約に違反したら何も追記しない
+        let definition = expected.definition().clone().primary_key(&["shohin_id"]);
+        let mut shohin = Table::new(definition.unwrap());
+        shohin.create_index("kubun_id");
+        shohin.insert_rows(&rows[..2]).unwrap();
+        assert_eq!(
+            shohin.insert_rows(&[values!(3, "キャベツ", 2, 200), values!(1, "りんご", 1, 300)]),
+            Err(Error::Unique {
+                columns: vec!["shohin_id".to_string()],
+                values: vec![1.into()],
+            })
+        );
+        assert_eq!(
+            shohin
+                .insert_rows(&[values!(3, "キャベツ", "野菜", 200)])
+                .err(),
+            Some(Error::TypeMismatch {
+                column: "kubun_id".to_string(),
+                expected: TypeKind::Integer,
+                value: "野菜".into(),
+            })
+        );
+        assert_eq!(owned_rows(&shohin), rows[..2].to_vec());
+        assert_eq!(shohin.equal_to("kubun_id", 2).num_rows(), 0);
+        shohin.insert_rows(&rows[2..]).unwrap();
+        assert_eq!(owned_rows(&shohin), rows);
+        assert_eq!(shohin.equal_to("kubun_id", 1).num_rows(), 3);
     }
-}
 
-impl fmt::Display for SelectedRelation<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Relation::fmt(self, f)
-    }
-}
+    #[test]
+    fn test_alter_table() {
+        let rows = owned_rows(&create_shohin_table());
+        let definition = create_shohin_table().definition().clone();
+        let mut shohin = Table::new(definition.clone().primary_key(&["shohin_id"]).unwrap());
+        shohin.insert_rows(&rows).unwrap();
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 6));
 
-///
-/// リレーションに対してカラムを指定して射影します。
-///
-pub trait Select: Relation {
-    fn select(&self, col_names: &[&str]) -> SelectedRelation;
-}
+        // 追加したカラムは既存の行もデフォルト値で埋まる
+        let stock = Attribute::create("stock", TypeKind::Integer)
+            .with_default(10)
+            .check(Predicate::greater_equal("stock", 0));
+        shohin.add_column(stock).unwrap();
+        assert_eq!(shohin.equal_to("stock", 10).num_rows(), 6);
+        let stock = Attribute::create("stock", TypeKind::Integer);
+        assert_eq!(
+            shohin.add_column(stock).err(),
+            Some(Error::ColumnExists("stock".to_string()))
+        );
+        let maker = Attribute::create("maker", TypeKind::Varchar).not_null();
+        assert!(shohin.add_column(maker).is_err());
+        assert_eq!(shohin.num_columns(), 5);
 
-impl<T> Select for T
-where
-    T: Relation,
-{
-    fn select(&self, col_names: &[&str]) -> SelectedRelation {
-        let mut col_ids = Vec::new();
-        for col_name in col_names {
-            if let Some(col_id) = self.definition().name_to_id(col_name) {
-                col_ids.push(col_id);
-            }
-        }
-        let definition = self.definition().select(&col_ids);
-        SelectedRelation {
-            definition: definition,
-            relation: self,
-            col_ids: col_ids.to_vec(),
-        }
-    }
-}
+        // 名前を変えるとCHECK制約も追従する
+        shohin.rename_column("stock", "zaiko").unwrap();
+        assert_eq!(
+            shohin.try_insert(values!(8, "なし", 1, 100, -1)).err(),
+            Some(Error::Check {
+                column: "zaiko".to_string(),
+                check: Predicate::greater_equal("zaiko", 0),
+                value: (-1).into(),
+            })
+        );
+        assert!(shohin
+            .alter_column_type("zaiko", TypeKind::Varchar)
+            .is_err());
+        assert_eq!(
+            shohin.drop_column("shohin_id").err(),
+            Some(Error::ColumnInUse("shohin_id".to_string()))
+        );
+        shohin.drop_column("zaiko").unwrap();
+        assert_eq!(
+            owned_rows(&shohin),
+            owned_rows(&create_shohin_table().filter(&Predicate::not_equal_to("shohin_id", 6)))
+        );
 
-///
-/// 抽出結果リレーションを表します。
-///
-#[derive(Clone)]
-pub struct FilteredRelation<'a> {
-    relation: &'a dyn Relation,
-    valid_row_ids: Vec<RowId>,
-}
+        // 型を変えると値も変換され、変換できなければ元のまま
+        shohin
+            .alter_column_type("shohin_id", TypeKind::Varchar)
+            .unwrap();
+        shohin.insert(values!("8", "なし", 1, 100)).unwrap();
+        assert_eq!(shohin.less_than("shohin_id", "3").num_rows(), 2);
+        shohin.insert(values!("x", "くり", 1, 400)).unwrap();
+        assert_eq!(
+            shohin
+                .alter_column_type("shohin_id", TypeKind::Integer)
+                .err(),
+            Some(Error::TypeMismatch {
+                column: "shohin_id".to_string(),
+                expected: TypeKind::Integer,
+                value: "x".into(),
+            })
+        );
+        shohin.delete_where(&Predicate::equal_to("shohin_id", "x"));
+        shohin.insert(values!("08", "ぶどう", 1, 500)).unwrap();
+        assert!(shohin
+            .alter_column_type("shohin_id", TypeKind::Integer)
+            .is_err());
+        assert_eq!(shohin.definition()[0].kind(), TypeKind::Varchar);
+        shohin.delete_where(&Predicate::equal_to("shohin_id", "08"));
+        shohin
+            .alter_column_type("shohin_id", TypeKind::Integer)
+            .unwrap();
+        let greater_than_5 = Predicate::greater_than("shohin_id", 5);
+        assert_eq!(shohin.filter(&greater_than_5).num_rows(), 2);
+        assert!(shohin.insert(values!(8, "なし", 1, 100)).is_none());
 
-impl<'a> Relation for FilteredRelation<'a> {
-    fn num_columns(&self) -> ColumnId {
-        self.relation.num_columns()
-    }
-    fn num_rows(&self) -> RowId {
-        self.valid_row_ids.len()
-    }
-    fn definition(&self) -> &Definition {
-        &self.relation.definition()
-    }
-    fn column_at(&self, col_id: ColumnId) -> &dyn AsColumn {
-        self.relation.column_at(col_id)
-    }
-    fn scan_row_ids<'b>(&self, range: Range<RowId>, dest: &'b mut [RowId]) -> &'b [RowId] {
-        let start = range.start;
-        let end = cmp::min(range.end, self.num_rows());
-        if start >= end {
-            return &dest[0..0];
-        }
-        let n_range = end - start;
-        if dest.len() < n_range {
-            return &dest[0..0];
-        }
-        let mut i = 0;
-        for row_id in start..end {
-            dest[i] = self.valid_row_ids[row_id];
-            i += 1;
-        }
-        &dest[0..i]
+        // 外部キーのカラムと、外部キーから参照されているカラムは名前も型も変えられない
+        let kubun = create_kubun_table().definition().clone();
+        let kubun = kubun.primary_key(&["kubun_id"]).unwrap();
+        let shohin = definition
+            .foreign_key(
+                &["kubun_id"],
+                "kubun",
+                &["kubun_id"],
+                ReferentialAction::Restrict,
+            )
+            .unwrap();
+        let mut table = Table::new(shohin.clone());
+        assert_eq!(
+            table.rename_column("kubun_id", "kubun").err(),
+            Some(Error::ColumnInUse("kubun_id".to_string()))
+        );
+        assert_eq!(
+            table.alter_column_type("kubun_id", TypeKind::Varchar).err(),
+            Some(Error::ColumnInUse("kubun_id".to_string()))
+        );
+        let mut db = Database::new();
+        db.create_table(kubun)
+            .unwrap()
+            .create_table(shohin)
+            .unwrap();
+        db.insert("kubun", values!(1, "くだもの")).unwrap();
+        let result = db.alter_table("kubun", |kubun| kubun.rename_column("kubun_id", "id"));
+        assert_eq!(
+            result.err(),
+            Some(Error::ColumnInUse("kubun_id".to_string()))
+        );
+        let result = db.alter_table("kubun", |kubun| {
+            kubun.alter_column_type("kubun_id", TypeKind::Varchar)
+        });
+        assert!(result.is_err());
+        let kubun = db.table("kubun").unwrap();
+        assert!(kubun.definition().name_to_id("kubun_id").is_some());
+        assert_eq!(
+            kubun
+                .filter(&Predicate::equal_to("kubun_name", "くだもの"))
+                .num_rows(),
+            1
+        );
+        // alter_tableの外では参照されているカラムも変えられる
+        let mut kubun = kubun.clone();
+        kubun.rename_column("kubun_id", "id").unwrap();
+        db.alter_table("kubun", |kubun| kubun.rename_column("kubun_name", "name"))
+            .unwrap();
+        assert_eq!(db.table("kubun").unwrap().definition()[1].name(), "name");
+        db.alter_table("kubun", |kubun| {
+            kubun
+                .add_column(Attribute::create("memo", TypeKind::Varchar))?
+                .alter_column_type("memo", TypeKind::Integer)?;
+            assert_eq!(kubun.table().num_columns(), 3);
+            kubun.drop_column("memo")
+        })
+        .unwrap();
+        assert_eq!(db.table("kubun").unwrap().num_columns(), 2);
     }
-}
 
-impl fmt::Display for FilteredRelation<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Relation::fmt(self, f)
-    }
-}
+    #[test]
+    fn test_save_load() {
+        let rows = owned_rows(&create_shohin_table());
+        let definition = Definition::create(
+            "shohin",
+            attributes![
+                ("shohin_id", TypeKind::Integer),
+                ("shohin_name", TypeKind::Varchar, not_null),
+                ("kubun_id", TypeKind::Integer, with_default(1)),
+                (
+                    "price",
+                    TypeKind::Integer,
+                    check(
+                        Predicate::greater_equal("price", 0)
+                            .or(Predicate::is_in("price", &[-1, -2]))
+                    )
+                )
+            ],
+        )
+        .primary_key(&["shohin_id"])
+        .and_then(|definition| {
+            definition.foreign_key_with_actions(
+                &["kubun_id"],
+                "kubun",
+                &["kubun_id"],
+                ReferentialAction::SetNull,
+                ReferentialAction::Cascade,
+            )
+        })
+        .unwrap();
+        let mut shohin = Table::new(definition);
+        shohin.insert_rows(&rows).unwrap();
+        shohin.insert(values!(8, "くり", 2, -1));
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 3));
+        shohin.set_encoding("kubun_id", Encoding::RunLength);
+        shohin.create_index("price");
+        shohin.sort_keys();
+        shohin.insert(values!(9, "あけび", 1, 100));
 
-///
-/// リレーションに対してカラム名と値を指定して '<' 比較します。
-///
-pub trait LessThan: Relation {
-    fn less_than<Key: AsValue>(&self, col_id: &str, key: Key) -> FilteredRelation;
-}
+        let path = std::env::temp_dir().join(format!("kawaii_test_{}.kwi", std::process::id()));
+        shohin.save(&path).unwrap();
+        let loaded = Table::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, shohin);
+        assert_eq!(loaded.num_deleted(), 1);
+        assert_eq!(loaded.columns[2].encoding(), Encoding::RunLength);
+        assert!(loaded.columns[3].index().is_some());
+        assert!(loaded.columns[0].is_sorted());
+        assert_eq!(
+            loaded.definition().foreign_keys(),
+            shohin.definition().foreign_keys()
+        );
+        assert!(loaded
+            .clone()
+            .insert(values!(1, "りんご", 1, 300))
+            .is_none());
 
-impl<T> LessThan for T
-where
-    T: Relation,
-{
-    fn less_than<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation {
-        let valid_row_ids = {
-            let mut valid_row_ids = Vec::new();
-            if let Some(col_id) = self.definition().name_to_id(col_name) {
-                let column = self.column_at(col_id);
-                if let Some(target_key_ids) = column.range_to(&key) {
-                    let n_rows = self.num_rows();
-                    valid_row_ids.reserve(n_rows);
-                    const STEP: RowId = 64;
-                    let mut start = 0;
-                    let mut buffer = [0; STEP];
-                    while start < n_rows {
-                        let step = cmp::min(STEP, n_rows - start);
-                        let end = start + step;
-                        let row_ids = self.scan_row_ids(start..end, &mut buffer);
-                        for row_id in row_ids {
-                            if target_key_ids.get(column.id_at(*row_id)).as_deref() == Some(&true) {
-                                valid_row_ids.push(*row_id);
-                            }
-                        }
-                        start += step;
-                    }
-                }
-            }
-            valid_row_ids.shrink_to_fit();
-            valid_row_ids
-        };
-        FilteredRelation {
-            relation: self,
-            valid_row_ids: valid_row_ids,
+        // 途中で切れたデータや別のファイルはエラーになる
+        let bytes = shohin.to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Table::from_bytes(&bytes[..len]).is_err());
         }
+        let mut bytes = shohin.to_bytes();
+        bytes[8] = 99;
+        assert_eq!(
+            Table::from_bytes(&bytes).err().map(|err| err.to_string()),
+            Some("ファイルの形式が正しくありません: バージョン99には対応していません".to_string())
+        );
+        assert!(Table::load(std::env::temp_dir().join("kawaii_no_such_file.kwi")).is_err());
+        let empty = Table::create("empty", &[]);
+        assert_eq!(Table::from_bytes(&empty.to_bytes()), Ok(empty));
     }
-}
 
-///
-/// リレーションに対してカラム名と値を指定して '==' 比較します。
-///
-pub trait EqualTo: Relation {
-    fn equal_to<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation;
-}
+    #[test]
+    fn test_mapped_table() {
+        let mut shohin = create_shohin_table();
+        shohin.insert(values!(8, "くり", NULL, 500));
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 3));
+        shohin.set_encoding("kubun_id", Encoding::RunLength);
+        shohin.create_index("price");
+        let mut sorted = shohin.clone();
+        sorted.sort_keys();
 
-impl<T> EqualTo for T
-where
-    T: Relation,
-{
-    fn equal_to<Key: AsValue>(&self, col_name: &str, key: Key) -> FilteredRelation {
-        let valid_row_ids = {
-            let mut valid_row_ids = Vec::new();
-            if let Some(col_id) = self.definition().name_to_id(col_name) {
-                let column = self.column_at(col_id);
-                if let Some(target_key_id) = column.id_of(&key) {
-                    let n_rows = self.num_rows();
-                    valid_row_ids.reserve(n_rows);
-                    const STEP: RowId = 64;
-                    let mut start = 0;
-                    let mut buffer = [0; STEP];
-                    while start < n_rows {
-                        let step = cmp::min(STEP, n_rows - start);
-                        let end = start + step;
-                        let row_ids = self.scan_row_ids(start..end, &mut buffer);
-                        for row_id in row_ids {
-                            if target_key_id == column.id_at(*row_id) {
-                                valid_row_ids.push(*row_id);
-                            }
-                        }
-                        start += step;
-                    }
+        for table in &[shohin, sorted] {
+            let path = std::env::temp_dir().join(format!("kawaii_mmap_{}.kwi", std::process::id()));
+            table.save(&path).unwrap();
+            let mapped = MappedTable::open(&path).unwrap();
+            assert_eq!(mapped.num_rows(), table.num_rows());
+            assert_eq!(mapped.num_deleted(), 1);
+            assert_eq!(owned_rows(&mapped), owned_rows(table));
+            for col_id in 0..table.num_columns() {
+                let (expected, actual) = (table.column_at(col_id), mapped.column_at(col_id));
+                assert_eq!(actual.is_sorted(), expected.is_sorted());
+                assert_eq!(actual.index().is_some(), expected.index().is_some());
+                for key_id in 0..expected.num_keys() {
+                    let key = OwnedValue::from(expected.key_of(key_id));
+                    assert_eq!(actual.id_of(&key), Some(key_id));
                 }
             }
-            valid_row_ids.shrink_to_fit();
-            valid_row_ids
-        };
-        FilteredRelation {
-            relation: self,
-            valid_row_ids: valid_row_ids,
-        }
-    }
-}
+            assert_eq!(mapped.column_at(1).id_of(&"すいか"), None);
+            assert_eq!(mapped.column_at(1).id_of(&1), None);
 
-/// Count集計用の構造体です。
-#[derive(Debug, Clone, Copy)]
-pub struct Count {
-    result: i32,
-}
+            let predicates = [
+                Predicate::less_than("price", 200),
+                Predicate::greater_equal("shohin_name", "みかん"),
+                Predicate::equal_to("kubun_id", 1).or(Predicate::is_null("kubun_id")),
+                !Predicate::is_in("price", &[100, 300]),
+            ];
+            for predicate in &predicates {
+                assert_eq!(
+                    owned_rows(&mapped.filter(predicate)),
+                    owned_rows(&table.filter(predicate))
+                );
+            }
+            // 集約結果の行の順序は決まっていないので並べてから比べる
+            let aggs = [Agg::count("shohin_name"), Agg::average("price")];
+            let group_by = |relation: &dyn Relation| {
+                let mut rows = owned_rows(relation);
+                rows.sort();
+                rows
+            };
+            assert_eq!(
+                group_by(&mapped.group_by(&["kubun_id"], &aggs)),
+                group_by(&table.group_by(&["kubun_id"], &aggs))
+            );
+            assert_eq!(
+                owned_rows(&mapped.order_by(&[SortKey::desc("price")])),
+                owned_rows(&table.order_by(&[SortKey::desc("price")]))
+            );
+            drop(mapped);
 
-impl Count {
-    fn calculate<T: AsValue>(&mut self, value: T) {
-        if let Value::Null(_) = value.as_value_ref() {
-            return;
+            // 途中で切れたファイルはエラーになる
+            let bytes = table.to_bytes();
+            for len in (0..bytes.len()).step_by(7) {
+                std::fs::write(&path, &bytes[..len]).unwrap();
+                assert!(MappedTable::open(&path).is_err());
+            }
+            std::fs::remove_file(&path).unwrap();
         }
-        self.result += 1;
-    }
-    fn get_result(&self) -> i32 {
-        self.result
     }
-}
 
-/// Average集計用の構造体です。
-#[derive(Debug, Clone, Copy)]
-pub struct Average {
-    count: i32,
-    sum: i32,
-}
+    #[test]
+    fn test_write_ahead_log() {
+        let path = std::env::temp_dir().join(format!("kawaii_wal_{}.kwi", std::process::id()));
+        let log_path = DurableTable::log_path(&path);
+        let definition = create_shohin_table()
+            .definition()
+            .clone()
+            .primary_key(&["shohin_id"])
+            .unwrap();
+        let mut durable =
+            DurableTable::create(&path, definition.clone(), SyncPolicy::Always).unwrap();
+        assert!(DurableTable::create(&path, definition.clone(), SyncPolicy::Always).is_err());
 
-impl Average {
-    fn calculate<T: AsValue>(&mut self, value: T) {
-        if let Value::Integer(val) = value.as_value_ref() {
-            self.sum += val;
-            self.count += 1;
-        }
-    }
-    fn get_result(&self) -> i32 {
-        if self.count == 0 {
-            return 0;
+        // 記録した操作の数ごとに、あるべきテーブルの状態を取っておく
+        let mut states = vec![Table::new(definition)];
+        let mut record = |durable: &mut DurableTable, record: LogRecord| {
+            let mut state = states.last().unwrap().clone();
+            let expected = record.apply(&mut state);
+            let actual = match record {
+                LogRecord::Insert(tuple) => durable.insert(&tuple).map(|_| 1),
+                LogRecord::Update {
+                    predicate,
+                    assignments,
+                } => {
+                    let assignments: Vec<(&str, OwnedValue)> = assignments
+                        .iter()
+                        .map(|(col_name, value)| (col_name.as_str(), value.clone()))
+                        .collect();
+                    durable.update(&predicate, &assignments)
+                }
+                LogRecord::Delete(predicate) => durable.delete_where(&predicate),
+            };
+            assert_eq!(actual, expected);
+            assert_eq!(**durable, state);
+            // 失敗した操作は記録されない
+            if expected.is_ok() {
+                states.push(state);
+            }
+        };
+        for row in owned_rows(&create_shohin_table()) {
+            record(&mut durable, LogRecord::Insert(row));
         }
-        self.sum / self.count
-    }
-}
-
-/// 集計関数をまとめたEnumです。
-#[derive(Debug, Clone, Copy)]
-pub enum AggFunc {
-    /// COUNT
-    Count(Count),
-    /// AVG
-    Average(Average),
-}
+        record(
+            &mut durable,
+            LogRecord::Update {
+                predicate: Predicate::equal_to("kubun_id", 1),
+                assignments: vec![("price".to_string(), 500.into())],
+            },
+        );
+        record(
+            &mut durable,
+            LogRecord::Delete(Predicate::is_null("kubun_id")),
+        );
+        let log_len = std::fs::metadata(&log_path).unwrap().len();
+        record(
+            &mut durable,
+            LogRecord::Insert(vec![1.into(), "りんご".into(), 1.into(), 300.into()]),
+        );
+        record(
+            &mut durable,
+            LogRecord::Update {
+                predicate: Predicate::equal_to("shohin_id", 2),
+                assignments: vec![("shohin_id".to_string(), 1.into())],
+            },
+        );
+        assert_eq!(std::fs::metadata(&log_path).unwrap().len(), log_len);
+        record(
+            &mut durable,
+            LogRecord::Update {
+                predicate: Predicate::equal_to("shohin_id", 2),
+                assignments: vec![("shohin_id".to_string(), 20.into())],
+            },
+        );
+        drop(durable);
 
-impl AggFunc {
-    fn calculate<T: AsValue>(&mut self, value: T) {
-        match self {
-            AggFunc::Count(func) => func.calculate(value),
-            AggFunc::Average(func) => func.calculate(value),
+        // ログをどこで切っても、完全に書けた記録までがやり直される
+        let bytes = std::fs::read(&log_path).unwrap();
+        let mut record_ends = vec![16];
+        while *record_ends.last().unwrap() < bytes.len() {
+            let pos = *record_ends.last().unwrap();
+            let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            record_ends.push(pos + 8 + len);
         }
-    }
-    fn get_result(&self) -> i32 {
-        match self {
-            AggFunc::Count(func) => func.get_result(),
-            AggFunc::Average(func) => func.get_result(),
+        assert_eq!(record_ends.len(), states.len());
+        for len in 0..=bytes.len() {
+            std::fs::write(&log_path, &bytes[..len]).unwrap();
+            let durable = DurableTable::open(&path, SyncPolicy::Never).unwrap();
+            let num_records = record_ends.iter().filter(|&&end| end <= len).count();
+            assert_eq!(*durable, states[num_records.saturating_sub(1)]);
         }
-    }
-}
+        // 途中の記録が壊れていればエラーになり、最後の記録が壊れていれば書きかけとして捨てられる
+        let mut corrupted = bytes.clone();
+        corrupted[record_ends[2] + 10] ^= 0xFF;
+        std::fs::write(&log_path, &corrupted).unwrap();
+        let corrupted_block = Block::LogRecord(record_ends[2] as u64);
+        assert_eq!(
+            DurableTable::open(&path, SyncPolicy::Always).err(),
+            Some(Error::Corrupted(corrupted_block.clone()))
+        );
+        assert_eq!(
+            DurableTable::verify(&path),
+            Err(Error::Corrupted(corrupted_block))
+        );
+        std::fs::write(&log_path, &corrupted[..record_ends[3]]).unwrap();
+        assert_eq!(
+            DurableTable::verify(&path),
+            Err(Error::Corrupted(Block::LogRecord(record_ends[2] as u64)))
+        );
+        let mut durable = DurableTable::open(&path, SyncPolicy::Every(2)).unwrap();
+        assert_eq!(*durable, states[2]);
+        assert_eq!(DurableTable::verify(&path), Ok(()));
+        durable.insert(values!(10, "すいか", 1, 900)).unwrap();
+        durable.sync().unwrap();
+        drop(durable);
+        let durable = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+        assert_eq!(durable.num_rows(), 3);
 
-/// 集計パラメーターを表します。
-#[derive(Debug, Clone)]
-pub struct Agg {
-    /// 対象カラム名
-    name: &'static str,
-    /// 集計関数
-    func: AggFunc,
-}
+        // チェックポイントの後にログを空にする前に落ちても、二重に適用されない
+        std::fs::write(&log_path, &bytes).unwrap();
+        let mut durable = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+        durable.checkpoint().unwrap();
+        assert_eq!(std::fs::metadata(&log_path).unwrap().len(), 16);
+        std::fs::write(&log_path, &bytes).unwrap();
+        let mut durable = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+        assert_eq!(*durable, *states.last().unwrap());
+        durable.insert(values!(10, "すいか", 1, 900)).unwrap();
+        drop(durable);
+        let durable = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+        assert_eq!(durable.num_rows(), states.last().unwrap().num_rows() + 1);
 
-impl Agg {
-    pub fn count(name: &'static str) -> Agg {
-        Agg {
-            name: name,
-            func: AggFunc::Count(Count { result: 0 }),
-        }
-    }
-    pub fn average(name: &'static str) -> Agg {
-        Agg {
-            name: name,
-            func: AggFunc::Average(Average { sum: 0, count: 0 }),
-        }
+        // チェックポイントと食い違うログは、やり直しに失敗してエラーになる
+        drop(durable);
+        let mut durable = DurableTable::open(&path, SyncPolicy::Always).unwrap();
+        durable.checkpoint().unwrap();
+        let lsn = durable.log.next_lsn() - 1;
+        durable.insert(values!(11, "もも", 1, 400)).unwrap();
+        DurableTable::write_checkpoint(&path, &durable, lsn).unwrap();
+        drop(durable);
+        assert!(matches!(
+            DurableTable::open(&path, SyncPolicy::Always),
+            Err(Error::Unique { .. })
+        ));
+
+        std::fs::write(&log_path, b"NOTAWAL!\x01\0\0\0\0\0\0\0").unwrap();
+        assert!(DurableTable::open(&path, SyncPolicy::Always).is_err());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
     }
-}
 
-///
-/// リレーションに対して集計集約を行います。
-///
-pub trait GroupBy: Relation {
-    fn group_by(&self, group_col_names: &[&str], agg_params: &[Agg]) -> Table;
-}
+    #[test]
+    fn test_corruption() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        let mut shohin = create_shohin_table();
+        shohin.insert(values!(8, "くり", NULL, 500));
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 3));
+        shohin.set_encoding("kubun_id", Encoding::RunLength);
+        shohin.create_index("price");
 
-impl<T> GroupBy for T
-where
-    T: Relation,
-{
-    fn group_by(&self, group_col_names: &[&str], agg_params: &[Agg]) -> Table {
-        // 集約列IDリスト
-        let mut group_col_ids = Vec::new();
-        // TODO: 不正なカラム名の処置(Optionで返すように変更?)
-        for group_col_name in group_col_names {
-            if let Some(col_id) = self.definition().name_to_id(group_col_name) {
-                group_col_ids.push(col_id);
-            }
-        }
-        // イミュータブルに再束縛
-        let group_col_ids = group_col_ids;
-        // 集計列IDリスト
-        let mut agg_col_ids = Vec::new();
-        // 集計関数リスト
-        let mut master_agg_funcs = Vec::new();
-        // TODO: 不正なカラム名の処置(Optionで返すように変更?)
-        for agg_param in agg_params {
-            if let Some(col_id) = self.definition().name_to_id(agg_param.name) {
-                agg_col_ids.push(col_id);
-                master_agg_funcs.push(agg_param.func);
-            }
-        }
-        let agg_col_ids = agg_col_ids;
-        let master_agg_funcs = master_agg_funcs;
-        let n_group_cols = group_col_ids.len();
-        let n_agg_cols = agg_col_ids.len();
-        let mut group_columns: Vec<&dyn AsColumn> = Vec::new();
-        for col_id in &group_col_ids {
-            group_columns.push(self.column_at(*col_id));
-        }
-        let group_columns = group_columns;
-        let mut agg_columns: Vec<&dyn AsColumn> = Vec::new();
-        for col_id in &agg_col_ids {
-            agg_columns.push(self.column_at(*col_id));
-        }
-        let agg_columns = agg_columns;
-        let mut group_map = HashMap::new();
-        let n_rows = self.num_rows();
-        const STEP: RowId = 64;
-        let mut start = 0;
-        let mut buffer = [0; STEP];
-        while start < n_rows {
-            let step = cmp::min(STEP, n_rows - start);
-            let end = start + step;
-            let row_ids = self.scan_row_ids(start..end, &mut buffer);
-            for row_id in row_ids {
-                let mut group_key_ids = Vec::new();
-                group_key_ids.reserve(group_columns.len());
-                for column in &group_columns {
-                    group_key_ids.push(column.id_at(*row_id));
-                }
-                let group_key_ids = group_key_ids;
-                let grouped_values = group_map
-                    .entry(group_key_ids)
-                    .or_insert(master_agg_funcs.clone());
-                for agg_i in 0..n_agg_cols {
-                    grouped_values[agg_i].calculate(agg_columns[agg_i].key_at(*row_id));
-                }
-            }
-            start += step;
-        }
-        let mut attributes = Vec::new();
-        for col_id in group_col_ids {
-            attributes.push(self.definition()[col_id].clone());
-        }
-        for agg_i in 0..n_agg_cols {
-            attributes.push(Attribute {
-                name: match master_agg_funcs[agg_i] {
-                    AggFunc::Count(_) => "count",
-                    AggFunc::Average(_) => "average",
-                }
-                .to_string(),
-                kind: TypeKind::Integer,
-            });
+        // どのバイトが壊れてもエラーになる
+        let bytes = shohin.to_bytes();
+        for pos in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[pos] ^= 0x01;
+            assert!(Table::from_bytes(&corrupted).is_err(), "{}", pos);
         }
-        let attributes = attributes;
-        let mut table = Table::create(&self.definition().name(), &attributes);
-        assert!(table.num_columns() == n_group_cols + n_agg_cols);
-        let mut num_rows = 0;
-        for (group_key_ids, grouped_values) in group_map.iter() {
-            for group_i in 0..n_group_cols {
-                let col_id = group_i;
-                let key_id = group_key_ids[group_i];
-                let datum = group_columns[group_i].key_of(key_id);
-                table.columns[col_id].append(&datum);
-            }
-            for agg_i in 0..n_agg_cols {
-                let col_id = agg_i + n_group_cols;
-                let result = grouped_values[agg_i].get_result();
-                table.columns[col_id].append(&result);
+
+        // ファイルのランダムなバイトを壊しても、パニックせずに壊れたブロックが報告される
+        let path = std::env::temp_dir().join(format!("kawaii_crc_{}.kwi", std::process::id()));
+        let seed = 44;
+        let mut rng = StdRng::seed_from_u64(seed);
+        for i in 0..200 {
+            let mut corrupted = bytes.clone();
+            let pos = rng.gen_range(0, bytes.len());
+            corrupted[pos] ^= rng.gen_range(1, 256) as u8;
+            std::fs::write(&path, &corrupted).unwrap();
+            let message = format!("シード{}の{}回目: {}バイト目", seed, i, pos);
+            let err = Table::load(&path).expect_err(&message);
+            assert_eq!(
+                MappedTable::open(&path).err(),
+                Some(err.clone()),
+                "{}",
+                message
+            );
+            // 読み込みで最初に見つかる壊れたブロックが、検査でも最初に報告される
+            let first = Table::verify(&path).map(|blocks| blocks.first().cloned());
+            match err {
+                Error::Corrupted(block) => assert_eq!(first, Ok(Some(block)), "{}", message),
+                err => assert_eq!(first, Err(err), "{}", message),
             }
-            num_rows += 1;
         }
-        table.num_rows = num_rows;
-        table
+        let mut corrupted = bytes.clone();
+        let key_ids_end = bytes.len() - 8;
+        corrupted[key_ids_end] ^= 0x80;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(
+            Table::load(&path).err().map(|err| err.to_string()),
+            Some("カラムpriceのキーIDが壊れています(チェックサムが一致しません)".to_string())
+        );
+        // 壊れたブロックが複数あれば全て報告される
+        let deleted_start = 24 + BLOCK_HEADER_LEN;
+        corrupted[deleted_start] ^= 0x01;
+        corrupted[FILE_MAGIC.len() + 8] ^= 0x01;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(
+            Table::verify(&path).unwrap(),
+            vec![
+                Block::Header,
+                Block::Deleted,
+                Block::KeyIds("price".to_string())
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
     }
-}
 
-///
-/// テーブルinser時のパラメーターを簡便にします。
-///
-/// # Examples
-///
-/// ```
-/// # use kawaii::*;
-/// # fn main() {
-/// #     let mut table = Table::create(
-/// #         "shohin",
-/// #         attributes![
-/// #             ("shohin_id", TypeKind::Integer),
-/// #             ("shohin_name", TypeKind::Varchar),
-/// #             ("kubun_id", TypeKind::Integer),
-/// #             ("price", TypeKind::Integer)
-/// #         ],
-/// #     );
-/// #     table
-/// #         .insert(values!(1, "りんご", 1, 300))
-/// #         .insert(values!(2, "みかん", 1, 130))
-/// #         .insert(values!(3, "キャベツ", 2, 200))
-/// #         .insert(values!(4, "さんま", 3, 220))
-/// #         .insert(values!(5, "わかめ", NULL, 250)) //区分がNULL
-/// #         .insert(values!(6, "しいたけ", 4, 180)) //該当区分なし
-/// #         .insert(values!(7, "ドリアン", 1, NULL));
-/// # }
-/// ```
-///
-#[macro_export]
-macro_rules! values {
-    ( $( $x:expr ),* ) => ( &[ $( Value::from($x) ),* ] )
-}
+    #[test]
+    fn test_csv_reader() {
+        let attributes = attributes![
+            ("shohin_id", TypeKind::Integer),
+            ("shohin_name", TypeKind::Varchar),
+            ("kubun_id", TypeKind::Integer),
+            ("price", TypeKind::Integer)
+        ];
+        // 引用符の中の改行・区切り文字・引用符、CRLF、BOM、空行、カラムの並べ替え
+        let csv = "\u{feff}price,shohin_name,shohin_id,kubun_id\r\n\
+                   300,りんご,1,1\r\n\
+                   130,\"みかん\n(愛媛)\",2,1\r\n\
+                   \r\n\
+                   ,\"\"\"ドリアン\"\"\",7,\r\n\
+                   0,\"\",8,2";
+        let shohin = CsvReader::new().parse("shohin", attributes, csv).unwrap();
+        assert_eq!(
+            owned_rows(&shohin),
+            vec![
+                vec![1.into(), "りんご".into(), 1.into(), 300.into()],
+                vec![2.into(), "みかん\n(愛媛)".into(), 1.into(), 130.into()],
+                vec![7.into(), "\"ドリアン\"".into(), NULL.into(), NULL.into()],
+                vec![8.into(), "".into(), 2.into(), 0.into()],
+            ]
+        );
+        let inferred = CsvReader::new().parse_inferred("shohin", csv).unwrap();
+        let kinds: Vec<TypeKind> = inferred
+            .definition()
+            .attributes
+            .iter()
+            .map(Attribute::kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TypeKind::Integer,
+                TypeKind::Varchar,
+                TypeKind::Integer,
+                TypeKind::Integer
+            ]
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // ヘッダーなしのTSV
+        let tsv = "1\tりんご\t1\t300\n2\tみかん\t\t130\n";
+        let reader = CsvReader::new().delimiter('\t').has_header(false);
+        let shohin = reader.parse("shohin", attributes, tsv).unwrap();
+        assert_eq!(shohin.num_rows(), 2);
+        assert!(shohin.fetch(1..2).unwrap()[0][2].is_null());
+        assert_eq!(
+            reader.infer_attributes(tsv).unwrap()[1],
+            Attribute::create("column2", TypeKind::Varchar)
+        );
+        // 推測に使う行の後に整数でない値があればエラーになる
+        let csv = "id\n1\n2\nx\n";
+        let reader = CsvReader::new().num_samples(2);
+        assert_eq!(
+            reader.parse_inferred("t", csv).err(),
+            Some(csv_error(4, 1, "整数ではありません(x)"))
+        );
+        assert_eq!(
+            CsvReader::new().infer_attributes(csv).unwrap(),
+            vec![Attribute::create("id", TypeKind::Varchar)]
+        );
+
+        // エラーは記録が始まる行と列で報告され、テーブルには何も追加されない
+        let mut shohin = Table::create("shohin", attributes);
+        let errors = [
+            (
+                "shohin_id,shohin_name\n1,\"a\nb\"\n2,\"c",
+                csv_error(4, 2, "引用符が閉じられていません"),
+            ),
+            (
+                "shohin_id,shohin_name\n1,\"a\"b\n",
+                csv_error(2, 2, "閉じた引用符の後に区切り文字がありません"),
+            ),
+            (
+                "shohin_id,shohin_name\n1,\"a\nb\"\n2,c,3\n",
+                csv_error(4, 3, "列の数が合いません(2列のはずが3列あります)"),
+            ),
+            ("shohin_id,,price\n", csv_error(1, 2, "カラム名が空です")),
+            (
+                "shohin_id,name\n1,a\n",
+                Error::UnknownColumn("name".to_string()),
+            ),
+            (
+                "price,price\n1,2\n",
+                Error::ColumnExists("price".to_string()),
+            ),
+        ];
+        for (csv, expected) in errors.iter() {
+            assert_eq!(
+                CsvReader::new().parse_into(&mut shohin, csv).as_ref().err(),
+                Some(expected)
+            );
+        }
+        assert_eq!(shohin.num_rows(), 0);
+        assert_eq!(
+            CsvReader::new().parse_into(&mut shohin, "shohin_id\n1\n2\n"),
+            Ok(2)
+        );
+        assert_eq!(
+            CsvReader::new()
+                .parse_inferred("empty", "")
+                .unwrap()
+                .num_columns(),
+            0
+        );
+    }
 
     #[test]
-    fn test_dictionary_varchar() {
-        let mut dictionary = Dictionary::new();
-        assert_eq!(dictionary.insert("Alice".to_string()), 0);
-        assert_eq!(dictionary.insert("Bob".to_string()), 1);
-        assert_eq!(dictionary.insert("Alice".to_string()), 0);
-        assert_eq!(dictionary.num_keys(), 2);
-        assert_eq!(dictionary.id_of("Alice"), Some(0));
-        assert_eq!(dictionary.id_of("Bob"), Some(1));
-        assert_eq!(dictionary.id_of("Chris"), None);
-        let mut iter = dictionary.range("Alice".to_string().."Chris".to_string());
-        assert_eq!(iter.next(), Some((&"Alice".to_string(), &0)));
-        assert_eq!(iter.next(), Some((&"Bob".to_string(), &1)));
-        assert_eq!(iter.next(), None);
-        assert_eq!(dictionary.key_of(0), &"Alice".to_string());
-        assert_eq!(dictionary.key_of(1), &"Bob".to_string());
+    fn test_csv_writer() {
+        let mut shohin = create_shohin_table();
+        shohin
+            .insert(values!(8, "\"特選\"メロン,\r\n夕張産", 1, 5000))
+            .insert(values!(9, "", 2, 100))
+            .insert(values!(10, "NULL", 2, 100));
+        let attributes: Vec<Attribute> = shohin.definition().attributes.clone();
+
+        // 読み戻すと、空文字列とNULLも区別された元の値になる
+        for writer in &[CsvWriter::new(), CsvWriter::new().delimiter('\t').bom(true)] {
+            let mut bytes = Vec::new();
+            writer.write(&shohin, &mut bytes).unwrap();
+            let text = String::from_utf8(bytes).unwrap();
+            assert_eq!(text, writer.format(&shohin));
+            let reader = CsvReader::new().delimiter(writer.delimiter);
+            let loaded = reader.parse("shohin", &attributes, &text).unwrap();
+            assert_eq!(owned_rows(&loaded), owned_rows(&shohin));
+        }
+        // NULLの表現と同じ文字列は引用符で囲まれる
+        let tsv = CsvWriter::new()
+            .delimiter('\t')
+            .null("NULL")
+            .line_terminator("\n")
+            .format(&shohin);
+        assert!(tsv.contains("\n5\tわかめ\tNULL\t250\n"));
+        assert!(tsv.ends_with("\n9\t\"\"\t2\t100\n10\t\"NULL\"\t2\t100\n"));
+
+        // 1カラムのNULLは空行になり、読み戻してもNULLの行になる
+        let mut names = Table::create("names", attributes![("shohin_name", TypeKind::Varchar)]);
+        names
+            .insert(values!("りんご"))
+            .insert(values!(NULL))
+            .insert(values!(""))
+            .insert(values!(NULL));
+        let csv = CsvWriter::new().format(&names);
+        assert_eq!(csv, "shohin_name\r\nりんご\r\n\r\n\"\"\r\n\r\n");
+        let reader = CsvReader::new();
+        let loaded = reader.parse("names", &names.definition().attributes, &csv);
+        assert_eq!(owned_rows(&loaded.unwrap()), owned_rows(&names));
+        let loaded = reader.parse_inferred("names", &csv).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&names));
+        // 2カラム以上なら空行は読み飛ばす
+        let loaded = reader
+            .parse_inferred("shohin", "\nid,name\n\n1,a\n\n")
+            .unwrap();
+        assert_eq!(loaded.num_rows(), 1);
+
+        // 抽出・射影・集約・並べ替えの結果も書き出せる
+        let filtered = shohin.filter(&Predicate::is_null("kubun_id"));
+        assert_eq!(
+            CsvWriter::new().format(&filtered),
+            "shohin_id,shohin_name,kubun_id,price\r\n5,わかめ,,250\r\n"
+        );
+        let selected = shohin.select(&["price", "shohin_name"]);
+        let writer = CsvWriter::new().has_header(false).line_terminator("\n");
+        assert!(writer
+            .format(&selected)
+            .starts_with("300,りんご\n130,みかん\n"));
+        let grouped = shohin
+            .filter(&Predicate::equal_to("kubun_id", 2))
+            .group_by(&["kubun_id"], &[Agg::count("shohin_name")]);
+        assert_eq!(writer.format(&grouped), "2,3\n");
+        let ordered = shohin.order_by(&[SortKey::desc("price")]);
+        assert!(writer
+            .format(&ordered)
+            .starts_with("7,ドリアン,1,\n8,\"\"\"特選\"\"メロン,\r\n夕張産\",1,5000\n"));
+
+        let path = std::env::temp_dir().join(format!("kawaii_csv_{}.csv", std::process::id()));
+        CsvWriter::new().write_file(&selected, &path).unwrap();
+        let loaded = CsvReader::new().read_inferred("selected", &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&selected));
     }
 
     #[test]
-    fn test_column_varchar() {
-        let mut column = Column::new();
-        assert_eq!(column.append("Alice".to_string()), 0);
-        assert_eq!(column.append("Bob".to_string()), 1);
-        assert_eq!(column.append("Alice".to_string()), 0);
-        assert_eq!(column.num_keys(), 2);
-        assert_eq!(column.num_rows(), 3);
-        assert_eq!(column.id_of("Alice"), Some(0));
-        assert_eq!(column.id_of("Bob"), Some(1));
-        assert_eq!(column.id_of("Chris"), None);
-        let mut iter = column.range("Alice".to_string().."Chris".to_string());
-        assert_eq!(iter.next(), Some((&"Alice".to_string(), &0)));
-        assert_eq!(iter.next(), Some((&"Bob".to_string(), &1)));
-        assert_eq!(iter.next(), None);
-        assert_eq!(column.key_of(0), &"Alice".to_string());
-        assert_eq!(column.key_of(1), &"Bob".to_string());
-        assert_eq!(column.key_at(0), Some(&"Alice".to_string()));
-        assert_eq!(column.key_at(1), Some(&"Bob".to_string()));
-        assert_eq!(column.key_at(2), Some(&"Alice".to_string()));
+    fn test_json_lines() {
+        let mut shohin = create_shohin_table();
+        shohin.insert(values!(8, "\"特選\"メロン\n夕張産\\", 1, -5000));
+        let writer = JsonLinesWriter::new();
+        let jsonl = writer.format(&shohin);
+        let mut bytes = Vec::new();
+        writer.write(&shohin, &mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), jsonl);
+        assert!(jsonl.starts_with(
+            "{\"shohin_id\":1,\"shohin_name\":\"りんご\",\"kubun_id\":1,\"price\":300}\n"
+        ));
+        assert!(jsonl.contains(
+            "{\"shohin_id\":5,\"shohin_name\":\"わかめ\",\"kubun_id\":null,\"price\":250}\n"
+        ));
+
+        // 書き出したものを読み込むと元に戻る
+        let mut loaded = Table::new(shohin.definition().clone());
+        assert_eq!(loaded.parse_json_lines(&jsonl), Ok(8));
+        assert_eq!(owned_rows(&loaded), owned_rows(&shohin));
+        let path = std::env::temp_dir().join(format!("kawaii_jsonl_{}.jsonl", std::process::id()));
+        let filtered = shohin.filter(&Predicate::equal_to("kubun_id", 1));
+        writer.write_file(&filtered, &path).unwrap();
+        let mut loaded = Table::new(shohin.definition().clone());
+        assert_eq!(loaded.read_json_lines(&path), Ok(4));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&filtered));
+        let grouped = shohin.group_by(&["kubun_id"], &[Agg::count("shohin_name")]);
+        let relation: &dyn Relation = &grouped;
+        assert!(writer
+            .format(relation)
+            .contains("{\"kubun_id\":null,\"count\":1}\n"));
+
+        // どこかの行が正しくなければ、行番号付きのエラーになり何も追加されない
+        let errors = [
+            (
+                "{\"shohin_id\": 9}\n\n{\"shohin_id\": 1.5}",
+                3,
+                "カラムshohin_idはINTEGER型ですが、値1.5が指定されました",
+            ),
+            (
+                "{\"price\": 3000000000}",
+                1,
+                "カラムpriceはINTEGER型ですが、値3000000000が指定されました",
+            ),
+            (
+                "{\"shohin_name\": true}",
+                1,
+                "カラムshohin_nameはVARCHAR型ですが、値trueが指定されました",
+            ),
+            (
+                "{\"shohin_name\": 1}",
+                1,
+                "カラムshohin_nameはVARCHAR型ですが、値1が指定されました",
+            ),
+            ("{}\n{\"name\": \"りんご\"}", 2, "カラムnameは存在しません"),
+        ];
+        let mut table = Table::new(shohin.definition().clone());
+        for (jsonl, line, message) in errors.iter() {
+            assert_eq!(
+                table.parse_json_lines(jsonl),
+                Err(Error::JsonLines {
+                    line: *line,
+                    message: message.to_string()
+                })
+            );
+        }
+        for jsonl in &["[1, 2]", "{\"shohin_id\": 1", "{\"shohin_id\": 1} {}"] {
+            let err = table.parse_json_lines(jsonl).err();
+            assert!(
+                matches!(err, Some(Error::JsonLines { line: 1, .. })),
+                "{:?}",
+                err
+            );
+        }
+        assert_eq!(table.num_rows(), 0);
+        assert_eq!(table.parse_json_lines("{}\n"), Ok(1));
+        assert_eq!(writer.format(&Table::create("empty", &[])), "");
     }
 
     #[test]
-    fn test_dictionary_integer() {
-        let mut dictionary = Dictionary::new();
-        assert_eq!(dictionary.insert(10), 0);
-        assert_eq!(dictionary.insert(20), 1);
-        assert_eq!(dictionary.insert(10), 0);
-        assert_eq!(dictionary.num_keys(), 2);
-        assert_eq!(dictionary.id_of(&10), Some(0));
-        assert_eq!(dictionary.id_of(&20), Some(1));
-        assert_eq!(dictionary.id_of(&30), None);
-        let mut iter = dictionary.range(10..30);
-        assert_eq!(iter.next(), Some((&10, &0)));
-        assert_eq!(iter.next(), Some((&20, &1)));
-        assert_eq!(iter.next(), None);
-        assert_eq!(dictionary.key_of(0), &10);
-        assert_eq!(dictionary.key_of(1), &20);
+    fn test_arrow() {
+        use arrow_array::types::UInt8Type;
+        use arrow_array::{Float64Array, LargeStringArray};
+
+        let definition = create_shohin_table()
+            .definition()
+            .clone()
+            .primary_key(&["shohin_id"])
+            .unwrap();
+        let mut shohin = Table::new(definition);
+        shohin
+            .insert_rows(&owned_rows(&create_shohin_table()))
+            .unwrap();
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 3));
+        shohin.sort_keys();
+        shohin.insert(values!(8, "あけび", 2, 100));
+
+        // 文字列のカラムは辞書とキーIDがそのまま使われる
+        let writer = ArrowIpcWriter::new();
+        let batch = writer.record_batch(&shohin).unwrap();
+        assert_eq!(batch.num_rows(), 7);
+        let names = batch.column(1).as_dictionary::<Int32Type>();
+        assert_eq!(names.values().len(), shohin.column_at(1).num_keys());
+        assert_eq!(
+            names.keys().value(0) as KeyId,
+            shohin.columns[1].id_at(0).unwrap()
+        );
+        let schema = batch.schema();
+        assert!(!schema.field(0).is_nullable());
+        assert!(schema.field(2).is_nullable());
+        assert_eq!(batch.column(2).null_count(), 1);
+        let loaded = Table::from_record_batches("shohin", &schema, &[batch]).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&shohin));
+        assert!(loaded.definition()[0].is_not_null());
+
+        // 抽出・集約の結果もIPCファイルを通して読み戻せる
+        let path = std::env::temp_dir().join(format!("kawaii_arrow_{}.arrow", std::process::id()));
+        let filtered = shohin.filter(&Predicate::less_than("price", 250));
+        let grouped = shohin.group_by(&["kubun_id"], &[Agg::average("price")]);
+        let batches = [
+            (
+                writer.record_batch(&filtered).unwrap(),
+                owned_rows(&filtered),
+            ),
+            (writer.record_batch(&grouped).unwrap(), owned_rows(&grouped)),
+        ];
+        for (batch, rows) in batches.iter() {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut writer = FileWriter::try_new(file, &batch.schema()).unwrap();
+            writer.write(batch).unwrap();
+            writer.write(batch).unwrap();
+            writer.finish().unwrap();
+            let loaded = Table::read_arrow_ipc("loaded", &path).unwrap();
+            assert_eq!(owned_rows(&loaded), [&rows[..], &rows[..]].concat());
+        }
+        writer.write_file(&filtered, &path).unwrap();
+        assert_eq!(
+            owned_rows(&Table::read_arrow_ipc("filtered", &path).unwrap()),
+            owned_rows(&filtered)
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(Table::read_arrow_ipc("none", &path).is_err());
+
+        // 他のツールが作った型の配列も読める
+        let keys =
+            arrow_array::PrimitiveArray::<UInt8Type>::from(vec![Some(1), None, Some(0), Some(1)]);
+        let values = Int32Array::from(vec![10, 20]);
+        let kubun = DictionaryArray::try_new(keys, Arc::new(values)).unwrap();
+        let names = LargeStringArray::from(vec![Some("りんご"), Some("みかん"), None, Some("")]);
+        let schema = Schema::new(vec![
+            Field::new("kubun_id", kubun.data_type().clone(), true),
+            Field::new("shohin_name", DataType::LargeUtf8, true),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(kubun), Arc::new(names)],
+        )
+        .unwrap();
+        let table =
+            Table::from_record_batches("t", &schema, &[batch.clone(), batch.clone()]).unwrap();
+        assert_eq!(table.num_rows(), 8);
+        assert_eq!(
+            owned_rows(&table)[..4].to_vec(),
+            vec![
+                vec![20.into(), "りんご".into()],
+                vec![NULL.into(), "みかん".into()],
+                vec![10.into(), NULL.into()],
+                vec![20.into(), "".into()],
+            ]
+        );
+        let prices = Float64Array::from(vec![1.5]);
+        let schema = Schema::new(vec![Field::new("price", DataType::Float64, false)]);
+        let batch = RecordBatch::try_new(Arc::new(schema.clone()), vec![Arc::new(prices)]).unwrap();
+        assert_eq!(
+            Table::from_record_batches("t", &schema, &[batch])
+                .err()
+                .map(|err| err.to_string()),
+            Some(
+                "ファイルの形式が正しくありません: カラムpriceの型Float64には対応していません"
+                    .to_string()
+            )
+        );
+        let empty = writer.record_batch(&Table::create("empty", &[])).unwrap();
+        assert_eq!((empty.num_rows(), empty.num_columns()), (0, 0));
     }
 
     #[test]
-    fn test_column_integer() {
-        let mut column = Column::new();
-        assert_eq!(column.append(10), 0);
-        assert_eq!(column.append(20), 1);
-        assert_eq!(column.append(10), 0);
-        assert_eq!(column.num_keys(), 2);
-        assert_eq!(column.num_rows(), 3);
-        assert_eq!(column.id_of(&10), Some(0));
-        assert_eq!(column.id_of(&20), Some(1));
-        assert_eq!(column.id_of(&30), None);
-        let mut iter = column.range(10..30);
-        assert_eq!(iter.next(), Some((&10, &0)));
-        assert_eq!(iter.next(), Some((&20, &1)));
-        assert_eq!(iter.next(), None);
-        assert_eq!(column.key_of(0), &10);
-        assert_eq!(column.key_of(1), &20);
-        assert_eq!(column.key_at(0), Some(&10));
-        assert_eq!(column.key_at(1), Some(&20));
-        assert_eq!(column.key_at(2), Some(&10));
-    }
+    fn test_parquet() {
+        use parquet::basic::{Encoding as ParquetEncoding, LogicalType, Type as PhysicalType};
+        use parquet::file::reader::{FileReader as _, SerializedFileReader};
+        use parquet::file::statistics::Statistics;
 
-    fn create_shohin_table() -> Table {
-        let mut table = Table::create(
-            "shohin",
-            attributes![
-                ("shohin_id", TypeKind::Integer),
-                ("shohin_name", TypeKind::Varchar),
-                ("kubun_id", TypeKind::Integer),
-                ("price", TypeKind::Integer)
-            ],
+        let mut shohin = create_shohin_table();
+        shohin.delete_where(&Predicate::equal_to("shohin_id", 3));
+        shohin.sort_keys();
+        let path =
+            std::env::temp_dir().join(format!("kawaii_parquet_{}.parquet", std::process::id()));
+        ParquetWriter::new()
+            .row_group_size(4)
+            .write_file(&shohin, &path)
+            .unwrap();
+        let loaded = Table::read_parquet("shohin", &path).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&shohin));
+
+        // 型・辞書エンコーディング・行グループごとの最小値と最大値
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        let schema = metadata.file_metadata().schema_descr();
+        assert_eq!(schema.column(0).physical_type(), PhysicalType::INT32);
+        assert_eq!(schema.column(1).physical_type(), PhysicalType::BYTE_ARRAY);
+        assert_eq!(schema.column(1).logical_type(), Some(LogicalType::String));
+        assert_eq!(metadata.num_row_groups(), 2);
+        let mut stats = Vec::new();
+        for row_group in metadata.row_groups() {
+            let names = row_group.column(1);
+            assert!(names.dictionary_page_offset().is_some());
+            assert!(names.encodings().contains(&ParquetEncoding::RLE_DICTIONARY));
+            assert!(row_group
+                .column(0)
+                .encodings()
+                .contains(&ParquetEncoding::RLE_DICTIONARY));
+            match (row_group.column(3).statistics(), names.statistics()) {
+                (Some(Statistics::Int32(prices)), Some(Statistics::ByteArray(names))) => stats
+                    .push((
+                        *prices.min_opt().unwrap(),
+                        *prices.max_opt().unwrap(),
+                        prices.null_count_opt(),
+                        names.min_opt().unwrap().as_utf8().unwrap().to_string(),
+                        names.max_opt().unwrap().as_utf8().unwrap().to_string(),
+                    )),
+                stats => panic!("{:?}", stats),
+            }
+        }
+        // 辞書ページには行グループで使われている値がカラムの辞書のキーIDの順に並ぶ
+        let live_row_ids: Vec<RowId> = shohin.tombstones.bits().iter_zeros().collect();
+        let column = shohin.column_at(1);
+        for (row_group_no, row_ids) in live_row_ids.chunks(4).enumerate() {
+            let mut key_ids: Vec<KeyId> = row_ids
+                .iter()
+                .filter_map(|&row_id| column.id_at(row_id))
+                .collect();
+            key_ids.sort_unstable();
+            key_ids.dedup();
+            let expected: Vec<Value> = key_ids
+                .iter()
+                .map(|&key_id| column.key_of(key_id))
+                .collect();
+            let row_group = reader.get_row_group(row_group_no).unwrap();
+            let mut pages = row_group.get_column_page_reader(1).unwrap();
+            let buf = match pages.get_next_page().unwrap() {
+                Some(Page::DictionaryPage { buf, is_sorted, .. }) => {
+                    assert!(is_sorted);
+                    buf
+                }
+                page => panic!("{:?}", page.map(|page| page.page_type())),
+            };
+            let mut values = Vec::new();
+            let mut rest = &buf[..];
+            while !rest.is_empty() {
+                let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+                values.push(std::str::from_utf8(&rest[4..4 + len]).unwrap());
+                rest = &rest[4 + len..];
+            }
+            let values: Vec<Value> = values.into_iter().map(Value::Varchar).collect();
+            assert_eq!(values, expected);
+            assert_eq!(
+                pages.get_next_page().unwrap().map(|page| page.encoding()),
+                Some(ParquetEncoding::RLE_DICTIONARY)
+            );
+        }
+        // 1つ目はりんご・みかん・さんま・わかめ、2つ目はしいたけ・ドリアン(価格がNULL)
+        assert_eq!(
+            stats,
+            vec![
+                (
+                    130,
+                    300,
+                    Some(0),
+                    "さんま".to_string(),
+                    "わかめ".to_string()
+                ),
+                (
+                    180,
+                    180,
+                    Some(1),
+                    "しいたけ".to_string(),
+                    "ドリアン".to_string()
+                ),
+            ]
         );
-        table
-            .insert(values!(1, "りんご", 1, 300))
-            .insert(values!(2, "みかん", 1, 130))
-            .insert(values!(3, "キャベツ", 2, 200))
-            .insert(values!(4, "さんま", 3, 220))
-            .insert(values!(5, "わかめ", NULL, 250)) //区分がNULL
-            .insert(values!(6, "しいたけ", 4, 180)) //該当区分なし
-            .insert(values!(7, "ドリアン", 1, NULL));
-        table
-    }
 
-    fn create_kubun_table() -> Table {
-        let mut table = Table::create(
-            "kubun",
-            attributes![
-                ("kubun_id", TypeKind::Integer),
-                ("kubun_name", TypeKind::Varchar)
-            ],
+        // 抽出結果も書き出せる
+        let filtered = shohin.filter(&Predicate::is_null("kubun_id"));
+        let mut bytes = Vec::new();
+        ParquetWriter::new().write(&filtered, &mut bytes).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+        let loaded = Table::read_parquet("filtered", &path).unwrap();
+        assert_eq!(owned_rows(&loaded), owned_rows(&filtered));
+
+        // データページが複数になる行数と、ランとばらばらの値が混ざったキーIDも読み戻せる
+        let mut large = Table::create(
+            "large",
+            attributes![("n", TypeKind::Integer), ("s", TypeKind::Varchar)],
         );
-        table
-            .insert(values!(1, "くだもの"))
-            .insert(values!(2, "野菜"))
-            .insert(values!(3, "魚"));
-        table
+        for i in 0..45_000 {
+            let n = if i % 7 == 0 {
+                NULL.into()
+            } else {
+                OwnedValue::from(i % 300)
+            };
+            large.insert(&[n, format!("{}", i / 1000).into()]);
+        }
+        let mut bytes = Vec::new();
+        ParquetWriter::new()
+            .row_group_size(30_000)
+            .write(&large, &mut bytes)
+            .unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            owned_rows(&Table::read_parquet("large", &path).unwrap()),
+            owned_rows(&large)
+        );
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            Table::read_parquet("broken", &path),
+            Err(Error::InvalidFormat(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
     }
 
-    #[test]
-    fn test_table() {
-        let shohin1 = create_shohin_table();
-        let shohin2 = create_shohin_table();
-        assert_eq!(shohin1, shohin2);
-        let kubun1 = create_kubun_table();
-        let kubun2 = create_kubun_table();
-        assert_eq!(kubun1, kubun2);
+    #[derive(Row, Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Shohin {
+        shohin_id: i32,
+        shohin_name: String,
+        kubun_id: Option<i32>,
+        price: Option<i32>,
     }
 
-    #[test]
-    fn test_select() {
-        let shohin = create_shohin_table();
-        let actual = shohin.select(&["shohin_id", "shohin_name"]);
-        let mut expected = Table::create(
-            "shohin",
-            attributes![
-                ("shohin_id", TypeKind::Integer),
-                ("shohin_name", TypeKind::Varchar)
-            ],
-        );
-        expected
-            .insert(values!(1, "りんご"))
-            .insert(values!(2, "みかん"))
-            .insert(values!(3, "キャベツ"))
-            .insert(values!(4, "さんま"))
-            .insert(values!(5, "わかめ")) //区分がNULL
-            .insert(values!(6, "しいたけ")) //該当区分なし
-            .insert(values!(7, "ドリアン"));
-        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+    #[derive(Row, Serialize, Deserialize, Debug, PartialEq)]
+    struct Price {
+        #[serde(rename = "shohin_name")]
+        name: String,
+        price: i32,
     }
 
     #[test]
-    fn test_less_than() {
-        let shohin = create_shohin_table();
-        let actual = shohin.less_than("shohin_id", 4);
-        let mut expected = Table::create(
+    fn test_derive_row() {
+        let expected: Vec<Shohin> = owned_rows(&create_shohin_table())
+            .iter()
+            .map(|row| {
+                let tuple: Vec<Value> = row.iter().map(OwnedValue::as_value_ref).collect();
+                Shohin::from_tuple(create_shohin_table().definition(), &tuple).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            expected[4],
+            Shohin {
+                shohin_id: 5,
+                shohin_name: "わかめ".to_string(),
+                kubun_id: None,
+                price: Some(250),
+            }
+        );
+
+        // 構造体から作ったテーブルと、既存のテーブルのどちらにも挿入できる
+        let definition = Definition::create("shohin", &Shohin::attributes())
+            .primary_key(&["shohin_id"])
+            .unwrap();
+        let columns: Vec<(&str, TypeKind, bool)> = definition
+            .attributes
+            .iter()
+            .map(|attribute| {
+                (
+                    attribute.name().as_str(),
+                    attribute.kind(),
+                    attribute.is_not_null(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("shohin_id", TypeKind::Integer, true),
+                ("shohin_name", TypeKind::Varchar, true),
+                ("kubun_id", TypeKind::Integer, false),
+                ("price", TypeKind::Integer, false),
+            ]
+        );
+        let mut shohin = Table::new(definition);
+        shohin.append_rows(&expected).unwrap();
+        assert_eq!(shohin.fetch_rows::<Shohin>(0..7), Ok(expected.clone()));
+        let mut duplicated = expected[..2].to_vec();
+        duplicated[1].shohin_id = 1;
+        assert!(matches!(
+            shohin.append_rows(&duplicated),
+            Err(Error::Unique { .. })
+        ));
+        assert!(shohin.insert_row(&expected[0]).is_err());
+        assert_eq!(shohin.num_rows(), 7);
+        let mut table = Table::create(
             "shohin",
             attributes![
-                ("shohin_id", TypeKind::Integer),
+                ("price", TypeKind::Integer),
                 ("shohin_name", TypeKind::Varchar),
-                ("kubun_id", TypeKind::Integer),
-                ("price", TypeKind::Integer)
+                ("zaiko", TypeKind::Integer, with_default(0))
             ],
         );
-        expected
-            .insert(values!(1, "りんご", 1, 300))
-            .insert(values!(2, "みかん", 1, 130))
-            .insert(values!(3, "キャベツ", 2, 200));
-        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
+        table
+            .insert_row(&Price {
+                name: "りんご".to_string(),
+                price: 300,
+            })
+            .unwrap();
+        assert_eq!(
+            owned_rows(&table),
+            vec![vec![300.into(), "りんご".into(), 0.into()]]
+        );
+        assert_eq!(
+            table.insert_row(&expected[0]).err(),
+            Some(Error::UnknownColumn("shohin_id".to_string()))
+        );
+
+        // 抽出・射影した結果もカラム名で対応付けて取り出せる
+        let cheap = shohin.filter(&Predicate::less_than("price", 200));
+        assert_eq!(
+            cheap.fetch_rows::<Price>(0..10),
+            Ok(vec![
+                Price {
+                    name: "みかん".to_string(),
+                    price: 130
+                },
+                Price {
+                    name: "しいたけ".to_string(),
+                    price: 180
+                },
+            ])
+        );
+        assert_eq!(
+            shohin
+                .select(&["price", "shohin_name"])
+                .fetch_rows::<Price>(0..2)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(shohin.fetch_rows::<Price>(7..8), Ok(Vec::new()));
+        assert_eq!(Price::column_ids(shohin.definition()), Ok(vec![1, 3]));
+        assert_eq!(
+            shohin.fetch_rows::<Price>(0..7).err(),
+            Some(Error::NotNull("price".to_string()))
+        );
+        assert_eq!(
+            shohin
+                .select(&["shohin_name"])
+                .fetch_rows::<Price>(0..1)
+                .err(),
+            Some(Error::UnknownColumn("price".to_string()))
+        );
+        let renamed = shohin.select(&["shohin_id", "price"]);
+        let tuple = renamed.fetch(0..1).unwrap();
+        let mut definition = renamed.definition().clone();
+        definition[0] = Attribute::create("shohin_name", TypeKind::Integer);
+        assert_eq!(
+            Price::from_tuple(&definition, &tuple[0]).err(),
+            Some(Error::TypeMismatch {
+                column: "shohin_name".to_string(),
+                expected: TypeKind::Varchar,
+                value: 1.into(),
+            })
+        );
     }
 
     #[test]
-    fn test_equal_to() {
-        let shohin = create_shohin_table();
-        let actual = shohin.equal_to("shohin_id", 4);
-        let mut expected = Table::create(
-            "shohin",
-            attributes![
-                ("shohin_id", TypeKind::Integer),
-                ("shohin_name", TypeKind::Varchar),
-                ("kubun_id", TypeKind::Integer),
-                ("price", TypeKind::Integer)
-            ],
+    fn test_foreign_keys() {
+        let create_database = |action: ReferentialAction| {
+            let kubun = create_kubun_table();
+            let definition = kubun.definition().clone().primary_key(&["kubun_id"]);
+            let mut kubun = Table::new(definition.unwrap());
+            for row in owned_rows(&create_kubun_table()) {
+                kubun.insert(&row);
+            }
+            let shohin = create_shohin_table();
+            let definition = shohin.definition().clone().primary_key(&["shohin_id"]);
+            let definition = definition
+                .and_then(|definition| {
+                    definition.foreign_key_with_actions(
+                        &["kubun_id"],
+                        "kubun",
+                        &["kubun_id"],
+                        action,
+                        action,
+                    )
+                })
+                .unwrap();
+            let mut db = Database::new();
+            db.add_table(kubun).unwrap();
+            // しいたけの区分4が存在しない
+            let mut shohin = Table::new(definition.clone());
+            for row in owned_rows(&create_shohin_table()) {
+                shohin.insert(&row);
+            }
+            assert_eq!(
+                db.add_table(shohin).err(),
+                Some(Error::ForeignKey {
+                    columns: vec!["kubun_id".to_string()],
+                    table: "kubun".to_string(),
+                    values: vec![4.into()],
+                })
+            );
+            db.insert("kubun", values!(4, "きのこ")).unwrap();
+            let mut shohin = Table::new(definition);
+            for row in owned_rows(&create_shohin_table()) {
+                shohin.insert(&row);
+            }
+            db.add_table(shohin).unwrap();
+            db
+        };
+
+        let mut db = create_database(ReferentialAction::Restrict);
+        assert_eq!(
+            db.insert("shohin", values!(8, "なし", 5, 100))
+                .err()
+                .map(|err| err.to_string()),
+            Some("外部キー(kubun_id)の値(5)はテーブルkubunに存在しません".to_string())
+        );
+        db.insert("shohin", values!(8, "しめじ", 4, 150)).unwrap();
+        let shimeji = Predicate::equal_to("shohin_id", 8);
+        assert!(db.update("shohin", &shimeji, &[("kubun_id", 9)]).is_err());
+        assert_eq!(db.update("shohin", &shimeji, &[("kubun_id", 2)]), Ok(1));
+        // 参照されている値は書き換えも削除もできない
+        let sakana = Predicate::equal_to("kubun_id", 3);
+        assert_eq!(
+            db.update("kubun", &sakana, &[("kubun_id", 30)])
+                .err()
+                .map(|err| err.to_string()),
+            Some("値(3)はテーブルshohinの外部キー(kubun_id)から参照されています".to_string())
+        );
+        assert_eq!(
+            db.update("kubun", &sakana, &[("kubun_name", "さかな")]),
+            Ok(1)
+        );
+        assert!(db.delete_where("kubun", &sakana).is_err());
+        assert!(db.drop_table("kubun").is_err());
+        assert_eq!(
+            db.delete_where("shohin", &Predicate::equal_to("kubun_id", 3)),
+            Ok(1)
+        );
+        assert_eq!(db.delete_where("kubun", &sakana), Ok(1));
+        assert_eq!(
+            db.insert("nothing", values!(1)).err(),
+            Some(Error::UnknownTable("nothing".to_string()))
+        );
+
+        let kudamono = Predicate::equal_to("kubun_id", 1);
+        let mut db = create_database(ReferentialAction::Cascade);
+        assert_eq!(db.delete_where("kubun", &kudamono), Ok(1));
+        let shohin = db.table("shohin").unwrap();
+        assert_eq!(shohin.num_rows(), 4);
+        assert_eq!(shohin.filter(&kudamono).num_rows(), 0);
+
+        let mut db = create_database(ReferentialAction::SetNull);
+        assert_eq!(db.delete_where("kubun", &kudamono), Ok(1));
+        let shohin = db.table("shohin").unwrap();
+        assert_eq!(shohin.num_rows(), 7);
+        assert_eq!(shohin.is_null("kubun_id").num_rows(), 4);
+
+        // 参照されている値を書き換えると、参照している行も書き換えるかNULLにする
+        let yasai = Predicate::equal_to("kubun_id", 2);
+        let mut db = create_database(ReferentialAction::Cascade);
+        assert_eq!(db.update("kubun", &kudamono, &[("kubun_id", 10)]), Ok(1));
+        let shohin = db.table("shohin").unwrap();
+        assert_eq!(shohin.equal_to("kubun_id", 10).num_rows(), 3);
+        assert_eq!(shohin.filter(&kudamono).num_rows(), 0);
+
+        let mut db = create_database(ReferentialAction::SetNull);
+        assert_eq!(db.update("kubun", &yasai, &[("kubun_id", 20)]), Ok(1));
+        assert_eq!(
+            db.table("shohin").unwrap().is_null("kubun_id").num_rows(),
+            2
+        );
+
+        // NULLにするとCHECK制約に違反する場合は、連鎖して書き換えた行も元に戻す
+        let tana = Definition::create(
+            "tana",
+            attributes![(
+                "kubun_id",
+                TypeKind::Integer,
+                check(Predicate::is_not_null("kubun_id"))
+            )],
+        )
+        .foreign_key_with_actions(
+            &["kubun_id"],
+            "kubun",
+            &["kubun_id"],
+            ReferentialAction::SetNull,
+            ReferentialAction::SetNull,
+        )
+        .unwrap();
+        db.create_table(tana).unwrap();
+        db.insert("tana", values!(1)).unwrap();
+        let rows = owned_rows(db.table("shohin").unwrap());
+        assert!(matches!(
+            db.update("kubun", &kudamono, &[("kubun_id", 10)]),
+            Err(Error::Check { .. })
+        ));
+        assert!(matches!(
+            db.delete_where("kubun", &kudamono),
+            Err(Error::Check { .. })
+        ));
+        assert_eq!(owned_rows(db.table("shohin").unwrap()), rows);
+        assert_eq!(db.table("kubun").unwrap().filter(&kudamono).num_rows(), 1);
+        assert_eq!(db.table("tana").unwrap().filter(&kudamono).num_rows(), 1);
+
+        // NULLにしたUNIQUEなカラムを参照している行にも、更新時の動作を連鎖させる
+        let create_database = |on_update: ReferentialAction| {
+            let mut db = create_database(ReferentialAction::Restrict);
+            let tana = Definition::create(
+                "tana",
+                attributes![
+                    ("tana_id", TypeKind::Integer),
+                    ("kubun_id", TypeKind::Integer)
+                ],
+            )
+            .primary_key(&["tana_id"])
+            .and_then(|tana| tana.unique(&["kubun_id"]))
+            .and_then(|tana| {
+                tana.foreign_key_with_actions(
+                    &["kubun_id"],
+                    "kubun",
+                    &["kubun_id"],
+                    ReferentialAction::SetNull,
+                    ReferentialAction::Restrict,
+                )
+            })
+            .unwrap();
+            let ranks = Definition::create(
+                "ranks",
+                attributes![("name", TypeKind::Varchar), ("kubun_id", TypeKind::Integer)],
+            )
+            .foreign_key_with_actions(
+                &["kubun_id"],
+                "tana",
+                &["kubun_id"],
+                ReferentialAction::Restrict,
+                on_update,
+            )
+            .unwrap();
+            db.create_table(tana).unwrap();
+            db.create_table(ranks).unwrap();
+            db.insert("tana", values!(1, 1)).unwrap();
+            db.insert("tana", values!(2, 2)).unwrap();
+            db.insert("ranks", values!("いちばん", 1)).unwrap();
+            db.delete_where("shohin", &kudamono).unwrap();
+            db
+        };
+        let mut db = create_database(ReferentialAction::Restrict);
+        let rows = owned_rows(db.table("tana").unwrap());
+        assert_eq!(
+            db.delete_where("kubun", &kudamono),
+            Err(Error::Referenced {
+                table: "ranks".to_string(),
+                columns: vec!["kubun_id".to_string()],
+                values: vec![1.into()],
+            })
+        );
+        assert_eq!(owned_rows(db.table("tana").unwrap()), rows);
+        assert_eq!(db.table("kubun").unwrap().filter(&kudamono).num_rows(), 1);
+        let mut db = create_database(ReferentialAction::Cascade);
+        assert_eq!(db.delete_where("kubun", &kudamono), Ok(1));
+        assert_eq!(db.table("tana").unwrap().is_null("kubun_id").num_rows(), 1);
+        assert_eq!(db.table("ranks").unwrap().is_null("kubun_id").num_rows(), 1);
+        let mut db = create_database(ReferentialAction::SetNull);
+        assert_eq!(db.delete_where("kubun", &kudamono), Ok(1));
+        assert_eq!(db.table("ranks").unwrap().is_null("kubun_id").num_rows(), 1);
+
+        // 衝突した行の書き換えでも外部キーを検査し、参照している行に動作を連鎖させる
+        let mut db = create_database(ReferentialAction::Restrict);
+        let rows = owned_rows(db.table("tana").unwrap());
+        assert!(matches!(
+            db.insert_on_conflict("tana", values!(3, 9), OnConflict::DoUpdate),
+            Err(Error::ForeignKey { .. })
+        ));
+        assert!(matches!(
+            db.insert_on_conflict("tana", values!(2, 9), OnConflict::DoUpdate),
+            Err(Error::ForeignKey { .. })
+        ));
+        assert!(matches!(
+            db.insert_on_conflict("tana", values!(1, 3), OnConflict::DoUpdate),
+            Err(Error::Referenced { .. })
+        ));
+        assert_eq!(
+            db.insert_on_conflict("tana", values!(1, 2), OnConflict::DoUpdate)
+                .err(),
+            Some(Error::Unique {
+                columns: vec!["kubun_id".to_string()],
+                values: vec![2.into()],
+            })
+        );
+        db.insert_on_conflict("tana", values!(1, 3), OnConflict::DoNothing)
+            .unwrap();
+        assert_eq!(owned_rows(db.table("tana").unwrap()), rows);
+        db.insert_on_conflict("tana", values!(2, 3), OnConflict::DoUpdate)
+            .unwrap()
+            .insert_on_conflict("tana", values!(3, 4), OnConflict::DoUpdate)
+            .unwrap();
+        assert_eq!(
+            owned_rows(db.table("tana").unwrap()),
+            vec![
+                vec![1.into(), 1.into()],
+                vec![2.into(), 3.into()],
+                vec![3.into(), 4.into()],
+            ]
+        );
+        let mut db = create_database(ReferentialAction::Cascade);
+        db.insert_on_conflict("tana", values!(1, 3), OnConflict::DoUpdate)
+            .unwrap();
+        assert_eq!(
+            db.table("ranks")
+                .unwrap()
+                .equal_to("kubun_id", 3)
+                .num_rows(),
+            1
         );
-        expected.insert(values!(4, "さんま", 3, 220));
-        assert_eq!(actual.fetch(0..10), expected.fetch(0..10));
     }
 
     #[test]
@@ -40,6 +40,21 @@ fn main() {
     {
         println!("{}", shohin.select(&["shohin_name", "price"]));
     }
+    {
+        println!("{}", shohin.is_null("kubun_id"));
+    }
+    {
+        println!(
+            "{}",
+            shohin.order_by(&[SortKey::desc("price"), SortKey::asc("shohin_id")])
+        );
+    }
+    {
+        println!(
+            "{}",
+            shohin.filter(&!Predicate::less_than("price", 200).or(Predicate::is_null("price")))
+        );
+    }
     {
         // 参照が切れるのでメソッドチェーンできない
         // Vec<Box<dyn Relation>> に入れようとしてもmut参照が2つになるのでエラーとなる